@@ -0,0 +1,146 @@
+//! Compares the ways of parsing class files. By default the class files under `test-resources`
+//! are parsed; pass directories to scan a classpath instead, as in
+//! `cargo bench --bench parse -- /path/to/classes`.
+
+extern crate pantomime_parser;
+
+use pantomime_parser::ClassFile;
+use pantomime_parser::view::ClassFileRef;
+
+use std::env;
+use std::ffi::OsStr;
+use std::fs::{self, File};
+use std::io::Read;
+use std::panic;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// The number of class files to parse in each measurement, going over the inputs as many times as
+/// it takes.
+const PARSES_PER_RUN: usize = 20_000;
+
+fn main() {
+    let mut directories: Vec<PathBuf> =
+        env::args().skip(1).filter(|arg| !arg.starts_with("--")).map(PathBuf::from).collect();
+    if directories.is_empty() {
+        directories.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("test-resources"));
+    }
+
+    let mut paths = vec![];
+    for directory in &directories {
+        find_class_files(directory, &mut paths);
+    }
+    if paths.is_empty() {
+        println!("no class files found");
+        return;
+    }
+
+    // leave out whatever the parser rejects, so that the timed loops never fail
+    let mut contents: Vec<Vec<u8>> = vec![];
+    let mut rejected = 0;
+    paths.retain(|path| {
+        let content = read(path);
+        if parses(&content) {
+            contents.push(content);
+            true
+        } else {
+            rejected += 1;
+            false
+        }
+    });
+    if rejected > 0 {
+        println!("skipping {} class files the parser rejects", rejected);
+    }
+    if paths.is_empty() {
+        return;
+    }
+
+    let bytes: usize = contents.iter().map(Vec::len).sum();
+    let runs = (PARSES_PER_RUN / paths.len()).max(1);
+    println!("{} class files, {} bytes, {} runs", paths.len(), bytes, runs);
+
+    report("unbuffered File::bytes", runs * paths.len(), bytes * runs, || {
+        for _ in 0..runs {
+            for path in &paths {
+                // the way `ClassFile::from` read files before it buffered them
+                #[allow(clippy::unbuffered_bytes)]
+                let mut bytes = File::open(path).unwrap().bytes();
+                ClassFile::from_iterator(&mut bytes).unwrap();
+            }
+        }
+    });
+
+    report("ClassFile::open", runs * paths.len(), bytes * runs, || {
+        for _ in 0..runs {
+            for path in &paths {
+                ClassFile::open(path).unwrap();
+            }
+        }
+    });
+
+    report("ClassFile::parse", runs * paths.len(), bytes * runs, || {
+        for _ in 0..runs {
+            for content in &contents {
+                ClassFile::parse(content).unwrap();
+            }
+        }
+    });
+
+    report("ClassFile::parse_lazy", runs * paths.len(), bytes * runs, || {
+        for _ in 0..runs {
+            for content in &contents {
+                ClassFile::parse_lazy(content).unwrap();
+            }
+        }
+    });
+
+    report("ClassFileRef::parse", runs * paths.len(), bytes * runs, || {
+        for _ in 0..runs {
+            for content in &contents {
+                ClassFileRef::parse(content).unwrap();
+            }
+        }
+    });
+}
+
+/// Whether `content` parses, where a panic counts as a rejection rather than ending the run.
+fn parses(content: &[u8]) -> bool {
+    panic::catch_unwind(|| ClassFile::parse(content).is_ok()).unwrap_or(false)
+}
+
+fn report<F: FnOnce()>(name: &str, parses: usize, bytes: usize, run: F) {
+    let start = Instant::now();
+    run();
+    let elapsed = seconds(start.elapsed());
+
+    println!("{:<24} {:>10.2} us/class {:>10.1} MB/s",
+             name,
+             elapsed * 1e6 / parses as f64,
+             bytes as f64 / elapsed / 1e6);
+}
+
+fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + duration.subsec_nanos() as f64 * 1e-9
+}
+
+fn read(path: &Path) -> Vec<u8> {
+    let mut bytes = vec![];
+    File::open(path).unwrap().read_to_end(&mut bytes).unwrap();
+    bytes
+}
+
+fn find_class_files(directory: &Path, paths: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_class_files(&path, paths);
+        } else if path.extension() == Some(OsStr::new("class")) {
+            paths.push(path);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use std::fmt;
+use std::ops::{BitAnd, BitOr};
+
+use super::primitives::U2;
+
+/// Declares a set of access flags for one context, with a constant for each flag. Flags are
+/// listed in the order Java source writes their modifiers, and those that have a modifier give
+/// its keyword after the flag's value.
+macro_rules! access_flags {
+    ($(#[$meta:meta])* pub struct $name:ident {
+        $($(#[$flag_meta:meta])* const $flag:ident = $value:expr $(, $keyword:expr)*;)*
+    }) => {
+        $(#[$meta])*
+        #[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+        pub struct $name(U2);
+
+        impl $name {
+            $($(#[$flag_meta])* pub const $flag: $name = $name($value);)*
+
+            /// The set of flags in `bits`, keeping any bits that have no meaning here.
+            pub fn from(bits: U2) -> $name {
+                $name(bits)
+            }
+
+            pub fn empty() -> $name {
+                $name(0)
+            }
+
+            pub fn bits(&self) -> U2 {
+                self.0
+            }
+
+            pub fn is_empty(&self) -> bool {
+                self.0 == 0
+            }
+
+            /// Whether every flag in `other` is set.
+            pub fn contains(&self, other: $name) -> bool {
+                (self.0 & other.0) == other.0
+            }
+
+            /// Whether any flag in `other` is set.
+            pub fn intersects(&self, other: $name) -> bool {
+                (self.0 & other.0) != 0
+            }
+
+            pub fn insert(&mut self, other: $name) {
+                self.0 |= other.0;
+            }
+
+            pub fn remove(&mut self, other: $name) {
+                self.0 &= !other.0;
+            }
+        }
+
+        impl BitOr for $name {
+            type Output = $name;
+
+            fn bitor(self, other: $name) -> $name {
+                $name(self.0 | other.0)
+            }
+        }
+
+        impl BitAnd for $name {
+            type Output = $name;
+
+            fn bitand(self, other: $name) -> $name {
+                $name(self.0 & other.0)
+            }
+        }
+
+        /// Writes the modifiers among the flags, separated by spaces.
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let mut keywords: Vec<&'static str> = vec![];
+                $(
+                    if self.contains($name::$flag) {
+                        $(keywords.push($keyword);)*
+                    }
+                )*
+                write!(f, "{}", keywords.join(" "))
+            }
+        }
+    }
+}
+
+access_flags! {
+    /// The access flags of a class, interface or module.
+    pub struct ClassAccess {
+        const PUBLIC = 0x0001, "public";
+        const ABSTRACT = 0x0400, "abstract";
+        const FINAL = 0x0010, "final";
+        /// Use the newer semantics of `invokespecial`, which every class file since Java 8 does.
+        const SUPER = 0x0020;
+        const INTERFACE = 0x0200;
+        const SYNTHETIC = 0x1000;
+        const ANNOTATION = 0x2000;
+        const ENUM = 0x4000;
+        const MODULE = 0x8000;
+    }
+}
+
+access_flags! {
+    /// The access flags of a field.
+    pub struct FieldAccess {
+        const PUBLIC = 0x0001, "public";
+        const PROTECTED = 0x0004, "protected";
+        const PRIVATE = 0x0002, "private";
+        const STATIC = 0x0008, "static";
+        const FINAL = 0x0010, "final";
+        const TRANSIENT = 0x0080, "transient";
+        const VOLATILE = 0x0040, "volatile";
+        const SYNTHETIC = 0x1000;
+        const ENUM = 0x4000;
+    }
+}
+
+access_flags! {
+    /// The access flags of a method.
+    pub struct MethodAccess {
+        const PUBLIC = 0x0001, "public";
+        const PROTECTED = 0x0004, "protected";
+        const PRIVATE = 0x0002, "private";
+        const ABSTRACT = 0x0400, "abstract";
+        const STATIC = 0x0008, "static";
+        const FINAL = 0x0010, "final";
+        const SYNCHRONIZED = 0x0020, "synchronized";
+        const NATIVE = 0x0100, "native";
+        const STRICT = 0x0800, "strictfp";
+        /// A method the compiler generated to bridge an erased signature to an overriding one.
+        const BRIDGE = 0x0040;
+        const VARARGS = 0x0080;
+        const SYNTHETIC = 0x1000;
+    }
+}
+
+access_flags! {
+    /// The access flags of a nested class, as listed in the `InnerClasses` attribute.
+    pub struct InnerClassAccess {
+        const PUBLIC = 0x0001, "public";
+        const PROTECTED = 0x0004, "protected";
+        const PRIVATE = 0x0002, "private";
+        const ABSTRACT = 0x0400, "abstract";
+        const STATIC = 0x0008, "static";
+        const FINAL = 0x0010, "final";
+        const INTERFACE = 0x0200;
+        const SYNTHETIC = 0x1000;
+        const ANNOTATION = 0x2000;
+        const ENUM = 0x4000;
+    }
+}
+
+access_flags! {
+    /// The flags of a module, as given in the `Module` attribute.
+    pub struct ModuleAccess {
+        const OPEN = 0x0020, "open";
+        const SYNTHETIC = 0x1000;
+        /// A module that was implicitly declared.
+        const MANDATED = 0x8000;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ClassAccess, FieldAccess, MethodAccess, ModuleAccess};
+
+    #[test]
+    fn can_print_modifiers_in_java_order() {
+        let method = MethodAccess::from(0x0829);
+        assert_that(&method.to_string()).is_equal_to(&"public static synchronized strictfp"
+            .to_string());
+
+        let field = FieldAccess::PRIVATE | FieldAccess::FINAL | FieldAccess::TRANSIENT |
+                    FieldAccess::SYNTHETIC;
+        assert_that(&field.to_string()).is_equal_to(&"private final transient".to_string());
+
+        assert_that(&ClassAccess::from(0x0631).to_string())
+            .is_equal_to(&"public abstract final".to_string());
+        assert_that(&ModuleAccess::MANDATED.to_string()).is_equal_to(&"".to_string());
+    }
+
+    #[test]
+    fn can_query_flags() {
+        let mut method = MethodAccess::from(0x0041);
+
+        assert_that(&method.contains(MethodAccess::BRIDGE)).is_equal_to(&true);
+        assert_that(&method.contains(MethodAccess::PUBLIC | MethodAccess::STATIC))
+            .is_equal_to(&false);
+        assert_that(&method.intersects(MethodAccess::PUBLIC | MethodAccess::STATIC))
+            .is_equal_to(&true);
+
+        method.remove(MethodAccess::BRIDGE);
+        method.insert(MethodAccess::VARARGS);
+        assert_that(&method.bits()).is_equal_to(&0x0081);
+        assert_that(&(method & MethodAccess::PRIVATE).is_empty()).is_equal_to(&true);
+    }
+
+}
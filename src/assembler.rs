@@ -0,0 +1,658 @@
+use super::{ParserError, ParserResult};
+use super::components::{Attribute, CodeAttribute, ExceptionHandler, LineNumber,
+                        LineNumberTableAttribute, LocalVariable, LocalVariableTableAttribute};
+use super::instructions::Instruction;
+use super::primitives::{U1, U2, U4};
+
+use std::collections::{BTreeSet, HashMap};
+use std::rc::Rc;
+
+/// A symbolic position in a method body, resolved to a pc when the code is assembled.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Label(usize);
+
+impl Label {
+    /// The label numbered `number`. Labels only have to be distinct within one method body, and
+    /// those made by a `CodeAssembler` are numbered from zero.
+    pub fn new(number: usize) -> Label {
+        Label(number)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum CodeItem {
+    Label(Label),
+    Instruction(Instruction<Label>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelledExceptionHandler {
+    pub start: Label,
+    pub end: Label,
+    pub handler: Label,
+    pub catch_type: U2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelledLineNumber {
+    pub start: Label,
+    pub line_number: U2,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct LabelledLocalVariable {
+    pub start: Label,
+    pub end: Label,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+    pub index: U2,
+}
+
+/// Builds a code array from instructions whose branch operands are labels.
+///
+/// Branch offsets and switch padding are computed when the code is assembled. A `goto` or `jsr`
+/// whose target is out of reach of a 16 bit offset is emitted as `goto_w` or `jsr_w`, and an
+/// out of reach conditional branch is emitted as the inverted condition jumping over a `goto_w`.
+#[derive(Clone, Debug, Default)]
+pub struct CodeAssembler {
+    pub items: Vec<CodeItem>,
+    pub exception_handlers: Vec<LabelledExceptionHandler>,
+    pub line_numbers: Vec<LabelledLineNumber>,
+    pub local_variables: Vec<LabelledLocalVariable>,
+    pub local_variable_types: Vec<LabelledLocalVariable>,
+    next_label: usize,
+}
+
+#[derive(Debug)]
+pub struct AssembledCode {
+    pub code: Vec<U1>,
+    pub exception_table: Vec<ExceptionHandler>,
+    pub line_numbers: Vec<LineNumber>,
+    pub local_variables: Vec<LocalVariable>,
+    pub local_variable_types: Vec<LocalVariable>,
+    label_offsets: HashMap<Label, U2>,
+}
+
+impl AssembledCode {
+    pub fn label_offset(&self, label: Label) -> Option<U2> {
+        self.label_offsets.get(&label).cloned()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum BranchForm {
+    Short,
+    Long,
+}
+
+impl CodeAssembler {
+    pub fn new() -> CodeAssembler {
+        CodeAssembler::default()
+    }
+
+    /// Decodes an existing code attribute, replacing every branch target, exception handler
+    /// boundary and debug table offset with a label.
+    pub fn from_code(code: &CodeAttribute) -> ParserResult<CodeAssembler> {
+        let mut instructions = vec![];
+        let mut instruction_pcs = BTreeSet::new();
+        for result in code.instructions() {
+            let (pc, instruction) = try!(result);
+            instruction_pcs.insert(pc as usize);
+            instructions.push((pc as usize, instruction));
+        }
+        let code_length = code.code.len();
+        instruction_pcs.insert(code_length);
+
+        let mut assembler = CodeAssembler::new();
+        let mut labels = HashMap::new();
+        let mut labelled_instructions = vec![];
+        let mut next_label = 0;
+
+        {
+            let mut label_at = |pc: usize| -> ParserResult<Label> {
+                if !instruction_pcs.contains(&pc) {
+                    return Err(ParserError::InvalidCodeOffset(pc));
+                }
+
+                let next_label = &mut next_label;
+                Ok(*labels.entry(pc).or_insert_with(|| {
+                    *next_label += 1;
+                    Label(*next_label - 1)
+                }))
+            };
+
+            for (pc, instruction) in instructions {
+                let mut targets = vec![];
+                for offset in instruction.branch_targets() {
+                    let target = pc as i64 + *offset as i64;
+                    if target < 0 {
+                        return Err(ParserError::InvalidCodeOffset(pc));
+                    }
+                    targets.push(try!(label_at(target as usize)));
+                }
+
+                let mut targets = targets.into_iter();
+                let instruction = instruction.map_targets(|_| targets.next().unwrap());
+                labelled_instructions.push((pc, instruction));
+            }
+
+            for handler in &code.exception_table {
+                let handler = LabelledExceptionHandler {
+                    start: try!(label_at(handler.start_pc as usize)),
+                    end: try!(label_at(handler.end_pc as usize)),
+                    handler: try!(label_at(handler.handler_pc as usize)),
+                    catch_type: handler.catch_type,
+                };
+                assembler.exception_handlers.push(handler);
+            }
+
+            for attribute in &code.attributes {
+                match *attribute {
+                    Attribute::LineNumberTable(ref table) => {
+                        for line_number in &table.line_number_table {
+                            let line_number = LabelledLineNumber {
+                                start: try!(label_at(line_number.start_pc as usize)),
+                                line_number: line_number.line_number,
+                            };
+                            assembler.line_numbers.push(line_number);
+                        }
+                    }
+                    Attribute::LocalVariableTable(ref table) |
+                    Attribute::LocalVariableTypeTable(ref table) => {
+                        let mut local_variables = vec![];
+                        for local_variable in &table.local_variable_table {
+                            let start_pc = local_variable.start_pc as usize;
+                            let end_pc = start_pc + local_variable.length as usize;
+
+                            local_variables.push(LabelledLocalVariable {
+                                start: try!(label_at(start_pc)),
+                                end: try!(label_at(end_pc)),
+                                name_index: local_variable.name_index,
+                                descriptor_index: local_variable.descriptor_index,
+                                index: local_variable.index,
+                            });
+                        }
+
+                        if let Attribute::LocalVariableTable(..) = *attribute {
+                            assembler.local_variables.extend(local_variables);
+                        } else {
+                            assembler.local_variable_types.extend(local_variables);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        assembler.next_label = next_label;
+        for (pc, instruction) in labelled_instructions {
+            if let Some(label) = labels.get(&pc) {
+                assembler.place_label(*label);
+            }
+            assembler.push(instruction);
+        }
+        if let Some(label) = labels.get(&code_length) {
+            assembler.place_label(*label);
+        }
+
+        Ok(assembler)
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.next_label += 1;
+        Label(self.next_label - 1)
+    }
+
+    pub fn place_label(&mut self, label: Label) {
+        self.items.push(CodeItem::Label(label));
+    }
+
+    pub fn push(&mut self, instruction: Instruction<Label>) {
+        self.items.push(CodeItem::Instruction(instruction));
+    }
+
+    pub fn exception_handler(&mut self, start: Label, end: Label, handler: Label, catch_type: U2) {
+        self.exception_handlers.push(LabelledExceptionHandler {
+            start: start,
+            end: end,
+            handler: handler,
+            catch_type: catch_type,
+        });
+    }
+
+    pub fn line_number(&mut self, start: Label, line_number: U2) {
+        self.line_numbers.push(LabelledLineNumber {
+            start: start,
+            line_number: line_number,
+        });
+    }
+
+    pub fn local_variable(&mut self,
+                          start: Label,
+                          end: Label,
+                          name_index: U2,
+                          descriptor_index: U2,
+                          index: U2) {
+        self.local_variables.push(LabelledLocalVariable {
+            start: start,
+            end: end,
+            name_index: name_index,
+            descriptor_index: descriptor_index,
+            index: index,
+        });
+    }
+
+    pub fn assemble(&self) -> ParserResult<AssembledCode> {
+        let mut forms = vec![BranchForm::Short; self.items.len()];
+
+        // branches only ever grow, so this settles once no further branch needs widening
+        let (item_pcs, label_offsets) = loop {
+            let (item_pcs, label_offsets) = try!(self.layout(&forms));
+
+            let mut widened = false;
+            for (i, item) in self.items.iter().enumerate() {
+                if let CodeItem::Instruction(ref instruction) = *item {
+                    if forms[i] == BranchForm::Short && has_short_branch(instruction) {
+                        let target = try!(resolve_label(*instruction.branch_targets()[0],
+                                                        &label_offsets));
+                        let offset = target as i64 - item_pcs[i] as i64;
+                        if offset != offset as i16 as i64 {
+                            forms[i] = BranchForm::Long;
+                            widened = true;
+                        }
+                    }
+                }
+            }
+
+            if !widened {
+                break (item_pcs, label_offsets);
+            }
+        };
+
+        let mut code = vec![];
+        for (i, item) in self.items.iter().enumerate() {
+            if let CodeItem::Instruction(ref instruction) = *item {
+                let pc = item_pcs[i];
+                let instruction = try!(relativize(instruction.clone(), pc, &label_offsets));
+                try!(encode_with_form(&instruction, forms[i], &mut code));
+            }
+        }
+
+        let mut exception_table = vec![];
+        for handler in &self.exception_handlers {
+            let start_pc = try!(resolve_label(handler.start, &label_offsets)) as U2;
+            let end_pc = try!(resolve_label(handler.end, &label_offsets)) as U2;
+
+            // a range emptied by the rewrite no longer protects anything
+            if start_pc < end_pc {
+                exception_table.push(ExceptionHandler {
+                    start_pc: start_pc,
+                    end_pc: end_pc,
+                    handler_pc: try!(resolve_label(handler.handler, &label_offsets)) as U2,
+                    catch_type: handler.catch_type,
+                });
+            }
+        }
+
+        let mut line_numbers = vec![];
+        for line_number in &self.line_numbers {
+            line_numbers.push(LineNumber {
+                start_pc: try!(resolve_label(line_number.start, &label_offsets)) as U2,
+                line_number: line_number.line_number,
+            });
+        }
+
+        let local_variables = try!(resolve_local_variables(&self.local_variables,
+                                                           &label_offsets));
+        let local_variable_types = try!(resolve_local_variables(&self.local_variable_types,
+                                                                &label_offsets));
+
+        Ok(AssembledCode {
+            code: code,
+            exception_table: exception_table,
+            line_numbers: line_numbers,
+            local_variables: local_variables,
+            local_variable_types: local_variable_types,
+            label_offsets: label_offsets.into_iter().map(|(k, v)| (k, v as U2)).collect(),
+        })
+    }
+
+    /// Assembles the code and returns a copy of `original` carrying the new code, exception
+    /// table and debug tables. A debug table takes the place of the original one, or is appended
+    /// when the original had none and the table isn't empty. Any other nested attribute is kept
+    /// as is, so a `StackMapTable` has to be recomputed separately. `max_stack` and `max_locals`
+    /// are copied from `original` as well; `limits::CodeLimits::compute` gives the ones the new
+    /// code needs.
+    pub fn assemble_into(&self, original: &CodeAttribute) -> ParserResult<CodeAttribute> {
+        let assembled = try!(self.assemble());
+
+        let mut line_number_table = Some(assembled.line_numbers);
+        let mut local_variable_table = Some(assembled.local_variables);
+        let mut local_variable_type_table = Some(assembled.local_variable_types);
+
+        let mut attributes = vec![];
+        for attribute in &original.attributes {
+            match *attribute {
+                Attribute::LineNumberTable(..) => {
+                    if let Some(table) = line_number_table.take() {
+                        attributes.push(line_number_table_attribute(table));
+                    }
+                }
+                Attribute::LocalVariableTable(..) => {
+                    if let Some(table) = local_variable_table.take() {
+                        attributes.push(Attribute::LocalVariableTable(
+                            local_variable_table_attribute(table)));
+                    }
+                }
+                Attribute::LocalVariableTypeTable(..) => {
+                    if let Some(table) = local_variable_type_table.take() {
+                        attributes.push(Attribute::LocalVariableTypeTable(
+                            local_variable_table_attribute(table)));
+                    }
+                }
+                ref attribute @ _ => attributes.push(attribute.clone()),
+            }
+        }
+
+        if let Some(table) = line_number_table.and_then(non_empty) {
+            attributes.push(line_number_table_attribute(table));
+        }
+        if let Some(table) = local_variable_table.and_then(non_empty) {
+            attributes.push(Attribute::LocalVariableTable(local_variable_table_attribute(table)));
+        }
+        if let Some(table) = local_variable_type_table.and_then(non_empty) {
+            attributes.push(Attribute::LocalVariableTypeTable(
+                local_variable_table_attribute(table)));
+        }
+
+        Ok(CodeAttribute {
+            max_stack: original.max_stack,
+            max_locals: original.max_locals,
+            code_length: assembled.code.len() as U4,
+            code: assembled.code,
+            exception_table_length: assembled.exception_table.len() as U2,
+            exception_table: assembled.exception_table,
+            attributes_count: attributes.len() as U2,
+            attributes: attributes,
+        })
+    }
+
+    fn layout(&self, forms: &[BranchForm]) -> ParserResult<(Vec<usize>, HashMap<Label, usize>)> {
+        let mut item_pcs = vec![];
+        let mut label_offsets = HashMap::new();
+        let mut scratch = vec![];
+
+        let mut pc = 0;
+        for (i, item) in self.items.iter().enumerate() {
+            item_pcs.push(pc);
+
+            match *item {
+                CodeItem::Label(label) => {
+                    label_offsets.insert(label, pc);
+                }
+                CodeItem::Instruction(ref instruction) => {
+                    // the switch padding depends on the pc, so size the instruction in place
+                    scratch.clear();
+                    scratch.resize(pc % 4, 0);
+                    let placeholder = instruction.clone().map_targets(|_| 0);
+                    try!(encode_with_form(&placeholder, forms[i], &mut scratch));
+                    pc += scratch.len() - pc % 4;
+                }
+            }
+        }
+
+        if pc > U2::max_value() as usize {
+            return Err(ParserError::CodeTooLarge(pc));
+        }
+
+        Ok((item_pcs, label_offsets))
+    }
+}
+
+fn has_short_branch<B>(instruction: &Instruction<B>) -> bool {
+    match *instruction {
+        Instruction::Tableswitch { .. } |
+        Instruction::Lookupswitch { .. } |
+        Instruction::GotoW(..) |
+        Instruction::JsrW(..) => false,
+        _ => !instruction.branch_targets().is_empty(),
+    }
+}
+
+fn resolve_label(label: Label, label_offsets: &HashMap<Label, usize>) -> ParserResult<usize> {
+    label_offsets.get(&label).cloned().ok_or(ParserError::UnplacedLabel(label.0))
+}
+
+fn relativize(instruction: Instruction<Label>,
+              pc: usize,
+              label_offsets: &HashMap<Label, usize>)
+              -> ParserResult<Instruction> {
+    let mut error = None;
+    let instruction = instruction.map_targets(|label| match resolve_label(label, label_offsets) {
+        Ok(target) => (target as i64 - pc as i64) as i32,
+        Err(e) => {
+            error = Some(e);
+            0
+        }
+    });
+
+    match error {
+        Some(error) => Err(error),
+        None => Ok(instruction),
+    }
+}
+
+fn encode_with_form(instruction: &Instruction,
+                    form: BranchForm,
+                    code: &mut Vec<U1>)
+                    -> ParserResult<()> {
+    if form == BranchForm::Short {
+        return instruction.encode(code);
+    }
+
+    match *instruction {
+        Instruction::Goto(offset) => Instruction::GotoW(offset).encode(code),
+        Instruction::Jsr(offset) => Instruction::JsrW(offset).encode(code),
+        ref conditional @ _ => {
+            // skip over the goto_w when the inverted condition holds, the goto_w itself starts
+            // three bytes after the original pc
+            let offset = *conditional.branch_targets()[0];
+            try!(invert_condition(conditional, 8).encode(code));
+            Instruction::GotoW(offset - 3).encode(code)
+        }
+    }
+}
+
+fn invert_condition(instruction: &Instruction, offset: i32) -> Instruction {
+    match *instruction {
+        Instruction::Ifeq(..) => Instruction::Ifne(offset),
+        Instruction::Ifne(..) => Instruction::Ifeq(offset),
+        Instruction::Iflt(..) => Instruction::Ifge(offset),
+        Instruction::Ifge(..) => Instruction::Iflt(offset),
+        Instruction::Ifgt(..) => Instruction::Ifle(offset),
+        Instruction::Ifle(..) => Instruction::Ifgt(offset),
+        Instruction::IfIcmpeq(..) => Instruction::IfIcmpne(offset),
+        Instruction::IfIcmpne(..) => Instruction::IfIcmpeq(offset),
+        Instruction::IfIcmplt(..) => Instruction::IfIcmpge(offset),
+        Instruction::IfIcmpge(..) => Instruction::IfIcmplt(offset),
+        Instruction::IfIcmpgt(..) => Instruction::IfIcmple(offset),
+        Instruction::IfIcmple(..) => Instruction::IfIcmpgt(offset),
+        Instruction::IfAcmpeq(..) => Instruction::IfAcmpne(offset),
+        Instruction::IfAcmpne(..) => Instruction::IfAcmpeq(offset),
+        Instruction::Ifnull(..) => Instruction::Ifnonnull(offset),
+        Instruction::Ifnonnull(..) => Instruction::Ifnull(offset),
+        _ => unreachable!("only conditional branches are inverted"),
+    }
+}
+
+fn non_empty<T>(table: Vec<T>) -> Option<Vec<T>> {
+    if table.is_empty() { None } else { Some(table) }
+}
+
+fn line_number_table_attribute(table: Vec<LineNumber>) -> Attribute {
+    Attribute::LineNumberTable(Rc::new(LineNumberTableAttribute {
+        line_number_table_length: table.len() as U2,
+        line_number_table: table,
+    }))
+}
+
+fn local_variable_table_attribute(table: Vec<LocalVariable>) -> Rc<LocalVariableTableAttribute> {
+    Rc::new(LocalVariableTableAttribute {
+        local_variable_table_length: table.len() as U2,
+        local_variable_table: table,
+    })
+}
+
+fn resolve_local_variables(local_variables: &[LabelledLocalVariable],
+                           label_offsets: &HashMap<Label, usize>)
+                           -> ParserResult<Vec<LocalVariable>> {
+    let mut resolved = vec![];
+    for local_variable in local_variables {
+        let start_pc = try!(resolve_label(local_variable.start, label_offsets));
+        let end_pc = try!(resolve_label(local_variable.end, label_offsets));
+
+        if start_pc <= end_pc {
+            resolved.push(LocalVariable {
+                start_pc: start_pc as U2,
+                length: (end_pc - start_pc) as U2,
+                name_index: local_variable.name_index,
+                descriptor_index: local_variable.descriptor_index,
+                index: local_variable.index,
+            });
+        }
+    }
+
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{CodeAssembler, CodeItem};
+    use super::super::components::{Attribute, CodeAttribute, ExceptionHandler, LineNumber};
+    use super::super::instructions::Instruction;
+
+    #[test]
+    fn can_assemble_forward_and_backward_branches() {
+        let mut assembler = CodeAssembler::new();
+        let top = assembler.new_label();
+        let end = assembler.new_label();
+
+        assembler.place_label(top);
+        assembler.push(Instruction::Iload0);
+        assembler.push(Instruction::Ifeq(end));
+        assembler.push(Instruction::Goto(top));
+        assembler.place_label(end);
+        assembler.push(Instruction::Return);
+
+        let assembled = assembler.assemble().unwrap();
+
+        assert_that(&assembled.code)
+            .is_equal_to(&vec![0x1a, 0x99, 0x00, 0x06, 0xa7, 0xff, 0xfc, 0xb1]);
+        assert_that(&assembled.label_offset(end)).is_equal_to(&Some(7));
+    }
+
+    #[test]
+    fn can_promote_out_of_range_branches() {
+        let mut assembler = CodeAssembler::new();
+        let end = assembler.new_label();
+
+        assembler.push(Instruction::Goto(end));
+        assembler.push(Instruction::Iload0);
+        assembler.push(Instruction::Ifne(end));
+        for _ in 0..40000 {
+            assembler.push(Instruction::Nop);
+        }
+        assembler.place_label(end);
+        assembler.push(Instruction::Return);
+
+        let assembled = assembler.assemble().unwrap();
+        let instructions: Vec<_> = CodeAttribute {
+                max_stack: 0,
+                max_locals: 0,
+                code_length: assembled.code.len() as u32,
+                code: assembled.code.clone(),
+                exception_table_length: 0,
+                exception_table: vec![],
+                attributes_count: 0,
+                attributes: vec![],
+            }
+            .instructions()
+            .take(4)
+            .map(|val| val.unwrap())
+            .collect();
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::GotoW(40014)),
+                                                      (5, Instruction::Iload0),
+                                                      (6, Instruction::Ifeq(8)),
+                                                      (9, Instruction::GotoW(40005))]);
+    }
+
+    #[test]
+    fn can_remap_exception_table_after_rewrite() {
+        let original = CodeAttribute {
+            max_stack: 1,
+            max_locals: 1,
+            code_length: 4,
+            code: vec![0x00, 0xb1, 0x4b, 0xb1],
+            exception_table_length: 1,
+            exception_table: vec![ExceptionHandler {
+                                      start_pc: 0,
+                                      end_pc: 2,
+                                      handler_pc: 2,
+                                      catch_type: 0,
+                                  }],
+            attributes_count: 0,
+            attributes: vec![],
+        };
+
+        let mut assembler = CodeAssembler::from_code(&original).unwrap();
+        assembler.items.insert(0, CodeItem::Instruction(Instruction::Nop));
+        let rewritten = assembler.assemble_into(&original).unwrap();
+
+        assert_that(&rewritten.code).is_equal_to(&vec![0x00, 0x00, 0xb1, 0x4b, 0xb1]);
+        assert_that(&rewritten.exception_table).is_equal_to(&vec![ExceptionHandler {
+                                                                      start_pc: 1,
+                                                                      end_pc: 3,
+                                                                      handler_pc: 3,
+                                                                      catch_type: 0,
+                                                                  }]);
+    }
+
+    #[test]
+    fn appends_debug_tables_the_original_did_not_have() {
+        let original = CodeAttribute {
+            max_stack: 0,
+            max_locals: 0,
+            code_length: 2,
+            code: vec![0x00, 0xb1],
+            exception_table_length: 0,
+            exception_table: vec![],
+            attributes_count: 0,
+            attributes: vec![],
+        };
+
+        let mut assembler = CodeAssembler::from_code(&original).unwrap();
+        let start = assembler.new_label();
+        assembler.items.insert(0, CodeItem::Label(start));
+        assembler.line_number(start, 7);
+        let rewritten = assembler.assemble_into(&original).unwrap();
+
+        assert_that(&rewritten.attributes_count).is_equal_to(&1);
+        assert_that(&rewritten.attributes[0]).matches(|attribute| match *attribute {
+            Attribute::LineNumberTable(ref table) => {
+                table.line_number_table ==
+                vec![LineNumber {
+                         start_pc: 0,
+                         line_number: 7,
+                     }]
+            }
+            _ => false,
+        });
+    }
+
+}
@@ -0,0 +1,2212 @@
+//! A line based textual assembly format for class files.
+//!
+//! `print` renders a `ClassFile` as text and `assemble` turns such text back into class file
+//! bytes. A file looks like this:
+//!
+//! ```text
+//! .version 52 0
+//! .class public super HelloWorld
+//! .super java/lang/Object
+//!
+//! .method public static main ([Ljava/lang/String;)V
+//!     .code stack 2 locals 1
+//!         getstatic Field java/lang/System out Ljava/io/PrintStream;
+//!         ldc String "hello world"
+//!         invokevirtual Method java/io/PrintStream println (Ljava/lang/String;)V
+//!         return
+//!     .end code
+//! .end method
+//!
+//! .sourcefile HelloWorld.java
+//! ```
+//!
+//! Constant pool entries are written symbolically, either as a bare name where the kind of
+//! entry is implied (class names, member names and descriptors), or as a tagged constant such
+//! as `Integer 5`, `String "text"` or `Method owner name descriptor`. Any reference can instead
+//! be written as a raw index like `#12`. Branch targets and code offsets are labels, and a
+//! `;` at the start of a token begins a comment.
+//!
+//! Symbolic references are interned into the constant pool in the order they appear. When the
+//! constant pool is preserved, it is printed as an explicit `.constant_pool` block and the
+//! references resolve to its entries, so assembling the printed text reproduces the original
+//! bytes. Attributes the format has no syntax for are kept as raw bytes with `.attribute`. Any
+//! constant pool index inside them is only meaningful when the constant pool is preserved.
+
+use super::{ClassFile, ParserError, ParserResult};
+use super::assembler::{CodeAssembler, Label};
+use super::components::{Attribute, CodeAttribute, ConstantPoolItem, StackMapFrame,
+                        VerificationTypeInfo};
+use super::instructions::{ArrayType, Instruction};
+use super::mutf8;
+use super::pool::{Constant, ConstantPool};
+use super::primitives::{ByteCursor, PrimitiveIterator, PrimitiveWriter, U1, U2, U4};
+
+use std::collections::{BTreeSet, HashMap};
+use std::str::FromStr;
+
+const INDENT: &'static str = "    ";
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const CLASS_FLAGS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0010, "final"),
+    (0x0020, "super"),
+    (0x0200, "interface"),
+    (0x0400, "abstract"),
+    (0x1000, "synthetic"),
+    (0x2000, "annotation"),
+    (0x4000, "enum"),
+    (0x8000, "module"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const FIELD_FLAGS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0040, "volatile"),
+    (0x0080, "transient"),
+    (0x1000, "synthetic"),
+    (0x4000, "enum"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const METHOD_FLAGS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0020, "synchronized"),
+    (0x0040, "bridge"),
+    (0x0080, "varargs"),
+    (0x0100, "native"),
+    (0x0400, "abstract"),
+    (0x0800, "strict"),
+    (0x1000, "synthetic"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const INNER_CLASS_FLAGS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0200, "interface"),
+    (0x0400, "abstract"),
+    (0x1000, "synthetic"),
+    (0x2000, "annotation"),
+    (0x4000, "enum"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const REFERENCE_KINDS: &'static [(U1, &'static str)] = &[
+    (1, "getField"),
+    (2, "getStatic"),
+    (3, "putField"),
+    (4, "putStatic"),
+    (5, "invokeVirtual"),
+    (6, "invokeStatic"),
+    (7, "invokeSpecial"),
+    (8, "newInvokeSpecial"),
+    (9, "invokeInterface"),
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct PrintOptions {
+    /// Prints the constant pool as is, so that assembling the output keeps every entry at its
+    /// original index instead of numbering entries in order of first use.
+    pub preserve_constant_pool: bool,
+}
+
+/// Renders `classfile` in the textual assembly format.
+pub fn print(classfile: &ClassFile, options: &PrintOptions) -> ParserResult<String> {
+    let mut printer = Printer {
+        classfile: classfile,
+        preserve_constant_pool: options.preserve_constant_pool,
+        canonical: canonical_constants(&classfile.constant_pool),
+        output: String::new(),
+    };
+
+    try!(printer.print_class());
+
+    Ok(printer.output)
+}
+
+/// Assembles text in the textual assembly format into class file bytes.
+pub fn assemble(source: &str) -> ParserResult<Vec<U1>> {
+    let lines = try!(tokenize(source));
+    let mut assembler = TextAssembler {
+        lines: &lines,
+        position: 0,
+        constant_pool: ConstantPool::new(),
+    };
+
+    assembler.assemble_class()
+}
+
+/// Works out which constant pool entries a symbolic reference resolves back to. That is the
+/// first entry with its value, provided the entries it refers to are themselves canonical.
+fn canonical_constants(constant_pool: &Vec<ConstantPoolItem>) -> Vec<bool> {
+    let mut constants = vec![None];
+    constants.extend(constant_pool.iter().map(Constant::from_item));
+
+    let mut first_indexes = HashMap::new();
+    for (index, constant) in constants.iter().enumerate() {
+        if let Some(ref constant) = *constant {
+            first_indexes.entry(constant.clone()).or_insert(index);
+        }
+    }
+
+    let mut canonical = vec![None; constants.len()];
+    for index in 0..constants.len() {
+        is_canonical(index, &constants, &first_indexes, &mut canonical);
+    }
+
+    canonical.into_iter().map(|canonical| canonical.unwrap_or(false)).collect()
+}
+
+fn is_canonical(index: usize,
+                constants: &Vec<Option<Constant>>,
+                first_indexes: &HashMap<Constant, usize>,
+                canonical: &mut Vec<Option<bool>>)
+                -> bool {
+    if index >= constants.len() {
+        return false;
+    }
+    if let Some(result) = canonical[index] {
+        return result;
+    }
+
+    // guards against reference cycles in malformed constant pools
+    canonical[index] = Some(false);
+
+    let result = match constants[index] {
+        Some(ref constant) => {
+            first_indexes.get(constant) == Some(&index) &&
+            constant.references()
+                .iter()
+                .all(|reference| is_canonical(*reference as usize, constants, first_indexes, canonical))
+        }
+        None => false,
+    };
+
+    canonical[index] = Some(result);
+    result
+}
+
+struct Printer<'c> {
+    classfile: &'c ClassFile,
+    preserve_constant_pool: bool,
+    canonical: Vec<bool>,
+    output: String,
+}
+
+impl<'c> Printer<'c> {
+    fn print_class(&mut self) -> ParserResult<()> {
+        let classfile = self.classfile;
+
+        self.line(0,
+                  &format!(".version {} {}", classfile.major_version, classfile.minor_version));
+
+        if self.preserve_constant_pool {
+            try!(self.print_constant_pool());
+        }
+
+        let mut class_line = String::from(".class");
+        for flag in flag_tokens(classfile.access_flags, CLASS_FLAGS) {
+            class_line.push(' ');
+            class_line.push_str(&flag);
+        }
+        class_line.push(' ');
+        class_line.push_str(&try!(self.class(classfile.this_class)));
+        self.line(0, &class_line);
+
+        if classfile.super_class != 0 {
+            let super_class = try!(self.class(classfile.super_class));
+            self.line(0, &format!(".super {}", super_class));
+        }
+        for interface in &classfile.interfaces {
+            let interface = try!(self.class(*interface));
+            self.line(0, &format!(".implements {}", interface));
+        }
+
+        for field in &classfile.fields {
+            self.line(0, "");
+            try!(self.print_member(".field",
+                                   "field",
+                                   field.access_flags,
+                                   FIELD_FLAGS,
+                                   &field.name,
+                                   &field.descriptor,
+                                   &field.attributes));
+        }
+        for method in &classfile.methods {
+            self.line(0, "");
+            try!(self.print_member(".method",
+                                   "method",
+                                   method.access_flags,
+                                   METHOD_FLAGS,
+                                   &method.name,
+                                   &method.descriptor,
+                                   &method.attributes));
+        }
+
+        if !classfile.attributes.is_empty() {
+            self.line(0, "");
+        }
+        for attribute in &classfile.attributes {
+            try!(self.print_attribute(0, attribute));
+        }
+
+        Ok(())
+    }
+
+    fn print_constant_pool(&mut self) -> ParserResult<()> {
+        self.line(0, ".constant_pool");
+
+        for (i, item) in self.classfile.constant_pool.iter().enumerate() {
+            let index = i + 1;
+            let entry = match Constant::from_item(item) {
+                Some(Constant::Utf8(ref value)) => format!("Utf8 {}", quote(value)),
+                Some(Constant::Integer(bytes)) => format!("Integer {}", bytes as i32),
+                Some(Constant::Float(bytes)) => format!("Float {}", float_literal(bytes)),
+                Some(Constant::Long(bytes)) => format!("Long {}", bytes as i64),
+                Some(Constant::Double(bytes)) => format!("Double {}", double_literal(bytes)),
+                Some(Constant::Class(index)) => format!("Class #{}", index),
+                Some(Constant::String(index)) => format!("String #{}", index),
+                Some(Constant::Field(class_index, name_and_type_index)) => {
+                    format!("Field #{} #{}", class_index, name_and_type_index)
+                }
+                Some(Constant::Method(class_index, name_and_type_index)) => {
+                    format!("Method #{} #{}", class_index, name_and_type_index)
+                }
+                Some(Constant::InterfaceMethod(class_index, name_and_type_index)) => {
+                    format!("InterfaceMethod #{} #{}", class_index, name_and_type_index)
+                }
+                Some(Constant::NameAndType(name_index, descriptor_index)) => {
+                    format!("NameAndType #{} #{}", name_index, descriptor_index)
+                }
+                Some(Constant::MethodHandle(reference_kind, reference_index)) => {
+                    format!("MethodHandle {} #{}",
+                            reference_kind_name(reference_kind),
+                            reference_index)
+                }
+                Some(Constant::MethodType(descriptor_index)) => {
+                    format!("MethodType #{}", descriptor_index)
+                }
+                Some(Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index)) => {
+                    format!("InvokeDynamic {} #{}",
+                            bootstrap_method_attr_index,
+                            name_and_type_index)
+                }
+                None => continue,
+            };
+
+            let entry = match *item {
+                ConstantPoolItem::Utf8(..) |
+                ConstantPoolItem::Integer(..) |
+                ConstantPoolItem::Float(..) |
+                ConstantPoolItem::Long(..) |
+                ConstantPoolItem::Double(..) => entry,
+                _ => {
+                    match self.render_constant(index as U2) {
+                        Ok(value) => format!("{} ; {}", entry, value),
+                        Err(..) => entry,
+                    }
+                }
+            };
+            self.line(1, &format!("#{} = {}", index, entry));
+        }
+
+        self.line(0, ".end constant_pool");
+
+        Ok(())
+    }
+
+    fn print_member(&mut self,
+                    directive: &str,
+                    kind: &str,
+                    access_flags: U2,
+                    flag_names: &[(U2, &'static str)],
+                    name: &str,
+                    descriptor: &str,
+                    attributes: &Vec<::std::rc::Rc<Attribute>>)
+                    -> ParserResult<()> {
+        let mut member_line = String::from(directive);
+        for flag in flag_tokens(access_flags, flag_names) {
+            member_line.push(' ');
+            member_line.push_str(&flag);
+        }
+        member_line.push(' ');
+        member_line.push_str(&token(name));
+        member_line.push(' ');
+        member_line.push_str(&token(descriptor));
+        self.line(0, &member_line);
+
+        for attribute in attributes {
+            try!(self.print_attribute(1, attribute));
+        }
+
+        self.line(0, &format!(".end {}", kind));
+
+        Ok(())
+    }
+
+    fn print_attribute(&mut self, indent: usize, attribute: &Attribute) -> ParserResult<()> {
+        match *attribute {
+            Attribute::Code(ref code) => self.print_code(indent, code),
+            Attribute::Unknown { ref attribute_name, ref info } => {
+                let lines = match self.known_attribute_lines(attribute_name, info) {
+                    Ok(Some(lines)) => lines,
+                    _ => vec![format!(".attribute {} {}", token(attribute_name), hex(info))],
+                };
+
+                for line in lines {
+                    self.line(indent, &line);
+                }
+
+                Ok(())
+            }
+            Attribute::Lazy(ref lazy) => self.print_attribute(indent, &*try!(lazy.decode())),
+            _ => {
+                // the debug and verification tables only have a syntax inside a code block, and
+                // none of them nests attributes
+                let mut info = vec![];
+                try!(attribute.write_info(&mut info, &mut |_| Ok(0)));
+                self.line(indent, &format!(".attribute {} {}", attribute.name(), hex(&info)));
+
+                Ok(())
+            }
+        }
+    }
+
+    /// The lines for an attribute the format has a dedicated syntax for, or `None` when the
+    /// attribute has to be printed as raw bytes.
+    fn known_attribute_lines(&self, name: &str, info: &[U1]) -> ParserResult<Option<Vec<String>>> {
+        let mut bytes = ByteCursor::new(info);
+        let mut lines = vec![];
+
+        match name {
+            "SourceFile" => {
+                let source_file = try!(self.utf8(try!(bytes.next_u2())));
+                lines.push(format!(".sourcefile {}", source_file));
+            }
+            "Signature" => {
+                let signature = try!(self.utf8(try!(bytes.next_u2())));
+                lines.push(format!(".signature {}", signature));
+            }
+            "ConstantValue" => {
+                let value = try!(self.constant(try!(bytes.next_u2())));
+                lines.push(format!(".constantvalue {}", value));
+            }
+            "Deprecated" => lines.push(".deprecated".to_string()),
+            "Synthetic" => lines.push(".synthetic".to_string()),
+            "Exceptions" | "NestMembers" => {
+                let mut line = if name == "Exceptions" {
+                    String::from(".exceptions")
+                } else {
+                    String::from(".nestmembers")
+                };
+
+                let count = try!(bytes.next_u2());
+                for _ in 0..count {
+                    line.push(' ');
+                    line.push_str(&try!(self.class(try!(bytes.next_u2()))));
+                }
+                lines.push(line);
+            }
+            "NestHost" => {
+                let host = try!(self.class(try!(bytes.next_u2())));
+                lines.push(format!(".nesthost {}", host));
+            }
+            "EnclosingMethod" => {
+                let class = try!(self.class(try!(bytes.next_u2())));
+                let method_index = try!(bytes.next_u2());
+
+                if method_index == 0 {
+                    lines.push(format!(".enclosingmethod {}", class));
+                } else {
+                    let method = try!(self.name_and_type(method_index));
+                    lines.push(format!(".enclosingmethod {} {}", class, method));
+                }
+            }
+            "InnerClasses" => {
+                lines.push(".innerclasses".to_string());
+
+                let count = try!(bytes.next_u2());
+                for _ in 0..count {
+                    let inner_class = try!(self.class(try!(bytes.next_u2())));
+                    let outer_class = try!(self.class(try!(bytes.next_u2())));
+                    let inner_name = try!(self.utf8(try!(bytes.next_u2())));
+
+                    let mut line = format!("{}{} {} {}",
+                                           INDENT,
+                                           inner_class,
+                                           outer_class,
+                                           inner_name);
+                    for flag in flag_tokens(try!(bytes.next_u2()), INNER_CLASS_FLAGS) {
+                        line.push(' ');
+                        line.push_str(&flag);
+                    }
+                    lines.push(line);
+                }
+
+                lines.push(".end innerclasses".to_string());
+            }
+            "BootstrapMethods" => {
+                lines.push(".bootstrapmethods".to_string());
+
+                let count = try!(bytes.next_u2());
+                for _ in 0..count {
+                    let mut line = format!("{}{}", INDENT, try!(self.constant(try!(bytes.next_u2()))));
+
+                    let argument_count = try!(bytes.next_u2());
+                    for _ in 0..argument_count {
+                        line.push(' ');
+                        line.push_str(&try!(self.constant(try!(bytes.next_u2()))));
+                    }
+                    lines.push(line);
+                }
+
+                lines.push(".end bootstrapmethods".to_string());
+            }
+            _ => return Ok(None),
+        }
+
+        if bytes.remaining() > 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(lines))
+    }
+
+    fn print_code(&mut self, indent: usize, code: &CodeAttribute) -> ParserResult<()> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+
+        let labels = try!(self.code_labels(code, &instructions));
+
+        self.line(indent,
+                  &format!(".code stack {} locals {}", code.max_stack, code.max_locals));
+
+        for &(pc, ref instruction) in &instructions {
+            if labels.contains(&(pc as usize)) {
+                self.line(indent, &format!("{}:", label_name(pc as usize)));
+            }
+
+            let text = try!(self.instruction(pc as usize, instruction));
+            self.line(indent + 1, &text);
+        }
+        if labels.contains(&code.code.len()) {
+            self.line(indent, &format!("{}:", label_name(code.code.len())));
+        }
+
+        for handler in &code.exception_table {
+            let catch_type = if handler.catch_type == 0 {
+                "any".to_string()
+            } else {
+                let catch_type = try!(self.class(handler.catch_type));
+                if catch_type == "any" { quote(&catch_type) } else { catch_type }
+            };
+
+            self.line(indent + 1,
+                      &format!(".catch {} {} {} {}",
+                               catch_type,
+                               label_name(handler.start_pc as usize),
+                               label_name(handler.end_pc as usize),
+                               label_name(handler.handler_pc as usize)));
+        }
+
+        for attribute in &code.attributes {
+            try!(self.print_code_table(indent + 1, attribute));
+        }
+
+        self.line(indent, ".end code");
+
+        Ok(())
+    }
+
+    fn print_code_table(&mut self, indent: usize, attribute: &Attribute) -> ParserResult<()> {
+        match *attribute {
+            Attribute::LineNumberTable(ref table) => {
+                self.line(indent, ".linenumbertable");
+                for line_number in &table.line_number_table {
+                    self.line(indent + 1,
+                              &format!("{} {}",
+                                       label_name(line_number.start_pc as usize),
+                                       line_number.line_number));
+                }
+                self.line(indent, ".end linenumbertable");
+            }
+            Attribute::LocalVariableTable(ref table) |
+            Attribute::LocalVariableTypeTable(ref table) => {
+                let directive = match *attribute {
+                    Attribute::LocalVariableTable(..) => "localvariabletable",
+                    _ => "localvariabletypetable",
+                };
+
+                self.line(indent, &format!(".{}", directive));
+                for local_variable in &table.local_variable_table {
+                    let start_pc = local_variable.start_pc as usize;
+                    let end_pc = start_pc + local_variable.length as usize;
+
+                    let name = try!(self.utf8(local_variable.name_index));
+                    let descriptor = try!(self.utf8(local_variable.descriptor_index));
+                    self.line(indent + 1,
+                              &format!("{} {} {} {} {}",
+                                       local_variable.index,
+                                       name,
+                                       descriptor,
+                                       label_name(start_pc),
+                                       label_name(end_pc)));
+                }
+                self.line(indent, &format!(".end {}", directive));
+            }
+            Attribute::StackMapTable(ref table) => {
+                self.line(indent, ".stackmaptable");
+
+                let mut pc = None;
+                for frame in &table.entries {
+                    let frame_pc = frame_pc(pc, frame);
+                    pc = Some(frame_pc);
+
+                    let text = try!(self.stack_map_frame(frame_pc, frame));
+                    self.line(indent + 1, &text);
+                }
+
+                self.line(indent, ".end stackmaptable");
+            }
+            _ => try!(self.print_attribute(indent, attribute)),
+        }
+
+        Ok(())
+    }
+
+    /// Collects every pc that needs a label, checking that each falls on an instruction.
+    fn code_labels(&self,
+                   code: &CodeAttribute,
+                   instructions: &[(U2, Instruction)])
+                   -> ParserResult<BTreeSet<usize>> {
+        let mut labels = BTreeSet::new();
+
+        for &(pc, ref instruction) in instructions {
+            for offset in instruction.branch_targets() {
+                labels.insert((pc as i64 + *offset as i64) as usize);
+            }
+        }
+
+        for handler in &code.exception_table {
+            labels.insert(handler.start_pc as usize);
+            labels.insert(handler.end_pc as usize);
+            labels.insert(handler.handler_pc as usize);
+        }
+
+        for attribute in &code.attributes {
+            match *attribute {
+                Attribute::LineNumberTable(ref table) => {
+                    for line_number in &table.line_number_table {
+                        labels.insert(line_number.start_pc as usize);
+                    }
+                }
+                Attribute::LocalVariableTable(ref table) |
+                Attribute::LocalVariableTypeTable(ref table) => {
+                    for local_variable in &table.local_variable_table {
+                        let start_pc = local_variable.start_pc as usize;
+                        labels.insert(start_pc);
+                        labels.insert(start_pc + local_variable.length as usize);
+                    }
+                }
+                Attribute::StackMapTable(ref table) => {
+                    let mut pc = None;
+                    for frame in &table.entries {
+                        let frame_pc = frame_pc(pc, frame);
+                        pc = Some(frame_pc);
+                        labels.insert(frame_pc);
+
+                        for verification_type in frame_verification_types(frame) {
+                            if let VerificationTypeInfo::Uninitialized(offset) = *verification_type {
+                                labels.insert(offset as usize);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let boundaries = instructions.iter()
+            .map(|&(pc, _)| pc as usize)
+            .chain(Some(code.code.len()))
+            .collect::<BTreeSet<_>>();
+        for label in &labels {
+            if !boundaries.contains(label) {
+                return Err(ParserError::InvalidCodeOffset(*label));
+            }
+        }
+
+        Ok(labels)
+    }
+
+    fn instruction(&self, pc: usize, instruction: &Instruction) -> ParserResult<String> {
+        let target = |offset: i32| label_name((pc as i64 + offset as i64) as usize);
+
+        let operands = match *instruction {
+            Instruction::Bipush(value) => value.to_string(),
+            Instruction::Sipush(value) => value.to_string(),
+            Instruction::Ldc(index) => try!(self.constant(index as U2)),
+            Instruction::LdcW(index) |
+            Instruction::Ldc2W(index) |
+            Instruction::Getstatic(index) |
+            Instruction::Putstatic(index) |
+            Instruction::Getfield(index) |
+            Instruction::Putfield(index) |
+            Instruction::Invokevirtual(index) |
+            Instruction::Invokespecial(index) |
+            Instruction::Invokestatic(index) |
+            Instruction::Invokedynamic(index) => try!(self.constant(index)),
+            Instruction::Invokeinterface(index, count) => {
+                format!("{} {}", try!(self.constant(index)), count)
+            }
+            Instruction::New(index) |
+            Instruction::Anewarray(index) |
+            Instruction::Checkcast(index) |
+            Instruction::Instanceof(index) => try!(self.class(index)),
+            Instruction::Multianewarray(index, dimensions) => {
+                format!("{} {}", try!(self.class(index)), dimensions)
+            }
+            Instruction::Newarray(atype) => atype.to_friendly_name().to_string(),
+            Instruction::Iload(index) |
+            Instruction::Lload(index) |
+            Instruction::Fload(index) |
+            Instruction::Dload(index) |
+            Instruction::Aload(index) |
+            Instruction::Istore(index) |
+            Instruction::Lstore(index) |
+            Instruction::Fstore(index) |
+            Instruction::Dstore(index) |
+            Instruction::Astore(index) |
+            Instruction::Ret(index) => index.to_string(),
+            Instruction::Iinc(index, constant) => format!("{} {}", index, constant),
+            Instruction::Tableswitch { default, low, ref offsets } => {
+                let targets = offsets.iter().map(|offset| target(*offset)).collect::<Vec<_>>();
+                format!("{} {} default {}", low, targets.join(" "), target(default))
+            }
+            Instruction::Lookupswitch { default, ref pairs } => {
+                let mut operands = String::new();
+                for &(key, offset) in pairs {
+                    operands.push_str(&format!("{} {} ", key, target(offset)));
+                }
+                operands.push_str(&format!("default {}", target(default)));
+                operands
+            }
+            _ => {
+                match instruction.branch_targets().first() {
+                    Some(offset) => target(**offset),
+                    None => return Ok(instruction.mnemonic().to_string()),
+                }
+            }
+        };
+
+        Ok(format!("{} {}", instruction.mnemonic(), operands))
+    }
+
+    fn stack_map_frame(&self, pc: usize, frame: &StackMapFrame) -> ParserResult<String> {
+        let label = label_name(pc);
+
+        let text = match *frame {
+            StackMapFrame::Same { .. } => format!("same {}", label),
+            StackMapFrame::SameExtended { .. } => format!("same_extended {}", label),
+            StackMapFrame::SameLocals1StackItem { ref stack, .. } => {
+                format!("same_locals_1_stack_item {} {}",
+                        label,
+                        try!(self.verification_type(stack)))
+            }
+            StackMapFrame::SameLocals1StackItemExtended { ref stack, .. } => {
+                format!("same_locals_1_stack_item_extended {} {}",
+                        label,
+                        try!(self.verification_type(stack)))
+            }
+            StackMapFrame::Chop { frame_type, .. } => {
+                format!("chop {} {}", label, 251 - frame_type)
+            }
+            StackMapFrame::Append { ref locals, .. } => {
+                format!("append {} {}", label, try!(self.verification_types(locals)))
+            }
+            StackMapFrame::Full { ref locals, ref stack, .. } => {
+                let mut text = format!("full {} locals", label);
+                if !locals.is_empty() {
+                    text.push(' ');
+                    text.push_str(&try!(self.verification_types(locals)));
+                }
+                text.push_str(" stack");
+                if !stack.is_empty() {
+                    text.push(' ');
+                    text.push_str(&try!(self.verification_types(stack)));
+                }
+                text
+            }
+        };
+
+        Ok(text)
+    }
+
+    fn verification_types(&self, types: &[VerificationTypeInfo]) -> ParserResult<String> {
+        let mut names = vec![];
+        for verification_type in types {
+            names.push(try!(self.verification_type(verification_type)));
+        }
+
+        Ok(names.join(" "))
+    }
+
+    fn verification_type(&self, verification_type: &VerificationTypeInfo) -> ParserResult<String> {
+        let name = match *verification_type {
+            VerificationTypeInfo::Top => "Top".to_string(),
+            VerificationTypeInfo::Integer => "Integer".to_string(),
+            VerificationTypeInfo::Float => "Float".to_string(),
+            VerificationTypeInfo::Double => "Double".to_string(),
+            VerificationTypeInfo::Long => "Long".to_string(),
+            VerificationTypeInfo::Null => "Null".to_string(),
+            VerificationTypeInfo::UninitializedThis => "UninitializedThis".to_string(),
+            VerificationTypeInfo::Object(index) => format!("Object {}", try!(self.class(index))),
+            VerificationTypeInfo::Uninitialized(offset) => {
+                format!("Uninitialized {}", label_name(offset as usize))
+            }
+        };
+
+        Ok(name)
+    }
+
+    /// A tagged constant, or its raw index when a symbolic reference would resolve elsewhere.
+    fn constant(&self, index: U2) -> ParserResult<String> {
+        self.reference(index, |printer| printer.render_constant(index))
+    }
+
+    fn class(&self, index: U2) -> ParserResult<String> {
+        self.reference(index, |printer| {
+            let resolver = printer.classfile.constant_pool_resolver();
+            Ok(token(&try!(resolver.resolve_class_name(index))))
+        })
+    }
+
+    fn utf8(&self, index: U2) -> ParserResult<String> {
+        self.reference(index, |printer| {
+            let constant_pool = &printer.classfile.constant_pool;
+            Ok(token(&try!(ConstantPoolItem::retrieve_utf8_info(index, constant_pool))))
+        })
+    }
+
+    fn name_and_type(&self, index: U2) -> ParserResult<String> {
+        self.reference(index, |printer| printer.render_name_and_type(index))
+    }
+
+    fn reference<F>(&self, index: U2, render: F) -> ParserResult<String>
+        where F: Fn(&Printer) -> ParserResult<String>
+    {
+        if index == 0 {
+            return Ok("#0".to_string());
+        }
+
+        if self.preserve_constant_pool {
+            if !self.canonical.get(index as usize).cloned().unwrap_or(false) {
+                return Ok(format!("#{}", index));
+            }
+
+            return Ok(render(self).unwrap_or_else(|_| format!("#{}", index)));
+        }
+
+        render(self)
+    }
+
+    fn render_constant(&self, index: U2) -> ParserResult<String> {
+        let constant_pool = &self.classfile.constant_pool;
+        let resolver = self.classfile.constant_pool_resolver();
+
+        let item = try!(ConstantPoolItem::retrieve_item(index as usize, constant_pool));
+        let text = match Constant::from_item(item) {
+            Some(Constant::Utf8(ref value)) => format!("Utf8 {}", quote(value)),
+            Some(Constant::Integer(bytes)) => format!("Integer {}", bytes as i32),
+            Some(Constant::Float(bytes)) => format!("Float {}", float_literal(bytes)),
+            Some(Constant::Long(bytes)) => format!("Long {}", bytes as i64),
+            Some(Constant::Double(bytes)) => format!("Double {}", double_literal(bytes)),
+            Some(Constant::Class(..)) => {
+                format!("Class {}", token(&try!(resolver.resolve_class_name(index))))
+            }
+            Some(Constant::String(string_index)) => {
+                let value = try!(ConstantPoolItem::retrieve_utf8_info(string_index, constant_pool));
+                format!("String {}", quote(&value))
+            }
+            Some(Constant::Field(class_index, name_and_type_index)) |
+            Some(Constant::Method(class_index, name_and_type_index)) |
+            Some(Constant::InterfaceMethod(class_index, name_and_type_index)) => {
+                format!("{} {} {}",
+                        item.to_friendly_name(),
+                        token(&try!(resolver.resolve_class_name(class_index))),
+                        try!(self.render_name_and_type(name_and_type_index)))
+            }
+            Some(Constant::NameAndType(..)) => {
+                format!("NameAndType {}", try!(self.render_name_and_type(index)))
+            }
+            Some(Constant::MethodHandle(reference_kind, reference_index)) => {
+                format!("MethodHandle {} {}",
+                        reference_kind_name(reference_kind),
+                        try!(self.render_constant(reference_index)))
+            }
+            Some(Constant::MethodType(descriptor_index)) => {
+                let descriptor = try!(ConstantPoolItem::retrieve_utf8_info(descriptor_index,
+                                                                           constant_pool));
+                format!("MethodType {}", token(&descriptor))
+            }
+            Some(Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index)) => {
+                format!("InvokeDynamic {} {}",
+                        bootstrap_method_attr_index,
+                        try!(self.render_name_and_type(name_and_type_index)))
+            }
+            None => return Err(ParserError::UnexpectedConstantPoolItem(item.to_friendly_name())),
+        };
+
+        Ok(text)
+    }
+
+    fn render_name_and_type(&self, index: U2) -> ParserResult<String> {
+        let resolver = self.classfile.constant_pool_resolver();
+        let (name, descriptor) = try!(resolver.resolve_name_and_type(index));
+
+        Ok(format!("{} {}", token(&name), token(&descriptor)))
+    }
+
+    fn line(&mut self, indent: usize, text: &str) {
+        if !text.is_empty() {
+            for _ in 0..indent {
+                self.output.push_str(INDENT);
+            }
+            self.output.push_str(text);
+        }
+        self.output.push('\n');
+    }
+}
+
+fn frame_pc(previous_pc: Option<usize>, frame: &StackMapFrame) -> usize {
+    match previous_pc {
+        Some(pc) => pc + frame.offset_delta() as usize + 1,
+        None => frame.offset_delta() as usize,
+    }
+}
+
+fn frame_verification_types(frame: &StackMapFrame) -> Vec<&VerificationTypeInfo> {
+    match *frame {
+        StackMapFrame::SameLocals1StackItem { ref stack, .. } |
+        StackMapFrame::SameLocals1StackItemExtended { ref stack, .. } => vec![stack],
+        StackMapFrame::Append { ref locals, .. } => locals.iter().collect(),
+        StackMapFrame::Full { ref locals, ref stack, .. } => {
+            locals.iter().chain(stack.iter()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+fn label_name(pc: usize) -> String {
+    format!("L{}", pc)
+}
+
+fn flag_tokens(access_flags: U2, names: &[(U2, &'static str)]) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut remaining = access_flags;
+
+    for &(flag, name) in names {
+        if (access_flags & flag) != 0 {
+            tokens.push(name.to_string());
+            remaining &= !flag;
+        }
+    }
+    if remaining != 0 {
+        tokens.push(format!("0x{:04x}", remaining));
+    }
+
+    tokens
+}
+
+fn reference_kind_name(reference_kind: U1) -> String {
+    REFERENCE_KINDS.iter()
+        .find(|&&(kind, _)| kind == reference_kind)
+        .map(|&(_, name)| name.to_string())
+        .unwrap_or_else(|| reference_kind.to_string())
+}
+
+/// Float constants that do not survive a decimal round trip, namely NaNs, are written as their
+/// raw bits.
+fn float_literal(bits: U4) -> String {
+    let value = f32::from_bits(bits);
+    if value.is_nan() {
+        format!("0x{:08x}", bits)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn double_literal(bits: u64) -> String {
+    let value = f64::from_bits(bits);
+    if value.is_nan() {
+        format!("0x{:016x}", bits)
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn hex(bytes: &[U1]) -> String {
+    if bytes.is_empty() {
+        return "\"\"".to_string();
+    }
+
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Writes `text` as a bare token when it would read back unchanged, and quoted otherwise.
+fn token(text: &str) -> String {
+    let needs_quotes = text.is_empty() || text.starts_with('#') || text.starts_with(';') ||
+                       text.ends_with(':') ||
+                       text.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"');
+
+    if needs_quotes {
+        quote(text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn quote(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
+
+#[derive(Debug)]
+struct Token {
+    text: String,
+    quoted: bool,
+}
+
+impl Token {
+    fn is(&self, keyword: &str) -> bool {
+        !self.quoted && self.text == keyword
+    }
+
+    /// The index of a raw constant pool reference such as `#12`.
+    fn raw_index(&self) -> Option<U2> {
+        if self.quoted || !self.text.starts_with('#') {
+            return None;
+        }
+
+        self.text[1..].parse().ok()
+    }
+}
+
+#[derive(Debug)]
+struct Line {
+    number: usize,
+    tokens: Vec<Token>,
+}
+
+impl Line {
+    fn directive(&self) -> &str {
+        if self.tokens[0].quoted {
+            ""
+        } else {
+            &self.tokens[0].text
+        }
+    }
+
+    fn error<T>(&self, message: &str) -> ParserResult<T> {
+        Err(ParserError::InvalidAssembly(self.number, message.to_string()))
+    }
+
+    fn cursor<'l>(&'l self) -> TokenCursor<'l> {
+        TokenCursor {
+            line: self,
+            position: 1,
+        }
+    }
+}
+
+/// Reads the operands of a line, after its directive or mnemonic.
+struct TokenCursor<'l> {
+    line: &'l Line,
+    position: usize,
+}
+
+impl<'l> TokenCursor<'l> {
+    fn next(&mut self) -> ParserResult<&'l Token> {
+        match self.line.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token)
+            }
+            None => self.line.error("unexpected end of line"),
+        }
+    }
+
+    fn peek(&self) -> Option<&'l Token> {
+        self.line.tokens.get(self.position)
+    }
+
+    fn remaining(&self) -> usize {
+        self.line.tokens.len() - self.position
+    }
+
+    fn number<T: FromStr>(&mut self) -> ParserResult<T> {
+        let token = try!(self.next());
+        match token.text.parse() {
+            Ok(number) if !token.quoted => Ok(number),
+            _ => self.line.error(&format!("expected a number but found {}", token.text)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> ParserResult<()> {
+        let token = try!(self.next());
+        if token.is(keyword) {
+            Ok(())
+        } else {
+            self.line.error(&format!("expected {} but found {}", keyword, token.text))
+        }
+    }
+
+    fn finish(&self) -> ParserResult<()> {
+        match self.peek() {
+            Some(token) => self.line.error(&format!("unexpected {}", token.text)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> ParserResult<Vec<Line>> {
+    let mut lines = vec![];
+
+    for (i, text) in source.lines().enumerate() {
+        let number = i + 1;
+        let mut tokens = vec![];
+        let mut chars = text.chars().peekable();
+
+        loop {
+            while chars.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                chars.next();
+            }
+
+            match chars.peek().cloned() {
+                None | Some(';') => break,
+                Some('"') => {
+                    chars.next();
+                    tokens.push(Token {
+                        text: try!(read_quoted(&mut chars, number)),
+                        quoted: true,
+                    });
+                }
+                Some(_) => {
+                    let mut text = String::new();
+                    while let Some(c) = chars.peek().cloned() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        text.push(c);
+                        chars.next();
+                    }
+                    tokens.push(Token {
+                        text: text,
+                        quoted: false,
+                    });
+                }
+            }
+        }
+
+        if !tokens.is_empty() {
+            lines.push(Line {
+                number: number,
+                tokens: tokens,
+            });
+        }
+    }
+
+    Ok(lines)
+}
+
+fn read_quoted<I: Iterator<Item = char>>(chars: &mut I, number: usize) -> ParserResult<String> {
+    let invalid = |message: &str| Err(ParserError::InvalidAssembly(number, message.to_string()));
+
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            None => return invalid("unterminated string"),
+            Some('"') => return Ok(text),
+            Some('\\') => {
+                match chars.next() {
+                    Some('"') => text.push('"'),
+                    Some('\\') => text.push('\\'),
+                    Some('n') => text.push('\n'),
+                    Some('r') => text.push('\r'),
+                    Some('t') => text.push('\t'),
+                    Some('u') => {
+                        let digits = chars.by_ref().take(4).collect::<String>();
+                        let c = u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32);
+                        match c {
+                            Some(c) if digits.len() == 4 => text.push(c),
+                            _ => return invalid("invalid unicode escape"),
+                        }
+                    }
+                    _ => return invalid("invalid escape"),
+                }
+            }
+            Some(c) => text.push(c),
+        }
+    }
+}
+
+/// A code table waiting for its labels to be resolved once the code is assembled.
+enum CodeTable {
+    Serialized(Vec<U1>),
+    LineNumbers(U2, Vec<(Label, U2)>),
+    LocalVariables(U2, Vec<(U2, U2, U2, Label, Label)>),
+    StackMap(U2, Vec<PendingFrame>),
+}
+
+struct PendingFrame {
+    kind: String,
+    label: Label,
+    chopped: U1,
+    locals: Vec<PendingVerificationType>,
+    stack: Vec<PendingVerificationType>,
+    line: usize,
+}
+
+enum PendingVerificationType {
+    Resolved(VerificationTypeInfo),
+    Uninitialized(Label),
+}
+
+struct TextAssembler<'l> {
+    lines: &'l [Line],
+    position: usize,
+    constant_pool: ConstantPool,
+}
+
+impl<'l> TextAssembler<'l> {
+    fn assemble_class(&mut self) -> ParserResult<Vec<U1>> {
+        let mut major_version = 52;
+        let mut minor_version = 0;
+        let mut class_line = None;
+        let mut super_class = 0;
+        let mut interfaces = vec![];
+        let mut fields = vec![];
+        let mut methods = vec![];
+        let mut attributes = vec![];
+
+        while let Some(line) = self.next_line() {
+            let mut cursor = line.cursor();
+
+            match line.directive() {
+                ".version" => {
+                    major_version = try!(cursor.number());
+                    minor_version = try!(cursor.number());
+                    try!(cursor.finish());
+                }
+                ".constant_pool" => {
+                    if self.constant_pool.next_index() != 1 {
+                        return line.error("the constant pool has to come before any reference");
+                    }
+                    try!(self.assemble_constant_pool());
+                }
+                ".class" => {
+                    let count = cursor.remaining();
+                    if count == 0 {
+                        return line.error("expected a class name");
+                    }
+
+                    let access_flags = try!(self.flags(line, &line.tokens[1..count], CLASS_FLAGS));
+                    cursor.position = count;
+                    let this_class = try!(self.class(&mut cursor));
+                    class_line = Some((access_flags, this_class));
+                }
+                ".super" => {
+                    super_class = try!(self.class(&mut cursor));
+                    try!(cursor.finish());
+                }
+                ".implements" => {
+                    interfaces.push(try!(self.class(&mut cursor)));
+                    try!(cursor.finish());
+                }
+                ".field" => fields.push(try!(self.assemble_member(line, "field", FIELD_FLAGS))),
+                ".method" => methods.push(try!(self.assemble_member(line, "method", METHOD_FLAGS))),
+                _ => attributes.push(try!(self.assemble_attribute(line))),
+            }
+        }
+
+        let (access_flags, this_class) = match class_line {
+            Some(class_line) => class_line,
+            None => return Err(ParserError::InvalidAssembly(0, "missing .class".to_string())),
+        };
+
+        let mut bytes = vec![];
+        bytes.write_u4(0xCAFEBABE);
+        bytes.write_u2(minor_version);
+        bytes.write_u2(major_version);
+        self.constant_pool.write(&mut bytes);
+        bytes.write_u2(access_flags);
+        bytes.write_u2(this_class);
+        bytes.write_u2(super_class);
+
+        bytes.write_u2(interfaces.len() as U2);
+        for interface in interfaces {
+            bytes.write_u2(interface);
+        }
+        for members in vec![fields, methods, attributes] {
+            bytes.write_u2(members.len() as U2);
+            for member in members {
+                bytes.extend(member);
+            }
+        }
+
+        Ok(bytes)
+    }
+
+    fn assemble_constant_pool(&mut self) -> ParserResult<()> {
+        while let Some(line) = self.next_line() {
+            if line.directive() == ".end" {
+                let mut cursor = line.cursor();
+                try!(cursor.keyword("constant_pool"));
+                return cursor.finish();
+            }
+
+            let index = match line.tokens[0].raw_index() {
+                Some(index) => index as usize,
+                None => return line.error("expected a constant pool entry such as #1 = ..."),
+            };
+            if index != self.constant_pool.next_index() {
+                return line.error(&format!("expected entry #{}",
+                                           self.constant_pool.next_index()));
+            }
+
+            let mut cursor = line.cursor();
+            try!(cursor.keyword("="));
+            let constant = try!(self.constant_pool_entry(&mut cursor));
+            try!(cursor.finish());
+
+            try!(self.constant_pool.push(constant));
+        }
+
+        Err(ParserError::InvalidAssembly(self.last_line_number(), "missing .end constant_pool".to_string()))
+    }
+
+    /// An entry of an explicit constant pool, where references are always raw indexes.
+    fn constant_pool_entry(&mut self, cursor: &mut TokenCursor) -> ParserResult<Constant> {
+        let tag = try!(cursor.next());
+        let line = cursor.line;
+
+        let constant = match &*tag.text {
+            "Utf8" => Constant::Utf8(try!(cursor.next()).text.clone()),
+            "Integer" | "Float" | "Long" | "Double" => {
+                cursor.position -= 1;
+                try!(self.literal(cursor))
+            }
+            "Class" => Constant::Class(try!(raw_index(cursor))),
+            "String" => Constant::String(try!(raw_index(cursor))),
+            "Field" => Constant::Field(try!(raw_index(cursor)), try!(raw_index(cursor))),
+            "Method" => Constant::Method(try!(raw_index(cursor)), try!(raw_index(cursor))),
+            "InterfaceMethod" => {
+                Constant::InterfaceMethod(try!(raw_index(cursor)), try!(raw_index(cursor)))
+            }
+            "NameAndType" => Constant::NameAndType(try!(raw_index(cursor)), try!(raw_index(cursor))),
+            "MethodHandle" => {
+                Constant::MethodHandle(try!(reference_kind(cursor)), try!(raw_index(cursor)))
+            }
+            "MethodType" => Constant::MethodType(try!(raw_index(cursor))),
+            "InvokeDynamic" => {
+                Constant::InvokeDynamic(try!(cursor.number()), try!(raw_index(cursor)))
+            }
+            _ => return line.error(&format!("unknown constant kind {}", tag.text)),
+        };
+
+        if let Constant::Utf8(ref value) = constant {
+            if mutf8::encoded_len(value) > U2::max_value() as usize {
+                return line.error("string constant is too long");
+            }
+        }
+
+        Ok(constant)
+    }
+
+    fn literal(&mut self, cursor: &mut TokenCursor) -> ParserResult<Constant> {
+        let tag = try!(cursor.next());
+        let value = try!(cursor.next());
+        let line = cursor.line;
+        let invalid = || line.error(&format!("invalid {} {}", tag.text, value.text));
+
+        let text = &value.text;
+        let hex_bits = if text.starts_with("0x") {
+            u64::from_str_radix(&text[2..], 16).ok()
+        } else {
+            None
+        };
+
+        let constant = match &*tag.text {
+            "Integer" => {
+                match text.parse::<i32>() {
+                    Ok(value) => Constant::Integer(value as U4),
+                    Err(..) => return invalid(),
+                }
+            }
+            "Long" => {
+                match text.parse::<i64>() {
+                    Ok(value) => Constant::Long(value as u64),
+                    Err(..) => return invalid(),
+                }
+            }
+            "Float" => {
+                match (hex_bits, text.parse::<f32>()) {
+                    (Some(bits), _) => Constant::Float(bits as U4),
+                    (None, Ok(value)) => Constant::Float(value.to_bits()),
+                    _ => return invalid(),
+                }
+            }
+            _ => {
+                match (hex_bits, text.parse::<f64>()) {
+                    (Some(bits), _) => Constant::Double(bits),
+                    (None, Ok(value)) => Constant::Double(value.to_bits()),
+                    _ => return invalid(),
+                }
+            }
+        };
+
+        Ok(constant)
+    }
+
+    /// A tagged constant such as `String "text"`, or a raw index.
+    fn constant(&mut self, cursor: &mut TokenCursor) -> ParserResult<U2> {
+        let tag = try!(cursor.next());
+        if let Some(index) = tag.raw_index() {
+            return Ok(index);
+        }
+
+        let line = cursor.line;
+        let constant = match &*tag.text {
+            "Utf8" => return self.utf8(cursor),
+            "Integer" | "Float" | "Long" | "Double" => {
+                cursor.position -= 1;
+                try!(self.literal(cursor))
+            }
+            "Class" => return self.class(cursor),
+            "String" => Constant::String(try!(self.utf8(cursor))),
+            "Field" => Constant::Field(try!(self.class(cursor)), try!(self.name_and_type(cursor))),
+            "Method" => Constant::Method(try!(self.class(cursor)), try!(self.name_and_type(cursor))),
+            "InterfaceMethod" => {
+                Constant::InterfaceMethod(try!(self.class(cursor)),
+                                          try!(self.name_and_type(cursor)))
+            }
+            "NameAndType" => return self.name_and_type(cursor),
+            "MethodHandle" => {
+                Constant::MethodHandle(try!(reference_kind(cursor)), try!(self.constant(cursor)))
+            }
+            "MethodType" => Constant::MethodType(try!(self.utf8(cursor))),
+            "InvokeDynamic" => {
+                Constant::InvokeDynamic(try!(cursor.number()), try!(self.name_and_type(cursor)))
+            }
+            _ => return line.error(&format!("unknown constant kind {}", tag.text)),
+        };
+
+        self.constant_pool.intern(constant)
+    }
+
+    fn utf8(&mut self, cursor: &mut TokenCursor) -> ParserResult<U2> {
+        let token = try!(cursor.next());
+        if let Some(index) = token.raw_index() {
+            return Ok(index);
+        }
+
+        if mutf8::encoded_len(&token.text) > U2::max_value() as usize {
+            return cursor.line.error("string constant is too long");
+        }
+        self.constant_pool.intern(Constant::Utf8(token.text.clone()))
+    }
+
+    fn utf8_value(&mut self, value: &str) -> ParserResult<U2> {
+        self.constant_pool.intern(Constant::Utf8(value.to_string()))
+    }
+
+    fn class(&mut self, cursor: &mut TokenCursor) -> ParserResult<U2> {
+        if let Some(index) = try!(cursor.next()).raw_index() {
+            return Ok(index);
+        }
+
+        cursor.position -= 1;
+        let name_index = try!(self.utf8(cursor));
+        self.constant_pool.intern(Constant::Class(name_index))
+    }
+
+    fn name_and_type(&mut self, cursor: &mut TokenCursor) -> ParserResult<U2> {
+        if let Some(index) = try!(cursor.next()).raw_index() {
+            return Ok(index);
+        }
+
+        cursor.position -= 1;
+        let name_index = try!(self.utf8(cursor));
+        let descriptor_index = try!(self.utf8(cursor));
+        self.constant_pool.intern(Constant::NameAndType(name_index, descriptor_index))
+    }
+
+    fn flags(&self, line: &Line, tokens: &[Token], names: &[(U2, &'static str)]) -> ParserResult<U2> {
+        let mut access_flags = 0;
+
+        for token in tokens {
+            let flag = names.iter().find(|&&(_, name)| token.is(name)).map(|&(flag, _)| flag);
+            access_flags |= match flag {
+                Some(flag) => flag,
+                None if token.text.starts_with("0x") && !token.quoted => {
+                    match U2::from_str_radix(&token.text[2..], 16) {
+                        Ok(flag) => flag,
+                        Err(..) => return line.error(&format!("invalid flags {}", token.text)),
+                    }
+                }
+                None => return line.error(&format!("unknown flag {}", token.text)),
+            };
+        }
+
+        Ok(access_flags)
+    }
+
+    fn assemble_member(&mut self,
+                       line: &'l Line,
+                       kind: &str,
+                       flag_names: &[(U2, &'static str)])
+                       -> ParserResult<Vec<U1>> {
+        let count = line.tokens.len();
+        if count < 3 {
+            return line.error("expected a name and a descriptor");
+        }
+
+        let access_flags = try!(self.flags(line, &line.tokens[1..count - 2], flag_names));
+        let mut cursor = line.cursor();
+        cursor.position = count - 2;
+        let name_index = try!(self.utf8(&mut cursor));
+        let descriptor_index = try!(self.utf8(&mut cursor));
+
+        let mut attributes = vec![];
+        loop {
+            let line = match self.next_line() {
+                Some(line) => line,
+                None => return line.error(&format!("missing .end {}", kind)),
+            };
+
+            if line.directive() == ".end" {
+                let mut cursor = line.cursor();
+                try!(cursor.keyword(kind));
+                try!(cursor.finish());
+                break;
+            }
+
+            attributes.push(try!(self.assemble_attribute(line)));
+        }
+
+        let mut bytes = vec![];
+        bytes.write_u2(access_flags);
+        bytes.write_u2(name_index);
+        bytes.write_u2(descriptor_index);
+        bytes.write_u2(attributes.len() as U2);
+        for attribute in attributes {
+            bytes.extend(attribute);
+        }
+
+        Ok(bytes)
+    }
+
+    /// Assembles the attribute starting at `line`, including its name index and length.
+    fn assemble_attribute(&mut self, line: &'l Line) -> ParserResult<Vec<U1>> {
+        let mut cursor = line.cursor();
+        let mut info = vec![];
+
+        let name = match line.directive() {
+            ".sourcefile" => {
+                info.write_u2(try!(self.utf8(&mut cursor)));
+                "SourceFile"
+            }
+            ".signature" => {
+                info.write_u2(try!(self.utf8(&mut cursor)));
+                "Signature"
+            }
+            ".constantvalue" => {
+                info.write_u2(try!(self.constant(&mut cursor)));
+                "ConstantValue"
+            }
+            ".deprecated" => "Deprecated",
+            ".synthetic" => "Synthetic",
+            ".exceptions" | ".nestmembers" => {
+                let mut classes = vec![];
+                while cursor.peek().is_some() {
+                    classes.push(try!(self.class(&mut cursor)));
+                }
+
+                info.write_u2(classes.len() as U2);
+                for class in classes {
+                    info.write_u2(class);
+                }
+
+                if line.directive() == ".exceptions" {
+                    "Exceptions"
+                } else {
+                    "NestMembers"
+                }
+            }
+            ".nesthost" => {
+                info.write_u2(try!(self.class(&mut cursor)));
+                "NestHost"
+            }
+            ".enclosingmethod" => {
+                info.write_u2(try!(self.class(&mut cursor)));
+                if cursor.peek().is_some() {
+                    info.write_u2(try!(self.name_and_type(&mut cursor)));
+                } else {
+                    info.write_u2(0);
+                }
+                "EnclosingMethod"
+            }
+            ".innerclasses" => {
+                try!(cursor.finish());
+                let entries = try!(self.block_lines("innerclasses", line));
+
+                info.write_u2(entries.len() as U2);
+                for entry in entries {
+                    if entry.tokens.len() < 3 {
+                        return entry.error("expected an inner class, outer class and name");
+                    }
+
+                    let mut cursor = TokenCursor {
+                        line: entry,
+                        position: 0,
+                    };
+                    info.write_u2(try!(self.class(&mut cursor)));
+                    info.write_u2(try!(self.class(&mut cursor)));
+                    info.write_u2(try!(self.utf8(&mut cursor)));
+                    info.write_u2(try!(self.flags(entry, &entry.tokens[3..], INNER_CLASS_FLAGS)));
+                }
+                "InnerClasses"
+            }
+            ".bootstrapmethods" => {
+                try!(cursor.finish());
+                let entries = try!(self.block_lines("bootstrapmethods", line));
+
+                info.write_u2(entries.len() as U2);
+                for entry in entries {
+                    let mut cursor = TokenCursor {
+                        line: entry,
+                        position: 0,
+                    };
+                    info.write_u2(try!(self.constant(&mut cursor)));
+
+                    let mut arguments = vec![];
+                    while cursor.peek().is_some() {
+                        arguments.push(try!(self.constant(&mut cursor)));
+                    }
+                    info.write_u2(arguments.len() as U2);
+                    for argument in arguments {
+                        info.write_u2(argument);
+                    }
+                }
+                "BootstrapMethods"
+            }
+            ".code" => {
+                let name_index = try!(self.utf8_value("Code"));
+                let info = try!(self.assemble_code(line));
+                return Ok(attribute_bytes(name_index, info));
+            }
+            ".attribute" => {
+                let name_index = try!(self.utf8(&mut cursor));
+                let data = try!(cursor.next());
+                try!(cursor.finish());
+
+                match parse_hex(&data.text) {
+                    Some(info) => return Ok(attribute_bytes(name_index, info)),
+                    None => return line.error("expected the attribute body as hex digits"),
+                }
+            }
+            _ => return line.error(&format!("unknown directive {}", line.tokens[0].text)),
+        };
+
+        try!(cursor.finish());
+
+        let name_index = try!(self.utf8_value(name));
+        Ok(attribute_bytes(name_index, info))
+    }
+
+    fn assemble_code(&mut self, code_line: &'l Line) -> ParserResult<Vec<U1>> {
+        let mut cursor = code_line.cursor();
+        try!(cursor.keyword("stack"));
+        let max_stack: U2 = try!(cursor.number());
+        try!(cursor.keyword("locals"));
+        let max_locals: U2 = try!(cursor.number());
+        try!(cursor.finish());
+
+        let mut assembler = CodeAssembler::new();
+        let mut labels = HashMap::new();
+        let mut tables = vec![];
+
+        loop {
+            let line = match self.next_line() {
+                Some(line) => line,
+                None => return code_line.error("missing .end code"),
+            };
+            let mut cursor = line.cursor();
+
+            match line.directive() {
+                ".end" => {
+                    try!(cursor.keyword("code"));
+                    try!(cursor.finish());
+                    break;
+                }
+                ".catch" => {
+                    let catch_type = if cursor.peek().map(|token| token.is("any")).unwrap_or(false) {
+                        cursor.position += 1;
+                        0
+                    } else {
+                        try!(self.class(&mut cursor))
+                    };
+
+                    let start = try!(label(&mut cursor, &mut labels, &mut assembler));
+                    let end = try!(label(&mut cursor, &mut labels, &mut assembler));
+                    let handler = try!(label(&mut cursor, &mut labels, &mut assembler));
+                    try!(cursor.finish());
+
+                    assembler.exception_handler(start, end, handler, catch_type);
+                }
+                ".linenumbertable" => {
+                    try!(cursor.finish());
+                    let name_index = try!(self.utf8_value("LineNumberTable"));
+
+                    let mut line_numbers = vec![];
+                    for entry in try!(self.block_lines("linenumbertable", line)) {
+                        let mut cursor = TokenCursor {
+                            line: entry,
+                            position: 0,
+                        };
+                        let start = try!(label(&mut cursor, &mut labels, &mut assembler));
+                        line_numbers.push((start, try!(cursor.number())));
+                        try!(cursor.finish());
+                    }
+
+                    tables.push(CodeTable::LineNumbers(name_index, line_numbers));
+                }
+                ".localvariabletable" | ".localvariabletypetable" => {
+                    try!(cursor.finish());
+                    let (name, block) = if line.directive() == ".localvariabletable" {
+                        ("LocalVariableTable", "localvariabletable")
+                    } else {
+                        ("LocalVariableTypeTable", "localvariabletypetable")
+                    };
+                    let name_index = try!(self.utf8_value(name));
+
+                    let mut local_variables = vec![];
+                    for entry in try!(self.block_lines(block, line)) {
+                        let mut cursor = TokenCursor {
+                            line: entry,
+                            position: 0,
+                        };
+                        let index = try!(cursor.number());
+                        let name_index = try!(self.utf8(&mut cursor));
+                        let descriptor_index = try!(self.utf8(&mut cursor));
+                        let start = try!(label(&mut cursor, &mut labels, &mut assembler));
+                        let end = try!(label(&mut cursor, &mut labels, &mut assembler));
+                        try!(cursor.finish());
+
+                        local_variables.push((index, name_index, descriptor_index, start, end));
+                    }
+
+                    tables.push(CodeTable::LocalVariables(name_index, local_variables));
+                }
+                ".stackmaptable" => {
+                    try!(cursor.finish());
+                    let name_index = try!(self.utf8_value("StackMapTable"));
+
+                    let mut frames = vec![];
+                    for entry in try!(self.block_lines("stackmaptable", line)) {
+                        frames.push(try!(self.stack_map_frame(entry, &mut labels, &mut assembler)));
+                    }
+
+                    tables.push(CodeTable::StackMap(name_index, frames));
+                }
+                directive if directive.starts_with('.') => {
+                    tables.push(CodeTable::Serialized(try!(self.assemble_attribute(line))));
+                }
+                directive if directive.ends_with(':') && line.tokens.len() == 1 => {
+                    let name = &directive[..directive.len() - 1];
+                    let label = label_named(name, &mut labels, &mut assembler);
+                    assembler.place_label(label);
+                }
+                _ => {
+                    let instruction = try!(self.instruction(line, &mut labels, &mut assembler));
+                    assembler.push(instruction);
+                }
+            }
+        }
+
+        let assembled = try!(assembler.assemble());
+        let offset = |label: Label, line: usize| match assembled.label_offset(label) {
+            Some(offset) => Ok(offset),
+            None => Err(ParserError::InvalidAssembly(line, "label is never placed".to_string())),
+        };
+
+        let mut info = vec![];
+        info.write_u2(max_stack);
+        info.write_u2(max_locals);
+        info.write_u4(assembled.code.len() as U4);
+        info.extend(assembled.code.iter().cloned());
+        info.write_u2(assembled.exception_table.len() as U2);
+        for handler in &assembled.exception_table {
+            handler.write(&mut info);
+        }
+
+        info.write_u2(tables.len() as U2);
+        for table in tables {
+            let mut table_info = vec![];
+
+            let name_index = match table {
+                CodeTable::Serialized(bytes) => {
+                    info.extend(bytes);
+                    continue;
+                }
+                CodeTable::LineNumbers(name_index, line_numbers) => {
+                    table_info.write_u2(line_numbers.len() as U2);
+                    for (start, line_number) in line_numbers {
+                        table_info.write_u2(try!(offset(start, code_line.number)));
+                        table_info.write_u2(line_number);
+                    }
+                    name_index
+                }
+                CodeTable::LocalVariables(name_index, local_variables) => {
+                    table_info.write_u2(local_variables.len() as U2);
+                    for (index, name_index, descriptor_index, start, end) in local_variables {
+                        let start_pc = try!(offset(start, code_line.number));
+                        let end_pc = try!(offset(end, code_line.number));
+                        if end_pc < start_pc {
+                            return code_line.error("local variable ends before it starts");
+                        }
+
+                        table_info.write_u2(start_pc);
+                        table_info.write_u2(end_pc - start_pc);
+                        table_info.write_u2(name_index);
+                        table_info.write_u2(descriptor_index);
+                        table_info.write_u2(index);
+                    }
+                    name_index
+                }
+                CodeTable::StackMap(name_index, frames) => {
+                    table_info.write_u2(frames.len() as U2);
+
+                    let mut previous_pc: Option<U2> = None;
+                    for frame in frames {
+                        let PendingFrame { kind, label, chopped, locals, stack, line } = frame;
+
+                        let pc = try!(offset(label, line));
+                        let offset_delta = match previous_pc {
+                            None => Some(pc),
+                            Some(previous_pc) => pc.checked_sub(previous_pc + 1),
+                        };
+                        let offset_delta = match offset_delta {
+                            Some(offset_delta) => offset_delta,
+                            None => return Err(ParserError::InvalidAssembly(line, "stack map frames must be in code order".to_string())),
+                        };
+                        previous_pc = Some(pc);
+
+                        let resolve = |types: Vec<PendingVerificationType>| -> ParserResult<Vec<VerificationTypeInfo>> {
+                            let mut resolved = vec![];
+                            for verification_type in types {
+                                resolved.push(match verification_type {
+                                    PendingVerificationType::Resolved(resolved) => resolved,
+                                    PendingVerificationType::Uninitialized(label) => {
+                                        VerificationTypeInfo::Uninitialized(try!(offset(label, line)))
+                                    }
+                                });
+                            }
+                            Ok(resolved)
+                        };
+
+                        let locals = try!(resolve(locals));
+                        let stack = try!(resolve(stack));
+                        let frame = try!(stack_map_frame(&kind, offset_delta, chopped, locals, stack, line));
+                        frame.write(&mut table_info);
+                    }
+                    name_index
+                }
+            };
+
+            info.extend(attribute_bytes(name_index, table_info));
+        }
+
+        Ok(info)
+    }
+
+    fn instruction(&mut self,
+                   line: &'l Line,
+                   labels: &mut HashMap<String, Label>,
+                   assembler: &mut CodeAssembler)
+                   -> ParserResult<Instruction<Label>> {
+        let mnemonic = line.directive();
+        let mut cursor = line.cursor();
+
+        if let Some(instruction) = Instruction::from_operandless_mnemonic(mnemonic) {
+            try!(cursor.finish());
+            return Ok(instruction);
+        }
+
+        let instruction = match mnemonic {
+            "bipush" => Instruction::Bipush(try!(cursor.number())),
+            "sipush" => Instruction::Sipush(try!(cursor.number())),
+            "ldc" => {
+                // renumbered constants may no longer fit in a single byte
+                let index = try!(self.constant(&mut cursor));
+                if index > U1::max_value() as U2 {
+                    Instruction::LdcW(index)
+                } else {
+                    Instruction::Ldc(index as U1)
+                }
+            }
+            "ldc_w" => Instruction::LdcW(try!(self.constant(&mut cursor))),
+            "ldc2_w" => Instruction::Ldc2W(try!(self.constant(&mut cursor))),
+            "iload" => Instruction::Iload(try!(cursor.number())),
+            "lload" => Instruction::Lload(try!(cursor.number())),
+            "fload" => Instruction::Fload(try!(cursor.number())),
+            "dload" => Instruction::Dload(try!(cursor.number())),
+            "aload" => Instruction::Aload(try!(cursor.number())),
+            "istore" => Instruction::Istore(try!(cursor.number())),
+            "lstore" => Instruction::Lstore(try!(cursor.number())),
+            "fstore" => Instruction::Fstore(try!(cursor.number())),
+            "dstore" => Instruction::Dstore(try!(cursor.number())),
+            "astore" => Instruction::Astore(try!(cursor.number())),
+            "ret" => Instruction::Ret(try!(cursor.number())),
+            "iinc" => Instruction::Iinc(try!(cursor.number()), try!(cursor.number())),
+            "ifeq" => Instruction::Ifeq(try!(label(&mut cursor, labels, assembler))),
+            "ifne" => Instruction::Ifne(try!(label(&mut cursor, labels, assembler))),
+            "iflt" => Instruction::Iflt(try!(label(&mut cursor, labels, assembler))),
+            "ifge" => Instruction::Ifge(try!(label(&mut cursor, labels, assembler))),
+            "ifgt" => Instruction::Ifgt(try!(label(&mut cursor, labels, assembler))),
+            "ifle" => Instruction::Ifle(try!(label(&mut cursor, labels, assembler))),
+            "if_icmpeq" => Instruction::IfIcmpeq(try!(label(&mut cursor, labels, assembler))),
+            "if_icmpne" => Instruction::IfIcmpne(try!(label(&mut cursor, labels, assembler))),
+            "if_icmplt" => Instruction::IfIcmplt(try!(label(&mut cursor, labels, assembler))),
+            "if_icmpge" => Instruction::IfIcmpge(try!(label(&mut cursor, labels, assembler))),
+            "if_icmpgt" => Instruction::IfIcmpgt(try!(label(&mut cursor, labels, assembler))),
+            "if_icmple" => Instruction::IfIcmple(try!(label(&mut cursor, labels, assembler))),
+            "if_acmpeq" => Instruction::IfAcmpeq(try!(label(&mut cursor, labels, assembler))),
+            "if_acmpne" => Instruction::IfAcmpne(try!(label(&mut cursor, labels, assembler))),
+            "goto" => Instruction::Goto(try!(label(&mut cursor, labels, assembler))),
+            "jsr" => Instruction::Jsr(try!(label(&mut cursor, labels, assembler))),
+            "ifnull" => Instruction::Ifnull(try!(label(&mut cursor, labels, assembler))),
+            "ifnonnull" => Instruction::Ifnonnull(try!(label(&mut cursor, labels, assembler))),
+            "goto_w" => Instruction::GotoW(try!(label(&mut cursor, labels, assembler))),
+            "jsr_w" => Instruction::JsrW(try!(label(&mut cursor, labels, assembler))),
+            "tableswitch" => {
+                let low = try!(cursor.number());
+                let mut offsets = vec![];
+                while !cursor.peek().map(|token| token.is("default")).unwrap_or(true) {
+                    offsets.push(try!(label(&mut cursor, labels, assembler)));
+                }
+                try!(cursor.keyword("default"));
+
+                if offsets.is_empty() {
+                    return line.error("tableswitch needs at least one target");
+                }
+                Instruction::Tableswitch {
+                    default: try!(label(&mut cursor, labels, assembler)),
+                    low: low,
+                    offsets: offsets,
+                }
+            }
+            "lookupswitch" => {
+                let mut pairs = vec![];
+                while !cursor.peek().map(|token| token.is("default")).unwrap_or(true) {
+                    let key = try!(cursor.number());
+                    pairs.push((key, try!(label(&mut cursor, labels, assembler))));
+                }
+                try!(cursor.keyword("default"));
+
+                Instruction::Lookupswitch {
+                    default: try!(label(&mut cursor, labels, assembler)),
+                    pairs: pairs,
+                }
+            }
+            "getstatic" => Instruction::Getstatic(try!(self.constant(&mut cursor))),
+            "putstatic" => Instruction::Putstatic(try!(self.constant(&mut cursor))),
+            "getfield" => Instruction::Getfield(try!(self.constant(&mut cursor))),
+            "putfield" => Instruction::Putfield(try!(self.constant(&mut cursor))),
+            "invokevirtual" => Instruction::Invokevirtual(try!(self.constant(&mut cursor))),
+            "invokespecial" => Instruction::Invokespecial(try!(self.constant(&mut cursor))),
+            "invokestatic" => Instruction::Invokestatic(try!(self.constant(&mut cursor))),
+            "invokeinterface" => {
+                Instruction::Invokeinterface(try!(self.constant(&mut cursor)), try!(cursor.number()))
+            }
+            "invokedynamic" => Instruction::Invokedynamic(try!(self.constant(&mut cursor))),
+            "new" => Instruction::New(try!(self.class(&mut cursor))),
+            "anewarray" => Instruction::Anewarray(try!(self.class(&mut cursor))),
+            "checkcast" => Instruction::Checkcast(try!(self.class(&mut cursor))),
+            "instanceof" => Instruction::Instanceof(try!(self.class(&mut cursor))),
+            "multianewarray" => {
+                Instruction::Multianewarray(try!(self.class(&mut cursor)), try!(cursor.number()))
+            }
+            "newarray" => {
+                let name = try!(cursor.next());
+                let atype = (4..12)
+                    .filter_map(|atype| ArrayType::from(atype).ok())
+                    .find(|atype| name.is(atype.to_friendly_name()));
+                match atype {
+                    Some(atype) => Instruction::Newarray(atype),
+                    None => return line.error(&format!("unknown array type {}", name.text)),
+                }
+            }
+            _ => return line.error(&format!("unknown instruction {}", line.tokens[0].text)),
+        };
+
+        try!(cursor.finish());
+
+        Ok(instruction)
+    }
+
+    fn stack_map_frame(&mut self,
+                       line: &'l Line,
+                       labels: &mut HashMap<String, Label>,
+                       assembler: &mut CodeAssembler)
+                       -> ParserResult<PendingFrame> {
+        let mut cursor = TokenCursor {
+            line: line,
+            position: 0,
+        };
+        let kind = try!(cursor.next()).text.clone();
+        let label = try!(label(&mut cursor, labels, assembler));
+
+        let mut chopped = 0;
+        let mut locals = vec![];
+        let mut stack = vec![];
+
+        match &*kind {
+            "same" | "same_extended" => {}
+            "same_locals_1_stack_item" |
+            "same_locals_1_stack_item_extended" => {
+                stack.push(try!(self.verification_type(&mut cursor, labels, assembler)));
+            }
+            "chop" => chopped = try!(cursor.number()),
+            "append" => {
+                while cursor.peek().is_some() {
+                    locals.push(try!(self.verification_type(&mut cursor, labels, assembler)));
+                }
+            }
+            "full" => {
+                try!(cursor.keyword("locals"));
+                while !cursor.peek().map(|token| token.is("stack")).unwrap_or(true) {
+                    locals.push(try!(self.verification_type(&mut cursor, labels, assembler)));
+                }
+                try!(cursor.keyword("stack"));
+                while cursor.peek().is_some() {
+                    stack.push(try!(self.verification_type(&mut cursor, labels, assembler)));
+                }
+            }
+            _ => return line.error(&format!("unknown stack map frame {}", kind)),
+        }
+
+        try!(cursor.finish());
+
+        Ok(PendingFrame {
+            kind: kind,
+            label: label,
+            chopped: chopped,
+            locals: locals,
+            stack: stack,
+            line: line.number,
+        })
+    }
+
+    fn verification_type(&mut self,
+                         cursor: &mut TokenCursor,
+                         labels: &mut HashMap<String, Label>,
+                         assembler: &mut CodeAssembler)
+                         -> ParserResult<PendingVerificationType> {
+        let name = try!(cursor.next());
+
+        let verification_type = match &*name.text {
+            "Top" => VerificationTypeInfo::Top,
+            "Integer" => VerificationTypeInfo::Integer,
+            "Float" => VerificationTypeInfo::Float,
+            "Double" => VerificationTypeInfo::Double,
+            "Long" => VerificationTypeInfo::Long,
+            "Null" => VerificationTypeInfo::Null,
+            "UninitializedThis" => VerificationTypeInfo::UninitializedThis,
+            "Object" => VerificationTypeInfo::Object(try!(self.class(cursor))),
+            "Uninitialized" => {
+                let label = try!(label(cursor, labels, assembler));
+                return Ok(PendingVerificationType::Uninitialized(label));
+            }
+            _ => return cursor.line.error(&format!("unknown verification type {}", name.text)),
+        };
+
+        Ok(PendingVerificationType::Resolved(verification_type))
+    }
+
+    /// Collects the lines of a block up to its `.end` line.
+    fn block_lines(&mut self, block: &str, start: &Line) -> ParserResult<Vec<&'l Line>> {
+        let mut lines = vec![];
+
+        while let Some(line) = self.next_line() {
+            if line.directive() == ".end" {
+                let mut cursor = line.cursor();
+                try!(cursor.keyword(block));
+                try!(cursor.finish());
+                return Ok(lines);
+            }
+
+            lines.push(line);
+        }
+
+        start.error(&format!("missing .end {}", block))
+    }
+
+    fn next_line(&mut self) -> Option<&'l Line> {
+        let lines = self.lines;
+        let line = lines.get(self.position);
+        if line.is_some() {
+            self.position += 1;
+        }
+
+        line
+    }
+
+    fn last_line_number(&self) -> usize {
+        self.lines.last().map(|line| line.number).unwrap_or(0)
+    }
+}
+
+fn raw_index(cursor: &mut TokenCursor) -> ParserResult<U2> {
+    let token = try!(cursor.next());
+    match token.raw_index() {
+        Some(index) => Ok(index),
+        None => cursor.line.error(&format!("expected a constant pool index but found {}", token.text)),
+    }
+}
+
+fn reference_kind(cursor: &mut TokenCursor) -> ParserResult<U1> {
+    let token = try!(cursor.next());
+    let kind = REFERENCE_KINDS.iter().find(|&&(_, name)| token.is(name)).map(|&(kind, _)| kind);
+
+    match kind.or_else(|| token.text.parse().ok()) {
+        Some(kind) => Ok(kind),
+        None => cursor.line.error(&format!("unknown reference kind {}", token.text)),
+    }
+}
+
+fn label(cursor: &mut TokenCursor,
+         labels: &mut HashMap<String, Label>,
+         assembler: &mut CodeAssembler)
+         -> ParserResult<Label> {
+    let token = try!(cursor.next());
+    if token.quoted {
+        return cursor.line.error(&format!("expected a label but found \"{}\"", token.text));
+    }
+
+    Ok(label_named(&token.text, labels, assembler))
+}
+
+fn label_named(name: &str, labels: &mut HashMap<String, Label>, assembler: &mut CodeAssembler) -> Label {
+    if let Some(label) = labels.get(name) {
+        return *label;
+    }
+
+    let label = assembler.new_label();
+    labels.insert(name.to_string(), label);
+    label
+}
+
+fn stack_map_frame(kind: &str,
+                   offset_delta: U2,
+                   chopped: U1,
+                   locals: Vec<VerificationTypeInfo>,
+                   stack: Vec<VerificationTypeInfo>,
+                   line: usize)
+                   -> ParserResult<StackMapFrame> {
+    let frame = match kind {
+        "same" if offset_delta <= 63 => StackMapFrame::Same { frame_type: offset_delta as U1 },
+        "same" | "same_extended" => StackMapFrame::SameExtended { offset_delta: offset_delta },
+        "same_locals_1_stack_item" if offset_delta <= 63 => {
+            StackMapFrame::SameLocals1StackItem {
+                frame_type: 64 + offset_delta as U1,
+                stack: stack[0].clone(),
+            }
+        }
+        "same_locals_1_stack_item" |
+        "same_locals_1_stack_item_extended" => {
+            StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: offset_delta,
+                stack: stack[0].clone(),
+            }
+        }
+        "chop" if chopped >= 1 && chopped <= 3 => {
+            StackMapFrame::Chop {
+                frame_type: 251 - chopped,
+                offset_delta: offset_delta,
+            }
+        }
+        "append" if locals.len() >= 1 && locals.len() <= 3 => {
+            StackMapFrame::Append {
+                frame_type: 251 + locals.len() as U1,
+                offset_delta: offset_delta,
+                locals: locals,
+            }
+        }
+        "full" => {
+            StackMapFrame::Full {
+                offset_delta: offset_delta,
+                locals: locals,
+                stack: stack,
+            }
+        }
+        _ => {
+            let message = "chop and append frames take between one and three locals";
+            return Err(ParserError::InvalidAssembly(line, message.to_string()));
+        }
+    };
+
+    Ok(frame)
+}
+
+fn attribute_bytes(name_index: U2, info: Vec<U1>) -> Vec<U1> {
+    let mut bytes = vec![];
+    bytes.write_u2(name_index);
+    bytes.write_u4(info.len() as U4);
+    bytes.extend(info);
+
+    bytes
+}
+
+fn parse_hex(text: &str) -> Option<Vec<U1>> {
+    if text.len() % 2 != 0 || !text.is_ascii() {
+        return None;
+    }
+
+    let mut bytes = vec![];
+    for i in 0..text.len() / 2 {
+        match U1::from_str_radix(&text[i * 2..i * 2 + 2], 16) {
+            Ok(byte) => bytes.push(byte),
+            Err(..) => return None,
+        }
+    }
+
+    Some(bytes)
+}
@@ -0,0 +1,720 @@
+//! Building class files in code.
+//!
+//! A `ClassBuilder` declares a class one part at a time. Every name, descriptor and constant it
+//! is given is interned into the constant pool as it is needed, so no constant pool index has to
+//! be worked out by hand, and every count is taken from what it counts:
+//!
+//! ```
+//! use pantomime_parser::access::MethodAccess;
+//! use pantomime_parser::builder::ClassBuilder;
+//! use pantomime_parser::instructions::Instruction;
+//!
+//! let bytes = ClassBuilder::new("com/example/Hello")
+//!     .method(MethodAccess::PUBLIC | MethodAccess::STATIC,
+//!             "main",
+//!             "([Ljava/lang/String;)V",
+//!             |code| {
+//!         code.field(Instruction::Getstatic, "java/lang/System", "out", "Ljava/io/PrintStream;")
+//!             .string("hello world")
+//!             .method(Instruction::Invokevirtual,
+//!                     "java/io/PrintStream",
+//!                     "println",
+//!                     "(Ljava/lang/String;)V")
+//!             .instruction(Instruction::Return);
+//!     })
+//!     .to_bytes()
+//!     .unwrap();
+//! ```
+//!
+//! The operand stack and local variable sizes of a method body are computed unless they are
+//! given with `CodeBuilder::limits`. A class of version 50 or later also gets a `StackMapTable`
+//! for every body that branches or handles exceptions, computed with the hierarchy given to
+//! `ClassBuilder::compute_frames`. By default that is `ObjectHierarchy`, which merges different
+//! classes to `java/lang/Object`, so code that relies on a more specific common superclass
+//! needs a hierarchy that knows it. Unreachable code and subroutines can't be given frames, so
+//! building such a body fails from version 50 on.
+//!
+//! An interning error, such as a name too long for a class file, is kept until the class is
+//! built, which keeps the methods of the builders chainable.
+
+use super::{ClassFile, ParserError, ParserResult};
+use super::access::{ClassAccess, FieldAccess, MethodAccess};
+use super::assembler::{CodeAssembler, Label};
+use super::components::{Attribute, CodeAttribute, ConstantPoolItem, Field,
+                        LineNumberTableAttribute, LocalVariableTableAttribute, Method, Utf8Info};
+use super::descriptors::MethodDescriptor;
+use super::hierarchy::{ClassHierarchy, ObjectHierarchy};
+use super::instructions::Instruction;
+use super::limits::CodeLimits;
+use super::mutf8;
+use super::pool::{Constant, ConstantPool};
+use super::primitives::{PrimitiveWriter, U1, U2, U4};
+use super::stackmap;
+
+use std::rc::Rc;
+
+/// Declares a class, which `build` turns into a `ClassFile` and `to_bytes` into class file
+/// bytes. A new class is public, extends `java/lang/Object` and has version 52.0.
+pub struct ClassBuilder<'h> {
+    minor_version: U2,
+    major_version: U2,
+    constant_pool: ConstantPool,
+    access: ClassAccess,
+    this_class: U2,
+    super_name: Option<String>,
+    interfaces: Vec<U2>,
+    fields: Vec<Member>,
+    methods: Vec<Member>,
+    attributes: Vec<Rc<Attribute>>,
+    hierarchy: &'h dyn ClassHierarchy,
+    error: Option<ParserError>,
+}
+
+struct Member {
+    access_flags: U2,
+    name_index: U2,
+    descriptor_index: U2,
+    code: Option<BuiltCode>,
+}
+
+struct BuiltCode {
+    code: CodeAttribute,
+    compute_limits: bool,
+}
+
+impl<'h> ClassBuilder<'h> {
+    /// A class named `name`, in its internal form such as `com/example/Foo`.
+    pub fn new(name: &str) -> ClassBuilder<'h> {
+        let mut builder = ClassBuilder {
+            minor_version: 0,
+            major_version: 52,
+            constant_pool: ConstantPool::new(),
+            access: ClassAccess::PUBLIC | ClassAccess::SUPER,
+            this_class: 0,
+            super_name: Some("java/lang/Object".to_string()),
+            interfaces: vec![],
+            fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+            hierarchy: &ObjectHierarchy,
+            error: None,
+        };
+        let this_class = builder.constant_pool.class(name);
+        builder.this_class = record(&mut builder.error, this_class);
+
+        builder
+    }
+
+    pub fn version(mut self, major_version: U2, minor_version: U2) -> ClassBuilder<'h> {
+        self.major_version = major_version;
+        self.minor_version = minor_version;
+        self
+    }
+
+    pub fn access(mut self, access: ClassAccess) -> ClassBuilder<'h> {
+        self.access = access;
+        self
+    }
+
+    pub fn extends(mut self, name: &str) -> ClassBuilder<'h> {
+        self.super_name = Some(name.to_string());
+        self
+    }
+
+    /// Leaves out the superclass, which only `java/lang/Object` and modules do.
+    pub fn extends_nothing(mut self) -> ClassBuilder<'h> {
+        self.super_name = None;
+        self
+    }
+
+    pub fn implements(mut self, name: &str) -> ClassBuilder<'h> {
+        let interface = self.constant_pool.class(name);
+        let interface = record(&mut self.error, interface);
+        self.interfaces.push(interface);
+        self
+    }
+
+    pub fn field(mut self, access: FieldAccess, name: &str, descriptor: &str) -> ClassBuilder<'h> {
+        let field = self.member(access.bits(), name, descriptor);
+        self.fields.push(field);
+        self
+    }
+
+    /// Declares a method whose body `body` writes.
+    pub fn method<F>(mut self,
+                     access: MethodAccess,
+                     name: &str,
+                     descriptor: &str,
+                     body: F)
+                     -> ClassBuilder<'h>
+        where F: FnOnce(&mut CodeBuilder)
+    {
+        let mut method = self.member(access.bits(), name, descriptor);
+
+        let code = {
+            let mut code = CodeBuilder::new(&mut self.constant_pool);
+            body(&mut code);
+            code.build()
+        };
+        match code {
+            Ok(code) => method.code = Some(code),
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+
+        self.methods.push(method);
+        self
+    }
+
+    /// Declares a method without a body, as abstract and native methods are.
+    pub fn abstract_method(mut self,
+                           access: MethodAccess,
+                           name: &str,
+                           descriptor: &str)
+                           -> ClassBuilder<'h> {
+        let method = self.member(access.bits(), name, descriptor);
+        self.methods.push(method);
+        self
+    }
+
+    /// Computes stack map frames with `hierarchy` rather than `ObjectHierarchy`. Classes before
+    /// version 50 are verified without them, so they get none either way.
+    pub fn compute_frames<H: ClassHierarchy>(mut self, hierarchy: &'h H) -> ClassBuilder<'h> {
+        self.hierarchy = hierarchy;
+        self
+    }
+
+    /// Adds a `SourceFile` attribute naming the file the class was compiled from.
+    pub fn source_file(mut self, name: &str) -> ClassBuilder<'h> {
+        let attribute_name = self.constant_pool.utf8("SourceFile");
+        record(&mut self.error, attribute_name);
+        let source_file = self.constant_pool.utf8(name);
+        let source_file = record(&mut self.error, source_file);
+
+        let mut info = vec![];
+        info.write_u2(source_file);
+        self.attributes.push(Rc::new(Attribute::Unknown {
+            attribute_name: utf8_info("SourceFile"),
+            info: info,
+        }));
+        self
+    }
+
+    pub fn build(mut self) -> ParserResult<ClassFile> {
+        let super_class = match self.super_name.take() {
+            Some(name) => {
+                let super_class = self.constant_pool.class(&name);
+                record(&mut self.error, super_class)
+            }
+            None => 0,
+        };
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let constant_pool = self.constant_pool.to_items();
+        let mut classfile = ClassFile {
+            magic: 0xCAFEBABE,
+            minor_version: self.minor_version,
+            major_version: self.major_version,
+            constant_pool_count: self.constant_pool.next_index() as U2,
+            constant_pool: constant_pool,
+            access_flags: self.access.bits(),
+            this_class: self.this_class,
+            super_class: super_class,
+            interfaces_count: self.interfaces.len() as U2,
+            interfaces: self.interfaces,
+            fields_count: 0,
+            fields: vec![],
+            methods_count: 0,
+            methods: vec![],
+            attributes_count: self.attributes.len() as U2,
+            attributes: self.attributes,
+        };
+
+        for member in self.fields {
+            let field = Field {
+                access_flags: member.access_flags,
+                name_index: member.name_index,
+                name: try!(ConstantPoolItem::retrieve_utf8_info(member.name_index,
+                                                                &classfile.constant_pool)),
+                descriptor_index: member.descriptor_index,
+                descriptor: try!(ConstantPoolItem::retrieve_utf8_info(member.descriptor_index,
+                                                                      &classfile.constant_pool)),
+                attributes_count: 0,
+                attributes: vec![],
+            };
+            classfile.fields.push(Rc::new(field));
+        }
+
+        for member in self.methods {
+            let mut method = Method {
+                access_flags: member.access_flags,
+                name_index: member.name_index,
+                name: try!(ConstantPoolItem::retrieve_utf8_info(member.name_index,
+                                                                &classfile.constant_pool)),
+                descriptor_index: member.descriptor_index,
+                descriptor: try!(ConstantPoolItem::retrieve_utf8_info(member.descriptor_index,
+                                                                      &classfile.constant_pool)),
+                attributes_count: 0,
+                attributes: vec![],
+            };
+
+            if let Some(BuiltCode { mut code, compute_limits }) = member.code {
+                if compute_limits {
+                    let limits = try!(CodeLimits::compute(&classfile, &method, &code));
+                    code.max_stack = limits.max_stack;
+                    code.max_locals = limits.max_locals;
+                }
+                if classfile.major_version >= 50 && try!(stackmap::is_required(&code)) {
+                    let table = try!(stackmap::compute(&mut classfile,
+                                                       &method,
+                                                       &code,
+                                                       &self.hierarchy));
+                    code.attributes.push(Attribute::StackMapTable(Rc::new(table)));
+                    code.attributes_count += 1;
+                }
+                method.attributes.push(Rc::new(Attribute::Code(Rc::new(code))));
+                method.attributes_count = 1;
+            }
+
+            classfile.methods.push(Rc::new(method));
+        }
+
+        classfile.fields_count = classfile.fields.len() as U2;
+        classfile.methods_count = classfile.methods.len() as U2;
+
+        Ok(classfile)
+    }
+
+    pub fn to_bytes(self) -> ParserResult<Vec<U1>> {
+        try!(self.build()).to_bytes()
+    }
+
+    fn member(&mut self, access_flags: U2, name: &str, descriptor: &str) -> Member {
+        let name_index = self.constant_pool.utf8(name);
+        let descriptor_index = self.constant_pool.utf8(descriptor);
+
+        Member {
+            access_flags: access_flags,
+            name_index: record(&mut self.error, name_index),
+            descriptor_index: record(&mut self.error, descriptor_index),
+            code: None,
+        }
+    }
+}
+
+/// Writes the body of a method declared with `ClassBuilder::method`. Instructions that refer to
+/// the constant pool are given what they refer to, and branch targets are labels.
+pub struct CodeBuilder<'p> {
+    constant_pool: &'p mut ConstantPool,
+    assembler: CodeAssembler,
+    limits: Option<CodeLimits>,
+    error: Option<ParserError>,
+}
+
+impl<'p> CodeBuilder<'p> {
+    fn new(constant_pool: &'p mut ConstantPool) -> CodeBuilder<'p> {
+        CodeBuilder {
+            constant_pool: constant_pool,
+            assembler: CodeAssembler::new(),
+            limits: None,
+            error: None,
+        }
+    }
+
+    pub fn instruction(&mut self, instruction: Instruction<Label>) -> &mut CodeBuilder<'p> {
+        self.assembler.push(instruction);
+        self
+    }
+
+    pub fn new_label(&mut self) -> Label {
+        self.assembler.new_label()
+    }
+
+    /// Places `label` before the next instruction.
+    pub fn place_label(&mut self, label: Label) -> &mut CodeBuilder<'p> {
+        self.assembler.place_label(label);
+        self
+    }
+
+    /// Pushes a field instruction such as `Instruction::Getfield` on the field named `name` of
+    /// `owner`.
+    pub fn field<F>(&mut self,
+                    instruction: F,
+                    owner: &str,
+                    name: &str,
+                    descriptor: &str)
+                    -> &mut CodeBuilder<'p>
+        where F: FnOnce(U2) -> Instruction<Label>
+    {
+        let index = self.constant_pool.field(owner, name, descriptor);
+        self.push_with(index, instruction)
+    }
+
+    /// Pushes an invocation such as `Instruction::Invokevirtual` of a method of the class
+    /// `owner`.
+    pub fn method<F>(&mut self,
+                     instruction: F,
+                     owner: &str,
+                     name: &str,
+                     descriptor: &str)
+                     -> &mut CodeBuilder<'p>
+        where F: FnOnce(U2) -> Instruction<Label>
+    {
+        let index = self.constant_pool.method(owner, name, descriptor);
+        self.push_with(index, instruction)
+    }
+
+    /// Pushes an `invokeinterface` of a method of the interface `owner`.
+    pub fn interface_method(&mut self,
+                            owner: &str,
+                            name: &str,
+                            descriptor: &str)
+                            -> &mut CodeBuilder<'p> {
+        let count = match MethodDescriptor::from(descriptor) {
+            Ok(parsed) => parsed.parameter_slots() as U1 + 1,
+            Err(error) => {
+                self.error.get_or_insert(error);
+                return self;
+            }
+        };
+
+        let index = self.constant_pool.interface_method(owner, name, descriptor);
+        self.push_with(index, |index| Instruction::Invokeinterface(index, count))
+    }
+
+    /// Pushes an instruction that takes a class, such as `Instruction::New` or
+    /// `Instruction::Checkcast`.
+    pub fn class<F>(&mut self, instruction: F, name: &str) -> &mut CodeBuilder<'p>
+        where F: FnOnce(U2) -> Instruction<Label>
+    {
+        let index = self.constant_pool.class(name);
+        self.push_with(index, instruction)
+    }
+
+    /// Pushes an `ldc` of the string `value`.
+    pub fn string(&mut self, value: &str) -> &mut CodeBuilder<'p> {
+        let index = self.constant_pool.string(value);
+        self.push_with(index, ldc)
+    }
+
+    /// Pushes the `ldc`, `ldc_w` or `ldc2_w` that loads `constant`.
+    pub fn constant(&mut self, constant: Constant) -> &mut CodeBuilder<'p> {
+        let is_wide = constant.is_wide();
+        let index = self.constant_pool.intern(constant);
+        if is_wide {
+            self.push_with(index, Instruction::Ldc2W)
+        } else {
+            self.push_with(index, ldc)
+        }
+    }
+
+    /// Catches exceptions of the class `catch_type`, or any exception if it is `None`, thrown
+    /// between `start` and `end` by jumping to `handler`.
+    pub fn exception_handler(&mut self,
+                             start: Label,
+                             end: Label,
+                             handler: Label,
+                             catch_type: Option<&str>)
+                             -> &mut CodeBuilder<'p> {
+        let catch_type = match catch_type {
+            Some(name) => {
+                let index = self.constant_pool.class(name);
+                record(&mut self.error, index)
+            }
+            None => 0,
+        };
+        self.assembler.exception_handler(start, end, handler, catch_type);
+        self
+    }
+
+    pub fn line_number(&mut self, start: Label, line_number: U2) -> &mut CodeBuilder<'p> {
+        self.assembler.line_number(start, line_number);
+        self
+    }
+
+    /// Declares the operand stack and local variable sizes of the body instead of having them
+    /// computed.
+    pub fn limits(&mut self, max_stack: U2, max_locals: U2) -> &mut CodeBuilder<'p> {
+        self.limits = Some(CodeLimits {
+            max_stack: max_stack,
+            max_locals: max_locals,
+        });
+        self
+    }
+
+    fn push_with<F>(&mut self, index: ParserResult<U2>, instruction: F) -> &mut CodeBuilder<'p>
+        where F: FnOnce(U2) -> Instruction<Label>
+    {
+        if let Ok(index) = index {
+            self.assembler.push(instruction(index));
+        } else {
+            record(&mut self.error, index);
+        }
+        self
+    }
+
+    fn build(self) -> ParserResult<BuiltCode> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let assembled = try!(self.assembler.assemble());
+
+        try!(self.constant_pool.utf8("Code"));
+        let mut attributes = vec![];
+        if !assembled.line_numbers.is_empty() {
+            try!(self.constant_pool.utf8("LineNumberTable"));
+            attributes.push(Attribute::LineNumberTable(Rc::new(LineNumberTableAttribute {
+                line_number_table_length: assembled.line_numbers.len() as U2,
+                line_number_table: assembled.line_numbers,
+            })));
+        }
+        if !assembled.local_variables.is_empty() {
+            try!(self.constant_pool.utf8("LocalVariableTable"));
+            attributes.push(Attribute::LocalVariableTable(Rc::new(LocalVariableTableAttribute {
+                local_variable_table_length: assembled.local_variables.len() as U2,
+                local_variable_table: assembled.local_variables,
+            })));
+        }
+
+        let limits = self.limits.unwrap_or(CodeLimits {
+            max_stack: 0,
+            max_locals: 0,
+        });
+        let code = CodeAttribute {
+            max_stack: limits.max_stack,
+            max_locals: limits.max_locals,
+            code_length: assembled.code.len() as U4,
+            code: assembled.code,
+            exception_table_length: assembled.exception_table.len() as U2,
+            exception_table: assembled.exception_table,
+            attributes_count: attributes.len() as U2,
+            attributes: attributes,
+        };
+
+        Ok(BuiltCode {
+            code: code,
+            compute_limits: self.limits.is_none(),
+        })
+    }
+}
+
+/// Keeps the first error of a builder, giving a placeholder index in place of the one that could
+/// not be had.
+fn record(error: &mut Option<ParserError>, index: ParserResult<U2>) -> U2 {
+    match index {
+        Ok(index) => index,
+        Err(new_error) => {
+            error.get_or_insert(new_error);
+            0
+        }
+    }
+}
+
+fn ldc(index: U2) -> Instruction<Label> {
+    if index <= U1::max_value() as U2 {
+        Instruction::Ldc(index as U1)
+    } else {
+        Instruction::LdcW(index)
+    }
+}
+
+fn utf8_info(value: &str) -> Rc<Utf8Info> {
+    Rc::new(Utf8Info {
+        tag: 1,
+        length: mutf8::encoded_len(value) as U2,
+        value: value.to_string(),
+        bytes: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::ClassBuilder;
+    use super::super::{ClassFile, ParserError};
+    use super::super::access::{ClassAccess, FieldAccess, MethodAccess};
+    use super::super::components::{Attribute, CodeAttribute, ConstantPoolItem};
+    use super::super::hierarchy::{ClassFileHierarchy, ObjectHierarchy};
+    use super::super::instructions::Instruction;
+    use super::super::verifier;
+
+    use std::collections::HashSet;
+
+    fn counter() -> ClassBuilder<'static> {
+        ClassBuilder::new("com/example/Counter")
+            .implements("java/lang/Runnable")
+            .field(FieldAccess::PRIVATE, "count", "I")
+            .method(MethodAccess::PUBLIC, "<init>", "()V", |code| {
+                code.instruction(Instruction::Aload0)
+                    .method(Instruction::Invokespecial, "java/lang/Object", "<init>", "()V")
+                    .instruction(Instruction::Return);
+            })
+            .method(MethodAccess::PUBLIC, "run", "()V", |code| {
+                code.instruction(Instruction::Aload0)
+                    .instruction(Instruction::Dup)
+                    .field(Instruction::Getfield, "com/example/Counter", "count", "I")
+                    .instruction(Instruction::Iconst1)
+                    .instruction(Instruction::Iadd)
+                    .field(Instruction::Putfield, "com/example/Counter", "count", "I")
+                    .instruction(Instruction::Return);
+            })
+            .source_file("Counter.java")
+    }
+
+    #[test]
+    fn builds_classes_with_interned_constants() {
+        let classfile = counter().build().unwrap();
+        let resolver = classfile.constant_pool_resolver();
+
+        assert_that(&classfile.classname().unwrap().as_str()).is_equal_to(&"com/example/Counter");
+        assert_that(&resolver.resolve_class_name(classfile.super_class).unwrap().as_str())
+            .is_equal_to(&"java/lang/Object");
+        assert_that(&resolver.resolve_class_name(classfile.interfaces[0]).unwrap().as_str())
+            .is_equal_to(&"java/lang/Runnable");
+        assert_that(&classfile.access()).is_equal_to(&(ClassAccess::PUBLIC | ClassAccess::SUPER));
+        assert_that(&classfile.fields_count).is_equal_to(&1);
+        assert_that(&classfile.methods_count).is_equal_to(&2);
+        assert_that(&classfile.constant_pool_count)
+            .is_equal_to(&(classfile.constant_pool.len() as u16 + 1));
+
+        let mut values = HashSet::new();
+        for item in &classfile.constant_pool {
+            if let ConstantPoolItem::Utf8(ref info) = *item {
+                assert_that(&values.insert(info.value.clone())).is_equal_to(&true);
+            }
+        }
+        assert_that(&values.contains("Code")).is_equal_to(&true);
+
+        let run = classfile.maybe_resolve_method("run").unwrap().code().unwrap();
+        assert_that(&run.max_stack).is_equal_to(&3);
+        assert_that(&run.max_locals).is_equal_to(&1);
+
+        // every name is in the pool already, so writing the class adds nothing to it
+        let bytes = counter().to_bytes().unwrap();
+        assert_that(&classfile.to_bytes().unwrap()).is_equal_to(&bytes);
+        let parsed = ClassFile::parse(&bytes).unwrap();
+        assert_that(&parsed.constant_pool_count).is_equal_to(&classfile.constant_pool_count);
+        assert_that(&verifier::verify(&parsed, &ObjectHierarchy).unwrap()).has_length(0);
+    }
+
+    #[test]
+    fn builds_method_bodies_with_labels_and_handlers() {
+        let classfile = ClassBuilder::new("Branches")
+            .method(MethodAccess::PUBLIC | MethodAccess::STATIC, "max", "(II)I", |code| {
+                let second = code.new_label();
+                code.instruction(Instruction::Iload0)
+                    .instruction(Instruction::Iload1)
+                    .instruction(Instruction::IfIcmplt(second))
+                    .instruction(Instruction::Iload0)
+                    .instruction(Instruction::Ireturn)
+                    .place_label(second)
+                    .instruction(Instruction::Iload1)
+                    .instruction(Instruction::Ireturn);
+            })
+            .method(MethodAccess::PUBLIC | MethodAccess::STATIC,
+                    "parse",
+                    "(Ljava/lang/String;)I",
+                    |code| {
+                let (start, end, handler) = (code.new_label(), code.new_label(), code.new_label());
+                code.place_label(start)
+                    .instruction(Instruction::Aload0)
+                    .method(Instruction::Invokestatic,
+                            "java/lang/Integer",
+                            "parseInt",
+                            "(Ljava/lang/String;)I")
+                    .place_label(end)
+                    .instruction(Instruction::Ireturn)
+                    .place_label(handler)
+                    .instruction(Instruction::Astore1)
+                    .instruction(Instruction::IconstM1)
+                    .instruction(Instruction::Ireturn)
+                    .exception_handler(start, end, handler, Some("java/lang/Throwable"))
+                    .line_number(start, 7)
+                    .limits(4, 4);
+            })
+            .build()
+            .unwrap();
+
+        let max = classfile.maybe_resolve_method("max").unwrap().code().unwrap();
+        assert_that(&max.max_stack).is_equal_to(&2);
+        assert_that(&max.max_locals).is_equal_to(&2);
+        assert_that(&stack_map_frames(&max)).is_equal_to(&Some(1));
+
+        let parse = classfile.maybe_resolve_method("parse").unwrap().code().unwrap();
+        assert_that(&parse.max_stack).is_equal_to(&4);
+        assert_that(&parse.exception_table).has_length(1);
+        assert_that(&parse.attributes).has_length(2);
+        assert_that(&stack_map_frames(&parse)).is_equal_to(&Some(1));
+
+        assert_that(&verifier::verify(&classfile, &ObjectHierarchy).unwrap()).has_length(0);
+    }
+
+    #[test]
+    fn computes_frames_with_the_given_hierarchy() {
+        let mut hierarchy = ClassFileHierarchy::new();
+        hierarchy.add_class("java/lang/Exception", Some("java/lang/Object"), false);
+        hierarchy.add_class("java/io/IOException", Some("java/lang/Exception"), false);
+        hierarchy.add_class("java/lang/RuntimeException", Some("java/lang/Exception"), false);
+
+        let either = |builder: ClassBuilder| {
+            builder.method(MethodAccess::PUBLIC | MethodAccess::STATIC,
+                           "either",
+                           "(ZLjava/io/IOException;Ljava/lang/RuntimeException;)\
+                            Ljava/lang/Exception;",
+                           |code| {
+                    let (second, end) = (code.new_label(), code.new_label());
+                    code.instruction(Instruction::Iload0)
+                        .instruction(Instruction::Ifeq(second))
+                        .instruction(Instruction::Aload1)
+                        .instruction(Instruction::Goto(end))
+                        .place_label(second)
+                        .instruction(Instruction::Aload2)
+                        .place_label(end)
+                        .instruction(Instruction::Areturn);
+                })
+                .build()
+                .unwrap()
+        };
+
+        // the branches merge to `java/lang/Object`, which can't be returned as an exception
+        let classfile = either(ClassBuilder::new("Either"));
+        assert_that(&verifier::verify(&classfile, &hierarchy).unwrap()).has_length(1);
+
+        let classfile = either(ClassBuilder::new("Either").compute_frames(&hierarchy));
+        assert_that(&verifier::verify(&classfile, &hierarchy).unwrap()).has_length(0);
+
+        let classfile = either(ClassBuilder::new("Either").version(49, 0));
+        let code = classfile.maybe_resolve_method("either").unwrap().code().unwrap();
+        assert_that(&stack_map_frames(&code)).is_equal_to(&None);
+    }
+
+    fn stack_map_frames(code: &CodeAttribute) -> Option<usize> {
+        code.attributes
+            .iter()
+            .filter_map(|attribute| match *attribute {
+                Attribute::StackMapTable(ref table) => Some(table.entries.len()),
+                _ => None,
+            })
+            .next()
+    }
+
+    #[test]
+    fn reports_interning_errors_when_built() {
+        let name = "x".repeat(70000);
+        let result = ClassBuilder::new("Long")
+            .field(FieldAccess::PUBLIC, &name, "I")
+            .build();
+
+        assert_that(&result).matches(|result| match *result {
+            Err(ParserError::StringTooLong(70000)) => true,
+            _ => false,
+        });
+    }
+}
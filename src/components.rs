@@ -1,7 +1,14 @@
 use super::{ParserError, ParserResult};
-use super::primitives::{PrimitiveIterator, U1, U2, U4};
-
-use std::ops::Deref;
+use super::access::{ClassAccess, FieldAccess, MethodAccess};
+use super::descriptors::{FieldType, MethodDescriptor};
+use super::instructions::InstructionIterator;
+use super::mutf8;
+use super::pool::ConstantPool;
+use super::primitives::{ByteCursor, PrimitiveIterator, PrimitiveWriter, U1, U2, U4};
+
+use std::cell::RefCell;
+use std::fmt;
+use std::ops::{Deref, Range};
 use std::rc::Rc;
 
 macro_rules! generate_constant_pool_retrieval_method {
@@ -9,7 +16,7 @@ macro_rules! generate_constant_pool_retrieval_method {
         pub fn $method_name(index: U2,
                             constant_pool: &Vec<ConstantPoolItem>)
             -> ParserResult<Rc<$struct_name>> {
-                let actual_index = ConstantPoolItem::shift_index(index as usize);
+                let actual_index = try!(ConstantPoolItem::shift_index(index as usize));
 
                 if let Some(item) = constant_pool.get(actual_index) {
                     match item {
@@ -71,6 +78,9 @@ pub struct Utf8Info {
     pub tag: U1,
     pub length: U2,
     pub value: String,
+    /// The bytes the entry was read from, kept only when encoding `value` doesn't give them
+    /// back, such as when they hold a surrogate without its other half.
+    pub bytes: Option<Vec<U1>>,
 }
 
 impl Utf8Info {
@@ -87,7 +97,7 @@ impl Deref for Utf8Info {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ConstantPoolItem {
     Empty,
     Class(Rc<ClassInfo>),
@@ -122,17 +132,14 @@ impl ConstantPoolItem {
             1 => {
                 let length = try!(iter.next_u2());
 
-                let mut byte_vec = vec![];
-                for _ in 0..length {
-                    byte_vec.push(try!(iter.next_u1()));
-                }
-
-                let value = try!(String::from_utf8(byte_vec));
+                let bytes = try!(iter.next_bytes(length as usize));
+                let (value, exact) = try!(mutf8::decode(&bytes));
 
                 Ok(ConstantPoolItem::Utf8(Rc::new(Utf8Info {
                     tag: tag,
                     length: length,
                     value: value,
+                    bytes: if exact { None } else { Some(bytes) },
                 })))
             }
             3 => {
@@ -201,6 +208,26 @@ impl ConstantPoolItem {
                     descriptor_index: try!(iter.next_u2()),
                 })))
             }
+            15 => {
+                Ok(ConstantPoolItem::MethodHandle {
+                    tag: tag,
+                    reference_kind: try!(iter.next_u1()),
+                    reference_index: try!(iter.next_u2()),
+                })
+            }
+            16 => {
+                Ok(ConstantPoolItem::MethodType {
+                    tag: tag,
+                    descriptor_index: try!(iter.next_u2()),
+                })
+            }
+            18 => {
+                Ok(ConstantPoolItem::InvokeDynamic {
+                    tag: tag,
+                    bootstrap_method_attr_index: try!(iter.next_u2()),
+                    name_and_type_index: try!(iter.next_u2()),
+                })
+            }
             _ => Err(ParserError::UnknownConstantPoolTag(tag)),
         }
     }
@@ -217,15 +244,19 @@ impl ConstantPoolItem {
             &ConstantPoolItem::InterfaceMethod(..) => "InterfaceMethod",
             &ConstantPoolItem::Integer(..) => "Integer",
             &ConstantPoolItem::Float(..) => "Float",
+            &ConstantPoolItem::Long(..) => "Long",
+            &ConstantPoolItem::Double(..) => "Double",
             &ConstantPoolItem::NameAndType(..) => "NameAndType",
-            _ => "Not yet implemented",
+            &ConstantPoolItem::MethodHandle { .. } => "MethodHandle",
+            &ConstantPoolItem::MethodType { .. } => "MethodType",
+            &ConstantPoolItem::InvokeDynamic { .. } => "InvokeDynamic",
         }
     }
 
     pub fn retrieve_item(index: usize,
                          constant_pool: &Vec<ConstantPoolItem>)
                          -> ParserResult<&ConstantPoolItem> {
-        let actual_index = Self::shift_index(index);
+        let actual_index = try!(Self::shift_index(index));
         return constant_pool.get(actual_index)
             .ok_or(ParserError::ConstantPoolIndexOutOfBounds(actual_index));
     }
@@ -250,8 +281,10 @@ impl ConstantPoolItem {
                                              NameAndTypeInfo,
                                              retrieve_name_and_type_info);
 
-    fn shift_index(unshifted_index: usize) -> usize {
-        unshifted_index - 1 // references to the constant pool start from one
+    fn shift_index(unshifted_index: usize) -> ParserResult<usize> {
+        // references to the constant pool start from one
+        unshifted_index.checked_sub(1)
+            .ok_or(ParserError::InvalidConstantPoolIndex(unshifted_index))
     }
 }
 
@@ -269,9 +302,144 @@ impl<'r> ConstantPoolResolver<'r> {
 
         Ok(utf8_info.to_string())
     }
+
+    pub fn resolve_class_name(&self, index: U2) -> ParserResult<Rc<Utf8Info>> {
+        let class_info = try!(ConstantPoolItem::retrieve_class_info(index, &self.constant_pool));
+
+        ConstantPoolItem::retrieve_utf8_info(class_info.name_index, &self.constant_pool)
+    }
+
+    pub fn resolve_name_and_type(&self,
+                                 index: U2)
+                                 -> ParserResult<(Rc<Utf8Info>, Rc<Utf8Info>)> {
+        let name_and_type = try!(ConstantPoolItem::retrieve_name_and_type_info(index,
+                                                                              &self.constant_pool));
+
+        let name = try!(ConstantPoolItem::retrieve_utf8_info(name_and_type.name_index,
+                                                             &self.constant_pool));
+        let descriptor = try!(ConstantPoolItem::retrieve_utf8_info(name_and_type.descriptor_index,
+                                                                   &self.constant_pool));
+
+        Ok((name, descriptor))
+    }
+
+    /// Resolves a `Fieldref`, `Methodref` or `InterfaceMethodref` entry.
+    pub fn resolve_member_reference(&self, index: U2) -> ParserResult<MemberReference> {
+        let member_info = match try!(ConstantPoolItem::retrieve_item(index as usize,
+                                                                     &self.constant_pool)) {
+            &ConstantPoolItem::Field(ref info) |
+            &ConstantPoolItem::Method(ref info) |
+            &ConstantPoolItem::InterfaceMethod(ref info) => info.clone(),
+            item @ _ => {
+                return Err(ParserError::UnexpectedConstantPoolItem(item.to_friendly_name()))
+            }
+        };
+
+        let class_name = try!(self.resolve_class_name(member_info.class_index));
+        let (name, descriptor) = try!(self.resolve_name_and_type(member_info.name_and_type_index));
+
+        Ok(MemberReference {
+            class_name: class_name,
+            name: name,
+            descriptor: descriptor,
+        })
+    }
+
+    pub fn resolve_invoke_dynamic(&self, index: U2) -> ParserResult<InvokeDynamicReference> {
+        match try!(ConstantPoolItem::retrieve_item(index as usize, &self.constant_pool)) {
+            &ConstantPoolItem::InvokeDynamic { bootstrap_method_attr_index,
+                                               name_and_type_index,
+                                               .. } => {
+                let (name, descriptor) = try!(self.resolve_name_and_type(name_and_type_index));
+
+                Ok(InvokeDynamicReference {
+                    bootstrap_method_attr_index: bootstrap_method_attr_index,
+                    name: name,
+                    descriptor: descriptor,
+                })
+            }
+            item @ _ => Err(ParserError::UnexpectedConstantPoolItem(item.to_friendly_name())),
+        }
+    }
+
+    /// Resolves an entry that can be pushed onto the operand stack by `ldc`, `ldc_w` or
+    /// `ldc2_w`.
+    pub fn resolve_loadable_constant(&self, index: U2) -> ParserResult<LoadableConstant> {
+        let item = try!(ConstantPoolItem::retrieve_item(index as usize, &self.constant_pool));
+
+        let constant = match item {
+            &ConstantPoolItem::Integer(ref info) => LoadableConstant::Integer(info.bytes as i32),
+            &ConstantPoolItem::Float(ref info) => {
+                LoadableConstant::Float(f32::from_bits(info.bytes))
+            }
+            &ConstantPoolItem::Long(ref info) => {
+                LoadableConstant::Long(((info.high_bytes as u64) << 32 |
+                                        info.low_bytes as u64) as i64)
+            }
+            &ConstantPoolItem::Double(ref info) => {
+                let bits = (info.high_bytes as u64) << 32 | info.low_bytes as u64;
+                LoadableConstant::Double(f64::from_bits(bits))
+            }
+            &ConstantPoolItem::String(ref info) => {
+                LoadableConstant::String(try!(ConstantPoolItem::retrieve_utf8_info(
+                    info.string_index,
+                    &self.constant_pool)))
+            }
+            &ConstantPoolItem::Class(ref info) => {
+                LoadableConstant::Class(try!(ConstantPoolItem::retrieve_utf8_info(
+                    info.name_index,
+                    &self.constant_pool)))
+            }
+            &ConstantPoolItem::MethodType { descriptor_index, .. } => {
+                LoadableConstant::MethodType(try!(ConstantPoolItem::retrieve_utf8_info(
+                    descriptor_index,
+                    &self.constant_pool)))
+            }
+            &ConstantPoolItem::MethodHandle { reference_kind, reference_index, .. } => {
+                LoadableConstant::MethodHandle {
+                    reference_kind: reference_kind,
+                    reference: try!(self.resolve_member_reference(reference_index)),
+                }
+            }
+            item @ _ => {
+                return Err(ParserError::UnexpectedConstantPoolItem(item.to_friendly_name()))
+            }
+        };
+
+        Ok(constant)
+    }
+}
+
+#[derive(Debug)]
+pub struct MemberReference {
+    pub class_name: Rc<Utf8Info>,
+    pub name: Rc<Utf8Info>,
+    pub descriptor: Rc<Utf8Info>,
+}
+
+#[derive(Debug)]
+pub struct InvokeDynamicReference {
+    pub bootstrap_method_attr_index: U2,
+    pub name: Rc<Utf8Info>,
+    pub descriptor: Rc<Utf8Info>,
 }
 
 #[derive(Debug)]
+pub enum LoadableConstant {
+    Integer(i32),
+    Float(f32),
+    Long(i64),
+    Double(f64),
+    String(Rc<Utf8Info>),
+    Class(Rc<Utf8Info>),
+    MethodType(Rc<Utf8Info>),
+    MethodHandle {
+        reference_kind: U1,
+        reference: MemberReference,
+    },
+}
+
+#[derive(Clone, Debug, Default)]
 pub struct CodeAttribute {
     pub max_stack: U2,
     pub max_locals: U2,
@@ -291,10 +459,7 @@ impl CodeAttribute {
         let max_locals = try!(iter.next_u2());
 
         let code_length = try!(iter.next_u4());
-        let mut code = vec![];
-        for _ in 0..code_length {
-            code.push(try!(iter.next_u1()));
-        }
+        let code = try!(iter.next_bytes(code_length as usize));
 
         let exception_table_length = try!(iter.next_u2());
         let mut exception_table = vec![];
@@ -305,7 +470,7 @@ impl CodeAttribute {
         let attributes_count = try!(iter.next_u2());
         let mut attributes = vec![];
         for _ in 0..attributes_count {
-            attributes.push(try!(Attribute::from(iter, constant_pool)));
+            attributes.push(try!(Attribute::from_code(iter, constant_pool)));
         }
 
         Ok(CodeAttribute {
@@ -319,9 +484,31 @@ impl CodeAttribute {
             attributes: attributes,
         })
     }
+
+    pub fn instructions(&self) -> InstructionIterator {
+        InstructionIterator::new(&self.code)
+    }
 }
 
-#[derive(Debug)]
+#[cfg(test)]
+impl CodeAttribute {
+    /// Wraps `code` for the tests of the analyses, which only look at the instructions and the
+    /// exception table. There is room for two values on the stack and four locals.
+    pub fn for_test(code: Vec<U1>, exception_table: Vec<ExceptionHandler>) -> CodeAttribute {
+        CodeAttribute {
+            max_stack: 2,
+            max_locals: 4,
+            code_length: code.len() as U4,
+            code: code,
+            exception_table_length: exception_table.len() as U2,
+            exception_table: exception_table,
+            attributes_count: 0,
+            attributes: vec![],
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct ExceptionHandler {
     pub start_pc: U2,
     pub end_pc: U2,
@@ -343,35 +530,417 @@ impl ExceptionHandler {
             catch_type: catch_type,
         })
     }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u2(self.start_pc);
+        writer.write_u2(self.end_pc);
+        writer.write_u2(self.handler_pc);
+        writer.write_u2(self.catch_type);
+    }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineNumber {
+    pub start_pc: U2,
+    pub line_number: U2,
+}
+
+impl LineNumber {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<LineNumber> {
+        let start_pc = try!(iter.next_u2());
+        let line_number = try!(iter.next_u2());
+
+        Ok(LineNumber {
+            start_pc: start_pc,
+            line_number: line_number,
+        })
+    }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u2(self.start_pc);
+        writer.write_u2(self.line_number);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LineNumberTableAttribute {
+    pub line_number_table_length: U2,
+    pub line_number_table: Vec<LineNumber>,
+}
+
+impl LineNumberTableAttribute {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<LineNumberTableAttribute> {
+        let line_number_table_length = try!(iter.next_u2());
+        let mut line_number_table = vec![];
+        for _ in 0..line_number_table_length {
+            line_number_table.push(try!(LineNumber::from(iter)));
+        }
+
+        Ok(LineNumberTableAttribute {
+            line_number_table_length: line_number_table_length,
+            line_number_table: line_number_table,
+        })
+    }
+}
+
+/// An entry of either a `LocalVariableTable` or a `LocalVariableTypeTable`. For the latter,
+/// `descriptor_index` refers to the generic signature of the variable.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LocalVariable {
+    pub start_pc: U2,
+    pub length: U2,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+    pub index: U2,
+}
+
+impl LocalVariable {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<LocalVariable> {
+        let start_pc = try!(iter.next_u2());
+        let length = try!(iter.next_u2());
+        let name_index = try!(iter.next_u2());
+        let descriptor_index = try!(iter.next_u2());
+        let index = try!(iter.next_u2());
+
+        Ok(LocalVariable {
+            start_pc: start_pc,
+            length: length,
+            name_index: name_index,
+            descriptor_index: descriptor_index,
+            index: index,
+        })
+    }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u2(self.start_pc);
+        writer.write_u2(self.length);
+        writer.write_u2(self.name_index);
+        writer.write_u2(self.descriptor_index);
+        writer.write_u2(self.index);
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct LocalVariableTableAttribute {
+    pub local_variable_table_length: U2,
+    pub local_variable_table: Vec<LocalVariable>,
+}
+
+impl LocalVariableTableAttribute {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<LocalVariableTableAttribute> {
+        let local_variable_table_length = try!(iter.next_u2());
+        let mut local_variable_table = vec![];
+        for _ in 0..local_variable_table_length {
+            local_variable_table.push(try!(LocalVariable::from(iter)));
+        }
+
+        Ok(LocalVariableTableAttribute {
+            local_variable_table_length: local_variable_table_length,
+            local_variable_table: local_variable_table,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerificationTypeInfo {
+    Top,
+    Integer,
+    Float,
+    Double,
+    Long,
+    Null,
+    UninitializedThis,
+    Object(U2),
+    Uninitialized(U2),
+}
+
+impl VerificationTypeInfo {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<VerificationTypeInfo> {
+        let tag = try!(iter.next_u1());
+
+        match tag {
+            0 => Ok(VerificationTypeInfo::Top),
+            1 => Ok(VerificationTypeInfo::Integer),
+            2 => Ok(VerificationTypeInfo::Float),
+            3 => Ok(VerificationTypeInfo::Double),
+            4 => Ok(VerificationTypeInfo::Long),
+            5 => Ok(VerificationTypeInfo::Null),
+            6 => Ok(VerificationTypeInfo::UninitializedThis),
+            7 => Ok(VerificationTypeInfo::Object(try!(iter.next_u2()))),
+            8 => Ok(VerificationTypeInfo::Uninitialized(try!(iter.next_u2()))),
+            _ => Err(ParserError::UnknownVerificationType(tag)),
+        }
+    }
+
+    pub fn tag(&self) -> U1 {
+        match *self {
+            VerificationTypeInfo::Top => 0,
+            VerificationTypeInfo::Integer => 1,
+            VerificationTypeInfo::Float => 2,
+            VerificationTypeInfo::Double => 3,
+            VerificationTypeInfo::Long => 4,
+            VerificationTypeInfo::Null => 5,
+            VerificationTypeInfo::UninitializedThis => 6,
+            VerificationTypeInfo::Object(..) => 7,
+            VerificationTypeInfo::Uninitialized(..) => 8,
+        }
+    }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u1(self.tag());
+        match *self {
+            VerificationTypeInfo::Object(index) => writer.write_u2(index),
+            VerificationTypeInfo::Uninitialized(offset) => writer.write_u2(offset),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum StackMapFrame {
+    Same { frame_type: U1 },
+    SameLocals1StackItem {
+        frame_type: U1,
+        stack: VerificationTypeInfo,
+    },
+    SameLocals1StackItemExtended {
+        offset_delta: U2,
+        stack: VerificationTypeInfo,
+    },
+    Chop { frame_type: U1, offset_delta: U2 },
+    SameExtended { offset_delta: U2 },
+    Append {
+        frame_type: U1,
+        offset_delta: U2,
+        locals: Vec<VerificationTypeInfo>,
+    },
+    Full {
+        offset_delta: U2,
+        locals: Vec<VerificationTypeInfo>,
+        stack: Vec<VerificationTypeInfo>,
+    },
+}
+
+impl StackMapFrame {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<StackMapFrame> {
+        let frame_type = try!(iter.next_u1());
+
+        match frame_type {
+            0..=63 => Ok(StackMapFrame::Same { frame_type: frame_type }),
+            64..=127 => {
+                Ok(StackMapFrame::SameLocals1StackItem {
+                    frame_type: frame_type,
+                    stack: try!(VerificationTypeInfo::from(iter)),
+                })
+            }
+            247 => {
+                let offset_delta = try!(iter.next_u2());
+                Ok(StackMapFrame::SameLocals1StackItemExtended {
+                    offset_delta: offset_delta,
+                    stack: try!(VerificationTypeInfo::from(iter)),
+                })
+            }
+            248..=250 => {
+                Ok(StackMapFrame::Chop {
+                    frame_type: frame_type,
+                    offset_delta: try!(iter.next_u2()),
+                })
+            }
+            251 => Ok(StackMapFrame::SameExtended { offset_delta: try!(iter.next_u2()) }),
+            252..=254 => {
+                let offset_delta = try!(iter.next_u2());
+                let mut locals = vec![];
+                for _ in 0..(frame_type - 251) {
+                    locals.push(try!(VerificationTypeInfo::from(iter)));
+                }
+
+                Ok(StackMapFrame::Append {
+                    frame_type: frame_type,
+                    offset_delta: offset_delta,
+                    locals: locals,
+                })
+            }
+            255 => {
+                let offset_delta = try!(iter.next_u2());
+
+                let number_of_locals = try!(iter.next_u2());
+                let mut locals = vec![];
+                for _ in 0..number_of_locals {
+                    locals.push(try!(VerificationTypeInfo::from(iter)));
+                }
+
+                let number_of_stack_items = try!(iter.next_u2());
+                let mut stack = vec![];
+                for _ in 0..number_of_stack_items {
+                    stack.push(try!(VerificationTypeInfo::from(iter)));
+                }
+
+                Ok(StackMapFrame::Full {
+                    offset_delta: offset_delta,
+                    locals: locals,
+                    stack: stack,
+                })
+            }
+            _ => Err(ParserError::UnknownStackMapFrameType(frame_type)),
+        }
+    }
+
+    pub fn frame_type(&self) -> U1 {
+        match *self {
+            StackMapFrame::Same { frame_type } |
+            StackMapFrame::SameLocals1StackItem { frame_type, .. } |
+            StackMapFrame::Chop { frame_type, .. } |
+            StackMapFrame::Append { frame_type, .. } => frame_type,
+            StackMapFrame::SameLocals1StackItemExtended { .. } => 247,
+            StackMapFrame::SameExtended { .. } => 251,
+            StackMapFrame::Full { .. } => 255,
+        }
+    }
+
+    pub fn offset_delta(&self) -> U2 {
+        match *self {
+            StackMapFrame::Same { frame_type } => frame_type as U2,
+            StackMapFrame::SameLocals1StackItem { frame_type, .. } => (frame_type - 64) as U2,
+            StackMapFrame::SameLocals1StackItemExtended { offset_delta, .. } |
+            StackMapFrame::Chop { offset_delta, .. } |
+            StackMapFrame::SameExtended { offset_delta } |
+            StackMapFrame::Append { offset_delta, .. } |
+            StackMapFrame::Full { offset_delta, .. } => offset_delta,
+        }
+    }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u1(self.frame_type());
+
+        match *self {
+            StackMapFrame::Same { .. } => {}
+            StackMapFrame::SameLocals1StackItem { ref stack, .. } => stack.write(writer),
+            StackMapFrame::SameLocals1StackItemExtended { offset_delta, ref stack } => {
+                writer.write_u2(offset_delta);
+                stack.write(writer);
+            }
+            StackMapFrame::Chop { offset_delta, .. } |
+            StackMapFrame::SameExtended { offset_delta } => writer.write_u2(offset_delta),
+            StackMapFrame::Append { offset_delta, ref locals, .. } => {
+                writer.write_u2(offset_delta);
+                for local in locals {
+                    local.write(writer);
+                }
+            }
+            StackMapFrame::Full { offset_delta, ref locals, ref stack } => {
+                writer.write_u2(offset_delta);
+                writer.write_u2(locals.len() as U2);
+                for local in locals {
+                    local.write(writer);
+                }
+                writer.write_u2(stack.len() as U2);
+                for item in stack {
+                    item.write(writer);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StackMapTableAttribute {
+    pub number_of_entries: U2,
+    pub entries: Vec<StackMapFrame>,
+}
+
+impl StackMapTableAttribute {
+    pub fn from<T: PrimitiveIterator>(iter: &mut T) -> ParserResult<StackMapTableAttribute> {
+        let number_of_entries = try!(iter.next_u2());
+        let mut entries = vec![];
+        for _ in 0..number_of_entries {
+            entries.push(try!(StackMapFrame::from(iter)));
+        }
+
+        Ok(StackMapTableAttribute {
+            number_of_entries: number_of_entries,
+            entries: entries,
+        })
+    }
+}
+
+#[derive(Clone, Debug)]
 pub enum Attribute {
     Code(Rc<CodeAttribute>),
+    LineNumberTable(Rc<LineNumberTableAttribute>),
+    LocalVariableTable(Rc<LocalVariableTableAttribute>),
+    LocalVariableTypeTable(Rc<LocalVariableTableAttribute>),
+    StackMapTable(Rc<StackMapTableAttribute>),
     Unknown {
         attribute_name: Rc<Utf8Info>,
         info: Vec<U1>,
     },
+    /// An attribute of a field or method that is decoded the first time it is needed.
+    Lazy(Rc<LazyAttribute>),
 }
 
 impl Attribute {
     pub fn from<T: PrimitiveIterator>(iter: &mut T,
                                       constant_pool: &Vec<ConstantPoolItem>)
                                       -> ParserResult<Attribute> {
+        Attribute::read(iter, constant_pool, false)
+    }
+
+    /// Parses an attribute nested in a `Code` attribute. The debug tables only belong there and
+    /// are kept as unknown attributes anywhere else.
+    pub fn from_code<T: PrimitiveIterator>(iter: &mut T,
+                                           constant_pool: &Vec<ConstantPoolItem>)
+                                           -> ParserResult<Attribute> {
+        Attribute::read(iter, constant_pool, true)
+    }
+
+    fn read<T: PrimitiveIterator>(iter: &mut T,
+                                  constant_pool: &Vec<ConstantPoolItem>,
+                                  in_code: bool)
+                                  -> ParserResult<Attribute> {
         let attribute_name_index = try!(iter.next_u2());
         let attribute_name = try!(ConstantPoolItem::retrieve_utf8_info(attribute_name_index,
                                                                        constant_pool));
 
         let attribute_length = try!(iter.next_u4());
-
-        match &**attribute_name {
-            "Code" => Ok(Attribute::Code(Rc::new(try!(CodeAttribute::from(iter, constant_pool))))),
-            _ => {
-                let mut info = vec![];
-                for _ in 0..attribute_length {
-                    info.push(try!(iter.next_u1()));
+        let info = try!(iter.next_bytes(attribute_length as usize));
+
+        // a known attribute is decoded from its own body only, which it has to use up
+        let attribute = {
+            let mut body = ByteCursor::new(&info);
+            let attribute = match (&**attribute_name, in_code) {
+                ("Code", _) => {
+                    Some(Attribute::Code(Rc::new(try!(CodeAttribute::from(&mut body,
+                                                                           constant_pool)))))
+                }
+                ("LineNumberTable", true) => {
+                    let table = try!(LineNumberTableAttribute::from(&mut body));
+                    Some(Attribute::LineNumberTable(Rc::new(table)))
+                }
+                ("LocalVariableTable", true) => {
+                    let table = try!(LocalVariableTableAttribute::from(&mut body));
+                    Some(Attribute::LocalVariableTable(Rc::new(table)))
+                }
+                ("LocalVariableTypeTable", true) => {
+                    let table = try!(LocalVariableTableAttribute::from(&mut body));
+                    Some(Attribute::LocalVariableTypeTable(Rc::new(table)))
                 }
+                ("StackMapTable", true) => {
+                    let table = try!(StackMapTableAttribute::from(&mut body));
+                    Some(Attribute::StackMapTable(Rc::new(table)))
+                }
+                _ => None,
+            };
+
+            if attribute.is_some() && body.remaining() != 0 {
+                return Err(ParserError::AttributeLengthMismatch(attribute_name.to_string()));
+            }
+
+            attribute
+        };
 
+        match attribute {
+            Some(attribute) => Ok(attribute),
+            None => {
                 Ok(Attribute::Unknown {
                     attribute_name: attribute_name,
                     info: info,
@@ -379,6 +948,166 @@ impl Attribute {
             }
         }
     }
+
+    /// The name the attribute is stored under.
+    pub fn name(&self) -> &str {
+        match *self {
+            Attribute::Code(..) => "Code",
+            Attribute::LineNumberTable(..) => "LineNumberTable",
+            Attribute::LocalVariableTable(..) => "LocalVariableTable",
+            Attribute::LocalVariableTypeTable(..) => "LocalVariableTypeTable",
+            Attribute::StackMapTable(..) => "StackMapTable",
+            Attribute::Unknown { ref attribute_name, .. } => attribute_name.as_str(),
+            Attribute::Lazy(ref lazy) => lazy.attribute_name.as_str(),
+        }
+    }
+
+    /// Writes the attribute headed by the index of its name and its length. `name_index` gives
+    /// the constant pool index of an attribute name, both for this attribute and for any nested
+    /// in it. A lazily parsed attribute is written exactly as it was read, since its body refers
+    /// to the constant pool it was read against anyway.
+    pub fn write<W, F>(&self, writer: &mut W, name_index: &mut F) -> ParserResult<()>
+        where W: PrimitiveWriter,
+              F: FnMut(&str) -> ParserResult<U2>
+    {
+        if let Attribute::Lazy(ref lazy) = *self {
+            writer.write_bytes(lazy.bytes());
+            return Ok(());
+        }
+
+        let mut info = vec![];
+        try!(self.write_info(&mut info, name_index));
+
+        writer.write_u2(try!(name_index(self.name())));
+        writer.write_u4(info.len() as U4);
+        writer.write_bytes(&info);
+
+        Ok(())
+    }
+
+    /// Writes the body of the attribute, which follows its name and length. Table lengths are
+    /// those of the tables themselves.
+    pub fn write_info<W, F>(&self, writer: &mut W, name_index: &mut F) -> ParserResult<()>
+        where W: PrimitiveWriter,
+              F: FnMut(&str) -> ParserResult<U2>
+    {
+        match *self {
+            Attribute::Code(ref code) => {
+                writer.write_u2(code.max_stack);
+                writer.write_u2(code.max_locals);
+                writer.write_u4(code.code.len() as U4);
+                writer.write_bytes(&code.code);
+
+                writer.write_u2(code.exception_table.len() as U2);
+                for handler in &code.exception_table {
+                    handler.write(writer);
+                }
+
+                writer.write_u2(code.attributes.len() as U2);
+                for attribute in &code.attributes {
+                    try!(attribute.write(writer, name_index));
+                }
+            }
+            Attribute::LineNumberTable(ref table) => {
+                writer.write_u2(table.line_number_table.len() as U2);
+                for line_number in &table.line_number_table {
+                    line_number.write(writer);
+                }
+            }
+            Attribute::LocalVariableTable(ref table) |
+            Attribute::LocalVariableTypeTable(ref table) => {
+                writer.write_u2(table.local_variable_table.len() as U2);
+                for local_variable in &table.local_variable_table {
+                    local_variable.write(writer);
+                }
+            }
+            Attribute::StackMapTable(ref table) => {
+                writer.write_u2(table.entries.len() as U2);
+                for frame in &table.entries {
+                    frame.write(writer);
+                }
+            }
+            Attribute::Unknown { ref info, .. } => writer.write_bytes(info),
+            Attribute::Lazy(ref lazy) => writer.write_bytes(lazy.info()),
+        }
+
+        Ok(())
+    }
+}
+
+/// An attribute recorded as the range of bytes it takes up in a class file, which is decoded
+/// against the constant pool of the class on first access and kept from then on.
+pub struct LazyAttribute {
+    pub attribute_name: Rc<Utf8Info>,
+    source: Rc<Vec<U1>>,
+    range: Range<usize>,
+    constant_pool: Rc<Vec<ConstantPoolItem>>,
+    decoded: RefCell<Option<Rc<Attribute>>>,
+}
+
+impl LazyAttribute {
+    /// Records the attribute at the cursor, which reads from `source`, and skips over it.
+    pub fn from(cursor: &mut ByteCursor,
+                source: &Rc<Vec<U1>>,
+                constant_pool: &Rc<Vec<ConstantPoolItem>>)
+                -> ParserResult<LazyAttribute> {
+        let start = cursor.position();
+        let attribute_name_index = try!(cursor.next_u2());
+        let attribute_name = try!(ConstantPoolItem::retrieve_utf8_info(attribute_name_index,
+                                                                       constant_pool));
+        let attribute_length = try!(cursor.next_u4());
+        try!(cursor.next_slice(attribute_length as usize));
+
+        Ok(LazyAttribute {
+            attribute_name: attribute_name,
+            source: source.clone(),
+            range: start..cursor.position(),
+            constant_pool: constant_pool.clone(),
+            decoded: RefCell::new(None),
+        })
+    }
+
+    /// The attribute as the class file holds it, from the index of its name to the end of its
+    /// body.
+    pub fn bytes(&self) -> &[U1] {
+        &self.source[self.range.clone()]
+    }
+
+    /// The body of the attribute, after its name and length.
+    pub fn info(&self) -> &[U1] {
+        &self.source[self.range.start + 6..self.range.end]
+    }
+
+    pub fn is_decoded(&self) -> bool {
+        self.decoded.borrow().is_some()
+    }
+
+    pub fn decode(&self) -> ParserResult<Rc<Attribute>> {
+        if let Some(ref decoded) = *self.decoded.borrow() {
+            return Ok(decoded.clone());
+        }
+
+        // the bytes hold exactly one attribute of a field or method, so they are decoded the way
+        // `Attribute::from` decodes those and has to be used up
+        let mut cursor = ByteCursor::new(self.bytes());
+        let decoded = Rc::new(try!(Attribute::from(&mut cursor, &self.constant_pool)));
+        if cursor.remaining() != 0 {
+            return Err(ParserError::AttributeLengthMismatch(self.attribute_name.to_string()));
+        }
+        *self.decoded.borrow_mut() = Some(decoded.clone());
+
+        Ok(decoded)
+    }
+}
+
+impl fmt::Debug for LazyAttribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("LazyAttribute")
+            .field("attribute_name", &self.attribute_name)
+            .field("range", &self.range)
+            .field("decoded", &self.decoded)
+            .finish()
+    }
 }
 
 macro_rules! generate_method_or_field_parser_impl {
@@ -387,6 +1116,16 @@ macro_rules! generate_method_or_field_parser_impl {
             pub fn from<T: PrimitiveIterator>(iter: &mut T,
                                               constant_pool: &Vec<ConstantPoolItem>)
                 -> ParserResult<$impl_name> {
+                    Self::from_with(iter, constant_pool, &mut Attribute::from)
+                }
+
+            /// Parses the member, reading each of its attributes with `read_attribute`.
+            pub fn from_with<T, F>(iter: &mut T,
+                                   constant_pool: &Vec<ConstantPoolItem>,
+                                   read_attribute: &mut F)
+                -> ParserResult<$impl_name>
+                where T: PrimitiveIterator,
+                      F: FnMut(&mut T, &Vec<ConstantPoolItem>) -> ParserResult<Attribute> {
                     let access_flags = try!(iter.next_u2());
 
                     let name_index = try!(iter.next_u2());
@@ -401,18 +1140,40 @@ macro_rules! generate_method_or_field_parser_impl {
                     let attributes_count = try!(iter.next_u2());
                     let mut attributes = vec![];
                     for _ in 0..attributes_count {
-                        attributes.push(Rc::new(try!(Attribute::from(iter, constant_pool))));
+                        attributes.push(Rc::new(try!(read_attribute(iter, constant_pool))));
                     }
 
                     Ok($impl_name {
                         access_flags: access_flags,
+                        name_index: name_index,
                         name: name,
+                        descriptor_index: descriptor_index,
                         descriptor: descriptor,
                         attributes_count: attributes_count,
                         attributes: attributes,
                     })
 
                 }
+
+            /// Writes the member, taking its attribute count from its attributes. Its name and
+            /// descriptor keep the entries they were read from while those still hold them, and
+            /// any other name is looked up in, or added to, `constant_pool`.
+            pub fn write<W: PrimitiveWriter>(&self,
+                                             writer: &mut W,
+                                             constant_pool: &mut ConstantPool)
+                -> ParserResult<()> {
+                    writer.write_u2(self.access_flags);
+                    writer.write_u2(try!(constant_pool.utf8_at(self.name_index, &self.name)));
+                    writer.write_u2(try!(constant_pool.utf8_at(self.descriptor_index,
+                                                               &self.descriptor)));
+
+                    writer.write_u2(self.attributes.len() as U2);
+                    for attribute in &self.attributes {
+                        try!(attribute.write(writer, &mut |name| constant_pool.utf8(name)));
+                    }
+
+                    Ok(())
+                }
         }
     }
 }
@@ -420,7 +1181,9 @@ macro_rules! generate_method_or_field_parser_impl {
 #[derive(Debug)]
 pub struct Field {
     pub access_flags: U2,
+    pub name_index: U2,
     pub name: Rc<Utf8Info>,
+    pub descriptor_index: U2,
     pub descriptor: Rc<Utf8Info>,
     pub attributes_count: U2,
     pub attributes: Vec<Rc<Attribute>>,
@@ -429,7 +1192,9 @@ pub struct Field {
 #[derive(Debug)]
 pub struct Method {
     pub access_flags: U2,
+    pub name_index: U2,
     pub name: Rc<Utf8Info>,
+    pub descriptor_index: U2,
     pub descriptor: Rc<Utf8Info>,
     pub attributes_count: U2,
     pub attributes: Vec<Rc<Attribute>>,
@@ -438,74 +1203,125 @@ pub struct Method {
 generate_method_or_field_parser_impl!(Field);
 generate_method_or_field_parser_impl!(Method);
 
+impl Field {
+    pub fn access(&self) -> FieldAccess {
+        FieldAccess::from(self.access_flags)
+    }
+
+    /// Parses the descriptor of the field.
+    pub fn field_type(&self) -> ParserResult<FieldType> {
+        FieldType::from(&self.descriptor)
+    }
+}
+
+impl Method {
+    pub fn access(&self) -> MethodAccess {
+        MethodAccess::from(self.access_flags)
+    }
+
+    /// Parses the descriptor of the method.
+    pub fn method_descriptor(&self) -> ParserResult<MethodDescriptor> {
+        MethodDescriptor::from(&self.descriptor)
+    }
+
+    /// The `Code` attribute of the method. A lazily parsed one is decoded here, and counts as
+    /// missing if it turns out to be malformed; `LazyAttribute::decode` reports why.
+    pub fn code(&self) -> Option<Rc<CodeAttribute>> {
+        for attribute in &self.attributes {
+            let attribute = match **attribute {
+                Attribute::Lazy(ref lazy) if lazy.attribute_name.as_str() == "Code" => {
+                    match lazy.decode() {
+                        Ok(decoded) => decoded,
+                        Err(_) => continue,
+                    }
+                }
+                _ => attribute.clone(),
+            };
+
+            if let Attribute::Code(ref code) = *attribute {
+                return Some(code.clone());
+            }
+        }
+
+        None
+    }
+}
+
+/// Tests for single access flags in raw `access_flags`, whichever kind of item they belong to.
+#[deprecated(note = "use `ClassAccess`, `FieldAccess` or `MethodAccess` instead")]
 pub struct AccessFlags;
 
+#[allow(deprecated)]
 impl AccessFlags {
     pub fn is_public(access_flags: U2) -> bool {
-        (access_flags & 0x0001) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::PUBLIC)
     }
 
     pub fn is_private(access_flags: U2) -> bool {
-        (access_flags & 0x0002) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::PRIVATE)
     }
 
     pub fn is_protected(access_flags: U2) -> bool {
-        (access_flags & 0x0004) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::PROTECTED)
     }
 
     pub fn is_static(access_flags: U2) -> bool {
-        (access_flags & 0x0008) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::STATIC)
     }
 
     pub fn is_final(access_flags: U2) -> bool {
-        (access_flags & 0x0010) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::FINAL)
     }
 
     pub fn is_super(access_flags: U2) -> bool {
-        (access_flags & 0x0020) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::SUPER)
+    }
+
+    pub fn is_synchronized(access_flags: U2) -> bool {
+        MethodAccess::from(access_flags).contains(MethodAccess::SYNCHRONIZED)
     }
 
     pub fn is_volatile(access_flags: U2) -> bool {
-        (access_flags & 0x0040) != 0
+        FieldAccess::from(access_flags).contains(FieldAccess::VOLATILE)
     }
 
     pub fn is_bridge(access_flags: U2) -> bool {
-        (access_flags & 0x0040) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::BRIDGE)
     }
 
     pub fn is_transient(access_flags: U2) -> bool {
-        (access_flags & 0x0080) != 0
+        FieldAccess::from(access_flags).contains(FieldAccess::TRANSIENT)
     }
 
     pub fn is_varargs(access_flags: U2) -> bool {
-        (access_flags & 0x0080) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::VARARGS)
     }
 
     pub fn is_native(access_flags: U2) -> bool {
-        (access_flags & 0x0100) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::NATIVE)
     }
 
     pub fn is_interface(access_flags: U2) -> bool {
-        (access_flags & 0x0200) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::INTERFACE)
     }
 
     pub fn is_abstract(access_flags: U2) -> bool {
-        (access_flags & 0x0400) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::ABSTRACT)
     }
 
     pub fn is_strict(access_flags: U2) -> bool {
-        (access_flags & 0x0800) != 0
+        MethodAccess::from(access_flags).contains(MethodAccess::STRICT)
     }
 
     pub fn is_synthetic(access_flags: U2) -> bool {
-        (access_flags & 0x1000) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::SYNTHETIC)
     }
 
     pub fn is_annotation(access_flags: U2) -> bool {
-        (access_flags & 0x2000) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::ANNOTATION)
     }
 
     pub fn is_enum(access_flags: U2) -> bool {
-        (access_flags & 0x4000) != 0
+        ClassAccess::from(access_flags).contains(ClassAccess::ENUM)
     }
 }
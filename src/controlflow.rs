@@ -0,0 +1,364 @@
+use super::{ParserError, ParserResult};
+use super::components::CodeAttribute;
+use super::instructions::Instruction;
+use super::primitives::U2;
+
+use std::collections::{BTreeSet, HashSet};
+
+pub type BlockId = usize;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EdgeKind {
+    /// Execution continues with the instruction that follows.
+    Fallthrough,
+    /// A `goto` or a taken conditional branch.
+    Branch,
+    /// A `tableswitch` or `lookupswitch` target.
+    Switch,
+    /// A `jsr` entering its subroutine.
+    Subroutine,
+    /// A handler from the exception table, with its catch type or 0 for any exception.
+    Exception(U2),
+    /// A return instruction leaving the method.
+    Return,
+    /// An `athrow` leaving the method when no handler catches the exception.
+    Throw,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Edge {
+    pub source: BlockId,
+    pub target: BlockId,
+    pub kind: EdgeKind,
+}
+
+/// A maximal run of instructions that is only entered at its first instruction and only left
+/// after its last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock {
+    pub start_pc: U2,
+    pub end_pc: U2,
+    pub instructions: Vec<(U2, Instruction)>,
+}
+
+impl BasicBlock {
+    pub fn last_instruction(&self) -> Option<&Instruction> {
+        self.instructions.last().map(|&(_, ref instruction)| instruction)
+    }
+}
+
+/// The basic blocks of a method body and the edges between them.
+///
+/// Blocks are ordered by pc, so the entry block comes first. A synthetic exit block with no
+/// instructions comes last, and every return and `athrow` has an edge to it. An `athrow` also
+/// has an edge to each handler that covers it, as it may be caught.
+///
+/// A `jsr` has a fallthrough edge to the instruction after it besides the edge into its
+/// subroutine, which stands in for the subroutine returning there. A `ret` has no edges.
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+    successors: Vec<Vec<usize>>,
+    predecessors: Vec<Vec<usize>>,
+}
+
+impl ControlFlowGraph {
+    pub fn from(code: &CodeAttribute) -> ParserResult<ControlFlowGraph> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+
+        let code_length = code.code.len();
+        let boundaries = instructions.iter().map(|&(pc, _)| pc as usize).collect::<HashSet<_>>();
+        let check_offset = |pc: usize, allow_end: bool| {
+            if boundaries.contains(&pc) || (allow_end && pc == code_length) {
+                Ok(pc)
+            } else {
+                Err(ParserError::InvalidCodeOffset(pc))
+            }
+        };
+
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+
+        for (i, &(pc, ref instruction)) in instructions.iter().enumerate() {
+            for target in branch_targets(pc, instruction) {
+                leaders.insert(try!(check_offset(target, false)));
+            }
+
+            if ends_block(instruction) {
+                if let Some(&(next_pc, _)) = instructions.get(i + 1) {
+                    leaders.insert(next_pc as usize);
+                }
+            }
+        }
+
+        for handler in &code.exception_table {
+            let start_pc = try!(check_offset(handler.start_pc as usize, false));
+            let end_pc = try!(check_offset(handler.end_pc as usize, true));
+            if start_pc >= end_pc {
+                return Err(ParserError::InvalidCodeOffset(end_pc));
+            }
+
+            leaders.insert(start_pc);
+            leaders.insert(try!(check_offset(handler.handler_pc as usize, false)));
+            if end_pc < code_length {
+                leaders.insert(end_pc);
+            }
+        }
+
+        let mut blocks: Vec<BasicBlock> = vec![];
+        for (pc, instruction) in instructions {
+            if leaders.contains(&(pc as usize)) || blocks.is_empty() {
+                blocks.push(BasicBlock {
+                    start_pc: pc,
+                    end_pc: pc,
+                    instructions: vec![],
+                });
+            }
+
+            let block = blocks.last_mut().unwrap();
+            block.instructions.push((pc, instruction));
+        }
+
+        for i in 0..blocks.len() {
+            blocks[i].end_pc = match blocks.get(i + 1) {
+                Some(next) => next.start_pc,
+                None => code_length as U2,
+            };
+        }
+
+        blocks.push(BasicBlock {
+            start_pc: code_length as U2,
+            end_pc: code_length as U2,
+            instructions: vec![],
+        });
+
+        let mut graph = ControlFlowGraph {
+            successors: vec![vec![]; blocks.len()],
+            predecessors: vec![vec![]; blocks.len()],
+            blocks: blocks,
+            edges: vec![],
+        };
+        try!(graph.connect(code));
+
+        Ok(graph)
+    }
+
+    pub fn entry(&self) -> BlockId {
+        0
+    }
+
+    pub fn exit(&self) -> BlockId {
+        self.blocks.len() - 1
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock {
+        &self.blocks[id]
+    }
+
+    /// The block containing the instruction at `pc`.
+    pub fn block_at(&self, pc: U2) -> Option<BlockId> {
+        let exit = self.exit();
+        let id = match self.blocks[..exit].binary_search_by_key(&pc, |block| block.start_pc) {
+            Ok(id) => id,
+            Err(0) => return None,
+            Err(next) => next - 1,
+        };
+
+        if pc < self.blocks[id].end_pc {
+            Some(id)
+        } else {
+            None
+        }
+    }
+
+    /// The edges leaving `id`, normal edges before exceptional ones.
+    pub fn successors(&self, id: BlockId) -> Vec<&Edge> {
+        self.successors[id].iter().map(|edge| &self.edges[*edge]).collect()
+    }
+
+    /// The edges entering `id`.
+    pub fn predecessors(&self, id: BlockId) -> Vec<&Edge> {
+        self.predecessors[id].iter().map(|edge| &self.edges[*edge]).collect()
+    }
+
+    fn connect(&mut self, code: &CodeAttribute) -> ParserResult<()> {
+        let exit = self.exit();
+
+        for id in 0..exit {
+            let next = if id + 1 < exit { Some(id + 1) } else { None };
+            let (pc, instruction) = match self.blocks[id].instructions.last() {
+                Some(&(pc, ref instruction)) => (pc, instruction.clone()),
+                None => continue,
+            };
+
+            let mut edges = vec![];
+            match instruction {
+                Instruction::Goto(..) |
+                Instruction::GotoW(..) => {
+                    for target in branch_targets(pc, &instruction) {
+                        edges.push((try!(self.block_starting_at(target)), EdgeKind::Branch));
+                    }
+                }
+                Instruction::Jsr(..) |
+                Instruction::JsrW(..) => {
+                    for target in branch_targets(pc, &instruction) {
+                        edges.push((try!(self.block_starting_at(target)), EdgeKind::Subroutine));
+                    }
+                    if let Some(next) = next {
+                        edges.push((next, EdgeKind::Fallthrough));
+                    }
+                }
+                Instruction::Tableswitch { .. } |
+                Instruction::Lookupswitch { .. } => {
+                    for target in branch_targets(pc, &instruction) {
+                        edges.push((try!(self.block_starting_at(target)), EdgeKind::Switch));
+                    }
+                }
+                Instruction::Ireturn |
+                Instruction::Lreturn |
+                Instruction::Freturn |
+                Instruction::Dreturn |
+                Instruction::Areturn |
+                Instruction::Return => edges.push((exit, EdgeKind::Return)),
+                Instruction::Athrow => edges.push((exit, EdgeKind::Throw)),
+                Instruction::Ret(..) => {}
+                _ => {
+                    for target in branch_targets(pc, &instruction) {
+                        edges.push((try!(self.block_starting_at(target)), EdgeKind::Branch));
+                    }
+                    if let Some(next) = next {
+                        edges.push((next, EdgeKind::Fallthrough));
+                    }
+                }
+            }
+
+            for (target, kind) in edges {
+                self.add_edge(id, target, kind);
+            }
+        }
+
+        for handler in &code.exception_table {
+            let handler_block = try!(self.block_starting_at(handler.handler_pc as usize));
+
+            for id in 0..exit {
+                let block = &self.blocks[id];
+                if block.start_pc >= handler.start_pc && block.end_pc <= handler.end_pc {
+                    self.add_edge(id, handler_block, EdgeKind::Exception(handler.catch_type));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_edge(&mut self, source: BlockId, target: BlockId, kind: EdgeKind) {
+        let edge = Edge {
+            source: source,
+            target: target,
+            kind: kind,
+        };
+
+        // switches often send several keys to the same target
+        if self.successors[source].iter().any(|index| self.edges[*index] == edge) {
+            return;
+        }
+
+        self.successors[source].push(self.edges.len());
+        self.predecessors[target].push(self.edges.len());
+        self.edges.push(edge);
+    }
+
+    fn block_starting_at(&self, pc: usize) -> ParserResult<BlockId> {
+        match self.block_at(pc as U2) {
+            Some(id) if self.blocks[id].start_pc as usize == pc => Ok(id),
+            _ => Err(ParserError::InvalidCodeOffset(pc)),
+        }
+    }
+}
+
+fn branch_targets(pc: U2, instruction: &Instruction) -> Vec<usize> {
+    instruction.branch_targets()
+        .iter()
+        .map(|offset| (pc as i64 + **offset as i64) as usize)
+        .collect()
+}
+
+/// Whether control can leave `instruction` other than by falling through to the next one.
+fn ends_block(instruction: &Instruction) -> bool {
+    match *instruction {
+        Instruction::Ireturn |
+        Instruction::Lreturn |
+        Instruction::Freturn |
+        Instruction::Dreturn |
+        Instruction::Areturn |
+        Instruction::Return |
+        Instruction::Athrow |
+        Instruction::Ret(..) => true,
+        _ => !instruction.branch_targets().is_empty(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ControlFlowGraph, Edge, EdgeKind};
+    use super::super::components::{CodeAttribute, ExceptionHandler};
+
+    #[test]
+    fn can_split_blocks_at_branches() {
+        // iload_0; ifeq +5; iconst_1; ireturn; iconst_0; ireturn
+        let code = CodeAttribute::for_test(vec![0x1a, 0x99, 0x00, 0x05, 0x04, 0xac, 0x03, 0xac], vec![]);
+        let graph = ControlFlowGraph::from(&code).unwrap();
+
+        let ranges: Vec<_> = graph.blocks.iter().map(|block| (block.start_pc, block.end_pc)).collect();
+        assert_that(&ranges).is_equal_to(&vec![(0, 4), (4, 6), (6, 8), (8, 8)]);
+
+        assert_that(&graph.successors(0)).is_equal_to(&vec![&Edge {
+                                                                  source: 0,
+                                                                  target: 2,
+                                                                  kind: EdgeKind::Branch,
+                                                              },
+                                                              &Edge {
+                                                                  source: 0,
+                                                                  target: 1,
+                                                                  kind: EdgeKind::Fallthrough,
+                                                              }]);
+        assert_that(&graph.predecessors(graph.exit())).has_length(2);
+        assert_that(&graph.block_at(5)).is_equal_to(&Some(1));
+        assert_that(&graph.block_at(8)).is_equal_to(&None);
+    }
+
+    #[test]
+    fn can_connect_exception_handlers() {
+        // aload_0; athrow; astore_1; return
+        let handler = ExceptionHandler {
+            start_pc: 0,
+            end_pc: 2,
+            handler_pc: 2,
+            catch_type: 0,
+        };
+        let code = CodeAttribute::for_test(vec![0x2a, 0xbf, 0x4c, 0xb1], vec![handler]);
+        let graph = ControlFlowGraph::from(&code).unwrap();
+
+        let kinds: Vec<_> = graph.successors(0).iter().map(|edge| (edge.target, edge.kind)).collect();
+        assert_that(&kinds).is_equal_to(&vec![(2, EdgeKind::Throw), (1, EdgeKind::Exception(0))]);
+        assert_that(&graph.predecessors(1)).has_length(1);
+    }
+
+    #[test]
+    fn rejects_branches_into_instructions() {
+        // goto +1; return
+        let code = CodeAttribute::for_test(vec![0xa7, 0x00, 0x01, 0xb1], vec![]);
+
+        assert_that(&ControlFlowGraph::from(&code)).is_err();
+    }
+
+}
@@ -0,0 +1,490 @@
+use super::controlflow::{BlockId, ControlFlowGraph, Edge, EdgeKind};
+use super::dominators::depth_first_postorder;
+use super::instructions::{Instruction, LocalAccess};
+use super::primitives::U2;
+
+use std::collections::BTreeSet;
+
+/// A join semilattice that dataflow facts are drawn from.
+pub trait Lattice: Clone {
+    /// Moves `self` up to the least upper bound of `self` and `other`, returning whether that
+    /// changed `self`.
+    fn join(&mut self, other: &Self) -> bool;
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Direction {
+    Forward,
+    Backward,
+}
+
+/// A dataflow problem, described by its facts and how instructions transform them.
+///
+/// Transfer functions have to be monotone for `solve` to terminate.
+pub trait Analysis {
+    type Fact: Lattice;
+
+    fn direction(&self) -> Direction;
+
+    /// The fact on method entry for a forward analysis, or on method exit for a backward one.
+    fn boundary(&self) -> Self::Fact;
+
+    /// The least fact, which every block starts from before anything flows into it.
+    fn bottom(&self) -> Self::Fact;
+
+    /// Applies the effect of `instruction` to `fact`. A backward analysis is given the fact after
+    /// the instruction and turns it into the fact before it.
+    fn transfer(&self, pc: U2, instruction: &Instruction, fact: &mut Self::Fact);
+
+    /// Adjusts a fact as it flows along `edge`, which by default leaves it unchanged.
+    fn transfer_edge(&self, _edge: &Edge, fact: &Self::Fact) -> Self::Fact {
+        fact.clone()
+    }
+}
+
+/// The facts a solved analysis holds at the boundaries of each block, in program order whatever
+/// the direction of the analysis.
+#[derive(Clone, Debug)]
+pub struct DataflowResult<F> {
+    /// The fact before the first instruction of each block.
+    pub entry: Vec<F>,
+    /// The fact after the last instruction of each block.
+    pub exit: Vec<F>,
+}
+
+impl<F: Lattice> DataflowResult<F> {
+    /// The fact before each instruction of block `id`, in program order.
+    pub fn facts_before<A>(&self, analysis: &A, graph: &ControlFlowGraph, id: BlockId) -> Vec<F>
+        where A: Analysis<Fact = F>
+    {
+        let block = graph.block(id);
+
+        match analysis.direction() {
+            Direction::Forward => {
+                let mut fact = self.entry[id].clone();
+                let mut facts = vec![];
+                for &(pc, ref instruction) in &block.instructions {
+                    facts.push(fact.clone());
+                    analysis.transfer(pc, instruction, &mut fact);
+                }
+                facts
+            }
+            Direction::Backward => {
+                let thrown = thrown_backward(analysis, graph, id, &self.entry);
+                let mut fact = self.exit[id].clone();
+                let mut facts = vec![];
+                for &(pc, ref instruction) in block.instructions.iter().rev() {
+                    analysis.transfer(pc, instruction, &mut fact);
+                    fact.join(&thrown);
+                    facts.push(fact.clone());
+                }
+                facts.reverse();
+                facts
+            }
+        }
+    }
+}
+
+/// Solves `analysis` over `graph` with a worklist, visiting blocks in reverse postorder for a
+/// forward analysis and in postorder for a backward one.
+///
+/// An exception can be thrown before any instruction of a block has completed, so the fact
+/// flowing along an exceptional edge is the join of the facts before each instruction in the
+/// block, rather than the fact at its end.
+pub fn solve<A: Analysis>(analysis: &A, graph: &ControlFlowGraph) -> DataflowResult<A::Fact> {
+    let count = graph.blocks.len();
+    let direction = analysis.direction();
+
+    let successors = |id| graph.successors(id).iter().map(|edge| edge.target).collect();
+    let mut order = depth_first_postorder(count, graph.entry(), successors);
+    if direction == Direction::Forward {
+        order.reverse();
+    }
+    for id in 0..count {
+        if !order.contains(&id) {
+            order.push(id);
+        }
+    }
+
+    let mut priorities = vec![0; count];
+    for (priority, id) in order.iter().enumerate() {
+        priorities[*id] = priority;
+    }
+
+    let mut entry = vec![analysis.bottom(); count];
+    let mut exit = vec![analysis.bottom(); count];
+    let mut thrown = vec![analysis.bottom(); count];
+
+    let mut worklist = (0..count).collect::<BTreeSet<_>>();
+    while let Some(priority) = worklist.iter().next().cloned() {
+        worklist.remove(&priority);
+        let id = order[priority];
+        let block = graph.block(id);
+
+        let changed = match direction {
+            Direction::Forward => {
+                let mut fact = if id == graph.entry() {
+                    analysis.boundary()
+                } else {
+                    analysis.bottom()
+                };
+                for edge in graph.predecessors(id) {
+                    let incoming = if is_exceptional(edge) {
+                        &thrown[edge.source]
+                    } else {
+                        &exit[edge.source]
+                    };
+                    fact.join(&analysis.transfer_edge(edge, incoming));
+                }
+                entry[id] = fact.clone();
+
+                let mut thrown_fact = fact.clone();
+                for &(pc, ref instruction) in &block.instructions {
+                    thrown_fact.join(&fact);
+                    analysis.transfer(pc, instruction, &mut fact);
+                }
+
+                exit[id].join(&fact) | thrown[id].join(&thrown_fact)
+            }
+            Direction::Backward => {
+                let mut fact = if id == graph.exit() {
+                    analysis.boundary()
+                } else {
+                    analysis.bottom()
+                };
+                for edge in graph.successors(id) {
+                    if !is_exceptional(edge) {
+                        fact.join(&analysis.transfer_edge(edge, &entry[edge.target]));
+                    }
+                }
+                exit[id] = fact.clone();
+
+                let thrown_fact = thrown_backward(analysis, graph, id, &entry);
+                for &(pc, ref instruction) in block.instructions.iter().rev() {
+                    analysis.transfer(pc, instruction, &mut fact);
+                    fact.join(&thrown_fact);
+                }
+
+                entry[id].join(&fact)
+            }
+        };
+
+        if changed {
+            let dependents = match direction {
+                Direction::Forward => {
+                    graph.successors(id).iter().map(|edge| edge.target).collect::<Vec<_>>()
+                }
+                Direction::Backward => {
+                    graph.predecessors(id).iter().map(|edge| edge.source).collect::<Vec<_>>()
+                }
+            };
+            worklist.extend(dependents.into_iter().map(|dependent| priorities[dependent]));
+        }
+    }
+
+    DataflowResult {
+        entry: entry,
+        exit: exit,
+    }
+}
+
+/// The join of the facts at the handlers of block `id`, which holds before each of its
+/// instructions in a backward analysis.
+fn thrown_backward<A: Analysis>(analysis: &A,
+                                graph: &ControlFlowGraph,
+                                id: BlockId,
+                                entry: &Vec<A::Fact>)
+                                -> A::Fact {
+    let mut thrown = analysis.bottom();
+    for edge in graph.successors(id) {
+        if is_exceptional(edge) {
+            thrown.join(&analysis.transfer_edge(edge, &entry[edge.target]));
+        }
+    }
+
+    thrown
+}
+
+fn is_exceptional(edge: &Edge) -> bool {
+    match edge.kind {
+        EdgeKind::Exception(..) => true,
+        _ => false,
+    }
+}
+
+/// A set joined by union, as used by may analyses.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UnionSet<T: Ord>(pub BTreeSet<T>);
+
+impl<T: Ord> Default for UnionSet<T> {
+    fn default() -> UnionSet<T> {
+        UnionSet(BTreeSet::new())
+    }
+}
+
+impl<T: Ord + Clone> Lattice for UnionSet<T> {
+    fn join(&mut self, other: &UnionSet<T>) -> bool {
+        let length = self.0.len();
+        self.0.extend(other.0.iter().cloned());
+        self.0.len() != length
+    }
+}
+
+/// A set joined by intersection, as used by must analyses. `Full` stands for the set of
+/// everything, which is where blocks start before anything flows into them.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum IntersectionSet<T: Ord> {
+    Full,
+    Elements(BTreeSet<T>),
+}
+
+impl<T: Ord> IntersectionSet<T> {
+    pub fn contains(&self, value: &T) -> bool {
+        match *self {
+            IntersectionSet::Full => true,
+            IntersectionSet::Elements(ref elements) => elements.contains(value),
+        }
+    }
+
+    pub fn insert(&mut self, value: T) {
+        if let IntersectionSet::Elements(ref mut elements) = *self {
+            elements.insert(value);
+        }
+    }
+}
+
+impl<T: Ord + Clone> Lattice for IntersectionSet<T> {
+    fn join(&mut self, other: &IntersectionSet<T>) -> bool {
+        let elements = match *other {
+            IntersectionSet::Full => return false,
+            IntersectionSet::Elements(ref elements) => elements,
+        };
+
+        match *self {
+            IntersectionSet::Full => {
+                *self = IntersectionSet::Elements(elements.clone());
+                true
+            }
+            IntersectionSet::Elements(ref mut current) => {
+                let length = current.len();
+                current.retain(|value| elements.contains(value));
+                current.len() != length
+            }
+        }
+    }
+}
+
+/// The local variable slots whose current value may still be read.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Liveness;
+
+impl Analysis for Liveness {
+    type Fact = UnionSet<U2>;
+
+    fn direction(&self) -> Direction {
+        Direction::Backward
+    }
+
+    fn boundary(&self) -> UnionSet<U2> {
+        UnionSet::default()
+    }
+
+    fn bottom(&self) -> UnionSet<U2> {
+        UnionSet::default()
+    }
+
+    fn transfer(&self, _pc: U2, instruction: &Instruction, fact: &mut UnionSet<U2>) {
+        if let Some(access) = instruction.local_variable_access() {
+            let slots = access.slot_indexes();
+
+            match access.access {
+                LocalAccess::Load | LocalAccess::Increment => fact.0.extend(slots),
+                LocalAccess::Store => {
+                    for slot in slots {
+                        fact.0.remove(&slot);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// A write to a local variable slot.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Definition {
+    pub local: U2,
+    /// The pc of the instruction that wrote the slot, or `None` for the value it held on method
+    /// entry.
+    pub pc: Option<U2>,
+}
+
+/// The writes to each local variable slot that may not have been overwritten yet.
+#[derive(Clone, Copy, Debug)]
+pub struct ReachingDefinitions {
+    /// The number of slots holding `this` and the parameters on method entry.
+    pub parameter_slots: U2,
+}
+
+impl ReachingDefinitions {
+    pub fn new(parameter_slots: U2) -> ReachingDefinitions {
+        ReachingDefinitions { parameter_slots: parameter_slots }
+    }
+}
+
+impl Analysis for ReachingDefinitions {
+    type Fact = UnionSet<Definition>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self) -> UnionSet<Definition> {
+        UnionSet((0..self.parameter_slots)
+            .map(|local| {
+                Definition {
+                    local: local,
+                    pc: None,
+                }
+            })
+            .collect())
+    }
+
+    fn bottom(&self) -> UnionSet<Definition> {
+        UnionSet::default()
+    }
+
+    fn transfer(&self, pc: U2, instruction: &Instruction, fact: &mut UnionSet<Definition>) {
+        let access = match instruction.local_variable_access() {
+            Some(access) => access,
+            None => return,
+        };
+        if access.access == LocalAccess::Load {
+            return;
+        }
+
+        for local in access.slot_indexes() {
+            fact.0.retain(|definition| definition.local != local);
+            fact.0.insert(Definition {
+                local: local,
+                pc: Some(pc),
+            });
+        }
+    }
+}
+
+/// The local variable slots that are written on every path to a point.
+#[derive(Clone, Copy, Debug)]
+pub struct DefiniteAssignment {
+    /// The number of slots holding `this` and the parameters on method entry.
+    pub parameter_slots: U2,
+}
+
+impl DefiniteAssignment {
+    pub fn new(parameter_slots: U2) -> DefiniteAssignment {
+        DefiniteAssignment { parameter_slots: parameter_slots }
+    }
+}
+
+impl Analysis for DefiniteAssignment {
+    type Fact = IntersectionSet<U2>;
+
+    fn direction(&self) -> Direction {
+        Direction::Forward
+    }
+
+    fn boundary(&self) -> IntersectionSet<U2> {
+        IntersectionSet::Elements((0..self.parameter_slots).collect())
+    }
+
+    fn bottom(&self) -> IntersectionSet<U2> {
+        IntersectionSet::Full
+    }
+
+    fn transfer(&self, _pc: U2, instruction: &Instruction, fact: &mut IntersectionSet<U2>) {
+        if let Some(access) = instruction.local_variable_access() {
+            if access.access == LocalAccess::Store {
+                for slot in access.slot_indexes() {
+                    fact.insert(slot);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{solve, Definition, DefiniteAssignment, IntersectionSet, Liveness,
+                ReachingDefinitions, UnionSet};
+    use super::super::components::{CodeAttribute, ExceptionHandler};
+    use super::super::controlflow::ControlFlowGraph;
+
+    fn graph(bytes: Vec<u8>, exception_table: Vec<ExceptionHandler>) -> ControlFlowGraph {
+        let code = CodeAttribute::for_test(bytes, exception_table);
+
+        ControlFlowGraph::from(&code).unwrap()
+    }
+
+    #[test]
+    fn can_compute_liveness_around_loop() {
+        // iconst_0; istore_1; iinc 1 1; iload_1; iload_0; if_icmplt -5; iload_1; ireturn
+        let graph = graph(vec![0x03, 0x3c, 0x84, 0x01, 0x01, 0x1b, 0x1a, 0xa1, 0xff, 0xfb, 0x1b,
+                               0xac],
+                          vec![]);
+        let result = solve(&Liveness, &graph);
+
+        assert_that(&result.entry[0]).is_equal_to(&UnionSet(vec![0].into_iter().collect()));
+        assert_that(&result.entry[1]).is_equal_to(&UnionSet(vec![0, 1].into_iter().collect()));
+
+        let facts = result.facts_before(&Liveness, &graph, 0);
+        assert_that(&facts[1]).is_equal_to(&UnionSet(vec![0].into_iter().collect()));
+    }
+
+    #[test]
+    fn can_compute_reaching_definitions_around_loop() {
+        // iconst_0; istore_1; iinc 1 1; iload_1; iload_0; if_icmplt -5; iload_1; ireturn
+        let graph = graph(vec![0x03, 0x3c, 0x84, 0x01, 0x01, 0x1b, 0x1a, 0xa1, 0xff, 0xfb, 0x1b,
+                               0xac],
+                          vec![]);
+        let analysis = ReachingDefinitions::new(1);
+        let result = solve(&analysis, &graph);
+
+        let expected = vec![Definition {
+                                local: 0,
+                                pc: None,
+                            },
+                            Definition {
+                                local: 1,
+                                pc: Some(1),
+                            },
+                            Definition {
+                                local: 1,
+                                pc: Some(2),
+                            }];
+        assert_that(&result.entry[1]).is_equal_to(&UnionSet(expected.into_iter().collect()));
+    }
+
+    #[test]
+    fn can_compute_definite_assignment_along_exceptional_edges() {
+        // aload_0; astore_1; aload_0; astore_2; return; astore_3; return
+        let handler = ExceptionHandler {
+            start_pc: 0,
+            end_pc: 5,
+            handler_pc: 5,
+            catch_type: 0,
+        };
+        let graph = graph(vec![0x2a, 0x4c, 0x2a, 0x4d, 0xb1, 0x4e, 0xb1], vec![handler]);
+        let analysis = DefiniteAssignment::new(1);
+        let result = solve(&analysis, &graph);
+
+        assert_that(&result.exit[0])
+            .is_equal_to(&IntersectionSet::Elements(vec![0, 1, 2].into_iter().collect()));
+        assert_that(&result.entry[1])
+            .is_equal_to(&IntersectionSet::Elements(vec![0].into_iter().collect()));
+    }
+
+}
@@ -0,0 +1,370 @@
+use super::{ParserError, ParserResult};
+
+/// A primitive type as it appears in a descriptor.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum BaseType {
+    Byte,
+    Char,
+    Double,
+    Float,
+    Int,
+    Long,
+    Short,
+    Boolean,
+}
+
+impl BaseType {
+    /// The base type a descriptor character stands for.
+    pub fn from(descriptor: char) -> Option<BaseType> {
+        match descriptor {
+            'B' => Some(BaseType::Byte),
+            'C' => Some(BaseType::Char),
+            'D' => Some(BaseType::Double),
+            'F' => Some(BaseType::Float),
+            'I' => Some(BaseType::Int),
+            'J' => Some(BaseType::Long),
+            'S' => Some(BaseType::Short),
+            'Z' => Some(BaseType::Boolean),
+            _ => None,
+        }
+    }
+
+    pub fn descriptor(&self) -> char {
+        match *self {
+            BaseType::Byte => 'B',
+            BaseType::Char => 'C',
+            BaseType::Double => 'D',
+            BaseType::Float => 'F',
+            BaseType::Int => 'I',
+            BaseType::Long => 'J',
+            BaseType::Short => 'S',
+            BaseType::Boolean => 'Z',
+        }
+    }
+
+    /// The name of the type in Java source, such as `int`.
+    pub fn java_name(&self) -> &'static str {
+        match *self {
+            BaseType::Byte => "byte",
+            BaseType::Char => "char",
+            BaseType::Double => "double",
+            BaseType::Float => "float",
+            BaseType::Int => "int",
+            BaseType::Long => "long",
+            BaseType::Short => "short",
+            BaseType::Boolean => "boolean",
+        }
+    }
+
+    /// The number of local variable or operand stack slots a value of the type takes.
+    pub fn slots(&self) -> usize {
+        match *self {
+            BaseType::Long | BaseType::Double => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// The type of a field, parameter or return value, parsed from a field descriptor such as
+/// `[Ljava/lang/String;`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum FieldType {
+    Base(BaseType),
+    /// A class or interface type, by its name in internal form, such as `java/lang/String`.
+    Object(String),
+    /// An array type, by its number of dimensions and the type of its elements, which is never
+    /// an array type itself.
+    Array(usize, Box<FieldType>),
+}
+
+impl FieldType {
+    pub fn from(descriptor: &str) -> ParserResult<FieldType> {
+        match parse_field_type(descriptor) {
+            Some((field_type, "")) => Ok(field_type),
+            _ => Err(ParserError::InvalidDescriptor(descriptor.to_string())),
+        }
+    }
+
+    /// The class or interface type named `name` in internal form.
+    pub fn object(name: &str) -> FieldType {
+        FieldType::Object(name.to_string())
+    }
+
+    /// The array type whose components are of type `component`.
+    pub fn array_of(component: FieldType) -> FieldType {
+        match component {
+            FieldType::Array(dimensions, element) => FieldType::Array(dimensions + 1, element),
+            element => FieldType::Array(1, Box::new(element)),
+        }
+    }
+
+    /// The number of array dimensions, which is zero for types other than arrays.
+    pub fn dimensions(&self) -> usize {
+        match *self {
+            FieldType::Array(dimensions, _) => dimensions,
+            _ => 0,
+        }
+    }
+
+    /// The type of the elements of an array after all of its dimensions, or the type itself for
+    /// types other than arrays.
+    pub fn element_type(&self) -> &FieldType {
+        match *self {
+            FieldType::Array(_, ref element) => element,
+            _ => self,
+        }
+    }
+
+    pub fn slots(&self) -> usize {
+        match *self {
+            FieldType::Base(base_type) => base_type.slots(),
+            _ => 1,
+        }
+    }
+
+    pub fn descriptor(&self) -> String {
+        let mut descriptor = String::new();
+        self.write_descriptor(&mut descriptor);
+        descriptor
+    }
+
+    /// The type as it is written in Java source, such as `java.lang.String[]`.
+    pub fn java_name(&self) -> String {
+        match *self {
+            FieldType::Base(base_type) => base_type.java_name().to_string(),
+            FieldType::Object(ref name) => name.replace('/', "."),
+            FieldType::Array(dimensions, ref element) => {
+                format!("{}{}", element.java_name(), "[]".repeat(dimensions))
+            }
+        }
+    }
+
+    fn write_descriptor(&self, descriptor: &mut String) {
+        match *self {
+            FieldType::Base(base_type) => descriptor.push(base_type.descriptor()),
+            FieldType::Object(ref name) => {
+                descriptor.push('L');
+                descriptor.push_str(name);
+                descriptor.push(';');
+            }
+            FieldType::Array(dimensions, ref element) => {
+                for _ in 0..dimensions {
+                    descriptor.push('[');
+                }
+                element.write_descriptor(descriptor);
+            }
+        }
+    }
+}
+
+/// The parameter types and return type of a method, parsed from a method descriptor such as
+/// `(ILjava/lang/String;)V`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MethodDescriptor {
+    pub params: Vec<FieldType>,
+    /// The return type, which is `None` for `void`.
+    pub return_type: Option<FieldType>,
+}
+
+impl MethodDescriptor {
+    pub fn from(descriptor: &str) -> ParserResult<MethodDescriptor> {
+        let invalid = || ParserError::InvalidDescriptor(descriptor.to_string());
+        if !descriptor.starts_with('(') {
+            return Err(invalid());
+        }
+
+        let mut params = vec![];
+        let mut rest = &descriptor[1..];
+        while !rest.starts_with(')') {
+            match parse_field_type(rest) {
+                Some((param, remaining)) => {
+                    params.push(param);
+                    rest = remaining;
+                }
+                None => return Err(invalid()),
+            }
+        }
+
+        let return_type = match &rest[1..] {
+            "V" => None,
+            return_type => Some(try!(FieldType::from(return_type).map_err(|_| invalid()))),
+        };
+
+        Ok(MethodDescriptor {
+            params: params,
+            return_type: return_type,
+        })
+    }
+
+    pub fn new(params: Vec<FieldType>, return_type: Option<FieldType>) -> MethodDescriptor {
+        MethodDescriptor {
+            params: params,
+            return_type: return_type,
+        }
+    }
+
+    /// The number of local variable slots the parameters take, not counting `this`.
+    pub fn parameter_slots(&self) -> usize {
+        self.params.iter().map(FieldType::slots).sum()
+    }
+
+    /// The number of operand stack slots the return value takes, which is zero for `void`.
+    pub fn return_slots(&self) -> usize {
+        self.return_type.as_ref().map_or(0, FieldType::slots)
+    }
+
+    pub fn descriptor(&self) -> String {
+        let mut descriptor = "(".to_string();
+        for param in &self.params {
+            param.write_descriptor(&mut descriptor);
+        }
+        descriptor.push(')');
+        match self.return_type {
+            Some(ref return_type) => return_type.write_descriptor(&mut descriptor),
+            None => descriptor.push('V'),
+        }
+
+        descriptor
+    }
+
+    /// The parameter types as they are written in Java source.
+    pub fn java_params(&self) -> Vec<String> {
+        self.params.iter().map(FieldType::java_name).collect()
+    }
+
+    /// The return type as it is written in Java source, which is `void` if there is none.
+    pub fn java_return_type(&self) -> String {
+        match self.return_type {
+            Some(ref return_type) => return_type.java_name(),
+            None => "void".to_string(),
+        }
+    }
+}
+
+/// The most dimensions an array type can have.
+pub const MAX_DIMENSIONS: usize = 255;
+
+/// Parses the field type at the start of `descriptor` and returns it with the rest of the
+/// descriptor.
+fn parse_field_type(descriptor: &str) -> Option<(FieldType, &str)> {
+    let dimensions = descriptor.bytes().take_while(|&byte| byte == b'[').count();
+    if dimensions > MAX_DIMENSIONS {
+        return None;
+    }
+
+    let descriptor = &descriptor[dimensions..];
+    let first = match descriptor.chars().next() {
+        Some(first) => first,
+        None => return None,
+    };
+
+    let (element, rest) = match first {
+        'L' => {
+            match descriptor.find(';') {
+                Some(end) if end > 1 => {
+                    (FieldType::object(&descriptor[1..end]), &descriptor[end + 1..])
+                }
+                _ => return None,
+            }
+        }
+        _ => {
+            match BaseType::from(first) {
+                Some(base_type) => (FieldType::Base(base_type), &descriptor[1..]),
+                None => return None,
+            }
+        }
+    };
+
+    if dimensions == 0 {
+        Some((element, rest))
+    } else {
+        Some((FieldType::Array(dimensions, Box::new(element)), rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{BaseType, FieldType, MethodDescriptor};
+
+    #[test]
+    fn can_parse_field_descriptors() {
+        assert_that(&FieldType::from("J").unwrap()).is_equal_to(&FieldType::Base(BaseType::Long));
+        assert_that(&FieldType::from("Ljava/lang/String;").unwrap())
+            .is_equal_to(&FieldType::object("java/lang/String"));
+
+        let matrix = FieldType::from("[[D").unwrap();
+        assert_that(&matrix).is_equal_to(&FieldType::array_of(
+            FieldType::array_of(FieldType::Base(BaseType::Double))));
+        assert_that(&matrix.dimensions()).is_equal_to(&2);
+        assert_that(&matrix.element_type()).is_equal_to(&&FieldType::Base(BaseType::Double));
+
+        for invalid in &["", "V", "L;", "Ljava/lang/String", "[", "II", "Q"] {
+            assert_that(&FieldType::from(invalid).is_err()).is_equal_to(&true);
+        }
+    }
+
+    #[test]
+    fn limits_array_dimensions() {
+        let deepest = format!("{}I", "[".repeat(255));
+        let field_type = FieldType::from(&deepest).unwrap();
+        assert_that(&field_type.dimensions()).is_equal_to(&255);
+        assert_that(&field_type.descriptor()).is_equal_to(&deepest);
+
+        let too_deep = format!("{}I", "[".repeat(256));
+        assert_that(&FieldType::from(&too_deep).is_err()).is_equal_to(&true);
+
+        // far too many dimensions to parse one at a time
+        let descriptor = format!("({}I)V", "[".repeat(65000));
+        assert_that(&MethodDescriptor::from(&descriptor).is_err()).is_equal_to(&true);
+    }
+
+    #[test]
+    fn can_parse_method_descriptors() {
+        let descriptor = MethodDescriptor::from("(IJ[Ljava/lang/Object;D)V").unwrap();
+
+        assert_that(&descriptor.params).is_equal_to(&vec![
+            FieldType::Base(BaseType::Int),
+            FieldType::Base(BaseType::Long),
+            FieldType::array_of(FieldType::object("java/lang/Object")),
+            FieldType::Base(BaseType::Double),
+        ]);
+        assert_that(&descriptor.return_type).is_none();
+        assert_that(&descriptor.parameter_slots()).is_equal_to(&6);
+        assert_that(&descriptor.return_slots()).is_equal_to(&0);
+
+        for invalid in &["", "V", "()", "(V)V", "(I", "(I)VV", "I)V"] {
+            assert_that(&MethodDescriptor::from(invalid).is_err()).is_equal_to(&true);
+        }
+    }
+
+    #[test]
+    fn can_print_java_types() {
+        let descriptor = MethodDescriptor::from("([[Ljava/util/Map$Entry;Z)[C").unwrap();
+
+        assert_that(&descriptor.java_params())
+            .is_equal_to(&vec!["java.util.Map$Entry[][]".to_string(), "boolean".to_string()]);
+        assert_that(&descriptor.java_return_type()).is_equal_to(&"char[]".to_string());
+        assert_that(&MethodDescriptor::new(vec![], None).java_return_type())
+            .is_equal_to(&"void".to_string());
+    }
+
+    #[test]
+    fn can_write_descriptors() {
+        let descriptor = MethodDescriptor::new(vec![FieldType::object("java/lang/String"),
+                                                    FieldType::Base(BaseType::Int)],
+                                               Some(FieldType::array_of(FieldType::Base(
+                                                   BaseType::Byte))));
+        assert_that(&descriptor.descriptor()).is_equal_to(&"(Ljava/lang/String;I)[B".to_string());
+
+        for descriptor in &["()V", "(JD)J", "([[ILjava/lang/Object;)Ljava/lang/String;"] {
+            assert_that(&MethodDescriptor::from(descriptor).unwrap().descriptor())
+                .is_equal_to(&descriptor.to_string());
+        }
+    }
+
+}
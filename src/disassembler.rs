@@ -0,0 +1,1120 @@
+use super::{ClassFile, ParserError, ParserResult};
+use super::access::{ClassAccess, InnerClassAccess, MethodAccess};
+use super::components::{Attribute, CodeAttribute, ConstantPoolItem, Field, Method,
+                        StackMapFrame, Utf8Info, VerificationTypeInfo};
+use super::descriptors::{FieldType, MethodDescriptor};
+use super::instructions::Instruction;
+use super::primitives::{U1, U2};
+use super::signatures::{self, ClassTypeSignature};
+
+use std::rc::Rc;
+
+const INDENT_WIDTH: usize = 2;
+const TAB_COLUMN: usize = 40;
+
+/// The opcode that prefixes the wide form of a local variable instruction.
+const WIDE: U1 = 0xc4;
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const CLASS_FLAG_NAMES: &'static [(U2, &'static str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0020, "ACC_SUPER"),
+    (0x0200, "ACC_INTERFACE"),
+    (0x0400, "ACC_ABSTRACT"),
+    (0x1000, "ACC_SYNTHETIC"),
+    (0x2000, "ACC_ANNOTATION"),
+    (0x4000, "ACC_ENUM"),
+    (0x8000, "ACC_MODULE"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const FIELD_FLAG_NAMES: &'static [(U2, &'static str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0002, "ACC_PRIVATE"),
+    (0x0004, "ACC_PROTECTED"),
+    (0x0008, "ACC_STATIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0040, "ACC_VOLATILE"),
+    (0x0080, "ACC_TRANSIENT"),
+    (0x1000, "ACC_SYNTHETIC"),
+    (0x4000, "ACC_ENUM"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const METHOD_FLAG_NAMES: &'static [(U2, &'static str)] = &[
+    (0x0001, "ACC_PUBLIC"),
+    (0x0002, "ACC_PRIVATE"),
+    (0x0004, "ACC_PROTECTED"),
+    (0x0008, "ACC_STATIC"),
+    (0x0010, "ACC_FINAL"),
+    (0x0020, "ACC_SYNCHRONIZED"),
+    (0x0040, "ACC_BRIDGE"),
+    (0x0080, "ACC_VARARGS"),
+    (0x0100, "ACC_NATIVE"),
+    (0x0400, "ACC_ABSTRACT"),
+    (0x0800, "ACC_STRICT"),
+    (0x1000, "ACC_SYNTHETIC"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const CLASS_MODIFIERS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0010, "final"),
+    (0x0400, "abstract"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const FIELD_MODIFIERS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0040, "volatile"),
+    (0x0080, "transient"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const METHOD_MODIFIERS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0020, "synchronized"),
+    (0x0100, "native"),
+    (0x0400, "abstract"),
+    (0x0800, "strictfp"),
+];
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+const INNER_CLASS_MODIFIERS: &'static [(U2, &'static str)] = &[
+    (0x0001, "public"),
+    (0x0002, "private"),
+    (0x0004, "protected"),
+    (0x0008, "static"),
+    (0x0010, "final"),
+    (0x0400, "abstract"),
+];
+
+/// Renders a class file in the layout of `javap -v -p`.
+///
+/// The output leaves out the header lines javap derives from the file itself (path, size and
+/// checksum), so it can be diffed against javap output from the `Compiled from` line onwards.
+pub fn disassemble(classfile: &ClassFile) -> ParserResult<String> {
+    let mut disassembler = Disassembler {
+        classfile: classfile,
+        output: String::new(),
+        line: String::new(),
+        indent: 0,
+    };
+
+    try!(disassembler.write_class());
+
+    Ok(disassembler.output)
+}
+
+struct Disassembler<'c> {
+    classfile: &'c ClassFile,
+    output: String,
+    line: String,
+    indent: usize,
+}
+
+impl<'c> Disassembler<'c> {
+    fn write_class(&mut self) -> ParserResult<()> {
+        let classfile = self.classfile;
+
+        for attribute in &classfile.attributes {
+            if let Some(info) = unknown_attribute_info(attribute, "SourceFile") {
+                let source_file = try!(self.utf8(try!(read_u2(info, 0))));
+                self.println(&format!("  Compiled from \"{}\"", source_file.as_str()));
+            }
+        }
+
+        try!(self.write_class_declaration());
+
+        self.indent(1);
+        self.println(&format!("minor version: {}", classfile.minor_version));
+        self.println(&format!("major version: {}", classfile.major_version));
+        self.println(&format!("flags: (0x{:04x}) {}",
+                              classfile.access_flags,
+                              flag_names(classfile.access_flags, CLASS_FLAG_NAMES)));
+
+        self.print(&format!("this_class: #{}", classfile.this_class));
+        self.tab();
+        let this_class = try!(self.constant_value(classfile.this_class));
+        self.println(&format!("// {}", this_class));
+
+        self.print(&format!("super_class: #{}", classfile.super_class));
+        if classfile.super_class != 0 {
+            self.tab();
+            let super_class = try!(self.constant_value(classfile.super_class));
+            self.print(&format!("// {}", super_class));
+        }
+        self.println("");
+
+        self.println(&format!("interfaces: {}, fields: {}, methods: {}, attributes: {}",
+                              classfile.interfaces.len(),
+                              classfile.fields.len(),
+                              classfile.methods.len(),
+                              classfile.attributes.len()));
+        self.indent(-1);
+
+        try!(self.write_constant_pool());
+
+        self.println("{");
+        self.indent(1);
+        let mut first = true;
+        for field in &classfile.fields {
+            if !first {
+                self.println("");
+            }
+            first = false;
+            try!(self.write_field(field));
+        }
+        for method in &classfile.methods {
+            if !first {
+                self.println("");
+            }
+            first = false;
+            try!(self.write_method(method));
+        }
+        self.indent(-1);
+        self.println("}");
+
+        for attribute in &classfile.attributes {
+            try!(self.write_attribute(attribute, None));
+        }
+
+        Ok(())
+    }
+
+    fn write_class_declaration(&mut self) -> ParserResult<()> {
+        let classfile = self.classfile;
+        let mut access = classfile.access();
+        let is_interface = access.contains(ClassAccess::INTERFACE);
+        if is_interface {
+            access.remove(ClassAccess::ABSTRACT);
+        }
+
+        let mut declaration = modifier_names(access.bits(), CLASS_MODIFIERS);
+        declaration.push_str(if is_interface { "interface " } else { "class " });
+        declaration.push_str(&java_class_name(&try!(classfile.classname())));
+
+        // Like `javap -v`, a generic class names its superclass even if it is `Object`
+        if let Ok(Some(signature)) = signatures::class_signature(classfile) {
+            let interfaces: Vec<_> =
+                signature.interfaces.iter().map(ClassTypeSignature::java_name).collect();
+            declaration.push_str(&signature.java_type_parameters());
+            if !is_interface {
+                declaration.push_str(" extends ");
+                declaration.push_str(&signature.superclass.java_name());
+            }
+            if !interfaces.is_empty() {
+                declaration.push_str(if is_interface { " extends " } else { " implements " });
+                declaration.push_str(&interfaces.join(", "));
+            }
+
+            self.println(&declaration);
+            return Ok(());
+        }
+
+        if classfile.super_class != 0 && !is_interface {
+            let resolver = classfile.constant_pool_resolver();
+            let super_class = try!(resolver.resolve_class_name(classfile.super_class));
+            if super_class.as_str() != "java/lang/Object" {
+                declaration.push_str(" extends ");
+                declaration.push_str(&java_class_name(&super_class));
+            }
+        }
+
+        if !classfile.interfaces.is_empty() {
+            let resolver = classfile.constant_pool_resolver();
+            let mut interfaces = vec![];
+            for interface in &classfile.interfaces {
+                interfaces.push(java_class_name(&try!(resolver.resolve_class_name(*interface))));
+            }
+
+            declaration.push_str(if is_interface { " extends " } else { " implements " });
+            // Unlike type arguments, javap leaves no space between erased interface names
+            declaration.push_str(&interfaces.join(","));
+        }
+
+        self.println(&declaration);
+
+        Ok(())
+    }
+
+    fn write_constant_pool(&mut self) -> ParserResult<()> {
+        let constant_pool = &self.classfile.constant_pool;
+        let width = (constant_pool.len() + 1).to_string().len() + 1;
+
+        self.println("Constant pool:");
+        self.indent(1);
+
+        for (i, item) in constant_pool.iter().enumerate() {
+            let index = (i + 1) as U2;
+
+            let operands = match *item {
+                ConstantPoolItem::Empty => continue,
+                ConstantPoolItem::Class(ref info) => Some(format!("#{}", info.name_index)),
+                ConstantPoolItem::Field(ref info) |
+                ConstantPoolItem::Method(ref info) |
+                ConstantPoolItem::InterfaceMethod(ref info) => {
+                    Some(format!("#{}.#{}", info.class_index, info.name_and_type_index))
+                }
+                ConstantPoolItem::String(ref info) => Some(format!("#{}", info.string_index)),
+                ConstantPoolItem::NameAndType(ref info) => {
+                    Some(format!("#{}:#{}", info.name_index, info.descriptor_index))
+                }
+                ConstantPoolItem::MethodHandle { reference_kind, reference_index, .. } => {
+                    Some(format!("{}:#{}", reference_kind, reference_index))
+                }
+                ConstantPoolItem::MethodType { descriptor_index, .. } => {
+                    Some(format!("#{}", descriptor_index))
+                }
+                ConstantPoolItem::InvokeDynamic { bootstrap_method_attr_index,
+                                                  name_and_type_index,
+                                                  .. } => {
+                    Some(format!("#{}:#{}", bootstrap_method_attr_index, name_and_type_index))
+                }
+                _ => None,
+            };
+
+            self.print(&format!("{:>width$} = {:<18} ",
+                                format!("#{}", index),
+                                constant_tag_name(item),
+                                width = width));
+
+            let value = try!(self.constant_value(index));
+            match operands {
+                Some(operands) => {
+                    self.print(&operands);
+                    self.tab();
+                    // javap separates method type descriptors with an extra space
+                    if let ConstantPoolItem::MethodType { .. } = *item {
+                        self.println(&format!("//  {}", value));
+                    } else {
+                        self.println(&format!("// {}", value));
+                    }
+                }
+                None => self.println(&value),
+            }
+        }
+
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    fn write_field(&mut self, field: &Field) -> ParserResult<()> {
+        let mut declaration = modifier_names(field.access_flags, FIELD_MODIFIERS);
+        match signatures::field_signature(self.classfile, field) {
+            Ok(Some(signature)) => declaration.push_str(&signature.java_name()),
+            _ => declaration.push_str(&java_type(&field.descriptor)),
+        }
+        declaration.push_str(" ");
+        declaration.push_str(field.name.as_str());
+        declaration.push_str(";");
+        self.println(&declaration);
+
+        self.indent(1);
+        self.println(&format!("descriptor: {}", field.descriptor.as_str()));
+        self.println(&format!("flags: (0x{:04x}) {}",
+                              field.access_flags,
+                              flag_names(field.access_flags, FIELD_FLAG_NAMES)));
+        for attribute in &field.attributes {
+            try!(self.write_attribute(attribute, None));
+        }
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    fn write_method(&mut self, method: &Method) -> ParserResult<()> {
+        let classfile = self.classfile;
+        let is_interface = classfile.access().contains(ClassAccess::INTERFACE);
+
+        let mut declaration = modifier_names(method.access_flags, METHOD_MODIFIERS);
+        let not_default = MethodAccess::PRIVATE | MethodAccess::STATIC | MethodAccess::ABSTRACT;
+        if is_interface && !method.access().intersects(not_default) {
+            declaration.push_str("default ");
+        }
+
+        let signature = signatures::method_signature(classfile, method).unwrap_or(None);
+        if let Some(ref signature) = signature {
+            if !signature.type_parameters.is_empty() {
+                declaration.push_str(&signature.java_type_parameters());
+                declaration.push_str(" ");
+            }
+        }
+
+        if method.name.as_str() == "<clinit>" {
+            declaration.push_str("{}");
+        } else {
+            let (parameters, return_type) = match signature {
+                Some(ref signature) => (signature.java_params(), signature.java_return_type()),
+                None => java_method_types(&method.descriptor),
+            };
+
+            if method.name.as_str() == "<init>" {
+                declaration.push_str(&java_class_name(&try!(classfile.classname())));
+            } else {
+                declaration.push_str(&return_type);
+                declaration.push_str(" ");
+                declaration.push_str(method.name.as_str());
+            }
+
+            let mut parameters = parameters;
+            let is_varargs = method.access().contains(MethodAccess::VARARGS);
+            if let Some(last) = parameters.last_mut() {
+                if is_varargs && last.ends_with("[]") {
+                    let length = last.len() - 2;
+                    last.truncate(length);
+                    last.push_str("...");
+                }
+            }
+
+            declaration.push_str("(");
+            declaration.push_str(&parameters.join(", "));
+            declaration.push_str(")");
+
+            for attribute in &method.attributes {
+                if let Some(info) = unknown_attribute_info(attribute, "Exceptions") {
+                    // The signature lists the exceptions when any of them is generic
+                    let exceptions = match signature {
+                        Some(ref signature) if !signature.throws.is_empty() => {
+                            signature.java_throws()
+                        }
+                        _ => try!(self.exception_names(info)),
+                    };
+                    declaration.push_str(" throws ");
+                    declaration.push_str(&exceptions.join(", "));
+                }
+            }
+        }
+        declaration.push_str(";");
+        self.println(&declaration);
+
+        self.indent(1);
+        self.println(&format!("descriptor: {}", method.descriptor.as_str()));
+        self.println(&format!("flags: (0x{:04x}) {}",
+                              method.access_flags,
+                              flag_names(method.access_flags, METHOD_FLAG_NAMES)));
+        for attribute in &method.attributes {
+            try!(self.write_attribute(attribute, Some(method)));
+        }
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    fn write_attribute(&mut self,
+                       attribute: &Attribute,
+                       method: Option<&Method>)
+                       -> ParserResult<()> {
+        match *attribute {
+            Attribute::Code(ref code) => try!(self.write_code(code, method)),
+            Attribute::LineNumberTable(ref table) => {
+                self.println("LineNumberTable:");
+                self.indent(1);
+                for line_number in &table.line_number_table {
+                    self.println(&format!("line {}: {}",
+                                          line_number.line_number,
+                                          line_number.start_pc));
+                }
+                self.indent(-1);
+            }
+            Attribute::LocalVariableTable(ref table) |
+            Attribute::LocalVariableTypeTable(ref table) => {
+                if let Attribute::LocalVariableTable(..) = *attribute {
+                    self.println("LocalVariableTable:");
+                } else {
+                    self.println("LocalVariableTypeTable:");
+                }
+
+                self.indent(1);
+                self.println("Start  Length  Slot  Name   Signature");
+                for local_variable in &table.local_variable_table {
+                    let name = try!(self.utf8(local_variable.name_index));
+                    let descriptor = try!(self.utf8(local_variable.descriptor_index));
+                    self.println(&format!("{:>5} {:>7} {:>5} {:>5}   {}",
+                                          local_variable.start_pc,
+                                          local_variable.length,
+                                          local_variable.index,
+                                          name.as_str(),
+                                          descriptor.as_str()));
+                }
+                self.indent(-1);
+            }
+            Attribute::StackMapTable(ref table) => {
+                self.println(&format!("StackMapTable: number_of_entries = {}",
+                                      table.entries.len()));
+                self.indent(1);
+                for frame in &table.entries {
+                    try!(self.write_stack_map_frame(frame));
+                }
+                self.indent(-1);
+            }
+            Attribute::Unknown { ref attribute_name, ref info } => {
+                try!(self.write_unknown_attribute(attribute_name, info))
+            }
+            Attribute::Lazy(ref lazy) => try!(self.write_attribute(&*try!(lazy.decode()), method)),
+        }
+
+        Ok(())
+    }
+
+    fn write_code(&mut self, code: &CodeAttribute, method: Option<&Method>) -> ParserResult<()> {
+        let args_size = match method {
+            Some(method) => {
+                let is_static = method.access().contains(MethodAccess::STATIC);
+                // javap counts parameters here rather than the local variable slots they occupy
+                java_method_types(&method.descriptor).0.len() + if is_static { 0 } else { 1 }
+            }
+            None => 0,
+        };
+
+        self.println("Code:");
+        self.indent(1);
+        self.println(&format!("stack={}, locals={}, args_size={}",
+                              code.max_stack,
+                              code.max_locals,
+                              args_size));
+
+        for result in code.instructions() {
+            let (pc, instruction) = try!(result);
+            let wide = code.code[pc as usize] == WIDE;
+            try!(self.write_instruction(pc, &instruction, wide));
+        }
+
+        if !code.exception_table.is_empty() {
+            self.println("Exception table:");
+            self.indent(1);
+            self.println(" from    to  target type");
+            for handler in &code.exception_table {
+                self.print(&format!(" {:>5} {:>5} {:>5}   ",
+                                    handler.start_pc,
+                                    handler.end_pc,
+                                    handler.handler_pc));
+                if handler.catch_type == 0 {
+                    self.println("any");
+                } else {
+                    let catch_type = try!(self.constant_value(handler.catch_type));
+                    self.println(&format!("Class {}", catch_type));
+                }
+            }
+            self.indent(-1);
+        }
+
+        for attribute in &code.attributes {
+            try!(self.write_attribute(attribute, None));
+        }
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    fn write_instruction(&mut self,
+                         pc: U2,
+                         instruction: &Instruction,
+                         wide: bool)
+                         -> ParserResult<()> {
+        // javap names the wide forms after the instruction they widen, e.g. `iinc_w`
+        let mnemonic = if wide {
+            format!("{}_w", instruction.mnemonic())
+        } else {
+            instruction.mnemonic().to_owned()
+        };
+        self.print(&format!("{:>4}: {:<13}", pc, mnemonic));
+
+        let absolute = |offset: i32| pc as i64 + offset as i64;
+        match *instruction {
+            Instruction::Bipush(value) => self.print(&format!(" {}", value)),
+            Instruction::Sipush(value) => self.print(&format!(" {}", value)),
+            Instruction::Iload(index) |
+            Instruction::Lload(index) |
+            Instruction::Fload(index) |
+            Instruction::Dload(index) |
+            Instruction::Aload(index) |
+            Instruction::Istore(index) |
+            Instruction::Lstore(index) |
+            Instruction::Fstore(index) |
+            Instruction::Dstore(index) |
+            Instruction::Astore(index) |
+            Instruction::Ret(index) => self.print(&format!(" {}", index)),
+            Instruction::Iinc(index, constant) => {
+                self.print(&format!(" {}, {}", index, constant))
+            }
+            Instruction::Newarray(atype) => self.print(&format!("  {}", atype.to_friendly_name())),
+            Instruction::Tableswitch { default, low, ref offsets } => {
+                let high = low as i64 + offsets.len() as i64 - 1;
+                self.println(&format!(" {{ // {} to {}", low, high));
+                self.indent(3);
+                for (i, offset) in offsets.iter().enumerate() {
+                    self.println(&format!("{:>12}: {}", low as i64 + i as i64, absolute(*offset)));
+                }
+                self.println(&format!("{:>12}: {}", "default", absolute(default)));
+                self.print("}");
+                self.indent(-3);
+            }
+            Instruction::Lookupswitch { default, ref pairs } => {
+                self.println(&format!(" {{ // {}", pairs.len()));
+                self.indent(3);
+                for &(key, offset) in pairs {
+                    self.println(&format!("{:>12}: {}", key, absolute(offset)));
+                }
+                self.println(&format!("{:>12}: {}", "default", absolute(default)));
+                self.print("}");
+                self.indent(-3);
+            }
+            Instruction::Invokeinterface(index, count) |
+            Instruction::Multianewarray(index, count) => {
+                self.print(&format!(" #{},  {}", index, count));
+                try!(self.write_operand_comment(index));
+            }
+            Instruction::Invokedynamic(index) => {
+                self.print(&format!(" #{},  0", index));
+                try!(self.write_operand_comment(index));
+            }
+            _ => {
+                if let Some(target) = instruction.branch_targets().first() {
+                    self.print(&format!(" {}", absolute(**target)));
+                } else if let Some(index) = instruction.constant_pool_index() {
+                    self.print(&format!(" #{}", index));
+                    try!(self.write_operand_comment(index));
+                }
+            }
+        }
+
+        self.println("");
+
+        Ok(())
+    }
+
+    fn write_operand_comment(&mut self, index: U2) -> ParserResult<()> {
+        let comment = try!(self.constant_reference(index));
+        self.tab();
+        self.print(&format!("// {}", comment));
+
+        Ok(())
+    }
+
+    fn write_stack_map_frame(&mut self, frame: &StackMapFrame) -> ParserResult<()> {
+        let name = match *frame {
+            StackMapFrame::Same { .. } => "same",
+            StackMapFrame::SameLocals1StackItem { .. } => "same_locals_1_stack_item",
+            StackMapFrame::SameLocals1StackItemExtended { .. } => {
+                "same_locals_1_stack_item_frame_extended"
+            }
+            StackMapFrame::Chop { .. } => "chop",
+            StackMapFrame::SameExtended { .. } => "same_frame_extended",
+            StackMapFrame::Append { .. } => "append",
+            StackMapFrame::Full { .. } => "full_frame",
+        };
+        self.println(&format!("frame_type = {} /* {} */", frame.frame_type(), name));
+
+        self.indent(1);
+        match *frame {
+            StackMapFrame::Same { .. } => {}
+            StackMapFrame::SameLocals1StackItem { ref stack, .. } => {
+                try!(self.write_verification_types("stack", &[stack.clone()]));
+            }
+            StackMapFrame::SameLocals1StackItemExtended { offset_delta, ref stack } => {
+                self.println(&format!("offset_delta = {}", offset_delta));
+                try!(self.write_verification_types("stack", &[stack.clone()]));
+            }
+            StackMapFrame::Chop { offset_delta, .. } |
+            StackMapFrame::SameExtended { offset_delta } => {
+                self.println(&format!("offset_delta = {}", offset_delta));
+            }
+            StackMapFrame::Append { offset_delta, ref locals, .. } => {
+                self.println(&format!("offset_delta = {}", offset_delta));
+                try!(self.write_verification_types("locals", locals));
+            }
+            StackMapFrame::Full { offset_delta, ref locals, ref stack } => {
+                self.println(&format!("offset_delta = {}", offset_delta));
+                try!(self.write_verification_types("locals", locals));
+                try!(self.write_verification_types("stack", stack));
+            }
+        }
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    fn write_verification_types(&mut self,
+                                name: &str,
+                                types: &[VerificationTypeInfo])
+                                -> ParserResult<()> {
+        let mut names = vec![];
+        for verification_type in types {
+            names.push(match *verification_type {
+                VerificationTypeInfo::Top => "top".to_string(),
+                VerificationTypeInfo::Integer => "int".to_string(),
+                VerificationTypeInfo::Float => "float".to_string(),
+                VerificationTypeInfo::Double => "double".to_string(),
+                VerificationTypeInfo::Long => "long".to_string(),
+                VerificationTypeInfo::Null => "null".to_string(),
+                VerificationTypeInfo::UninitializedThis => "this".to_string(),
+                VerificationTypeInfo::Object(index) => try!(self.constant_reference(index)),
+                VerificationTypeInfo::Uninitialized(offset) => format!("uninitialized {}", offset),
+            });
+        }
+
+        if names.is_empty() {
+            self.println(&format!("{} = []", name));
+        } else {
+            self.println(&format!("{} = [ {} ]", name, names.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    fn write_unknown_attribute(&mut self,
+                               attribute_name: &Utf8Info,
+                               info: &[U1])
+                               -> ParserResult<()> {
+        match attribute_name.as_str() {
+            "SourceFile" => {
+                let source_file = try!(self.utf8(try!(read_u2(info, 0))));
+                self.println(&format!("SourceFile: \"{}\"", source_file.as_str()));
+            }
+            "Signature" => {
+                let index = try!(read_u2(info, 0));
+                self.print(&format!("Signature: #{}", index));
+                self.tab();
+                let signature = try!(self.utf8(index));
+                self.println(&format!("// {}", signature.as_str()));
+            }
+            "ConstantValue" => {
+                let value = try!(self.constant_reference(try!(read_u2(info, 0))));
+                self.println(&format!("ConstantValue: {}", value));
+            }
+            "Exceptions" => {
+                let exceptions = try!(self.exception_names(info));
+                self.println("Exceptions:");
+                self.indent(1);
+                self.println(&format!("throws {}", exceptions.join(", ")));
+                self.indent(-1);
+            }
+            "Deprecated" | "Synthetic" => {
+                self.println(&format!("{}: true", attribute_name.as_str()));
+            }
+            "EnclosingMethod" => {
+                let class_index = try!(read_u2(info, 0));
+                let method_index = try!(read_u2(info, 2));
+                self.print(&format!("EnclosingMethod: #{}.#{}", class_index, method_index));
+                self.tab();
+
+                let resolver = self.classfile.constant_pool_resolver();
+                let mut comment = java_class_name(&try!(resolver.resolve_class_name(class_index)));
+                if method_index != 0 {
+                    let (name, _) = try!(resolver.resolve_name_and_type(method_index));
+                    comment.push_str(".");
+                    comment.push_str(name.as_str());
+                }
+                self.println(&format!("// {}", comment));
+            }
+            "NestHost" => {
+                let host = try!(self.constant_reference(try!(read_u2(info, 0))));
+                self.println(&format!("NestHost: {}", host));
+            }
+            "NestMembers" => {
+                self.println("NestMembers:");
+                self.indent(1);
+                let count = try!(read_u2(info, 0)) as usize;
+                for i in 0..count {
+                    let member = try!(self.constant_value(try!(read_u2(info, 2 + i * 2))));
+                    self.println(&member);
+                }
+                self.indent(-1);
+            }
+            "BootstrapMethods" => try!(self.write_bootstrap_methods(info)),
+            "InnerClasses" => try!(self.write_inner_classes(info)),
+            _ => {
+                self.println(&format!("{}: length = 0x{:X} (unknown attribute)",
+                                      attribute_name.as_str(),
+                                      info.len()));
+                for chunk in info.chunks(16) {
+                    let bytes = chunk.iter().map(|byte| format!("{:02X}", byte)).collect::<Vec<_>>();
+                    self.println(&format!(" {}", bytes.join(" ")));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write_bootstrap_methods(&mut self, info: &[U1]) -> ParserResult<()> {
+        self.println("BootstrapMethods:");
+
+        let count = try!(read_u2(info, 0));
+        let mut position = 2;
+        for i in 0..count {
+            let method_ref = try!(read_u2(info, position));
+            let argument_count = try!(read_u2(info, position + 2)) as usize;
+            position += 4;
+
+            self.indent(1);
+            let method = try!(self.constant_value(method_ref));
+            self.println(&format!("{}: #{} {}", i, method_ref, method));
+
+            self.indent(1);
+            self.println("Method arguments:");
+            self.indent(1);
+            for _ in 0..argument_count {
+                let argument = try!(read_u2(info, position));
+                position += 2;
+
+                let value = try!(self.constant_value(argument));
+                self.println(&format!("#{} {}", argument, value));
+            }
+            self.indent(-3);
+        }
+
+        Ok(())
+    }
+
+    fn write_inner_classes(&mut self, info: &[U1]) -> ParserResult<()> {
+        self.println("InnerClasses:");
+        self.indent(1);
+
+        let count = try!(read_u2(info, 0)) as usize;
+        for i in 0..count {
+            let position = 2 + i * 8;
+            let inner_class_index = try!(read_u2(info, position));
+            let outer_class_index = try!(read_u2(info, position + 2));
+            let inner_name_index = try!(read_u2(info, position + 4));
+            let mut access = InnerClassAccess::from(try!(read_u2(info, position + 6)));
+            if access.contains(InnerClassAccess::INTERFACE) {
+                access.remove(InnerClassAccess::ABSTRACT);
+            }
+
+            let mut entry = modifier_names(access.bits(), INNER_CLASS_MODIFIERS);
+            let mut comment = String::from("// ");
+            if inner_name_index != 0 {
+                entry.push_str(&format!("#{}= ", inner_name_index));
+                comment.push_str(&format!("{}=", try!(self.utf8(inner_name_index)).as_str()));
+            }
+
+            entry.push_str(&format!("#{}", inner_class_index));
+            comment.push_str(&try!(self.constant_reference(inner_class_index)));
+
+            if outer_class_index != 0 {
+                entry.push_str(&format!(" of #{}", outer_class_index));
+                comment.push_str(" of ");
+                comment.push_str(&try!(self.constant_reference(outer_class_index)));
+            }
+            entry.push_str(";");
+
+            self.print(&entry);
+            self.tab();
+            self.println(&comment);
+        }
+
+        self.indent(-1);
+
+        Ok(())
+    }
+
+    /// The value of a constant pool entry as javap shows it in the constant pool listing.
+    fn constant_value(&self, index: U2) -> ParserResult<String> {
+        let constant_pool = &self.classfile.constant_pool;
+        let resolver = self.classfile.constant_pool_resolver();
+
+        let value = match *try!(ConstantPoolItem::retrieve_item(index as usize, constant_pool)) {
+            ConstantPoolItem::Empty => String::new(),
+            ConstantPoolItem::Utf8(ref info) => escape(info.as_str()),
+            ConstantPoolItem::Integer(ref info) => (info.bytes as i32).to_string(),
+            ConstantPoolItem::Float(ref info) => {
+                format!("{}f", java_float(f32::from_bits(info.bytes) as f64, true))
+            }
+            ConstantPoolItem::Long(ref info) => {
+                format!("{}l", ((info.high_bytes as u64) << 32 | info.low_bytes as u64) as i64)
+            }
+            ConstantPoolItem::Double(ref info) => {
+                let bits = (info.high_bytes as u64) << 32 | info.low_bytes as u64;
+                format!("{}d", java_float(f64::from_bits(bits), false))
+            }
+            ConstantPoolItem::Class(ref info) => check_name(&try!(self.utf8(info.name_index))),
+            ConstantPoolItem::String(ref info) => escape(&try!(self.utf8(info.string_index))),
+            ConstantPoolItem::Field(ref info) |
+            ConstantPoolItem::Method(ref info) |
+            ConstantPoolItem::InterfaceMethod(ref info) => {
+                let class_name = try!(resolver.resolve_class_name(info.class_index));
+                format!("{}.{}",
+                        check_name(&class_name),
+                        try!(self.constant_value(info.name_and_type_index)))
+            }
+            ConstantPoolItem::NameAndType(ref info) => {
+                format!("{}:{}",
+                        check_name(&try!(self.utf8(info.name_index))),
+                        try!(self.utf8(info.descriptor_index)).as_str())
+            }
+            ConstantPoolItem::MethodHandle { reference_kind, reference_index, .. } => {
+                format!("{} {}",
+                        reference_kind_name(reference_kind),
+                        try!(self.constant_value(reference_index)))
+            }
+            ConstantPoolItem::MethodType { descriptor_index, .. } => {
+                try!(self.utf8(descriptor_index)).to_string()
+            }
+            ConstantPoolItem::InvokeDynamic { bootstrap_method_attr_index,
+                                              name_and_type_index,
+                                              .. } => {
+                format!("#{}:{}",
+                        bootstrap_method_attr_index,
+                        try!(self.constant_value(name_and_type_index)))
+            }
+        };
+
+        Ok(value)
+    }
+
+    /// A constant pool entry as javap shows it when it is referenced from code or attributes,
+    /// prefixed with its kind and leaving out the class of members of this class.
+    fn constant_reference(&self, index: U2) -> ParserResult<String> {
+        let constant_pool = &self.classfile.constant_pool;
+        let item = try!(ConstantPoolItem::retrieve_item(index as usize, constant_pool));
+
+        let kind = match *item {
+            ConstantPoolItem::Class(..) => "class",
+            ConstantPoolItem::Field(..) => "Field",
+            ConstantPoolItem::Method(..) => "Method",
+            ConstantPoolItem::InterfaceMethod(..) => "InterfaceMethod",
+            ConstantPoolItem::String(..) => "String",
+            ConstantPoolItem::Integer(..) => "int",
+            ConstantPoolItem::Float(..) => "float",
+            ConstantPoolItem::Long(..) => "long",
+            ConstantPoolItem::Double(..) => "double",
+            ConstantPoolItem::MethodHandle { .. } => "MethodHandle",
+            ConstantPoolItem::MethodType { .. } => "MethodType",
+            ConstantPoolItem::InvokeDynamic { .. } => "InvokeDynamic",
+            _ => item.to_friendly_name(),
+        };
+
+        let value = match *item {
+            ConstantPoolItem::Field(ref info) |
+            ConstantPoolItem::Method(ref info) |
+            ConstantPoolItem::InterfaceMethod(ref info) if info.class_index ==
+                                                           self.classfile.this_class => {
+                try!(self.constant_value(info.name_and_type_index))
+            }
+            _ => try!(self.constant_value(index)),
+        };
+
+        Ok(format!("{} {}", kind, value))
+    }
+
+    fn exception_names(&self, info: &[U1]) -> ParserResult<Vec<String>> {
+        let resolver = self.classfile.constant_pool_resolver();
+
+        let count = try!(read_u2(info, 0)) as usize;
+        let mut names = vec![];
+        for i in 0..count {
+            let class_name = try!(resolver.resolve_class_name(try!(read_u2(info, 2 + i * 2))));
+            names.push(java_class_name(&class_name));
+        }
+
+        Ok(names)
+    }
+
+    fn utf8(&self, index: U2) -> ParserResult<Rc<Utf8Info>> {
+        ConstantPoolItem::retrieve_utf8_info(index, &self.classfile.constant_pool)
+    }
+
+    fn print(&mut self, text: &str) {
+        if self.line.is_empty() && !text.is_empty() {
+            for _ in 0..(self.indent * INDENT_WIDTH) {
+                self.line.push(' ');
+            }
+        }
+
+        self.line.push_str(text);
+    }
+
+    fn println(&mut self, text: &str) {
+        self.print(text);
+        while self.line.ends_with(' ') {
+            self.line.pop();
+        }
+        self.output.push_str(&self.line);
+        self.output.push('\n');
+        self.line.clear();
+    }
+
+    fn tab(&mut self) {
+        let column = self.indent * INDENT_WIDTH + TAB_COLUMN;
+        if self.line.len() >= column {
+            self.line.push(' ');
+        }
+        while self.line.len() < column {
+            self.line.push(' ');
+        }
+    }
+
+    fn indent(&mut self, delta: isize) {
+        self.indent = (self.indent as isize + delta) as usize;
+    }
+}
+
+fn unknown_attribute_info<'a>(attribute: &'a Attribute, name: &str) -> Option<&'a [U1]> {
+    match *attribute {
+        Attribute::Unknown { ref attribute_name, ref info } if attribute_name.as_str() == name => {
+            Some(info)
+        }
+        // attributes without a dedicated representation decode to their own bytes
+        Attribute::Lazy(ref lazy) if lazy.attribute_name.as_str() == name => Some(lazy.info()),
+        _ => None,
+    }
+}
+
+fn read_u2(info: &[U1], position: usize) -> ParserResult<U2> {
+    if position + 2 > info.len() {
+        return Err(ParserError::TruncatedAttribute(position));
+    }
+
+    Ok(((info[position] as U2) << 8) | info[position + 1] as U2)
+}
+
+fn flag_names(access_flags: U2, names: &[(U2, &'static str)]) -> String {
+    names.iter()
+        .filter(|&&(flag, _)| (access_flags & flag) != 0)
+        .map(|&(_, name)| name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn modifier_names(access_flags: U2, names: &[(U2, &'static str)]) -> String {
+    let mut modifiers = String::new();
+    for &(flag, name) in names {
+        if (access_flags & flag) != 0 {
+            modifiers.push_str(name);
+            modifiers.push(' ');
+        }
+    }
+
+    modifiers
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn constant_tag_name(item: &ConstantPoolItem) -> &'static str {
+    match *item {
+        ConstantPoolItem::Field(..) => "Fieldref",
+        ConstantPoolItem::Method(..) => "Methodref",
+        ConstantPoolItem::InterfaceMethod(..) => "InterfaceMethodref",
+        _ => item.to_friendly_name(),
+    }
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn reference_kind_name(reference_kind: U1) -> &'static str {
+    match reference_kind {
+        1 => "REF_getField",
+        2 => "REF_getStatic",
+        3 => "REF_putField",
+        4 => "REF_putStatic",
+        5 => "REF_invokeVirtual",
+        6 => "REF_invokeStatic",
+        7 => "REF_invokeSpecial",
+        8 => "REF_newInvokeSpecial",
+        9 => "REF_invokeInterface",
+        _ => "REF_unknown",
+    }
+}
+
+fn java_class_name(internal_name: &str) -> String {
+    internal_name.replace('/', ".")
+}
+
+/// Quotes names that javap would not print bare, such as `"<init>"` or array class names.
+fn check_name(name: &str) -> String {
+    let mut previous = '/';
+    for c in name.chars() {
+        let is_start = c.is_alphabetic() || c == '_' || c == '$';
+        let is_part = is_start || c.is_numeric();
+
+        if (previous == '/' && !is_start) || (c != '/' && !is_part) {
+            return format!("\"{}\"", escape(name));
+        }
+        previous = c;
+    }
+
+    if name.is_empty() {
+        return "\"\"".to_string();
+    }
+
+    name.to_string()
+}
+
+fn escape(value: &str) -> String {
+    let mut escaped = String::new();
+    for c in value.chars() {
+        match c {
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\u{8}' => escaped.push_str("\\b"),
+            '\u{c}' => escaped.push_str("\\f"),
+            '"' => escaped.push_str("\\\""),
+            '\'' => escaped.push_str("\\'"),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// Formats a floating point value the way `Float.toString` and `Double.toString` do.
+fn java_float(value: f64, single_precision: bool) -> String {
+    if value.is_nan() {
+        return "NaN".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "Infinity" } else { "-Infinity" }.to_string();
+    }
+
+    let magnitude = value.abs();
+    if magnitude == 0.0 || (magnitude >= 1e-3 && magnitude < 1e7) {
+        return if single_precision {
+            format!("{:?}", value as f32)
+        } else {
+            format!("{:?}", value)
+        };
+    }
+
+    let scientific = if single_precision {
+        format!("{:e}", value as f32)
+    } else {
+        format!("{:e}", value)
+    };
+    let mut parts = scientific.splitn(2, 'e');
+    let mantissa = parts.next().unwrap_or("");
+    let exponent = parts.next().unwrap_or("0");
+
+    if mantissa.contains('.') {
+        format!("{}E{}", mantissa, exponent)
+    } else {
+        format!("{}.0E{}", mantissa, exponent)
+    }
+}
+
+fn java_type(descriptor: &str) -> String {
+    FieldType::from(descriptor)
+        .map(|field_type| field_type.java_name())
+        .unwrap_or_else(|_| descriptor.to_string())
+}
+
+fn java_method_types(descriptor: &str) -> (Vec<String>, String) {
+    match MethodDescriptor::from(descriptor) {
+        Ok(method_descriptor) => {
+            (method_descriptor.java_params(), method_descriptor.java_return_type())
+        }
+        Err(_) => (vec![], descriptor.to_string()),
+    }
+}
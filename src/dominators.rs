@@ -0,0 +1,247 @@
+use super::controlflow::{BlockId, ControlFlowGraph};
+
+/// The dominator tree of a control-flow graph, computed with the iterative algorithm of Cooper,
+/// Harvey and Kennedy.
+///
+/// The same structure holds post-dominators, which are the dominators of the reversed graph
+/// rooted at the exit block. Blocks that cannot be reached from the root, such as the body of an
+/// infinite loop when computing post-dominators, are not part of the tree.
+#[derive(Clone, Debug)]
+pub struct DominatorTree {
+    root: BlockId,
+    immediate_dominators: Vec<Option<BlockId>>,
+    children: Vec<Vec<BlockId>>,
+    intervals: Vec<(usize, usize)>,
+}
+
+impl DominatorTree {
+    pub fn dominators(graph: &ControlFlowGraph) -> DominatorTree {
+        DominatorTree::build(graph.blocks.len(),
+                             graph.entry(),
+                             |id| graph.successors(id).iter().map(|edge| edge.target).collect(),
+                             |id| graph.predecessors(id).iter().map(|edge| edge.source).collect())
+    }
+
+    pub fn post_dominators(graph: &ControlFlowGraph) -> DominatorTree {
+        DominatorTree::build(graph.blocks.len(),
+                             graph.exit(),
+                             |id| graph.predecessors(id).iter().map(|edge| edge.source).collect(),
+                             |id| graph.successors(id).iter().map(|edge| edge.target).collect())
+    }
+
+    fn build<F, G>(count: usize, root: BlockId, successors: F, predecessors: G) -> DominatorTree
+        where F: Fn(BlockId) -> Vec<BlockId>,
+              G: Fn(BlockId) -> Vec<BlockId>
+    {
+        let postorder = depth_first_postorder(count, root, &successors);
+
+        let mut postorder_numbers = vec![None; count];
+        for (number, id) in postorder.iter().enumerate() {
+            postorder_numbers[*id] = Some(number);
+        }
+
+        let mut immediate_dominators = vec![None; count];
+        immediate_dominators[root] = Some(root);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+
+            for id in postorder.iter().rev().filter(|id| **id != root) {
+                let mut new_dominator = None;
+                for predecessor in predecessors(*id) {
+                    if immediate_dominators[predecessor].is_none() {
+                        continue;
+                    }
+
+                    new_dominator = match new_dominator {
+                        None => Some(predecessor),
+                        Some(dominator) => {
+                            Some(intersect(dominator,
+                                           predecessor,
+                                           &immediate_dominators,
+                                           &postorder_numbers))
+                        }
+                    };
+                }
+
+                if new_dominator.is_some() && immediate_dominators[*id] != new_dominator {
+                    immediate_dominators[*id] = new_dominator;
+                    changed = true;
+                }
+            }
+        }
+
+        immediate_dominators[root] = None;
+
+        let mut children = vec![vec![]; count];
+        for id in 0..count {
+            if let Some(dominator) = immediate_dominators[id] {
+                children[dominator].push(id);
+            }
+        }
+
+        let mut tree = DominatorTree {
+            root: root,
+            immediate_dominators: immediate_dominators,
+            children: children,
+            intervals: vec![(0, 0); count],
+        };
+        tree.number_intervals();
+
+        tree
+    }
+
+    pub fn root(&self) -> BlockId {
+        self.root
+    }
+
+    /// The closest strict dominator of `id`, or `None` for the root and unreachable blocks.
+    pub fn immediate_dominator(&self, id: BlockId) -> Option<BlockId> {
+        self.immediate_dominators[id]
+    }
+
+    /// The blocks whose immediate dominator is `id`.
+    pub fn children(&self, id: BlockId) -> &[BlockId] {
+        &self.children[id]
+    }
+
+    pub fn is_reachable(&self, id: BlockId) -> bool {
+        id == self.root || self.immediate_dominators[id].is_some()
+    }
+
+    /// Whether every path from the root to `dominated` goes through `dominator`. A block
+    /// dominates itself.
+    pub fn dominates(&self, dominator: BlockId, dominated: BlockId) -> bool {
+        if !self.is_reachable(dominator) || !self.is_reachable(dominated) {
+            return false;
+        }
+
+        let (start, end) = self.intervals[dominator];
+        let (position, _) = self.intervals[dominated];
+        start <= position && position < end
+    }
+
+    pub fn strictly_dominates(&self, dominator: BlockId, dominated: BlockId) -> bool {
+        dominator != dominated && self.dominates(dominator, dominated)
+    }
+
+    /// Numbers the tree in preorder, so that the blocks a block dominates are exactly those
+    /// numbered from its own number up to the end of its interval.
+    fn number_intervals(&mut self) {
+        let mut counter = 0;
+        let mut stack = vec![(self.root, 0)];
+
+        while let Some((id, child)) = stack.pop() {
+            if child == 0 {
+                self.intervals[id].0 = counter;
+                counter += 1;
+            }
+
+            match self.children[id].get(child).cloned() {
+                Some(next) => {
+                    stack.push((id, child + 1));
+                    stack.push((next, 0));
+                }
+                None => self.intervals[id].1 = counter,
+            }
+        }
+    }
+}
+
+/// The blocks reachable from `root` in depth-first postorder.
+pub fn depth_first_postorder<F>(count: usize, root: BlockId, successors: F) -> Vec<BlockId>
+    where F: Fn(BlockId) -> Vec<BlockId>
+{
+    let mut visited = vec![false; count];
+    let mut postorder = vec![];
+    let mut stack = vec![(root, successors(root), 0)];
+    visited[root] = true;
+
+    while let Some((id, next, position)) = stack.pop() {
+        match next.get(position).cloned() {
+            Some(successor) => {
+                stack.push((id, next, position + 1));
+                if !visited[successor] {
+                    visited[successor] = true;
+                    stack.push((successor, successors(successor), 0));
+                }
+            }
+            None => postorder.push(id),
+        }
+    }
+
+    postorder
+}
+
+fn intersect(mut first: BlockId,
+             mut second: BlockId,
+             immediate_dominators: &Vec<Option<BlockId>>,
+             postorder_numbers: &Vec<Option<usize>>)
+             -> BlockId {
+    while first != second {
+        while postorder_numbers[first] < postorder_numbers[second] {
+            first = immediate_dominators[first].unwrap();
+        }
+        while postorder_numbers[second] < postorder_numbers[first] {
+            second = immediate_dominators[second].unwrap();
+        }
+    }
+
+    first
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::DominatorTree;
+    use super::super::components::CodeAttribute;
+    use super::super::controlflow::ControlFlowGraph;
+
+    fn graph(bytes: Vec<u8>) -> ControlFlowGraph {
+        let code = CodeAttribute::for_test(bytes, vec![]);
+
+        ControlFlowGraph::from(&code).unwrap()
+    }
+
+    #[test]
+    fn can_compute_dominators_of_diamond() {
+        // iload_0; ifeq +5; iconst_1; ireturn; iconst_0; ireturn
+        let graph = graph(vec![0x1a, 0x99, 0x00, 0x05, 0x04, 0xac, 0x03, 0xac]);
+        let dominators = DominatorTree::dominators(&graph);
+
+        assert_that(&dominators.immediate_dominator(0)).is_equal_to(&None);
+        assert_that(&dominators.immediate_dominator(1)).is_equal_to(&Some(0));
+        assert_that(&dominators.immediate_dominator(2)).is_equal_to(&Some(0));
+        assert_that(&dominators.immediate_dominator(3)).is_equal_to(&Some(0));
+        assert_that(&dominators.dominates(0, 2)).is_equal_to(&true);
+        assert_that(&dominators.dominates(1, 3)).is_equal_to(&false);
+    }
+
+    #[test]
+    fn can_compute_post_dominators_of_diamond() {
+        // iload_0; ifeq +5; iconst_1; ireturn; iconst_0; ireturn
+        let graph = graph(vec![0x1a, 0x99, 0x00, 0x05, 0x04, 0xac, 0x03, 0xac]);
+        let post_dominators = DominatorTree::post_dominators(&graph);
+
+        assert_that(&post_dominators.root()).is_equal_to(&3);
+        assert_that(&post_dominators.immediate_dominator(0)).is_equal_to(&Some(3));
+        assert_that(&post_dominators.immediate_dominator(1)).is_equal_to(&Some(3));
+        assert_that(&post_dominators.strictly_dominates(1, 0)).is_equal_to(&false);
+    }
+
+    #[test]
+    fn leaves_unreachable_blocks_out_of_tree() {
+        // return; nop; return
+        let graph = graph(vec![0xb1, 0x00, 0xb1]);
+        let dominators = DominatorTree::dominators(&graph);
+
+        assert_that(&dominators.is_reachable(1)).is_equal_to(&false);
+        assert_that(&dominators.dominates(0, 1)).is_equal_to(&false);
+    }
+
+}
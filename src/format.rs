@@ -0,0 +1,515 @@
+use super::{ClassFile, ParserResult};
+use super::access::{ClassAccess, FieldAccess, MethodAccess};
+use super::components::{ConstantPoolItem, ConstantPoolResolver};
+use super::descriptors::{FieldType, MethodDescriptor};
+use super::primitives::{U2, U4};
+
+use std::collections::HashSet;
+
+/// The value every class file starts with.
+pub const MAGIC: U4 = 0xCAFEBABE;
+
+/// The oldest major version of the class file format, that of JDK 1.0.2.
+pub const MIN_MAJOR_VERSION: U2 = 45;
+
+/// The newest major version of the class file format that is checked, that of Java SE 21.
+pub const MAX_MAJOR_VERSION: U2 = 65;
+
+/// The part of a class file a format error was found in.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Location {
+    /// The header of the class file, from the magic value to the list of interfaces.
+    Class,
+    /// An entry of the constant pool, by index.
+    Constant(U2),
+    Field { name: String, descriptor: String },
+    Method { name: String, descriptor: String },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatErrorKind {
+    /// The class file does not start with `MAGIC`.
+    InvalidMagic(U4),
+    /// A version outside of the supported range, or a minor version that is neither `0` nor
+    /// `65535` from version 56 onwards.
+    UnsupportedVersion { major: U2, minor: U2 },
+    /// A class, field or method name that is not legal where it is used.
+    InvalidName(String),
+    /// A descriptor that is not well formed or not of the kind needed, or a method descriptor
+    /// whose parameters take more than 255 slots.
+    InvalidDescriptor(String),
+    /// A combination of access flags that is not allowed, along with all of the flags.
+    IllegalAccessFlags(U2),
+    /// A field or method with the same name and descriptor as one before it.
+    DuplicateMember,
+    /// An `<init>` method in an interface, an `<init>` or `<clinit>` method that does not
+    /// return `void`, or a `<clinit>` method that is not `static` or takes parameters.
+    InvalidInitializer,
+    /// A class other than `java/lang/Object` without a superclass, or an interface whose
+    /// superclass is not `java/lang/Object`.
+    InvalidSuperclass,
+}
+
+/// A violation of the format of a class file found without looking at its bytecode.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FormatError {
+    pub location: Location,
+    pub kind: FormatErrorKind,
+}
+
+/// Checks that `classfile` is well formed, as the JVM does before it verifies the bytecode, and
+/// returns every problem found.
+///
+/// The checks cover the magic value and version, the names and descriptors in the constant pool
+/// and of fields and methods, access flags, duplicate members and the rules for `<init>` and
+/// `<clinit>`. Where the JVM is lenient with older class files, so is this check. A constant pool
+/// reference that does not point at an entry of the expected kind fails the whole check with the
+/// error from resolving it.
+pub fn check(classfile: &ClassFile) -> ParserResult<Vec<FormatError>> {
+    let mut checker = FormatChecker {
+        classfile: classfile,
+        resolver: ConstantPoolResolver { constant_pool: &classfile.constant_pool },
+        errors: vec![],
+    };
+
+    try!(checker.check_header());
+    try!(checker.check_constant_pool());
+    checker.check_fields();
+    checker.check_methods();
+
+    Ok(checker.errors)
+}
+
+struct FormatChecker<'c> {
+    classfile: &'c ClassFile,
+    resolver: ConstantPoolResolver<'c>,
+    errors: Vec<FormatError>,
+}
+
+impl<'c> FormatChecker<'c> {
+    fn report(&mut self, location: Location, kind: FormatErrorKind) {
+        self.errors.push(FormatError {
+            location: location,
+            kind: kind,
+        });
+    }
+
+    fn major_version(&self) -> U2 {
+        self.classfile.major_version
+    }
+
+    fn is_interface(&self) -> bool {
+        self.classfile.access().contains(ClassAccess::INTERFACE)
+    }
+
+    fn check_header(&mut self) -> ParserResult<()> {
+        let classfile = self.classfile;
+
+        if classfile.magic != MAGIC {
+            self.report(Location::Class, FormatErrorKind::InvalidMagic(classfile.magic));
+        }
+
+        let (major, minor) = (classfile.major_version, classfile.minor_version);
+        let supported = (MIN_MAJOR_VERSION..=MAX_MAJOR_VERSION).contains(&major) &&
+                        (major < 56 || minor == 0 || minor == 0xFFFF);
+        if !supported {
+            self.report(Location::Class,
+                        FormatErrorKind::UnsupportedVersion {
+                            major: major,
+                            minor: minor,
+                        });
+        }
+
+        if !legal_class_flags(classfile.access(), major) {
+            self.report(Location::Class,
+                        FormatErrorKind::IllegalAccessFlags(classfile.access_flags));
+        }
+
+        let mut class_names = vec![classfile.this_class];
+        class_names.extend(classfile.interfaces.iter().cloned());
+        if classfile.super_class != 0 {
+            class_names.push(classfile.super_class);
+        }
+        for index in class_names {
+            let name = try!(self.resolver.resolve_class_name(index));
+            if !is_binary_name(&name) {
+                self.report(Location::Class, FormatErrorKind::InvalidName(name.to_string()));
+            }
+        }
+
+        let this_class = try!(self.resolver.resolve_class_name(classfile.this_class));
+        let legal_superclass = if classfile.super_class == 0 {
+            &**this_class == "java/lang/Object"
+        } else {
+            let super_class = try!(self.resolver.resolve_class_name(classfile.super_class));
+            !self.is_interface() || &**super_class == "java/lang/Object"
+        };
+        if !legal_superclass {
+            self.report(Location::Class, FormatErrorKind::InvalidSuperclass);
+        }
+
+        Ok(())
+    }
+
+    fn check_constant_pool(&mut self) -> ParserResult<()> {
+        let constant_pool = &self.classfile.constant_pool;
+
+        for (position, item) in constant_pool.iter().enumerate() {
+            let index = (position + 1) as U2;
+            let location = Location::Constant(index);
+
+            match *item {
+                ConstantPoolItem::Class(..) => {
+                    let name = try!(self.resolver.resolve_class_name(index));
+                    let legal = if name.starts_with('[') {
+                        is_field_descriptor(&name)
+                    } else {
+                        is_binary_name(&name)
+                    };
+                    if !legal {
+                        self.report(location, FormatErrorKind::InvalidName(name.to_string()));
+                    }
+                }
+                ConstantPoolItem::Field(ref info) => {
+                    let (name, descriptor) =
+                        try!(self.resolver.resolve_name_and_type(info.name_and_type_index));
+                    if !is_unqualified_name(&name) {
+                        self.report(location.clone(),
+                                    FormatErrorKind::InvalidName(name.to_string()));
+                    }
+                    if !is_field_descriptor(&descriptor) {
+                        self.report(location,
+                                    FormatErrorKind::InvalidDescriptor(descriptor.to_string()));
+                    }
+                }
+                ConstantPoolItem::Method(ref info) |
+                ConstantPoolItem::InterfaceMethod(ref info) => {
+                    let (name, descriptor) =
+                        try!(self.resolver.resolve_name_and_type(info.name_and_type_index));
+                    let initializer = match *item {
+                        ConstantPoolItem::Method(..) => &**name == "<init>",
+                        _ => false,
+                    };
+                    self.check_method_reference(location, &name, &descriptor, initializer);
+                }
+                ConstantPoolItem::InvokeDynamic { name_and_type_index, .. } => {
+                    let (name, descriptor) =
+                        try!(self.resolver.resolve_name_and_type(name_and_type_index));
+                    self.check_method_reference(location, &name, &descriptor, false);
+                }
+                ConstantPoolItem::MethodType { descriptor_index, .. } => {
+                    let descriptor =
+                        try!(ConstantPoolItem::retrieve_utf8_info(descriptor_index,
+                                                                  constant_pool));
+                    match method_parameter_slots(&descriptor) {
+                        Some(slots) if slots <= 255 => {}
+                        _ => {
+                            self.report(location,
+                                        FormatErrorKind::InvalidDescriptor(descriptor.to_string()))
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a method named by a reference in the constant pool, which may only be an
+    /// `initializer` if the reference allows it.
+    fn check_method_reference(&mut self,
+                              location: Location,
+                              name: &str,
+                              descriptor: &str,
+                              initializer: bool) {
+        if !(initializer || is_method_name(name)) {
+            self.report(location.clone(), FormatErrorKind::InvalidName(name.to_string()));
+        }
+
+        match method_parameter_slots(descriptor) {
+            Some(slots) if slots <= 255 => {
+                if initializer && !descriptor.ends_with(")V") {
+                    self.report(location, FormatErrorKind::InvalidInitializer);
+                }
+            }
+            _ => {
+                self.report(location,
+                            FormatErrorKind::InvalidDescriptor(descriptor.to_string()))
+            }
+        }
+    }
+
+    fn check_fields(&mut self) {
+        let classfile = self.classfile;
+        let mut declared = HashSet::new();
+
+        for field in &classfile.fields {
+            let location = Location::Field {
+                name: field.name.to_string(),
+                descriptor: field.descriptor.to_string(),
+            };
+
+            if !is_unqualified_name(&field.name) {
+                self.report(location.clone(),
+                            FormatErrorKind::InvalidName(field.name.to_string()));
+            }
+            if !is_field_descriptor(&field.descriptor) {
+                self.report(location.clone(),
+                            FormatErrorKind::InvalidDescriptor(field.descriptor.to_string()));
+            }
+
+            let legal = legal_field_flags(field.access(),
+                                          self.is_interface(),
+                                          self.major_version());
+            if !legal {
+                self.report(location.clone(),
+                            FormatErrorKind::IllegalAccessFlags(field.access_flags));
+            }
+
+            if !declared.insert((field.name.to_string(), field.descriptor.to_string())) {
+                self.report(location, FormatErrorKind::DuplicateMember);
+            }
+        }
+    }
+
+    fn check_methods(&mut self) {
+        let classfile = self.classfile;
+        let mut declared = HashSet::new();
+
+        for method in &classfile.methods {
+            let name = method.name.as_str();
+            let descriptor = method.descriptor.as_str();
+            let access_flags = method.access_flags;
+            let is_static = method.access().contains(MethodAccess::STATIC);
+            let location = Location::Method {
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+            };
+
+            let special = name == "<init>" || name == "<clinit>";
+            if !(special || is_method_name(name)) {
+                self.report(location.clone(), FormatErrorKind::InvalidName(name.to_string()));
+            }
+
+            let this_slot = if is_static { 0 } else { 1 };
+            match method_parameter_slots(descriptor) {
+                Some(slots) if slots + this_slot <= 255 => {}
+                _ => {
+                    self.report(location.clone(),
+                                FormatErrorKind::InvalidDescriptor(descriptor.to_string()))
+                }
+            }
+
+            let initializer = if name == "<init>" {
+                !self.is_interface() && descriptor.ends_with(")V")
+            } else if name == "<clinit>" {
+                // before version 51 the flags of a <clinit> method are ignored
+                let needs_static = self.major_version() >= 51;
+                descriptor.ends_with(")V") &&
+                !(needs_static && (!is_static || descriptor != "()V"))
+            } else {
+                true
+            };
+            if !initializer {
+                self.report(location.clone(), FormatErrorKind::InvalidInitializer);
+            }
+
+            let legal = name == "<clinit>" ||
+                        legal_method_flags(method.access(),
+                                           name == "<init>",
+                                           self.is_interface(),
+                                           self.major_version());
+            if !legal {
+                self.report(location.clone(), FormatErrorKind::IllegalAccessFlags(access_flags));
+            }
+
+            if !declared.insert((name.to_string(), descriptor.to_string())) {
+                self.report(location, FormatErrorKind::DuplicateMember);
+            }
+        }
+    }
+}
+
+/// Whether the access flags of a class are allowed for its major version. Class files before
+/// version 49 may use some combinations that later became illegal, and interfaces before version
+/// 50 are taken to be abstract.
+fn legal_class_flags(access: ClassAccess, major_version: U2) -> bool {
+    let since_java_5 = major_version >= 49;
+    let interface = access.contains(ClassAccess::INTERFACE);
+    let is_abstract = access.contains(ClassAccess::ABSTRACT) || (interface && major_version < 50);
+    let is_final = access.contains(ClassAccess::FINAL);
+
+    if interface {
+        let class_only = access.intersects(ClassAccess::SUPER | ClassAccess::ENUM);
+        is_abstract && !is_final && !(since_java_5 && class_only)
+    } else {
+        !(is_abstract && is_final) && !(since_java_5 && access.contains(ClassAccess::ANNOTATION))
+    }
+}
+
+fn legal_field_flags(access: FieldAccess, in_interface: bool, major_version: U2) -> bool {
+    if in_interface {
+        let required = FieldAccess::PUBLIC | FieldAccess::STATIC | FieldAccess::FINAL;
+        let mut forbidden = FieldAccess::PRIVATE | FieldAccess::PROTECTED | FieldAccess::VOLATILE |
+                            FieldAccess::TRANSIENT;
+        if major_version >= 49 {
+            forbidden.insert(FieldAccess::ENUM);
+        }
+        access.contains(required) && !access.intersects(forbidden)
+    } else {
+        has_legal_visibility(access.bits()) &&
+        !access.contains(FieldAccess::FINAL | FieldAccess::VOLATILE)
+    }
+}
+
+/// Whether the access flags of a method other than `<clinit>` are allowed. Interface methods
+/// became more flexible in version 52, and `strict` has no meaning from version 61 onwards.
+fn legal_method_flags(access: MethodAccess,
+                      initializer: bool,
+                      in_interface: bool,
+                      major_version: U2)
+                      -> bool {
+    let since_java_5 = major_version >= 49;
+    let strict = access.contains(MethodAccess::STRICT) && major_version < 61;
+    let is_abstract = access.contains(MethodAccess::ABSTRACT);
+    let public = access.contains(MethodAccess::PUBLIC);
+    let private = access.contains(MethodAccess::PRIVATE);
+    let protected = access.contains(MethodAccess::PROTECTED);
+    let is_static = access.contains(MethodAccess::STATIC);
+    let is_final = access.contains(MethodAccess::FINAL);
+    let synchronized = access.contains(MethodAccess::SYNCHRONIZED);
+    let native = access.contains(MethodAccess::NATIVE);
+
+    if in_interface {
+        if major_version >= 52 {
+            public != private && !native && !protected && !is_final && !synchronized &&
+            !(is_abstract && (private || is_static || strict))
+        } else if since_java_5 {
+            public && is_abstract && !private && !protected && !is_static && !is_final &&
+            !synchronized && !native && !strict
+        } else {
+            public && is_abstract && !is_static && !is_final && !native
+        }
+    } else if !has_legal_visibility(access.bits()) {
+        false
+    } else if initializer {
+        !is_static && !is_final && !synchronized && !native && !is_abstract &&
+        !(since_java_5 && access.contains(MethodAccess::BRIDGE))
+    } else if is_abstract {
+        !is_final && !native && !private && !is_static &&
+        !(since_java_5 && (synchronized || strict))
+    } else {
+        true
+    }
+}
+
+/// Whether at most one of `public`, `private` and `protected` is set, which are the same bits for
+/// fields and methods.
+fn has_legal_visibility(access_flags: U2) -> bool {
+    let visibility = MethodAccess::from(access_flags) &
+                     (MethodAccess::PUBLIC | MethodAccess::PRIVATE | MethodAccess::PROTECTED);
+    visibility.bits().count_ones() <= 1
+}
+
+/// Whether `name` is a legal unqualified name, as used for fields and local variables.
+fn is_unqualified_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(&['.', ';', '[', '/'][..])
+}
+
+/// Whether `name` is a legal name for a method other than `<init>` and `<clinit>`.
+fn is_method_name(name: &str) -> bool {
+    is_unqualified_name(name) && !name.contains(&['<', '>'][..])
+}
+
+/// Whether `name` is a class or interface name in internal form, such as `java/lang/Object`.
+fn is_binary_name(name: &str) -> bool {
+    name.split('/').all(is_unqualified_name)
+}
+
+fn is_field_descriptor(descriptor: &str) -> bool {
+    FieldType::from(descriptor).map(|field_type| is_legal_field_type(&field_type)).unwrap_or(false)
+}
+
+/// The number of slots the parameters of a method descriptor take, or `None` if it is not well
+/// formed.
+fn method_parameter_slots(descriptor: &str) -> Option<usize> {
+    let descriptor = match MethodDescriptor::from(descriptor) {
+        Ok(descriptor) => descriptor,
+        Err(_) => return None,
+    };
+
+    if descriptor.params.iter().chain(descriptor.return_type.iter()).all(is_legal_field_type) {
+        Some(descriptor.parameter_slots())
+    } else {
+        None
+    }
+}
+
+/// Whether `field_type` names its classes by legal binary names. Parsing the descriptor already
+/// rejects arrays of more than 255 dimensions.
+fn is_legal_field_type(field_type: &FieldType) -> bool {
+    match *field_type.element_type() {
+        FieldType::Object(ref name) => is_binary_name(name),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{is_binary_name, is_field_descriptor, is_method_name, legal_class_flags,
+                legal_method_flags, method_parameter_slots};
+    use super::super::access::{ClassAccess, MethodAccess};
+
+    #[test]
+    fn can_check_names() {
+        assert_that(&is_binary_name("java/lang/Object")).is_equal_to(&true);
+        assert_that(&is_binary_name("Outer$Inner")).is_equal_to(&true);
+        assert_that(&is_binary_name("java.lang.Object")).is_equal_to(&false);
+        assert_that(&is_binary_name("java//Object")).is_equal_to(&false);
+        assert_that(&is_binary_name("/Object")).is_equal_to(&false);
+        assert_that(&is_method_name("lambda$main$0")).is_equal_to(&true);
+        assert_that(&is_method_name("<init>")).is_equal_to(&false);
+        assert_that(&is_method_name("")).is_equal_to(&false);
+    }
+
+    #[test]
+    fn can_check_descriptors() {
+        assert_that(&is_field_descriptor("[[Ljava/lang/String;")).is_equal_to(&true);
+        assert_that(&is_field_descriptor("V")).is_equal_to(&false);
+        assert_that(&is_field_descriptor("L;")).is_equal_to(&false);
+        assert_that(&is_field_descriptor("Ljava.lang.String;")).is_equal_to(&false);
+        assert_that(&is_field_descriptor("II")).is_equal_to(&false);
+        assert_that(&is_field_descriptor(&format!("{}I", "[".repeat(256)))).is_equal_to(&false);
+
+        assert_that(&method_parameter_slots("(IJLjava/lang/String;[D)V")).is_equal_to(&Some(5));
+        assert_that(&method_parameter_slots("()[I")).is_equal_to(&Some(0));
+        assert_that(&method_parameter_slots("(V)V")).is_none();
+        assert_that(&method_parameter_slots("(I)")).is_none();
+        assert_that(&method_parameter_slots("I")).is_none();
+    }
+
+    #[test]
+    fn can_check_access_flags_by_version() {
+        // an interface before version 50 is abstract whether or not it says so
+        assert_that(&legal_class_flags(ClassAccess::from(0x0201), 49)).is_equal_to(&true);
+        assert_that(&legal_class_flags(ClassAccess::from(0x0201), 50)).is_equal_to(&false);
+        assert_that(&legal_class_flags(ClassAccess::from(0x0411), 52)).is_equal_to(&false);
+
+        // interfaces may have private static methods from version 52 onwards
+        assert_that(&legal_method_flags(MethodAccess::from(0x000A), false, true, 51))
+            .is_equal_to(&false);
+        assert_that(&legal_method_flags(MethodAccess::from(0x000A), false, true, 52))
+            .is_equal_to(&true);
+        assert_that(&legal_method_flags(MethodAccess::from(0x0003), false, false, 52))
+            .is_equal_to(&false);
+        assert_that(&legal_method_flags(MethodAccess::from(0x0009), true, false, 52))
+            .is_equal_to(&false);
+    }
+
+}
@@ -0,0 +1,928 @@
+use super::{ClassFile, ParserError, ParserResult};
+use super::access::MethodAccess;
+use super::components::{CodeAttribute, ConstantPoolResolver, LoadableConstant, Method};
+use super::descriptors::{BaseType, FieldType, MethodDescriptor};
+use super::hierarchy::{ClassHierarchy, ObjectHierarchy};
+use super::instructions::{ArrayType, Instruction};
+use super::primitives::U2;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::collections::btree_map::Iter;
+
+/// The type of a local variable or operand stack entry, as the verifier sees it.
+///
+/// References are named the way `CONSTANT_Class` entries name them: classes and interfaces by
+/// their internal name, such as `java/lang/String`, and arrays by their descriptor, such as
+/// `[I` or `[Ljava/lang/String;`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum VerificationType {
+    /// An unusable value, such as a local variable that holds different types on different
+    /// paths, or the second slot of a long or double local.
+    Top,
+    Integer,
+    Float,
+    Long,
+    Double,
+    Null,
+    /// `this` in a constructor before the superclass constructor has been called.
+    UninitializedThis,
+    Object(String),
+    /// An object created by the `new` at the given pc whose constructor has not been called yet.
+    Uninitialized(U2),
+    /// The return address pushed by a `jsr`.
+    ReturnAddress,
+}
+
+impl VerificationType {
+    /// The type a value of `field_type` has on the stack, where every integral type below `long`
+    /// is an `Integer`.
+    pub fn from_field_type(field_type: &FieldType) -> VerificationType {
+        match *field_type {
+            FieldType::Base(BaseType::Float) => VerificationType::Float,
+            FieldType::Base(BaseType::Long) => VerificationType::Long,
+            FieldType::Base(BaseType::Double) => VerificationType::Double,
+            FieldType::Base(..) => VerificationType::Integer,
+            FieldType::Object(ref name) => VerificationType::Object(name.clone()),
+            FieldType::Array(..) => VerificationType::Object(field_type.descriptor()),
+        }
+    }
+
+    /// The number of stack or local variable slots the type takes up.
+    pub fn size(&self) -> usize {
+        match *self {
+            VerificationType::Long |
+            VerificationType::Double => 2,
+            _ => 1,
+        }
+    }
+
+    pub fn is_reference(&self) -> bool {
+        match *self {
+            VerificationType::Null |
+            VerificationType::UninitializedThis |
+            VerificationType::Object(..) |
+            VerificationType::Uninitialized(..) => true,
+            _ => false,
+        }
+    }
+}
+
+/// The local variables and operand stack at some point of a method body.
+///
+/// A long or double local variable is followed by a `Top` for its second slot, while on the
+/// operand stack it is a single entry.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub locals: Vec<VerificationType>,
+    /// The operand stack, from bottom to top.
+    pub stack: Vec<VerificationType>,
+}
+
+impl Frame {
+    /// The number of slots the operand stack takes up.
+    pub fn stack_height(&self) -> usize {
+        self.stack.iter().map(|entry| entry.size()).sum()
+    }
+
+    /// The type of local variable `index`, which is `Top` if it has never been assigned.
+    pub fn local(&self, index: U2) -> VerificationType {
+        self.locals.get(index as usize).cloned().unwrap_or(VerificationType::Top)
+    }
+
+    fn store(&mut self, index: U2, value: VerificationType) {
+        let index = index as usize;
+        let size = value.size();
+        if self.locals.len() < index + size {
+            self.locals.resize(index + size, VerificationType::Top);
+        }
+
+        // overwriting the second slot of a long or double destroys it
+        if index > 0 && self.locals[index - 1].size() == 2 {
+            self.locals[index - 1] = VerificationType::Top;
+        }
+
+        self.locals[index] = value;
+        if size == 2 {
+            self.locals[index + 1] = VerificationType::Top;
+        }
+    }
+
+    fn push(&mut self, value: VerificationType) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self, pc: U2) -> ParserResult<VerificationType> {
+        self.stack.pop().ok_or(ParserError::StackUnderflow(pc as usize))
+    }
+
+    fn pop_entries(&mut self, count: usize, pc: U2) -> ParserResult<()> {
+        for _ in 0..count {
+            try!(self.pop(pc));
+        }
+
+        Ok(())
+    }
+
+    /// Pops entries taking up exactly `slots` slots, as the untyped stack instructions do, and
+    /// returns them from bottom to top.
+    fn pop_slots(&mut self, slots: usize, pc: U2) -> ParserResult<Vec<VerificationType>> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < slots {
+            let value = try!(self.pop(pc));
+            popped += value.size();
+            values.push(value);
+        }
+
+        if popped != slots {
+            return Err(ParserError::IncompatibleStackTypes(pc as usize));
+        }
+
+        values.reverse();
+        Ok(values)
+    }
+
+    /// Replaces every occurrence of an uninitialized object once its constructor has run.
+    fn initialize(&mut self, uninitialized: &VerificationType, class_name: &str) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if value == uninitialized {
+                *value = VerificationType::Object(class_name.to_string());
+            }
+        }
+    }
+}
+
+/// The frame before each reachable instruction of a method body, found by simulating the
+/// instructions over verification types from the method descriptor onwards.
+///
+/// Frames meeting at a join point are merged: local variables that disagree become `Top`, and
+/// references merge to their common superclass. Operand stacks that differ in height, or hold
+/// entries that cannot be merged, are reported as errors.
+///
+/// The successors of a `ret` are taken to be the instructions following every `jsr` in the
+/// method, which is imprecise when a method has several subroutines. Inlining them with
+/// `subroutines::inline` first avoids this.
+#[derive(Clone, Debug)]
+pub struct Frames {
+    entry: Frame,
+    frames: BTreeMap<U2, Frame>,
+}
+
+impl Frames {
+    /// Simulates the code of `method` with no knowledge of the class hierarchy. A method without
+    /// code has no frames.
+    pub fn from(classfile: &ClassFile, method: &Method) -> ParserResult<Frames> {
+        match method.code() {
+            Some(code) => Frames::simulate(classfile, method, &code, &ObjectHierarchy),
+            None => Frames::simulate(classfile, method, &CodeAttribute::default(), &ObjectHierarchy),
+        }
+    }
+
+    /// Simulates `code` as the body of `method`, which need not be the code the method was
+    /// parsed with.
+    pub fn simulate<H: ClassHierarchy>(classfile: &ClassFile,
+                                       method: &Method,
+                                       code: &CodeAttribute,
+                                       hierarchy: &H)
+                                       -> ParserResult<Frames> {
+        let resolver = ConstantPoolResolver { constant_pool: &classfile.constant_pool };
+        let this_class = try!(resolver.resolve_class_name(classfile.this_class)).to_string();
+        let simulator = Simulator {
+            resolver: resolver,
+            this_class: this_class,
+            hierarchy: hierarchy,
+        };
+
+        simulator.run(method, code)
+    }
+
+    /// The frame the method starts with, as given by its descriptor, before anything is merged
+    /// into it.
+    pub fn entry(&self) -> &Frame {
+        &self.entry
+    }
+
+    /// The frame before the instruction at `pc`, or `None` if it cannot be reached.
+    pub fn at(&self, pc: U2) -> Option<&Frame> {
+        self.frames.get(&pc)
+    }
+
+    /// The frames of reachable instructions in pc order.
+    pub fn iter<'f>(&'f self) -> Iter<'f, U2, Frame> {
+        self.frames.iter()
+    }
+}
+
+struct Simulator<'r, 'h, H: ClassHierarchy + 'h> {
+    resolver: ConstantPoolResolver<'r>,
+    this_class: String,
+    hierarchy: &'h H,
+}
+
+impl<'r, 'h, H: ClassHierarchy> Simulator<'r, 'h, H> {
+    fn run(&self, method: &Method, code: &CodeAttribute) -> ParserResult<Frames> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+        let entry = try!(self.initial_frame(method));
+        if instructions.is_empty() {
+            return Ok(Frames {
+                entry: entry,
+                frames: BTreeMap::new(),
+            });
+        }
+
+        let indices = instructions.iter()
+            .enumerate()
+            .map(|(index, &(pc, _))| (pc as usize, index))
+            .collect::<HashMap<_, _>>();
+        let index_of = |pc: usize| indices.get(&pc).cloned().ok_or(ParserError::InvalidCodeOffset(pc));
+
+        let mut return_sites = vec![];
+        for (index, &(_, ref instruction)) in instructions.iter().enumerate() {
+            match *instruction {
+                Instruction::Jsr(..) |
+                Instruction::JsrW(..) if index + 1 < instructions.len() => {
+                    return_sites.push(index + 1)
+                }
+                _ => {}
+            }
+        }
+
+        let mut frames = vec![None; instructions.len()];
+        frames[0] = Some(entry.clone());
+
+        let mut worklist = BTreeSet::new();
+        worklist.insert(0);
+        while let Some(index) = worklist.iter().next().cloned() {
+            worklist.remove(&index);
+            let (pc, ref instruction) = instructions[index];
+            let before = frames[index].clone().unwrap();
+
+            for handler in &code.exception_table {
+                if pc < handler.start_pc || pc >= handler.end_pc {
+                    continue;
+                }
+
+                let catch_type = if handler.catch_type == 0 {
+                    "java/lang/Throwable".to_string()
+                } else {
+                    try!(self.resolver.resolve_class_name(handler.catch_type)).to_string()
+                };
+                let frame = Frame {
+                    locals: before.locals.clone(),
+                    stack: vec![VerificationType::Object(catch_type)],
+                };
+
+                let target = try!(index_of(handler.handler_pc as usize));
+                if try!(self.merge(&mut frames[target], frame, handler.handler_pc)) {
+                    worklist.insert(target);
+                }
+            }
+
+            let mut after = before;
+            try!(self.execute(pc, instruction, &mut after, &instructions, &indices));
+
+            let mut successors = vec![];
+            for target in instruction.branch_targets() {
+                successors.push(try!(index_of((pc as i64 + *target as i64) as usize)));
+            }
+            match *instruction {
+                Instruction::Goto(..) |
+                Instruction::GotoW(..) |
+                Instruction::Jsr(..) |
+                Instruction::JsrW(..) |
+                Instruction::Tableswitch { .. } |
+                Instruction::Lookupswitch { .. } |
+                Instruction::Ireturn |
+                Instruction::Lreturn |
+                Instruction::Freturn |
+                Instruction::Dreturn |
+                Instruction::Areturn |
+                Instruction::Return |
+                Instruction::Athrow => {}
+                Instruction::Ret(..) => successors.extend(return_sites.iter().cloned()),
+                _ => {
+                    if index + 1 == instructions.len() {
+                        return Err(ParserError::InvalidCodeOffset(code.code.len()));
+                    }
+                    successors.push(index + 1);
+                }
+            }
+
+            for target in successors {
+                let target_pc = instructions[target].0;
+                if try!(self.merge(&mut frames[target], after.clone(), target_pc)) {
+                    worklist.insert(target);
+                }
+            }
+        }
+
+        let frames = instructions.iter()
+            .zip(frames)
+            .filter_map(|(&(pc, _), frame)| frame.map(|frame| (pc, frame)))
+            .collect();
+
+        Ok(Frames {
+            entry: entry,
+            frames: frames,
+        })
+    }
+
+    fn initial_frame(&self, method: &Method) -> ParserResult<Frame> {
+        let mut frame = Frame {
+            locals: vec![],
+            stack: vec![],
+        };
+
+        if !method.access().contains(MethodAccess::STATIC) {
+            let this = if method.name.as_str() == "<init>" && self.this_class != "java/lang/Object" {
+                VerificationType::UninitializedThis
+            } else {
+                VerificationType::Object(self.this_class.clone())
+            };
+            frame.locals.push(this);
+        }
+
+        let (parameters, _) = try!(method_types(&method.descriptor));
+        for parameter in parameters {
+            let index = frame.locals.len() as U2;
+            frame.store(index, parameter);
+        }
+
+        Ok(frame)
+    }
+
+    /// Merges `incoming` into the frame at `pc`, returning whether that changed it.
+    fn merge(&self, existing: &mut Option<Frame>, incoming: Frame, pc: U2) -> ParserResult<bool> {
+        let frame = match *existing {
+            Some(ref mut frame) => frame,
+            None => {
+                *existing = Some(incoming);
+                return Ok(true);
+            }
+        };
+
+        if frame.stack.len() != incoming.stack.len() ||
+           frame.stack_height() != incoming.stack_height() {
+            return Err(ParserError::InconsistentStackHeight(pc as usize));
+        }
+
+        let mut changed = false;
+        for (value, other) in frame.stack.iter_mut().zip(incoming.stack.iter()) {
+            let merged = match merge_types(value, other, self.hierarchy) {
+                Some(ref merged) if merged.size() == value.size() => merged.clone(),
+                _ => return Err(ParserError::IncompatibleStackTypes(pc as usize)),
+            };
+            if *value != merged {
+                *value = merged;
+                changed = true;
+            }
+        }
+
+        let length = ::std::cmp::max(frame.locals.len(), incoming.locals.len());
+        for index in 0..length {
+            let value = frame.local(index as U2);
+            let merged = merge_types(&value, &incoming.local(index as U2), self.hierarchy)
+                .unwrap_or(VerificationType::Top);
+            if index >= frame.locals.len() {
+                frame.locals.push(merged);
+                changed = true;
+            } else if value != merged {
+                frame.locals[index] = merged;
+                changed = true;
+            }
+        }
+
+        Ok(changed)
+    }
+
+    fn execute(&self,
+               pc: U2,
+               instruction: &Instruction,
+               frame: &mut Frame,
+               instructions: &Vec<(U2, Instruction)>,
+               indices: &HashMap<usize, usize>)
+               -> ParserResult<()> {
+        match *instruction {
+            Instruction::Nop |
+            Instruction::Goto(..) |
+            Instruction::GotoW(..) |
+            Instruction::Ret(..) |
+            Instruction::Iinc(..) |
+            Instruction::Return |
+            Instruction::Breakpoint |
+            Instruction::Impdep1 |
+            Instruction::Impdep2 => {}
+            Instruction::AconstNull => frame.push(VerificationType::Null),
+            Instruction::IconstM1 |
+            Instruction::Iconst0 |
+            Instruction::Iconst1 |
+            Instruction::Iconst2 |
+            Instruction::Iconst3 |
+            Instruction::Iconst4 |
+            Instruction::Iconst5 |
+            Instruction::Bipush(..) |
+            Instruction::Sipush(..) |
+            Instruction::Iload(..) |
+            Instruction::Iload0 |
+            Instruction::Iload1 |
+            Instruction::Iload2 |
+            Instruction::Iload3 => frame.push(VerificationType::Integer),
+            Instruction::Lconst0 |
+            Instruction::Lconst1 |
+            Instruction::Lload(..) |
+            Instruction::Lload0 |
+            Instruction::Lload1 |
+            Instruction::Lload2 |
+            Instruction::Lload3 => frame.push(VerificationType::Long),
+            Instruction::Fconst0 |
+            Instruction::Fconst1 |
+            Instruction::Fconst2 |
+            Instruction::Fload(..) |
+            Instruction::Fload0 |
+            Instruction::Fload1 |
+            Instruction::Fload2 |
+            Instruction::Fload3 => frame.push(VerificationType::Float),
+            Instruction::Dconst0 |
+            Instruction::Dconst1 |
+            Instruction::Dload(..) |
+            Instruction::Dload0 |
+            Instruction::Dload1 |
+            Instruction::Dload2 |
+            Instruction::Dload3 => frame.push(VerificationType::Double),
+            Instruction::Ldc(index) => frame.push(try!(self.constant_type(index as U2))),
+            Instruction::LdcW(index) |
+            Instruction::Ldc2W(index) => frame.push(try!(self.constant_type(index))),
+            Instruction::Aload(index) => {
+                let value = frame.local(index);
+                frame.push(value);
+            }
+            Instruction::Aload0 => {
+                let value = frame.local(0);
+                frame.push(value);
+            }
+            Instruction::Aload1 => {
+                let value = frame.local(1);
+                frame.push(value);
+            }
+            Instruction::Aload2 => {
+                let value = frame.local(2);
+                frame.push(value);
+            }
+            Instruction::Aload3 => {
+                let value = frame.local(3);
+                frame.push(value);
+            }
+            Instruction::Iaload |
+            Instruction::Baload |
+            Instruction::Caload |
+            Instruction::Saload => try!(replace(frame, 2, VerificationType::Integer, pc)),
+            Instruction::Laload => try!(replace(frame, 2, VerificationType::Long, pc)),
+            Instruction::Faload => try!(replace(frame, 2, VerificationType::Float, pc)),
+            Instruction::Daload => try!(replace(frame, 2, VerificationType::Double, pc)),
+            Instruction::Aaload => {
+                try!(frame.pop(pc));
+                let component = match try!(frame.pop(pc)) {
+                    VerificationType::Null => VerificationType::Null,
+                    VerificationType::Object(ref array) if array.starts_with('[') => {
+                        match component_name(&array[1..]) {
+                            Some(name) => VerificationType::Object(name.to_string()),
+                            None => VerificationType::Top,
+                        }
+                    }
+                    _ => VerificationType::Top,
+                };
+                frame.push(component);
+            }
+            Instruction::Istore(index) |
+            Instruction::Lstore(index) |
+            Instruction::Fstore(index) |
+            Instruction::Dstore(index) |
+            Instruction::Astore(index) => try!(store(frame, index, pc)),
+            Instruction::Istore0 |
+            Instruction::Lstore0 |
+            Instruction::Fstore0 |
+            Instruction::Dstore0 |
+            Instruction::Astore0 => try!(store(frame, 0, pc)),
+            Instruction::Istore1 |
+            Instruction::Lstore1 |
+            Instruction::Fstore1 |
+            Instruction::Dstore1 |
+            Instruction::Astore1 => try!(store(frame, 1, pc)),
+            Instruction::Istore2 |
+            Instruction::Lstore2 |
+            Instruction::Fstore2 |
+            Instruction::Dstore2 |
+            Instruction::Astore2 => try!(store(frame, 2, pc)),
+            Instruction::Istore3 |
+            Instruction::Lstore3 |
+            Instruction::Fstore3 |
+            Instruction::Dstore3 |
+            Instruction::Astore3 => try!(store(frame, 3, pc)),
+            Instruction::Iastore |
+            Instruction::Lastore |
+            Instruction::Fastore |
+            Instruction::Dastore |
+            Instruction::Aastore |
+            Instruction::Bastore |
+            Instruction::Castore |
+            Instruction::Sastore => try!(frame.pop_entries(3, pc)),
+            Instruction::Pop => {
+                try!(frame.pop_slots(1, pc));
+            }
+            Instruction::Pop2 => {
+                try!(frame.pop_slots(2, pc));
+            }
+            Instruction::Dup => try!(duplicate(frame, 1, 0, pc)),
+            Instruction::DupX1 => try!(duplicate(frame, 1, 1, pc)),
+            Instruction::DupX2 => try!(duplicate(frame, 1, 2, pc)),
+            Instruction::Dup2 => try!(duplicate(frame, 2, 0, pc)),
+            Instruction::Dup2X1 => try!(duplicate(frame, 2, 1, pc)),
+            Instruction::Dup2X2 => try!(duplicate(frame, 2, 2, pc)),
+            Instruction::Swap => {
+                let top = try!(frame.pop_slots(1, pc));
+                let below = try!(frame.pop_slots(1, pc));
+                frame.stack.extend(top);
+                frame.stack.extend(below);
+            }
+            Instruction::Iadd |
+            Instruction::Isub |
+            Instruction::Imul |
+            Instruction::Idiv |
+            Instruction::Irem |
+            Instruction::Ishl |
+            Instruction::Ishr |
+            Instruction::Iushr |
+            Instruction::Iand |
+            Instruction::Ior |
+            Instruction::Ixor |
+            Instruction::Lcmp |
+            Instruction::Fcmpl |
+            Instruction::Fcmpg |
+            Instruction::Dcmpl |
+            Instruction::Dcmpg => try!(replace(frame, 2, VerificationType::Integer, pc)),
+            Instruction::Ladd |
+            Instruction::Lsub |
+            Instruction::Lmul |
+            Instruction::Ldiv |
+            Instruction::Lrem |
+            Instruction::Lshl |
+            Instruction::Lshr |
+            Instruction::Lushr |
+            Instruction::Land |
+            Instruction::Lor |
+            Instruction::Lxor => try!(replace(frame, 2, VerificationType::Long, pc)),
+            Instruction::Fadd |
+            Instruction::Fsub |
+            Instruction::Fmul |
+            Instruction::Fdiv |
+            Instruction::Frem => try!(replace(frame, 2, VerificationType::Float, pc)),
+            Instruction::Dadd |
+            Instruction::Dsub |
+            Instruction::Dmul |
+            Instruction::Ddiv |
+            Instruction::Drem => try!(replace(frame, 2, VerificationType::Double, pc)),
+            Instruction::Ineg |
+            Instruction::L2i |
+            Instruction::F2i |
+            Instruction::D2i |
+            Instruction::I2b |
+            Instruction::I2c |
+            Instruction::I2s |
+            Instruction::Arraylength |
+            Instruction::Instanceof(..) => try!(replace(frame, 1, VerificationType::Integer, pc)),
+            Instruction::Lneg |
+            Instruction::I2l |
+            Instruction::F2l |
+            Instruction::D2l => try!(replace(frame, 1, VerificationType::Long, pc)),
+            Instruction::Fneg |
+            Instruction::I2f |
+            Instruction::L2f |
+            Instruction::D2f => try!(replace(frame, 1, VerificationType::Float, pc)),
+            Instruction::Dneg |
+            Instruction::I2d |
+            Instruction::L2d |
+            Instruction::F2d => try!(replace(frame, 1, VerificationType::Double, pc)),
+            Instruction::Ifeq(..) |
+            Instruction::Ifne(..) |
+            Instruction::Iflt(..) |
+            Instruction::Ifge(..) |
+            Instruction::Ifgt(..) |
+            Instruction::Ifle(..) |
+            Instruction::Ifnull(..) |
+            Instruction::Ifnonnull(..) |
+            Instruction::Tableswitch { .. } |
+            Instruction::Lookupswitch { .. } |
+            Instruction::Ireturn |
+            Instruction::Lreturn |
+            Instruction::Freturn |
+            Instruction::Dreturn |
+            Instruction::Areturn |
+            Instruction::Athrow |
+            Instruction::Monitorenter |
+            Instruction::Monitorexit |
+            Instruction::Putstatic(..) => try!(frame.pop_entries(1, pc)),
+            Instruction::IfIcmpeq(..) |
+            Instruction::IfIcmpne(..) |
+            Instruction::IfIcmplt(..) |
+            Instruction::IfIcmpge(..) |
+            Instruction::IfIcmpgt(..) |
+            Instruction::IfIcmple(..) |
+            Instruction::IfAcmpeq(..) |
+            Instruction::IfAcmpne(..) |
+            Instruction::Putfield(..) => try!(frame.pop_entries(2, pc)),
+            Instruction::Jsr(..) |
+            Instruction::JsrW(..) => frame.push(VerificationType::ReturnAddress),
+            Instruction::Getstatic(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                frame.push(try!(field_type(&field.descriptor)));
+            }
+            Instruction::Getfield(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                try!(replace(frame, 1, try!(field_type(&field.descriptor)), pc));
+            }
+            Instruction::Invokevirtual(index) |
+            Instruction::Invokespecial(index) |
+            Instruction::Invokestatic(index) |
+            Instruction::Invokeinterface(index, _) => {
+                let method = try!(self.resolver.resolve_member_reference(index));
+                let (parameters, return_type) = try!(method_types(&method.descriptor));
+                try!(frame.pop_entries(parameters.len(), pc));
+
+                match *instruction {
+                    Instruction::Invokestatic(..) => {}
+                    Instruction::Invokespecial(..) if method.name.as_str() == "<init>" => {
+                        let receiver = try!(frame.pop(pc));
+                        let class_name = try!(self.initialized_class(&receiver,
+                                                                     pc,
+                                                                     instructions,
+                                                                     indices));
+                        frame.initialize(&receiver, &class_name);
+                    }
+                    _ => try!(frame.pop_entries(1, pc)),
+                }
+
+                if let Some(return_type) = return_type {
+                    frame.push(return_type);
+                }
+            }
+            Instruction::Invokedynamic(index) => {
+                let call_site = try!(self.resolver.resolve_invoke_dynamic(index));
+                let (parameters, return_type) = try!(method_types(&call_site.descriptor));
+                try!(frame.pop_entries(parameters.len(), pc));
+
+                if let Some(return_type) = return_type {
+                    frame.push(return_type);
+                }
+            }
+            Instruction::New(..) => frame.push(VerificationType::Uninitialized(pc)),
+            Instruction::Newarray(ref array_type) => {
+                let descriptor = match *array_type {
+                    ArrayType::Boolean => "[Z",
+                    ArrayType::Char => "[C",
+                    ArrayType::Float => "[F",
+                    ArrayType::Double => "[D",
+                    ArrayType::Byte => "[B",
+                    ArrayType::Short => "[S",
+                    ArrayType::Int => "[I",
+                    ArrayType::Long => "[J",
+                };
+                try!(replace(frame, 1, VerificationType::Object(descriptor.to_string()), pc));
+            }
+            Instruction::Anewarray(index) => {
+                let component = try!(self.resolver.resolve_class_name(index));
+                try!(replace(frame, 1, VerificationType::Object(array_of(&component)), pc));
+            }
+            Instruction::Checkcast(index) => {
+                let class_name = try!(self.resolver.resolve_class_name(index));
+                try!(replace(frame, 1, VerificationType::Object(class_name.to_string()), pc));
+            }
+            Instruction::Multianewarray(index, dimensions) => {
+                let class_name = try!(self.resolver.resolve_class_name(index));
+                try!(replace(frame,
+                             dimensions as usize,
+                             VerificationType::Object(class_name.to_string()),
+                             pc));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn constant_type(&self, index: U2) -> ParserResult<VerificationType> {
+        let constant_type = match try!(self.resolver.resolve_loadable_constant(index)) {
+            LoadableConstant::Integer(..) => VerificationType::Integer,
+            LoadableConstant::Float(..) => VerificationType::Float,
+            LoadableConstant::Long(..) => VerificationType::Long,
+            LoadableConstant::Double(..) => VerificationType::Double,
+            LoadableConstant::String(..) => VerificationType::Object("java/lang/String".to_string()),
+            LoadableConstant::Class(..) => VerificationType::Object("java/lang/Class".to_string()),
+            LoadableConstant::MethodType(..) => {
+                VerificationType::Object("java/lang/invoke/MethodType".to_string())
+            }
+            LoadableConstant::MethodHandle { .. } => {
+                VerificationType::Object("java/lang/invoke/MethodHandle".to_string())
+            }
+        };
+
+        Ok(constant_type)
+    }
+
+    /// The class of an object a constructor has been called on, which for an object created by
+    /// `new` is the class named by that instruction.
+    fn initialized_class(&self,
+                         receiver: &VerificationType,
+                         pc: U2,
+                         instructions: &Vec<(U2, Instruction)>,
+                         indices: &HashMap<usize, usize>)
+                         -> ParserResult<String> {
+        match *receiver {
+            VerificationType::UninitializedThis => Ok(self.this_class.clone()),
+            VerificationType::Uninitialized(new_pc) => {
+                let index = try!(indices.get(&(new_pc as usize))
+                    .ok_or(ParserError::InvalidCodeOffset(new_pc as usize)));
+                match instructions[*index].1 {
+                    Instruction::New(class_index) => {
+                        Ok(try!(self.resolver.resolve_class_name(class_index)).to_string())
+                    }
+                    _ => Err(ParserError::InvalidCodeOffset(new_pc as usize)),
+                }
+            }
+            _ => Err(ParserError::IncompatibleStackTypes(pc as usize)),
+        }
+    }
+}
+
+/// Pops `count` entries and pushes `result` in their place.
+fn replace(frame: &mut Frame, count: usize, result: VerificationType, pc: U2) -> ParserResult<()> {
+    try!(frame.pop_entries(count, pc));
+    frame.push(result);
+
+    Ok(())
+}
+
+fn store(frame: &mut Frame, index: U2, pc: U2) -> ParserResult<()> {
+    let value = try!(frame.pop(pc));
+    frame.store(index, value);
+
+    Ok(())
+}
+
+/// Duplicates the top `slots` slots of the stack, inserting the copy below the `skipped` slots
+/// under them, as the `dup` family does.
+fn duplicate(frame: &mut Frame, slots: usize, skipped: usize, pc: U2) -> ParserResult<()> {
+    let top = try!(frame.pop_slots(slots, pc));
+    let below = try!(frame.pop_slots(skipped, pc));
+
+    frame.stack.extend(top.iter().cloned());
+    frame.stack.extend(below);
+    frame.stack.extend(top);
+
+    Ok(())
+}
+
+/// The name of an array component type given by its descriptor, or `None` for primitive types.
+pub fn component_name(descriptor: &str) -> Option<&str> {
+    if descriptor.starts_with('[') {
+        Some(descriptor)
+    } else if descriptor.starts_with('L') && descriptor.ends_with(';') {
+        Some(&descriptor[1..descriptor.len() - 1])
+    } else {
+        None
+    }
+}
+
+/// The name of the array type whose components are named `component`.
+pub fn array_of(component: &str) -> String {
+    if component.starts_with('[') {
+        format!("[{}", component)
+    } else {
+        format!("[L{};", component)
+    }
+}
+
+/// The most specific type both values can be used as, or `None` if they have nothing in common.
+/// Classes merge to the common superclass `hierarchy` gives, and arrays component-wise.
+pub fn merge_types<H: ClassHierarchy>(first: &VerificationType,
+                                      second: &VerificationType,
+                                      hierarchy: &H)
+                                      -> Option<VerificationType> {
+    match (first, second) {
+        _ if first == second => Some(first.clone()),
+        (&VerificationType::Null, &VerificationType::Object(_)) => Some(second.clone()),
+        (&VerificationType::Object(_), &VerificationType::Null) => Some(first.clone()),
+        (&VerificationType::Object(ref first), &VerificationType::Object(ref second)) => {
+            Some(VerificationType::Object(merge_references(first, second, hierarchy)))
+        }
+        _ => None,
+    }
+}
+
+fn merge_references<H: ClassHierarchy>(first: &str, second: &str, hierarchy: &H) -> String {
+    if first == second {
+        return first.to_string();
+    }
+
+    match (first.starts_with('['), second.starts_with('[')) {
+        (true, true) => {
+            match (component_name(&first[1..]), component_name(&second[1..])) {
+                (Some(first), Some(second)) => {
+                    array_of(&merge_references(first, second, hierarchy))
+                }
+                _ => "java/lang/Object".to_string(),
+            }
+        }
+        (false, false) => hierarchy.common_superclass(first, second),
+        _ => "java/lang/Object".to_string(),
+    }
+}
+
+/// The type a field descriptor stores on the stack, where every integral type below `long` is an
+/// `Integer`.
+pub fn field_type(descriptor: &str) -> ParserResult<VerificationType> {
+    Ok(VerificationType::from_field_type(&try!(FieldType::from(descriptor))))
+}
+
+/// The parameter types and the return type of a method descriptor, where a `void` method has no
+/// return type.
+pub fn method_types(descriptor: &str)
+                    -> ParserResult<(Vec<VerificationType>, Option<VerificationType>)> {
+    let descriptor = try!(MethodDescriptor::from(descriptor));
+    let parameters = descriptor.params.iter().map(VerificationType::from_field_type).collect();
+    let return_type = descriptor.return_type.as_ref().map(VerificationType::from_field_type);
+
+    Ok((parameters, return_type))
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{merge_references, merge_types, Frame, Simulator, VerificationType};
+    use super::super::components::ConstantPoolResolver;
+    use super::super::hierarchy::ObjectHierarchy;
+    use super::super::ParserError;
+
+    #[test]
+    fn can_merge_array_references() {
+        assert_that(&merge_references("[Ljava/lang/String;",
+                                      "[Ljava/lang/Integer;",
+                                      &ObjectHierarchy))
+            .is_equal_to(&"[Ljava/lang/Object;".to_string());
+        assert_that(&merge_references("[[I", "[[I", &ObjectHierarchy))
+            .is_equal_to(&"[[I".to_string());
+        assert_that(&merge_references("[I", "[J", &ObjectHierarchy))
+            .is_equal_to(&"java/lang/Object".to_string());
+        assert_that(&merge_types(&VerificationType::Null,
+                                 &VerificationType::Object("[I".to_string()),
+                                 &ObjectHierarchy))
+            .is_equal_to(&Some(VerificationType::Object("[I".to_string())));
+        assert_that(&merge_types(&VerificationType::Integer,
+                                 &VerificationType::Float,
+                                 &ObjectHierarchy))
+            .is_equal_to(&None);
+    }
+
+    #[test]
+    fn reports_inconsistent_stack_heights() {
+        let constant_pool = vec![];
+        let simulator = Simulator {
+            resolver: ConstantPoolResolver { constant_pool: &constant_pool },
+            this_class: "Example".to_string(),
+            hierarchy: &ObjectHierarchy,
+        };
+        let mut existing = Some(Frame {
+            locals: vec![],
+            stack: vec![VerificationType::Integer, VerificationType::Integer],
+        });
+        let incoming = Frame {
+            locals: vec![],
+            stack: vec![VerificationType::Long],
+        };
+
+        assert_that(&simulator.merge(&mut existing, incoming, 7))
+            .matches(|result| match *result {
+                Err(ParserError::InconsistentStackHeight(7)) => true,
+                _ => false,
+            });
+    }
+
+    #[test]
+    fn stores_invalidate_overlapping_longs() {
+        let mut frame = Frame {
+            locals: vec![],
+            stack: vec![],
+        };
+        frame.store(0, VerificationType::Long);
+        frame.store(1, VerificationType::Integer);
+
+        assert_that(&frame.locals)
+            .is_equal_to(&vec![VerificationType::Top, VerificationType::Integer]);
+    }
+
+}
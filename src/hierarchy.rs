@@ -0,0 +1,188 @@
+use super::{ClassFile, ParserResult};
+use super::access::ClassAccess;
+use super::components::ConstantPoolResolver;
+
+use std::collections::HashMap;
+
+/// Answers the questions about the class hierarchy that merging reference types depends on.
+pub trait ClassHierarchy {
+    /// The closest common superclass of two classes or interfaces, by internal name.
+    fn common_superclass(&self, first: &str, second: &str) -> String;
+
+    fn is_interface(&self, _name: &str) -> bool {
+        false
+    }
+
+    /// Whether a value of class `from` can be used where class `to` is expected. Like the
+    /// verifier, this treats every interface as if it were `java/lang/Object`.
+    fn is_assignable(&self, from: &str, to: &str) -> bool {
+        from == to || to == "java/lang/Object" || self.is_interface(to) ||
+        self.common_superclass(from, to) == to
+    }
+}
+
+/// A hierarchy that knows nothing about any class, so different classes merge to
+/// `java/lang/Object`.
+#[derive(Clone, Copy, Debug)]
+pub struct ObjectHierarchy;
+
+impl ClassHierarchy for ObjectHierarchy {
+    fn common_superclass(&self, _first: &str, _second: &str) -> String {
+        "java/lang/Object".to_string()
+    }
+}
+
+/// A borrowed hierarchy answers for the one it borrows, which lets a hierarchy be chosen at run
+/// time and held as a trait object.
+impl<H: ClassHierarchy + ?Sized> ClassHierarchy for &H {
+    fn common_superclass(&self, first: &str, second: &str) -> String {
+        (**self).common_superclass(first, second)
+    }
+
+    fn is_interface(&self, name: &str) -> bool {
+        (**self).is_interface(name)
+    }
+
+    fn is_assignable(&self, from: &str, to: &str) -> bool {
+        (**self).is_assignable(from, to)
+    }
+}
+
+#[derive(Clone, Debug)]
+struct KnownClass {
+    superclass: Option<String>,
+    is_interface: bool,
+}
+
+/// A hierarchy made up of the classes it has been given, either as parsed class files or by name.
+///
+/// Interfaces merge to `java/lang/Object`, as the verifier treats them like it. Classes whose
+/// superclass chains never meet within the known classes merge to `java/lang/Object` as well.
+#[derive(Clone, Debug, Default)]
+pub struct ClassFileHierarchy {
+    classes: HashMap<String, KnownClass>,
+}
+
+impl ClassFileHierarchy {
+    pub fn new() -> ClassFileHierarchy {
+        ClassFileHierarchy { classes: HashMap::new() }
+    }
+
+    pub fn add(&mut self, classfile: &ClassFile) -> ParserResult<()> {
+        let resolver = ConstantPoolResolver { constant_pool: &classfile.constant_pool };
+        let name = try!(resolver.resolve_class_name(classfile.this_class));
+        let superclass = if classfile.super_class == 0 {
+            None
+        } else {
+            Some(try!(resolver.resolve_class_name(classfile.super_class)))
+        };
+
+        self.add_class(&name,
+                       superclass.as_ref().map(|superclass| superclass.as_str()),
+                       classfile.access().contains(ClassAccess::INTERFACE));
+        Ok(())
+    }
+
+    /// Records a class that is not available as a class file, such as one from the JDK.
+    pub fn add_class(&mut self, name: &str, superclass: Option<&str>, is_interface: bool) {
+        self.classes.insert(name.to_string(),
+                            KnownClass {
+                                superclass: superclass.map(|superclass| superclass.to_string()),
+                                is_interface: is_interface,
+                            });
+    }
+
+    pub fn superclass(&self, name: &str) -> Option<&str> {
+        self.classes
+            .get(name)
+            .and_then(|class| class.superclass.as_ref())
+            .map(|superclass| superclass.as_str())
+    }
+
+    /// The class itself followed by its known superclasses, nearest first.
+    fn superclass_chain<'h>(&'h self, name: &'h str) -> Vec<&'h str> {
+        let mut chain = vec![name];
+        while let Some(superclass) = self.superclass(chain[chain.len() - 1]) {
+            // a malformed hierarchy could contain a cycle
+            if chain.contains(&superclass) {
+                break;
+            }
+            chain.push(superclass);
+        }
+
+        chain
+    }
+}
+
+impl ClassHierarchy for ClassFileHierarchy {
+    fn is_interface(&self, name: &str) -> bool {
+        self.classes.get(name).map(|class| class.is_interface).unwrap_or(false)
+    }
+
+    fn common_superclass(&self, first: &str, second: &str) -> String {
+        if self.is_interface(first) || self.is_interface(second) {
+            return "java/lang/Object".to_string();
+        }
+
+        let first_chain = self.superclass_chain(first);
+        self.superclass_chain(second)
+            .into_iter()
+            .find(|class| first_chain.contains(class))
+            .unwrap_or("java/lang/Object")
+            .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ClassFileHierarchy, ClassHierarchy};
+
+    fn hierarchy() -> ClassFileHierarchy {
+        let mut hierarchy = ClassFileHierarchy::new();
+        hierarchy.add_class("java/lang/Object", None, false);
+        hierarchy.add_class("java/util/AbstractCollection", Some("java/lang/Object"), false);
+        hierarchy.add_class("java/util/AbstractList", Some("java/util/AbstractCollection"), false);
+        hierarchy.add_class("java/util/AbstractSet", Some("java/util/AbstractCollection"), false);
+        hierarchy.add_class("java/util/ArrayList", Some("java/util/AbstractList"), false);
+        hierarchy.add_class("java/util/List", Some("java/lang/Object"), true);
+        hierarchy
+    }
+
+    #[test]
+    fn can_find_common_superclass() {
+        let hierarchy = hierarchy();
+
+        assert_that(&hierarchy.common_superclass("java/util/ArrayList", "java/util/AbstractSet"))
+            .is_equal_to(&"java/util/AbstractCollection".to_string());
+        assert_that(&hierarchy.common_superclass("java/util/AbstractList", "java/util/ArrayList"))
+            .is_equal_to(&"java/util/AbstractList".to_string());
+    }
+
+    #[test]
+    fn merges_interfaces_and_unknown_classes_to_object() {
+        let hierarchy = hierarchy();
+
+        assert_that(&hierarchy.common_superclass("java/util/ArrayList", "java/util/List"))
+            .is_equal_to(&"java/lang/Object".to_string());
+        assert_that(&hierarchy.common_superclass("java/util/ArrayList", "com/example/Unknown"))
+            .is_equal_to(&"java/lang/Object".to_string());
+    }
+
+    #[test]
+    fn can_check_assignability() {
+        let hierarchy = hierarchy();
+
+        assert_that(&hierarchy.is_assignable("java/util/ArrayList", "java/util/AbstractCollection"))
+            .is_equal_to(&true);
+        assert_that(&hierarchy.is_assignable("java/util/AbstractSet", "java/util/AbstractList"))
+            .is_equal_to(&false);
+        assert_that(&hierarchy.is_assignable("java/util/AbstractSet", "java/util/List"))
+            .is_equal_to(&true);
+    }
+
+}
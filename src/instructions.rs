@@ -0,0 +1,1268 @@
+use super::{ParserError, ParserResult};
+use super::primitives::{U1, U2, U4};
+
+/// A single decoded JVM instruction.
+///
+/// Branch operands are stored as `B`, which for decoded bytecode is the signed offset relative
+/// to the pc of the branching instruction. Local variable indexes are always widened to `U2`, so
+/// an instruction prefixed by `wide` decodes to the same variant as its narrow form.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Instruction<B = i32> {
+    Nop,
+    AconstNull,
+    IconstM1,
+    Iconst0,
+    Iconst1,
+    Iconst2,
+    Iconst3,
+    Iconst4,
+    Iconst5,
+    Lconst0,
+    Lconst1,
+    Fconst0,
+    Fconst1,
+    Fconst2,
+    Dconst0,
+    Dconst1,
+    Bipush(i8),
+    Sipush(i16),
+    Ldc(U1),
+    LdcW(U2),
+    Ldc2W(U2),
+    Iload(U2),
+    Lload(U2),
+    Fload(U2),
+    Dload(U2),
+    Aload(U2),
+    Iload0,
+    Iload1,
+    Iload2,
+    Iload3,
+    Lload0,
+    Lload1,
+    Lload2,
+    Lload3,
+    Fload0,
+    Fload1,
+    Fload2,
+    Fload3,
+    Dload0,
+    Dload1,
+    Dload2,
+    Dload3,
+    Aload0,
+    Aload1,
+    Aload2,
+    Aload3,
+    Iaload,
+    Laload,
+    Faload,
+    Daload,
+    Aaload,
+    Baload,
+    Caload,
+    Saload,
+    Istore(U2),
+    Lstore(U2),
+    Fstore(U2),
+    Dstore(U2),
+    Astore(U2),
+    Istore0,
+    Istore1,
+    Istore2,
+    Istore3,
+    Lstore0,
+    Lstore1,
+    Lstore2,
+    Lstore3,
+    Fstore0,
+    Fstore1,
+    Fstore2,
+    Fstore3,
+    Dstore0,
+    Dstore1,
+    Dstore2,
+    Dstore3,
+    Astore0,
+    Astore1,
+    Astore2,
+    Astore3,
+    Iastore,
+    Lastore,
+    Fastore,
+    Dastore,
+    Aastore,
+    Bastore,
+    Castore,
+    Sastore,
+    Pop,
+    Pop2,
+    Dup,
+    DupX1,
+    DupX2,
+    Dup2,
+    Dup2X1,
+    Dup2X2,
+    Swap,
+    Iadd,
+    Ladd,
+    Fadd,
+    Dadd,
+    Isub,
+    Lsub,
+    Fsub,
+    Dsub,
+    Imul,
+    Lmul,
+    Fmul,
+    Dmul,
+    Idiv,
+    Ldiv,
+    Fdiv,
+    Ddiv,
+    Irem,
+    Lrem,
+    Frem,
+    Drem,
+    Ineg,
+    Lneg,
+    Fneg,
+    Dneg,
+    Ishl,
+    Lshl,
+    Ishr,
+    Lshr,
+    Iushr,
+    Lushr,
+    Iand,
+    Land,
+    Ior,
+    Lor,
+    Ixor,
+    Lxor,
+    Iinc(U2, i16),
+    I2l,
+    I2f,
+    I2d,
+    L2i,
+    L2f,
+    L2d,
+    F2i,
+    F2l,
+    F2d,
+    D2i,
+    D2l,
+    D2f,
+    I2b,
+    I2c,
+    I2s,
+    Lcmp,
+    Fcmpl,
+    Fcmpg,
+    Dcmpl,
+    Dcmpg,
+    Ifeq(B),
+    Ifne(B),
+    Iflt(B),
+    Ifge(B),
+    Ifgt(B),
+    Ifle(B),
+    IfIcmpeq(B),
+    IfIcmpne(B),
+    IfIcmplt(B),
+    IfIcmpge(B),
+    IfIcmpgt(B),
+    IfIcmple(B),
+    IfAcmpeq(B),
+    IfAcmpne(B),
+    Goto(B),
+    Jsr(B),
+    Ret(U2),
+    Tableswitch {
+        default: B,
+        low: i32,
+        offsets: Vec<B>,
+    },
+    Lookupswitch { default: B, pairs: Vec<(i32, B)> },
+    Ireturn,
+    Lreturn,
+    Freturn,
+    Dreturn,
+    Areturn,
+    Return,
+    Getstatic(U2),
+    Putstatic(U2),
+    Getfield(U2),
+    Putfield(U2),
+    Invokevirtual(U2),
+    Invokespecial(U2),
+    Invokestatic(U2),
+    Invokeinterface(U2, U1),
+    Invokedynamic(U2),
+    New(U2),
+    Newarray(ArrayType),
+    Anewarray(U2),
+    Arraylength,
+    Athrow,
+    Checkcast(U2),
+    Instanceof(U2),
+    Monitorenter,
+    Monitorexit,
+    Multianewarray(U2, U1),
+    Ifnull(B),
+    Ifnonnull(B),
+    GotoW(B),
+    JsrW(B),
+    Breakpoint,
+    Impdep1,
+    Impdep2,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LocalAccess {
+    Load,
+    Store,
+    /// An `iinc`, which reads and writes its local variable.
+    Increment,
+}
+
+/// A local variable read or written by an instruction. Longs and doubles take up two slots,
+/// starting at `index`.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LocalVariableAccess {
+    pub access: LocalAccess,
+    pub index: U2,
+    pub slots: U2,
+}
+
+impl LocalVariableAccess {
+    /// The indexes of the slots taken up. The second slot of a long or double stored in the
+    /// last slot there is would lie past any index a local can have, so it is left out.
+    pub fn slot_indexes(&self) -> Vec<U2> {
+        (self.index as U4..self.index as U4 + self.slots as U4)
+            .filter(|&slot| slot <= U2::max_value() as U4)
+            .map(|slot| slot as U2)
+            .collect()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ArrayType {
+    Boolean,
+    Char,
+    Float,
+    Double,
+    Byte,
+    Short,
+    Int,
+    Long,
+}
+
+impl ArrayType {
+    pub fn from(atype: U1) -> ParserResult<ArrayType> {
+        match atype {
+            4 => Ok(ArrayType::Boolean),
+            5 => Ok(ArrayType::Char),
+            6 => Ok(ArrayType::Float),
+            7 => Ok(ArrayType::Double),
+            8 => Ok(ArrayType::Byte),
+            9 => Ok(ArrayType::Short),
+            10 => Ok(ArrayType::Int),
+            11 => Ok(ArrayType::Long),
+            _ => Err(ParserError::UnknownArrayType(atype)),
+        }
+    }
+
+    pub fn atype(&self) -> U1 {
+        match *self {
+            ArrayType::Boolean => 4,
+            ArrayType::Char => 5,
+            ArrayType::Float => 6,
+            ArrayType::Double => 7,
+            ArrayType::Byte => 8,
+            ArrayType::Short => 9,
+            ArrayType::Int => 10,
+            ArrayType::Long => 11,
+        }
+    }
+
+    pub fn to_friendly_name(&self) -> &'static str {
+        match *self {
+            ArrayType::Boolean => "boolean",
+            ArrayType::Char => "char",
+            ArrayType::Float => "float",
+            ArrayType::Double => "double",
+            ArrayType::Byte => "byte",
+            ArrayType::Short => "short",
+            ArrayType::Int => "int",
+            ArrayType::Long => "long",
+        }
+    }
+}
+
+macro_rules! generate_operandless_instruction_table {
+    ($($variant:ident => $opcode:expr, $mnemonic:expr;)*) => {
+        fn decode_operandless<B>(opcode: U1) -> Option<Instruction<B>> {
+            match opcode {
+                $($opcode => Some(Instruction::$variant),)*
+                _ => None,
+            }
+        }
+
+        fn map_operandless<B, C>(instruction: &Instruction<B>) -> Option<Instruction<C>> {
+            match instruction {
+                $(&Instruction::$variant => Some(Instruction::$variant),)*
+                _ => None,
+            }
+        }
+
+        fn operandless_opcode_and_mnemonic<B>(instruction: &Instruction<B>)
+                                              -> Option<(U1, &'static str)> {
+            match instruction {
+                $(&Instruction::$variant => Some(($opcode, $mnemonic)),)*
+                _ => None,
+            }
+        }
+
+        fn operandless_from_mnemonic<B>(mnemonic: &str) -> Option<Instruction<B>> {
+            match mnemonic {
+                $($mnemonic => Some(Instruction::$variant),)*
+                _ => None,
+            }
+        }
+    }
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+generate_operandless_instruction_table! {
+    Nop => 0x00, "nop";
+    AconstNull => 0x01, "aconst_null";
+    IconstM1 => 0x02, "iconst_m1";
+    Iconst0 => 0x03, "iconst_0";
+    Iconst1 => 0x04, "iconst_1";
+    Iconst2 => 0x05, "iconst_2";
+    Iconst3 => 0x06, "iconst_3";
+    Iconst4 => 0x07, "iconst_4";
+    Iconst5 => 0x08, "iconst_5";
+    Lconst0 => 0x09, "lconst_0";
+    Lconst1 => 0x0a, "lconst_1";
+    Fconst0 => 0x0b, "fconst_0";
+    Fconst1 => 0x0c, "fconst_1";
+    Fconst2 => 0x0d, "fconst_2";
+    Dconst0 => 0x0e, "dconst_0";
+    Dconst1 => 0x0f, "dconst_1";
+    Iload0 => 0x1a, "iload_0";
+    Iload1 => 0x1b, "iload_1";
+    Iload2 => 0x1c, "iload_2";
+    Iload3 => 0x1d, "iload_3";
+    Lload0 => 0x1e, "lload_0";
+    Lload1 => 0x1f, "lload_1";
+    Lload2 => 0x20, "lload_2";
+    Lload3 => 0x21, "lload_3";
+    Fload0 => 0x22, "fload_0";
+    Fload1 => 0x23, "fload_1";
+    Fload2 => 0x24, "fload_2";
+    Fload3 => 0x25, "fload_3";
+    Dload0 => 0x26, "dload_0";
+    Dload1 => 0x27, "dload_1";
+    Dload2 => 0x28, "dload_2";
+    Dload3 => 0x29, "dload_3";
+    Aload0 => 0x2a, "aload_0";
+    Aload1 => 0x2b, "aload_1";
+    Aload2 => 0x2c, "aload_2";
+    Aload3 => 0x2d, "aload_3";
+    Iaload => 0x2e, "iaload";
+    Laload => 0x2f, "laload";
+    Faload => 0x30, "faload";
+    Daload => 0x31, "daload";
+    Aaload => 0x32, "aaload";
+    Baload => 0x33, "baload";
+    Caload => 0x34, "caload";
+    Saload => 0x35, "saload";
+    Istore0 => 0x3b, "istore_0";
+    Istore1 => 0x3c, "istore_1";
+    Istore2 => 0x3d, "istore_2";
+    Istore3 => 0x3e, "istore_3";
+    Lstore0 => 0x3f, "lstore_0";
+    Lstore1 => 0x40, "lstore_1";
+    Lstore2 => 0x41, "lstore_2";
+    Lstore3 => 0x42, "lstore_3";
+    Fstore0 => 0x43, "fstore_0";
+    Fstore1 => 0x44, "fstore_1";
+    Fstore2 => 0x45, "fstore_2";
+    Fstore3 => 0x46, "fstore_3";
+    Dstore0 => 0x47, "dstore_0";
+    Dstore1 => 0x48, "dstore_1";
+    Dstore2 => 0x49, "dstore_2";
+    Dstore3 => 0x4a, "dstore_3";
+    Astore0 => 0x4b, "astore_0";
+    Astore1 => 0x4c, "astore_1";
+    Astore2 => 0x4d, "astore_2";
+    Astore3 => 0x4e, "astore_3";
+    Iastore => 0x4f, "iastore";
+    Lastore => 0x50, "lastore";
+    Fastore => 0x51, "fastore";
+    Dastore => 0x52, "dastore";
+    Aastore => 0x53, "aastore";
+    Bastore => 0x54, "bastore";
+    Castore => 0x55, "castore";
+    Sastore => 0x56, "sastore";
+    Pop => 0x57, "pop";
+    Pop2 => 0x58, "pop2";
+    Dup => 0x59, "dup";
+    DupX1 => 0x5a, "dup_x1";
+    DupX2 => 0x5b, "dup_x2";
+    Dup2 => 0x5c, "dup2";
+    Dup2X1 => 0x5d, "dup2_x1";
+    Dup2X2 => 0x5e, "dup2_x2";
+    Swap => 0x5f, "swap";
+    Iadd => 0x60, "iadd";
+    Ladd => 0x61, "ladd";
+    Fadd => 0x62, "fadd";
+    Dadd => 0x63, "dadd";
+    Isub => 0x64, "isub";
+    Lsub => 0x65, "lsub";
+    Fsub => 0x66, "fsub";
+    Dsub => 0x67, "dsub";
+    Imul => 0x68, "imul";
+    Lmul => 0x69, "lmul";
+    Fmul => 0x6a, "fmul";
+    Dmul => 0x6b, "dmul";
+    Idiv => 0x6c, "idiv";
+    Ldiv => 0x6d, "ldiv";
+    Fdiv => 0x6e, "fdiv";
+    Ddiv => 0x6f, "ddiv";
+    Irem => 0x70, "irem";
+    Lrem => 0x71, "lrem";
+    Frem => 0x72, "frem";
+    Drem => 0x73, "drem";
+    Ineg => 0x74, "ineg";
+    Lneg => 0x75, "lneg";
+    Fneg => 0x76, "fneg";
+    Dneg => 0x77, "dneg";
+    Ishl => 0x78, "ishl";
+    Lshl => 0x79, "lshl";
+    Ishr => 0x7a, "ishr";
+    Lshr => 0x7b, "lshr";
+    Iushr => 0x7c, "iushr";
+    Lushr => 0x7d, "lushr";
+    Iand => 0x7e, "iand";
+    Land => 0x7f, "land";
+    Ior => 0x80, "ior";
+    Lor => 0x81, "lor";
+    Ixor => 0x82, "ixor";
+    Lxor => 0x83, "lxor";
+    I2l => 0x85, "i2l";
+    I2f => 0x86, "i2f";
+    I2d => 0x87, "i2d";
+    L2i => 0x88, "l2i";
+    L2f => 0x89, "l2f";
+    L2d => 0x8a, "l2d";
+    F2i => 0x8b, "f2i";
+    F2l => 0x8c, "f2l";
+    F2d => 0x8d, "f2d";
+    D2i => 0x8e, "d2i";
+    D2l => 0x8f, "d2l";
+    D2f => 0x90, "d2f";
+    I2b => 0x91, "i2b";
+    I2c => 0x92, "i2c";
+    I2s => 0x93, "i2s";
+    Lcmp => 0x94, "lcmp";
+    Fcmpl => 0x95, "fcmpl";
+    Fcmpg => 0x96, "fcmpg";
+    Dcmpl => 0x97, "dcmpl";
+    Dcmpg => 0x98, "dcmpg";
+    Ireturn => 0xac, "ireturn";
+    Lreturn => 0xad, "lreturn";
+    Freturn => 0xae, "freturn";
+    Dreturn => 0xaf, "dreturn";
+    Areturn => 0xb0, "areturn";
+    Return => 0xb1, "return";
+    Arraylength => 0xbe, "arraylength";
+    Athrow => 0xbf, "athrow";
+    Monitorenter => 0xc2, "monitorenter";
+    Monitorexit => 0xc3, "monitorexit";
+    Breakpoint => 0xca, "breakpoint";
+    Impdep1 => 0xfe, "impdep1";
+    Impdep2 => 0xff, "impdep2";
+}
+
+const WIDE: U1 = 0xc4;
+
+impl Instruction {
+    /// Decodes the instruction starting at `pc`, returning it along with the pc of the
+    /// instruction that follows it.
+    pub fn decode(code: &[U1], pc: usize) -> ParserResult<(Instruction, usize)> {
+        let mut reader = CodeReader {
+            code: code,
+            instruction_pc: pc,
+            position: pc,
+        };
+
+        let opcode = try!(reader.next_u1());
+        if let Some(instruction) = decode_operandless(opcode) {
+            return Ok((instruction, reader.position));
+        }
+
+        let instruction = match opcode {
+            0x10 => Instruction::Bipush(try!(reader.next_u1()) as i8),
+            0x11 => Instruction::Sipush(try!(reader.next_u2()) as i16),
+            0x12 => Instruction::Ldc(try!(reader.next_u1())),
+            0x13 => Instruction::LdcW(try!(reader.next_u2())),
+            0x14 => Instruction::Ldc2W(try!(reader.next_u2())),
+            0x15 => Instruction::Iload(try!(reader.next_u1()) as U2),
+            0x16 => Instruction::Lload(try!(reader.next_u1()) as U2),
+            0x17 => Instruction::Fload(try!(reader.next_u1()) as U2),
+            0x18 => Instruction::Dload(try!(reader.next_u1()) as U2),
+            0x19 => Instruction::Aload(try!(reader.next_u1()) as U2),
+            0x36 => Instruction::Istore(try!(reader.next_u1()) as U2),
+            0x37 => Instruction::Lstore(try!(reader.next_u1()) as U2),
+            0x38 => Instruction::Fstore(try!(reader.next_u1()) as U2),
+            0x39 => Instruction::Dstore(try!(reader.next_u1()) as U2),
+            0x3a => Instruction::Astore(try!(reader.next_u1()) as U2),
+            0x84 => {
+                let index = try!(reader.next_u1()) as U2;
+                let constant = try!(reader.next_u1()) as i8;
+                Instruction::Iinc(index, constant as i16)
+            }
+            0x99 => Instruction::Ifeq(try!(reader.next_branch_offset())),
+            0x9a => Instruction::Ifne(try!(reader.next_branch_offset())),
+            0x9b => Instruction::Iflt(try!(reader.next_branch_offset())),
+            0x9c => Instruction::Ifge(try!(reader.next_branch_offset())),
+            0x9d => Instruction::Ifgt(try!(reader.next_branch_offset())),
+            0x9e => Instruction::Ifle(try!(reader.next_branch_offset())),
+            0x9f => Instruction::IfIcmpeq(try!(reader.next_branch_offset())),
+            0xa0 => Instruction::IfIcmpne(try!(reader.next_branch_offset())),
+            0xa1 => Instruction::IfIcmplt(try!(reader.next_branch_offset())),
+            0xa2 => Instruction::IfIcmpge(try!(reader.next_branch_offset())),
+            0xa3 => Instruction::IfIcmpgt(try!(reader.next_branch_offset())),
+            0xa4 => Instruction::IfIcmple(try!(reader.next_branch_offset())),
+            0xa5 => Instruction::IfAcmpeq(try!(reader.next_branch_offset())),
+            0xa6 => Instruction::IfAcmpne(try!(reader.next_branch_offset())),
+            0xa7 => Instruction::Goto(try!(reader.next_branch_offset())),
+            0xa8 => Instruction::Jsr(try!(reader.next_branch_offset())),
+            0xa9 => Instruction::Ret(try!(reader.next_u1()) as U2),
+            0xaa => try!(Self::decode_tableswitch(&mut reader)),
+            0xab => try!(Self::decode_lookupswitch(&mut reader)),
+            0xb2 => Instruction::Getstatic(try!(reader.next_u2())),
+            0xb3 => Instruction::Putstatic(try!(reader.next_u2())),
+            0xb4 => Instruction::Getfield(try!(reader.next_u2())),
+            0xb5 => Instruction::Putfield(try!(reader.next_u2())),
+            0xb6 => Instruction::Invokevirtual(try!(reader.next_u2())),
+            0xb7 => Instruction::Invokespecial(try!(reader.next_u2())),
+            0xb8 => Instruction::Invokestatic(try!(reader.next_u2())),
+            0xb9 => {
+                let index = try!(reader.next_u2());
+                let count = try!(reader.next_u1());
+                try!(reader.next_u1()); // always zero, kept for historical reasons
+                Instruction::Invokeinterface(index, count)
+            }
+            0xba => {
+                let index = try!(reader.next_u2());
+                try!(reader.next_u2()); // always zero
+                Instruction::Invokedynamic(index)
+            }
+            0xbb => Instruction::New(try!(reader.next_u2())),
+            0xbc => Instruction::Newarray(try!(ArrayType::from(try!(reader.next_u1())))),
+            0xbd => Instruction::Anewarray(try!(reader.next_u2())),
+            0xc0 => Instruction::Checkcast(try!(reader.next_u2())),
+            0xc1 => Instruction::Instanceof(try!(reader.next_u2())),
+            WIDE => try!(Self::decode_wide(&mut reader)),
+            0xc5 => {
+                let index = try!(reader.next_u2());
+                let dimensions = try!(reader.next_u1());
+                Instruction::Multianewarray(index, dimensions)
+            }
+            0xc6 => Instruction::Ifnull(try!(reader.next_branch_offset())),
+            0xc7 => Instruction::Ifnonnull(try!(reader.next_branch_offset())),
+            0xc8 => Instruction::GotoW(try!(reader.next_i4())),
+            0xc9 => Instruction::JsrW(try!(reader.next_i4())),
+            _ => return Err(ParserError::UnknownOpcode(opcode)),
+        };
+
+        Ok((instruction, reader.position))
+    }
+
+    fn decode_wide(reader: &mut CodeReader) -> ParserResult<Instruction> {
+        let opcode = try!(reader.next_u1());
+        let index = try!(reader.next_u2());
+
+        let instruction = match opcode {
+            0x15 => Instruction::Iload(index),
+            0x16 => Instruction::Lload(index),
+            0x17 => Instruction::Fload(index),
+            0x18 => Instruction::Dload(index),
+            0x19 => Instruction::Aload(index),
+            0x36 => Instruction::Istore(index),
+            0x37 => Instruction::Lstore(index),
+            0x38 => Instruction::Fstore(index),
+            0x39 => Instruction::Dstore(index),
+            0x3a => Instruction::Astore(index),
+            0xa9 => Instruction::Ret(index),
+            0x84 => Instruction::Iinc(index, try!(reader.next_u2()) as i16),
+            _ => return Err(ParserError::InvalidWideOpcode(opcode)),
+        };
+
+        Ok(instruction)
+    }
+
+    fn decode_tableswitch(reader: &mut CodeReader) -> ParserResult<Instruction> {
+        try!(reader.skip_switch_padding());
+
+        let default = try!(reader.next_i4());
+        let low = try!(reader.next_i4());
+        let high = try!(reader.next_i4());
+        if high < low {
+            return Err(ParserError::InvalidSwitchBounds(low, high));
+        }
+
+        let mut offsets = vec![];
+        for _ in 0..(high as i64 - low as i64 + 1) {
+            offsets.push(try!(reader.next_i4()));
+        }
+
+        Ok(Instruction::Tableswitch {
+            default: default,
+            low: low,
+            offsets: offsets,
+        })
+    }
+
+    fn decode_lookupswitch(reader: &mut CodeReader) -> ParserResult<Instruction> {
+        try!(reader.skip_switch_padding());
+
+        let default = try!(reader.next_i4());
+        let npairs = try!(reader.next_i4());
+        if npairs < 0 {
+            return Err(ParserError::InvalidSwitchBounds(0, npairs));
+        }
+
+        let mut pairs = vec![];
+        for _ in 0..npairs {
+            let key = try!(reader.next_i4());
+            let offset = try!(reader.next_i4());
+            pairs.push((key, offset));
+        }
+
+        Ok(Instruction::Lookupswitch {
+            default: default,
+            pairs: pairs,
+        })
+    }
+}
+
+impl Instruction {
+    /// Appends the encoding of this instruction to `code`, using the current length of `code` as
+    /// the pc of the instruction. Local variable instructions are prefixed with `wide` only when
+    /// their operands do not fit the narrow form.
+    pub fn encode(&self, code: &mut Vec<U1>) -> ParserResult<()> {
+        let pc = code.len();
+        let opcode = self.opcode();
+
+        match *self {
+            Instruction::Bipush(value) => {
+                code.push(opcode);
+                code.push(value as U1);
+            }
+            Instruction::Sipush(value) => {
+                code.push(opcode);
+                push_u2(code, value as U2);
+            }
+            Instruction::Ldc(index) => {
+                code.push(opcode);
+                code.push(index);
+            }
+            Instruction::Iload(index) |
+            Instruction::Lload(index) |
+            Instruction::Fload(index) |
+            Instruction::Dload(index) |
+            Instruction::Aload(index) |
+            Instruction::Istore(index) |
+            Instruction::Lstore(index) |
+            Instruction::Fstore(index) |
+            Instruction::Dstore(index) |
+            Instruction::Astore(index) |
+            Instruction::Ret(index) => {
+                if index > U1::max_value() as U2 {
+                    code.push(WIDE);
+                    code.push(opcode);
+                    push_u2(code, index);
+                } else {
+                    code.push(opcode);
+                    code.push(index as U1);
+                }
+            }
+            Instruction::Iinc(index, constant) => {
+                if index > U1::max_value() as U2 || constant != constant as i8 as i16 {
+                    code.push(WIDE);
+                    code.push(opcode);
+                    push_u2(code, index);
+                    push_u2(code, constant as U2);
+                } else {
+                    code.push(opcode);
+                    code.push(index as U1);
+                    code.push(constant as i8 as U1);
+                }
+            }
+            Instruction::Ifeq(offset) |
+            Instruction::Ifne(offset) |
+            Instruction::Iflt(offset) |
+            Instruction::Ifge(offset) |
+            Instruction::Ifgt(offset) |
+            Instruction::Ifle(offset) |
+            Instruction::IfIcmpeq(offset) |
+            Instruction::IfIcmpne(offset) |
+            Instruction::IfIcmplt(offset) |
+            Instruction::IfIcmpge(offset) |
+            Instruction::IfIcmpgt(offset) |
+            Instruction::IfIcmple(offset) |
+            Instruction::IfAcmpeq(offset) |
+            Instruction::IfAcmpne(offset) |
+            Instruction::Goto(offset) |
+            Instruction::Jsr(offset) |
+            Instruction::Ifnull(offset) |
+            Instruction::Ifnonnull(offset) => {
+                if offset != offset as i16 as i32 {
+                    return Err(ParserError::BranchOffsetOverflow(pc));
+                }
+
+                code.push(opcode);
+                push_u2(code, offset as U2);
+            }
+            Instruction::GotoW(offset) |
+            Instruction::JsrW(offset) => {
+                code.push(opcode);
+                push_i4(code, offset);
+            }
+            Instruction::Tableswitch { default, low, ref offsets } => {
+                if offsets.is_empty() {
+                    return Err(ParserError::InvalidSwitchBounds(low, low - 1));
+                }
+
+                code.push(opcode);
+                push_switch_padding(code);
+                push_i4(code, default);
+                push_i4(code, low);
+                push_i4(code, low.wrapping_add(offsets.len() as i32 - 1));
+                for offset in offsets {
+                    push_i4(code, *offset);
+                }
+            }
+            Instruction::Lookupswitch { default, ref pairs } => {
+                code.push(opcode);
+                push_switch_padding(code);
+                push_i4(code, default);
+                push_i4(code, pairs.len() as i32);
+                for &(key, offset) in pairs {
+                    push_i4(code, key);
+                    push_i4(code, offset);
+                }
+            }
+            Instruction::Invokeinterface(index, count) => {
+                code.push(opcode);
+                push_u2(code, index);
+                code.push(count);
+                code.push(0);
+            }
+            Instruction::Invokedynamic(index) => {
+                code.push(opcode);
+                push_u2(code, index);
+                push_u2(code, 0);
+            }
+            Instruction::Newarray(atype) => {
+                code.push(opcode);
+                code.push(atype.atype());
+            }
+            Instruction::Multianewarray(index, dimensions) => {
+                code.push(opcode);
+                push_u2(code, index);
+                code.push(dimensions);
+            }
+            _ => {
+                code.push(opcode);
+                if let Some(index) = self.constant_pool_index() {
+                    push_u2(code, index);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn push_u2(code: &mut Vec<U1>, value: U2) {
+    code.push((value >> 8) as U1);
+    code.push(value as U1);
+}
+
+fn push_i4(code: &mut Vec<U1>, value: i32) {
+    push_u2(code, ((value as u32) >> 16) as U2);
+    push_u2(code, value as U2);
+}
+
+fn push_switch_padding(code: &mut Vec<U1>) {
+    while code.len() % 4 != 0 {
+        code.push(0);
+    }
+}
+
+impl<B> Instruction<B> {
+    /// Looks up an instruction that takes no operands by its mnemonic, such as `iadd`.
+    pub fn from_operandless_mnemonic(mnemonic: &str) -> Option<Instruction<B>> {
+        operandless_from_mnemonic(mnemonic)
+    }
+
+    pub fn opcode(&self) -> U1 {
+        self.opcode_and_mnemonic().0
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        self.opcode_and_mnemonic().1
+    }
+
+    /// The constant pool index referenced by this instruction, if any.
+    pub fn constant_pool_index(&self) -> Option<U2> {
+        match *self {
+            Instruction::Ldc(index) => Some(index as U2),
+            Instruction::LdcW(index) |
+            Instruction::Ldc2W(index) |
+            Instruction::Getstatic(index) |
+            Instruction::Putstatic(index) |
+            Instruction::Getfield(index) |
+            Instruction::Putfield(index) |
+            Instruction::Invokevirtual(index) |
+            Instruction::Invokespecial(index) |
+            Instruction::Invokestatic(index) |
+            Instruction::Invokeinterface(index, _) |
+            Instruction::Invokedynamic(index) |
+            Instruction::New(index) |
+            Instruction::Anewarray(index) |
+            Instruction::Checkcast(index) |
+            Instruction::Instanceof(index) |
+            Instruction::Multianewarray(index, _) => Some(index),
+            _ => None,
+        }
+    }
+
+    /// The local variable this instruction loads, stores or increments, if any. The return
+    /// address read by `ret` counts as a load.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    pub fn local_variable_access(&self) -> Option<LocalVariableAccess> {
+        let (access, index, slots) = match *self {
+            Instruction::Iload(index) | Instruction::Fload(index) | Instruction::Aload(index) |
+            Instruction::Ret(index) => (LocalAccess::Load, index, 1),
+            Instruction::Lload(index) | Instruction::Dload(index) => (LocalAccess::Load, index, 2),
+            Instruction::Iload0 | Instruction::Fload0 | Instruction::Aload0 => (LocalAccess::Load, 0, 1),
+            Instruction::Iload1 | Instruction::Fload1 | Instruction::Aload1 => (LocalAccess::Load, 1, 1),
+            Instruction::Iload2 | Instruction::Fload2 | Instruction::Aload2 => (LocalAccess::Load, 2, 1),
+            Instruction::Iload3 | Instruction::Fload3 | Instruction::Aload3 => (LocalAccess::Load, 3, 1),
+            Instruction::Lload0 | Instruction::Dload0 => (LocalAccess::Load, 0, 2),
+            Instruction::Lload1 | Instruction::Dload1 => (LocalAccess::Load, 1, 2),
+            Instruction::Lload2 | Instruction::Dload2 => (LocalAccess::Load, 2, 2),
+            Instruction::Lload3 | Instruction::Dload3 => (LocalAccess::Load, 3, 2),
+            Instruction::Istore(index) | Instruction::Fstore(index) |
+            Instruction::Astore(index) => (LocalAccess::Store, index, 1),
+            Instruction::Lstore(index) | Instruction::Dstore(index) => (LocalAccess::Store, index, 2),
+            Instruction::Istore0 | Instruction::Fstore0 | Instruction::Astore0 => (LocalAccess::Store, 0, 1),
+            Instruction::Istore1 | Instruction::Fstore1 | Instruction::Astore1 => (LocalAccess::Store, 1, 1),
+            Instruction::Istore2 | Instruction::Fstore2 | Instruction::Astore2 => (LocalAccess::Store, 2, 1),
+            Instruction::Istore3 | Instruction::Fstore3 | Instruction::Astore3 => (LocalAccess::Store, 3, 1),
+            Instruction::Lstore0 | Instruction::Dstore0 => (LocalAccess::Store, 0, 2),
+            Instruction::Lstore1 | Instruction::Dstore1 => (LocalAccess::Store, 1, 2),
+            Instruction::Lstore2 | Instruction::Dstore2 => (LocalAccess::Store, 2, 2),
+            Instruction::Lstore3 | Instruction::Dstore3 => (LocalAccess::Store, 3, 2),
+            Instruction::Iinc(index, _) => (LocalAccess::Increment, index, 1),
+            _ => return None,
+        };
+
+        Some(LocalVariableAccess {
+            access: access,
+            index: index,
+            slots: slots,
+        })
+    }
+
+    /// The branch operands of this instruction, with the default target of a switch first.
+    pub fn branch_targets(&self) -> Vec<&B> {
+        match *self {
+            Instruction::Ifeq(ref target) |
+            Instruction::Ifne(ref target) |
+            Instruction::Iflt(ref target) |
+            Instruction::Ifge(ref target) |
+            Instruction::Ifgt(ref target) |
+            Instruction::Ifle(ref target) |
+            Instruction::IfIcmpeq(ref target) |
+            Instruction::IfIcmpne(ref target) |
+            Instruction::IfIcmplt(ref target) |
+            Instruction::IfIcmpge(ref target) |
+            Instruction::IfIcmpgt(ref target) |
+            Instruction::IfIcmple(ref target) |
+            Instruction::IfAcmpeq(ref target) |
+            Instruction::IfAcmpne(ref target) |
+            Instruction::Goto(ref target) |
+            Instruction::Jsr(ref target) |
+            Instruction::Ifnull(ref target) |
+            Instruction::Ifnonnull(ref target) |
+            Instruction::GotoW(ref target) |
+            Instruction::JsrW(ref target) => vec![target],
+            Instruction::Tableswitch { ref default, ref offsets, .. } => {
+                let mut targets = vec![default];
+                targets.extend(offsets.iter());
+                targets
+            }
+            Instruction::Lookupswitch { ref default, ref pairs } => {
+                let mut targets = vec![default];
+                targets.extend(pairs.iter().map(|&(_, ref target)| target));
+                targets
+            }
+            _ => vec![],
+        }
+    }
+
+    /// Converts the branch operands of this instruction, leaving every other operand as is.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    pub fn map_targets<C, F: FnMut(B) -> C>(self, mut f: F) -> Instruction<C> {
+        if let Some(instruction) = map_operandless(&self) {
+            return instruction;
+        }
+
+        match self {
+            Instruction::Bipush(value) => Instruction::Bipush(value),
+            Instruction::Sipush(value) => Instruction::Sipush(value),
+            Instruction::Ldc(index) => Instruction::Ldc(index),
+            Instruction::LdcW(index) => Instruction::LdcW(index),
+            Instruction::Ldc2W(index) => Instruction::Ldc2W(index),
+            Instruction::Iload(index) => Instruction::Iload(index),
+            Instruction::Lload(index) => Instruction::Lload(index),
+            Instruction::Fload(index) => Instruction::Fload(index),
+            Instruction::Dload(index) => Instruction::Dload(index),
+            Instruction::Aload(index) => Instruction::Aload(index),
+            Instruction::Istore(index) => Instruction::Istore(index),
+            Instruction::Lstore(index) => Instruction::Lstore(index),
+            Instruction::Fstore(index) => Instruction::Fstore(index),
+            Instruction::Dstore(index) => Instruction::Dstore(index),
+            Instruction::Astore(index) => Instruction::Astore(index),
+            Instruction::Iinc(index, constant) => Instruction::Iinc(index, constant),
+            Instruction::Ifeq(target) => Instruction::Ifeq(f(target)),
+            Instruction::Ifne(target) => Instruction::Ifne(f(target)),
+            Instruction::Iflt(target) => Instruction::Iflt(f(target)),
+            Instruction::Ifge(target) => Instruction::Ifge(f(target)),
+            Instruction::Ifgt(target) => Instruction::Ifgt(f(target)),
+            Instruction::Ifle(target) => Instruction::Ifle(f(target)),
+            Instruction::IfIcmpeq(target) => Instruction::IfIcmpeq(f(target)),
+            Instruction::IfIcmpne(target) => Instruction::IfIcmpne(f(target)),
+            Instruction::IfIcmplt(target) => Instruction::IfIcmplt(f(target)),
+            Instruction::IfIcmpge(target) => Instruction::IfIcmpge(f(target)),
+            Instruction::IfIcmpgt(target) => Instruction::IfIcmpgt(f(target)),
+            Instruction::IfIcmple(target) => Instruction::IfIcmple(f(target)),
+            Instruction::IfAcmpeq(target) => Instruction::IfAcmpeq(f(target)),
+            Instruction::IfAcmpne(target) => Instruction::IfAcmpne(f(target)),
+            Instruction::Goto(target) => Instruction::Goto(f(target)),
+            Instruction::Jsr(target) => Instruction::Jsr(f(target)),
+            Instruction::Ret(index) => Instruction::Ret(index),
+            Instruction::Tableswitch { default, low, offsets } => {
+                Instruction::Tableswitch {
+                    default: f(default),
+                    low: low,
+                    offsets: offsets.into_iter().map(|target| f(target)).collect(),
+                }
+            }
+            Instruction::Lookupswitch { default, pairs } => {
+                Instruction::Lookupswitch {
+                    default: f(default),
+                    pairs: pairs.into_iter().map(|(key, target)| (key, f(target))).collect(),
+                }
+            }
+            Instruction::Getstatic(index) => Instruction::Getstatic(index),
+            Instruction::Putstatic(index) => Instruction::Putstatic(index),
+            Instruction::Getfield(index) => Instruction::Getfield(index),
+            Instruction::Putfield(index) => Instruction::Putfield(index),
+            Instruction::Invokevirtual(index) => Instruction::Invokevirtual(index),
+            Instruction::Invokespecial(index) => Instruction::Invokespecial(index),
+            Instruction::Invokestatic(index) => Instruction::Invokestatic(index),
+            Instruction::Invokeinterface(index, count) => Instruction::Invokeinterface(index, count),
+            Instruction::Invokedynamic(index) => Instruction::Invokedynamic(index),
+            Instruction::New(index) => Instruction::New(index),
+            Instruction::Newarray(atype) => Instruction::Newarray(atype),
+            Instruction::Anewarray(index) => Instruction::Anewarray(index),
+            Instruction::Checkcast(index) => Instruction::Checkcast(index),
+            Instruction::Instanceof(index) => Instruction::Instanceof(index),
+            Instruction::Multianewarray(index, dimensions) => {
+                Instruction::Multianewarray(index, dimensions)
+            }
+            Instruction::Ifnull(target) => Instruction::Ifnull(f(target)),
+            Instruction::Ifnonnull(target) => Instruction::Ifnonnull(f(target)),
+            Instruction::GotoW(target) => Instruction::GotoW(f(target)),
+            Instruction::JsrW(target) => Instruction::JsrW(f(target)),
+            _ => unreachable!("operandless instructions are covered by the instruction table"),
+        }
+    }
+
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    fn opcode_and_mnemonic(&self) -> (U1, &'static str) {
+        if let Some(opcode_and_mnemonic) = operandless_opcode_and_mnemonic(self) {
+            return opcode_and_mnemonic;
+        }
+
+        match self {
+            &Instruction::Bipush(..) => (0x10, "bipush"),
+            &Instruction::Sipush(..) => (0x11, "sipush"),
+            &Instruction::Ldc(..) => (0x12, "ldc"),
+            &Instruction::LdcW(..) => (0x13, "ldc_w"),
+            &Instruction::Ldc2W(..) => (0x14, "ldc2_w"),
+            &Instruction::Iload(..) => (0x15, "iload"),
+            &Instruction::Lload(..) => (0x16, "lload"),
+            &Instruction::Fload(..) => (0x17, "fload"),
+            &Instruction::Dload(..) => (0x18, "dload"),
+            &Instruction::Aload(..) => (0x19, "aload"),
+            &Instruction::Istore(..) => (0x36, "istore"),
+            &Instruction::Lstore(..) => (0x37, "lstore"),
+            &Instruction::Fstore(..) => (0x38, "fstore"),
+            &Instruction::Dstore(..) => (0x39, "dstore"),
+            &Instruction::Astore(..) => (0x3a, "astore"),
+            &Instruction::Iinc(..) => (0x84, "iinc"),
+            &Instruction::Ifeq(..) => (0x99, "ifeq"),
+            &Instruction::Ifne(..) => (0x9a, "ifne"),
+            &Instruction::Iflt(..) => (0x9b, "iflt"),
+            &Instruction::Ifge(..) => (0x9c, "ifge"),
+            &Instruction::Ifgt(..) => (0x9d, "ifgt"),
+            &Instruction::Ifle(..) => (0x9e, "ifle"),
+            &Instruction::IfIcmpeq(..) => (0x9f, "if_icmpeq"),
+            &Instruction::IfIcmpne(..) => (0xa0, "if_icmpne"),
+            &Instruction::IfIcmplt(..) => (0xa1, "if_icmplt"),
+            &Instruction::IfIcmpge(..) => (0xa2, "if_icmpge"),
+            &Instruction::IfIcmpgt(..) => (0xa3, "if_icmpgt"),
+            &Instruction::IfIcmple(..) => (0xa4, "if_icmple"),
+            &Instruction::IfAcmpeq(..) => (0xa5, "if_acmpeq"),
+            &Instruction::IfAcmpne(..) => (0xa6, "if_acmpne"),
+            &Instruction::Goto(..) => (0xa7, "goto"),
+            &Instruction::Jsr(..) => (0xa8, "jsr"),
+            &Instruction::Ret(..) => (0xa9, "ret"),
+            &Instruction::Tableswitch { .. } => (0xaa, "tableswitch"),
+            &Instruction::Lookupswitch { .. } => (0xab, "lookupswitch"),
+            &Instruction::Getstatic(..) => (0xb2, "getstatic"),
+            &Instruction::Putstatic(..) => (0xb3, "putstatic"),
+            &Instruction::Getfield(..) => (0xb4, "getfield"),
+            &Instruction::Putfield(..) => (0xb5, "putfield"),
+            &Instruction::Invokevirtual(..) => (0xb6, "invokevirtual"),
+            &Instruction::Invokespecial(..) => (0xb7, "invokespecial"),
+            &Instruction::Invokestatic(..) => (0xb8, "invokestatic"),
+            &Instruction::Invokeinterface(..) => (0xb9, "invokeinterface"),
+            &Instruction::Invokedynamic(..) => (0xba, "invokedynamic"),
+            &Instruction::New(..) => (0xbb, "new"),
+            &Instruction::Newarray(..) => (0xbc, "newarray"),
+            &Instruction::Anewarray(..) => (0xbd, "anewarray"),
+            &Instruction::Checkcast(..) => (0xc0, "checkcast"),
+            &Instruction::Instanceof(..) => (0xc1, "instanceof"),
+            &Instruction::Multianewarray(..) => (0xc5, "multianewarray"),
+            &Instruction::Ifnull(..) => (0xc6, "ifnull"),
+            &Instruction::Ifnonnull(..) => (0xc7, "ifnonnull"),
+            &Instruction::GotoW(..) => (0xc8, "goto_w"),
+            &Instruction::JsrW(..) => (0xc9, "jsr_w"),
+            _ => unreachable!("operandless instructions are covered by the instruction table"),
+        }
+    }
+}
+
+/// Iterates over the instructions of a code array, yielding each with its pc.
+///
+/// Iteration stops after the first decoding error.
+pub struct InstructionIterator<'c> {
+    code: &'c [U1],
+    pc: usize,
+    failed: bool,
+}
+
+impl<'c> InstructionIterator<'c> {
+    pub fn new(code: &'c [U1]) -> InstructionIterator<'c> {
+        InstructionIterator {
+            code: code,
+            pc: 0,
+            failed: false,
+        }
+    }
+}
+
+impl<'c> Iterator for InstructionIterator<'c> {
+    type Item = ParserResult<(U2, Instruction)>;
+
+    fn next(&mut self) -> Option<ParserResult<(U2, Instruction)>> {
+        if self.failed || self.pc >= self.code.len() {
+            return None;
+        }
+
+        if self.pc > U2::max_value() as usize {
+            self.failed = true;
+            return Some(Err(ParserError::CodeTooLarge(self.code.len())));
+        }
+
+        let pc = self.pc;
+        match Instruction::decode(self.code, pc) {
+            Ok((instruction, next_pc)) => {
+                self.pc = next_pc;
+                Some(Ok((pc as U2, instruction)))
+            }
+            Err(error) => {
+                self.failed = true;
+                Some(Err(error))
+            }
+        }
+    }
+}
+
+struct CodeReader<'c> {
+    code: &'c [U1],
+    instruction_pc: usize,
+    position: usize,
+}
+
+impl<'c> CodeReader<'c> {
+    fn next_u1(&mut self) -> ParserResult<U1> {
+        match self.code.get(self.position) {
+            Some(byte) => {
+                self.position += 1;
+                Ok(*byte)
+            }
+            None => Err(ParserError::TruncatedInstruction(self.instruction_pc)),
+        }
+    }
+
+    fn next_u2(&mut self) -> ParserResult<U2> {
+        let first = try!(self.next_u1()) as U2;
+        let second = try!(self.next_u1()) as U2;
+
+        Ok((first << 8) | second)
+    }
+
+    fn next_i4(&mut self) -> ParserResult<i32> {
+        let first = try!(self.next_u2()) as u32;
+        let second = try!(self.next_u2()) as u32;
+
+        Ok(((first << 16) | second) as i32)
+    }
+
+    fn next_branch_offset(&mut self) -> ParserResult<i32> {
+        Ok(try!(self.next_u2()) as i16 as i32)
+    }
+
+    fn skip_switch_padding(&mut self) -> ParserResult<()> {
+        while self.position % 4 != 0 {
+            try!(self.next_u1());
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ArrayType, Instruction, InstructionIterator};
+    use super::super::ParserError;
+    use super::super::primitives::U2;
+
+    fn decode_all(code: &[u8]) -> Vec<(U2, Instruction)> {
+        InstructionIterator::new(code).map(|result| result.unwrap()).collect()
+    }
+
+    #[test]
+    fn can_decode_simple_instructions() {
+        let instructions = decode_all(&[0x2a, 0xb7, 0x00, 0x01, 0xb1]);
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::Aload0),
+                                                      (1, Instruction::Invokespecial(1)),
+                                                      (4, Instruction::Return)]);
+    }
+
+    #[test]
+    fn can_decode_negative_branch_offsets() {
+        let instructions = decode_all(&[0x00, 0xa7, 0xff, 0xff]);
+
+        assert_that(&instructions[1]).is_equal_to(&(1, Instruction::Goto(-1)));
+    }
+
+    #[test]
+    fn can_decode_wide_instructions() {
+        let instructions = decode_all(&[0xc4, 0x15, 0x01, 0x00, 0xc4, 0x84, 0x01, 0x00, 0xff,
+                                        0x38]);
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::Iload(256)),
+                                                      (4, Instruction::Iinc(256, -200))]);
+    }
+
+    #[test]
+    fn can_decode_tableswitch_with_padding() {
+        let instructions = decode_all(&[0x00, 0xaa, 0x00, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00,
+                                        0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00,
+                                        0x00, 0x10, 0x00, 0x00, 0x00, 0x18]);
+
+        assert_that(&instructions[1]).is_equal_to(&(1,
+                                                     Instruction::Tableswitch {
+                                                         default: 32,
+                                                         low: 1,
+                                                         offsets: vec![16, 24],
+                                                     }));
+    }
+
+    #[test]
+    fn can_decode_lookupswitch_with_padding() {
+        let instructions = decode_all(&[0xab, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x10, 0x00,
+                                        0x00, 0x00, 0x01, 0xff, 0xff, 0xff, 0xff, 0x00, 0x00,
+                                        0x00, 0x14]);
+
+        assert_that(&instructions).is_equal_to(&vec![(0,
+                                                       Instruction::Lookupswitch {
+                                                           default: 16,
+                                                           pairs: vec![(-1, 20)],
+                                                       })]);
+    }
+
+    #[test]
+    fn can_decode_invokeinterface_and_multianewarray() {
+        let instructions = decode_all(&[0xb9, 0x00, 0x07, 0x02, 0x00, 0xc5, 0x00, 0x08, 0x03,
+                                        0xbc, 0x0a]);
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::Invokeinterface(7, 2)),
+                                                      (5, Instruction::Multianewarray(8, 3)),
+                                                      (9, Instruction::Newarray(ArrayType::Int))]);
+    }
+
+    #[test]
+    fn reports_truncated_instructions() {
+        let results: Vec<_> = InstructionIterator::new(&[0x00, 0x11, 0x01]).collect();
+
+        assert_that(&results).has_length(2);
+        asserting("truncated sipush is an error")
+            .that(&results[1])
+            .matches(|val| match *val {
+                Err(ParserError::TruncatedInstruction(1)) => true,
+                _ => false,
+            });
+    }
+
+    #[test]
+    fn reports_unknown_opcodes() {
+        let results: Vec<_> = InstructionIterator::new(&[0xe0]).collect();
+
+        asserting("unknown opcode is an error")
+            .that(&results[0])
+            .matches(|val| match *val {
+                Err(ParserError::UnknownOpcode(0xe0)) => true,
+                _ => false,
+            });
+    }
+
+    #[test]
+    fn leaves_out_slots_past_the_last_local() {
+        let access = Instruction::Lstore::<i32>(0xffff).local_variable_access().unwrap();
+        assert_that(&access.slot_indexes()).is_equal_to(&vec![0xffff]);
+
+        let access = Instruction::Dload::<i32>(0xfffe).local_variable_access().unwrap();
+        assert_that(&access.slot_indexes()).is_equal_to(&vec![0xfffe, 0xffff]);
+    }
+
+}
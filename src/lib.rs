@@ -1,13 +1,42 @@
-use components::{Attribute, ConstantPoolItem, ConstantPoolResolver, Field, Method, Utf8Info};
-use primitives::{PrimitiveIterator, U1, U2, U4};
+use access::ClassAccess;
+use components::{Attribute, ConstantPoolItem, ConstantPoolResolver, Field, LazyAttribute, Method,
+                 Utf8Info};
+use pool::ConstantPool;
+use primitives::{ByteCursor, PrimitiveIterator, PrimitiveWriter, U1, U2, U4};
+use stream::AttributeOwner;
 
 use std::fs::File;
-use std::io::{Error as IoError, Read};
+use std::io::{Error as IoError, Read, Write};
+use std::path::Path;
 use std::rc::Rc;
 use std::string::FromUtf8Error;
 
+pub mod access;
+pub mod assembler;
+pub mod assembly;
+pub mod builder;
 pub mod components;
+pub mod controlflow;
+pub mod dataflow;
+pub mod descriptors;
+pub mod disassembler;
+pub mod dominators;
+pub mod format;
+pub mod frames;
+pub mod hierarchy;
+pub mod instructions;
+pub mod limits;
+pub mod loops;
+pub mod mutf8;
+pub mod pool;
 pub mod primitives;
+pub mod signatures;
+pub mod stackmap;
+pub mod stream;
+pub mod subroutines;
+pub mod transform;
+pub mod verifier;
+pub mod view;
 
 pub type ParserResult<T> = Result<T, ParserError>;
 
@@ -16,7 +45,34 @@ pub enum ParserError {
     UnknownConstantPoolTag(U1),
     UnexpectedConstantPoolItem(&'static str),
     ConstantPoolIndexOutOfBounds(usize),
+    InvalidConstantPoolIndex(usize),
+    InvalidConstantPoolCount(U2),
     InvalidUtf8(FromUtf8Error),
+    UnknownOpcode(U1),
+    InvalidWideOpcode(U1),
+    UnknownArrayType(U1),
+    InvalidSwitchBounds(i32, i32),
+    TruncatedInstruction(usize),
+    CodeTooLarge(usize),
+    BranchOffsetOverflow(usize),
+    InvalidCodeOffset(usize),
+    UnplacedLabel(usize),
+    AttributeLengthMismatch(String),
+    UnknownVerificationType(U1),
+    UnknownStackMapFrameType(U1),
+    TruncatedAttribute(usize),
+    ConstantPoolTooLarge(usize),
+    StringTooLong(usize),
+    MissingClassHeader,
+    InvalidAssembly(usize, String),
+    InvalidDescriptor(String),
+    InvalidSignature(String),
+    StackUnderflow(usize),
+    InconsistentStackHeight(usize),
+    IncompatibleStackTypes(usize),
+    UnreachableCode(usize),
+    UnsupportedSubroutine(usize),
+    UnsupportedClassVersion(U2),
     Io(IoError),
 }
 
@@ -80,15 +136,78 @@ pub struct ClassFile {
 
 impl ClassFile {
     pub fn from(file: File) -> ParserResult<ClassFile> {
-        let mut bytes = file.bytes();
+        Self::from_reader(file)
+    }
+
+    /// Parses a class file held in memory, such as one read out of a jar.
+    pub fn parse(bytes: &[U1]) -> ParserResult<ClassFile> {
+        Self::from_iterator(&mut ByteCursor::new(bytes))
+    }
+
+    /// Parses a class file from `reader`, which is read to its end first.
+    pub fn from_reader<R: Read>(mut reader: R) -> ParserResult<ClassFile> {
+        let mut bytes = vec![];
+        try!(reader.read_to_end(&mut bytes));
+        Self::parse(&bytes)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> ParserResult<ClassFile> {
+        Self::from_reader(try!(File::open(path)))
+    }
+
+    /// Parses a class file the way `parse` does, except that the attributes of fields and
+    /// methods are only recorded as ranges of `bytes`. Each one is decoded the first time it is
+    /// needed, so reading the names and flags of members skips building their code.
+    pub fn parse_lazy(bytes: &[U1]) -> ParserResult<ClassFile> {
+        Self::read_lazily(bytes, &[AttributeOwner::Field, AttributeOwner::Method])
+    }
+
+    /// Parses a class file so that `to_bytes` gives back exactly `bytes`. Every attribute,
+    /// including those of the class, is kept as it was read, the way `parse_lazy` keeps those of
+    /// members, so unknown and malformed ones come through untouched. The constant pool is kept
+    /// in its order with any duplicate and unused entries, and names refer to the entries they
+    /// were read from.
+    pub fn parse_lossless(bytes: &[U1]) -> ParserResult<ClassFile> {
+        Self::read_lazily(bytes,
+                          &[AttributeOwner::Class, AttributeOwner::Field, AttributeOwner::Method])
+    }
+
+    /// Parses a class file from any source of bytes, which every other way of parsing one
+    /// comes down to.
+    pub fn from_iterator<T: PrimitiveIterator>(bytes: &mut T) -> ParserResult<ClassFile> {
+        Self::read(bytes, &mut |bytes, constant_pool, _| Attribute::from(bytes, constant_pool))
+    }
 
+    /// Parses a class file, recording the attributes of `owners` as ranges of `bytes`.
+    fn read_lazily(bytes: &[U1], owners: &[AttributeOwner]) -> ParserResult<ClassFile> {
+        let source = Rc::new(bytes.to_vec());
+        let mut shared_constant_pool = None;
+
+        Self::read(&mut ByteCursor::new(&source), &mut |cursor, constant_pool, owner| {
+            if !owners.contains(&owner) {
+                return Attribute::from(cursor, constant_pool);
+            }
+
+            let shared_constant_pool =
+                shared_constant_pool.get_or_insert_with(|| Rc::new(constant_pool.clone()));
+            let attribute = try!(LazyAttribute::from(cursor, &source, shared_constant_pool));
+            Ok(Attribute::Lazy(Rc::new(attribute)))
+        })
+    }
+
+    fn read<T, F>(bytes: &mut T, read_attribute: &mut F) -> ParserResult<ClassFile>
+        where T: PrimitiveIterator,
+              F: FnMut(&mut T, &Vec<ConstantPoolItem>, AttributeOwner) -> ParserResult<Attribute>
+    {
         let magic = try!(bytes.next_u4());
         let minor_version = try!(bytes.next_u2());
         let major_version = try!(bytes.next_u2());
 
         let constant_pool_count = try!(bytes.next_u2());
-        let actual_constant_pool_count = constant_pool_count - 1;
-        let constant_pool = try!(Self::build_constant_pool(actual_constant_pool_count, &mut bytes));
+        // the count is one more than the number of entries, so it is never zero
+        let actual_constant_pool_count = try!(constant_pool_count.checked_sub(1)
+            .ok_or(ParserError::InvalidConstantPoolCount(constant_pool_count)));
+        let constant_pool = try!(Self::build_constant_pool(actual_constant_pool_count, bytes));
 
         let access_flags = try!(bytes.next_u2());
         let this_class = try!(bytes.next_u2());
@@ -98,14 +217,28 @@ impl ClassFile {
         let interfaces = populate_vec!(interfaces_count, bytes.next_u2());
 
         let fields_count = try!(bytes.next_u2());
-        let fields = rc_populate_vec!(fields_count, Field::from(&mut bytes, &constant_pool));
+        let mut read_field_attribute = |bytes: &mut T, constant_pool: &Vec<ConstantPoolItem>| {
+            read_attribute(bytes, constant_pool, AttributeOwner::Field)
+        };
+        let fields = rc_populate_vec!(fields_count,
+                                      Field::from_with(bytes,
+                                                       &constant_pool,
+                                                       &mut read_field_attribute));
 
         let methods_count = try!(bytes.next_u2());
-        let methods = rc_populate_vec!(methods_count, Method::from(&mut bytes, &constant_pool));
+        let mut read_method_attribute = |bytes: &mut T, constant_pool: &Vec<ConstantPoolItem>| {
+            read_attribute(bytes, constant_pool, AttributeOwner::Method)
+        };
+        let methods = rc_populate_vec!(methods_count,
+                                       Method::from_with(bytes,
+                                                         &constant_pool,
+                                                         &mut read_method_attribute));
 
         let attributes_count = try!(bytes.next_u2());
         let attributes = rc_populate_vec!(attributes_count,
-                                          Attribute::from(&mut bytes, &constant_pool));
+                                          read_attribute(bytes,
+                                                         &constant_pool,
+                                                         AttributeOwner::Class));
 
         Ok(ClassFile {
             magic: magic,
@@ -127,6 +260,63 @@ impl ClassFile {
         })
     }
 
+    /// Writes the class file to `writer`.
+    pub fn write<W: Write>(&self, writer: &mut W) -> ParserResult<()> {
+        let bytes = try!(self.to_bytes());
+        try!(writer.write_all(&bytes));
+
+        Ok(())
+    }
+
+    /// Serializes the class file. Every count is taken from what it counts rather than from the
+    /// `*_count` fields, and the constant pool is written as it is, with the `Empty` item after
+    /// each `Long` or `Double` standing for the slot the entry takes up. Names are written as
+    /// the index of a `Utf8` entry holding them: the entry a member name was read from while it
+    /// still holds the name, and otherwise the first such entry, which is added to the end of
+    /// the pool if there is none.
+    pub fn to_bytes(&self) -> ParserResult<Vec<U1>> {
+        let mut constant_pool = ConstantPool::from_items(&self.constant_pool);
+
+        // the pool comes first but is only complete once the names have been looked up
+        let mut body = vec![];
+        body.write_u2(self.access_flags);
+        body.write_u2(self.this_class);
+        body.write_u2(self.super_class);
+
+        body.write_u2(self.interfaces.len() as U2);
+        for interface in &self.interfaces {
+            body.write_u2(*interface);
+        }
+
+        body.write_u2(self.fields.len() as U2);
+        for field in &self.fields {
+            try!(field.write(&mut body, &mut constant_pool));
+        }
+
+        body.write_u2(self.methods.len() as U2);
+        for method in &self.methods {
+            try!(method.write(&mut body, &mut constant_pool));
+        }
+
+        body.write_u2(self.attributes.len() as U2);
+        for attribute in &self.attributes {
+            try!(attribute.write(&mut body, &mut |name| constant_pool.utf8(name)));
+        }
+
+        let mut bytes = vec![];
+        bytes.write_u4(self.magic);
+        bytes.write_u2(self.minor_version);
+        bytes.write_u2(self.major_version);
+        constant_pool.write(&mut bytes);
+        bytes.write_bytes(&body);
+
+        Ok(bytes)
+    }
+
+    pub fn access(&self) -> ClassAccess {
+        ClassAccess::from(self.access_flags)
+    }
+
     pub fn classname(&self) -> ParserResult<Rc<Utf8Info>> {
         let this_class = self.this_class;
         let constant_pool = &self.constant_pool;
@@ -191,12 +381,42 @@ mod tests {
 
     use self::spectral::prelude::*;
 
-    use super::ClassFile;
-    use super::components::{Attribute, AccessFlags, ConstantPoolItem};
-    use super::primitives::U2;
-
-    use std::fs::File;
+    use super::{ClassFile, ParserError, ParserResult};
+    use super::access::{ClassAccess, MethodAccess};
+    use super::assembler::{CodeAssembler, LabelledLineNumber};
+    use super::assembly::{assemble, print, PrintOptions};
+    #[allow(deprecated)]
+    use super::components::{Attribute, AccessFlags, ConstantPoolItem, LazyAttribute,
+                            LoadableConstant, Method, StackMapFrame, Utf8Info,
+                            VerificationTypeInfo};
+    use super::controlflow::{ControlFlowGraph, EdgeKind};
+    use super::dataflow::{self, DefiniteAssignment, IntersectionSet, Liveness, UnionSet};
+    use super::descriptors::{BaseType, FieldType};
+    use super::disassembler::disassemble;
+    use super::dominators::DominatorTree;
+    use super::format::{self, FormatError, FormatErrorKind, Location};
+    use super::frames::{Frames, VerificationType};
+    use super::hierarchy::{ClassFileHierarchy, ObjectHierarchy};
+    use super::instructions::Instruction;
+    use super::limits::{self, CodeLimits};
+    use super::loops::LoopForest;
+    use super::pool::{Constant, ConstantPool};
+    use super::primitives::{ByteCursor, PrimitiveIterator, PrimitiveWriter, U1, U2, U4};
+    use super::signatures;
+    use super::stackmap;
+    use super::stream::{self, AttributeOwner, ClassFileVisitor};
+    use super::subroutines;
+    use super::transform::{ClassHeader, ClassReader, ClassVisitor, ClassWriter, MemberHeader,
+                           MethodVisitor, SymbolTable, WriterOptions};
+    use super::verifier::{self, VerifyErrorKind};
+    use super::view::ClassFileRef;
+
+    use std::cmp;
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{Cursor, ErrorKind, Read};
     use std::path::PathBuf;
+    use std::rc::Rc;
 
     const MANIFEST_DIR: &'static str = env!("CARGO_MANIFEST_DIR");
 
@@ -227,6 +447,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(deprecated)]
     fn can_successfully_parse_access_flags() {
         let test_file = open_test_resource("classfile/HelloWorld.class");
         let classfile = ClassFile::from(test_file).unwrap();
@@ -238,6 +459,19 @@ mod tests {
         asserting("class is super").that(&access_flags).matches(|val| AccessFlags::is_super(*val));
     }
 
+    #[test]
+    fn can_successfully_parse_typed_access_flags() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let access = classfile.access();
+        asserting("class is public")
+            .that(&access)
+            .matches(|val| val.contains(ClassAccess::PUBLIC));
+        asserting("class is super").that(&access).matches(|val| val.contains(ClassAccess::SUPER));
+        assert_that(&access.to_string()).is_equal_to(&"public".to_string());
+    }
+
     #[test]
     fn can_successfully_parse_class_references() {
         let test_file = open_test_resource("classfile/HelloWorld.class");
@@ -291,6 +525,804 @@ mod tests {
             });
     }
 
+    #[test]
+    fn can_parse_member_descriptors() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let field_types: Vec<_> =
+            classfile.fields.iter().map(|field| field.field_type().unwrap()).collect();
+        assert_that(&field_types).is_equal_to(&vec![FieldType::Base(BaseType::Int),
+                                                    FieldType::Base(BaseType::Long),
+                                                    FieldType::Base(BaseType::Double)]);
+
+        let method = classfile.maybe_resolve_method("mixed").unwrap();
+        let descriptor = method.method_descriptor().unwrap();
+        assert_that(&descriptor.java_params()).is_equal_to(&vec!["long".to_string(),
+                                                                 "double".to_string(),
+                                                                 "float".to_string()]);
+        assert_that(&descriptor.java_return_type()).is_equal_to(&"double".to_string());
+        assert_that(&descriptor.parameter_slots()).is_equal_to(&5);
+
+        for method in &classfile.methods {
+            let descriptor = method.method_descriptor().unwrap();
+            assert_that(&descriptor.descriptor()).is_equal_to(&method.descriptor.to_string());
+        }
+    }
+
+    #[test]
+    fn can_parse_generic_signatures() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        assert_that(&signatures::class_signature(&classfile).unwrap()).is_none();
+
+        let method = classfile.maybe_resolve_method("generic").unwrap();
+        let signature = signatures::method_signature(&classfile, &method).unwrap().unwrap();
+        assert_that(&signature.java_type_parameters())
+            .is_equal_to(&"<K extends java.lang.Comparable<K>, V extends java.lang.Object>"
+                .to_string());
+        assert_that(&signature.java_params())
+            .is_equal_to(&vec!["java.util.Map<? extends K, java.util.List<V>>".to_string(),
+                               "K".to_string()]);
+        assert_that(&signature.java_return_type()).is_equal_to(&"int".to_string());
+
+        let method = classfile.maybe_resolve_method("mixed").unwrap();
+        assert_that(&signatures::method_signature(&classfile, &method).unwrap()).is_none();
+    }
+
+    #[test]
+    fn can_parse_from_bytes_readers_and_paths() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+
+        let mut file_path = PathBuf::from(MANIFEST_DIR);
+        file_path.push("test-resources/classfile/ControlFlow.class");
+
+        let classfiles = vec![ClassFile::parse(&bytes).unwrap(),
+                              ClassFile::from_reader(Cursor::new(bytes.clone())).unwrap(),
+                              ClassFile::open(&file_path).unwrap(),
+                              ClassFile::from_iterator(&mut ByteSource(bytes.iter())).unwrap()];
+        for classfile in &classfiles {
+            assert_that(&classfile.classname().unwrap().to_string())
+                .is_equal_to(&"ControlFlow".to_string());
+            assert_that(&classfile.methods).has_length(classfiles[0].methods.len());
+        }
+
+        assert_that(&ClassFile::parse(&bytes[..bytes.len() - 1]).is_err()).is_equal_to(&true);
+        assert_that(&ClassFile::open("does/not/exist.class").is_err()).is_equal_to(&true);
+    }
+
+    #[test]
+    fn can_write_class_files() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+
+        for classfile in &[ClassFile::parse(&bytes).unwrap(),
+                           ClassFile::parse_lazy(&bytes).unwrap()] {
+            assert_that(&classfile.to_bytes().unwrap()).is_equal_to(&bytes);
+
+            let mut written = Cursor::new(vec![]);
+            classfile.write(&mut written).unwrap();
+            assert_that(written.get_ref()).is_equal_to(&bytes);
+        }
+    }
+
+    #[test]
+    fn writes_counts_of_what_the_class_file_holds() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+        let mut classfile = ClassFile::parse(&bytes).unwrap();
+        let constant_pool_count = classfile.constant_pool_count;
+
+        classfile.methods.pop();
+        classfile.fields.clear();
+        let name = Rc::new(Utf8Info {
+            tag: 1,
+            length: 6,
+            value: "Custom".to_string(),
+            bytes: None,
+        });
+        classfile.attributes.push(Rc::new(Attribute::Unknown {
+            attribute_name: name,
+            info: vec![1, 2, 3],
+        }));
+
+        let written = ClassFile::parse(&classfile.to_bytes().unwrap()).unwrap();
+        assert_that(&written.methods_count).is_equal_to(&(classfile.methods_count - 1));
+        assert_that(&written.fields_count).is_equal_to(&0);
+        assert_that(&written.attributes_count).is_equal_to(&(classfile.attributes_count + 1));
+        assert_that(&written.constant_pool_count).is_equal_to(&(constant_pool_count + 1));
+        match *written.attributes[written.attributes.len() - 1] {
+            Attribute::Unknown { ref attribute_name, ref info } => {
+                assert_that(&attribute_name.as_str()).is_equal_to(&"Custom");
+                assert_that(info).is_equal_to(&vec![1, 2, 3]);
+            }
+            ref attribute => panic!("expected the added attribute, got {:?}", attribute),
+        }
+    }
+
+    /// The class files of the test resources, and variants of them that only come back the same
+    /// if nothing is normalized: ones with duplicate and unused constants and members named by
+    /// the duplicates, ones with malformed attributes, and a class whose code is named by a
+    /// duplicate `Code` entry.
+    fn lossless_fixtures() -> Vec<Vec<U1>> {
+        let mut directory = PathBuf::from(MANIFEST_DIR);
+        directory.push("test-resources/classfile");
+        let mut originals = vec![];
+        for entry in fs::read_dir(directory).unwrap() {
+            let mut bytes = vec![];
+            File::open(entry.unwrap().path()).unwrap().read_to_end(&mut bytes).unwrap();
+            originals.push(bytes);
+        }
+
+        let mut fixtures = originals.clone();
+        for bytes in &originals {
+            let mut classfile = ClassFile::parse(bytes).unwrap();
+            classfile.constant_pool.push(utf8_constant("unused"));
+            for method in &mut classfile.methods {
+                let method = Rc::get_mut(method).unwrap();
+                classfile.constant_pool.push(utf8_constant(&method.name));
+                method.name_index = classfile.constant_pool.len() as U2;
+            }
+            fixtures.push(classfile.to_bytes().unwrap());
+
+            let method_attribute = unknown("LineNumberTable", vec![0, 5]);
+            let bytes = with_attributes(bytes, vec![method_attribute], vec![]);
+            fixtures.push(with_attributes(&bytes, vec![], vec![unknown("Code", vec![0xff])]));
+        }
+        fixtures.push(duplicate_code_name_fixture());
+
+        fixtures
+    }
+
+    /// `bytes` with `method_attributes` added to its first method and `class_attributes` to the
+    /// class, however malformed they are.
+    fn with_attributes(bytes: &[U1],
+                       method_attributes: Vec<Attribute>,
+                       class_attributes: Vec<Attribute>)
+                       -> Vec<U1> {
+        let mut classfile = ClassFile::parse(bytes).unwrap();
+        {
+            let method = Rc::get_mut(&mut classfile.methods[0]).unwrap();
+            method.attributes.extend(method_attributes.into_iter().map(Rc::new));
+        }
+        classfile.attributes.extend(class_attributes.into_iter().map(Rc::new));
+
+        classfile.to_bytes().unwrap()
+    }
+
+    /// A class with a single method, whose `Code` attribute is named by the second of two
+    /// `Code` entries.
+    fn duplicate_code_name_fixture() -> Vec<U1> {
+        let mut constant_pool = ConstantPool::new();
+        let this_class = constant_pool.class("Duplicates").unwrap();
+        let super_class = constant_pool.class("java/lang/Object").unwrap();
+        let name = constant_pool.utf8("run").unwrap();
+        let descriptor = constant_pool.utf8("()V").unwrap();
+        constant_pool.utf8("Code").unwrap();
+        let code_name = constant_pool.push(Constant::Utf8("Code".to_string())).unwrap();
+
+        let mut bytes = vec![];
+        bytes.write_u4(0xCAFEBABE);
+        bytes.write_u2(0);
+        bytes.write_u2(52);
+        constant_pool.write(&mut bytes);
+        bytes.write_u2((ClassAccess::PUBLIC | ClassAccess::SUPER).bits());
+        bytes.write_u2(this_class);
+        bytes.write_u2(super_class);
+        bytes.write_u2(0);
+        bytes.write_u2(0);
+
+        bytes.write_u2(1);
+        bytes.write_u2((MethodAccess::PUBLIC | MethodAccess::STATIC).bits());
+        bytes.write_u2(name);
+        bytes.write_u2(descriptor);
+        bytes.write_u2(1);
+        bytes.write_u2(code_name);
+        bytes.write_u4(13);
+        bytes.write_u2(0);
+        bytes.write_u2(0);
+        bytes.write_u4(1);
+        bytes.write_u1(0xb1);
+        bytes.write_u2(0);
+        bytes.write_u2(0);
+
+        bytes.write_u2(0);
+
+        bytes
+    }
+
+    fn unknown(name: &str, info: Vec<U1>) -> Attribute {
+        Attribute::Unknown {
+            attribute_name: Rc::new(Utf8Info {
+                tag: 1,
+                length: name.len() as U2,
+                value: name.to_string(),
+                bytes: None,
+            }),
+            info: info,
+        }
+    }
+
+    #[test]
+    fn round_trips_class_files_losslessly() {
+        for bytes in &lossless_fixtures() {
+            let classfile = ClassFile::parse_lossless(bytes).unwrap();
+            assert_that(&classfile.to_bytes().unwrap()).is_equal_to(bytes);
+        }
+    }
+
+    /// A xorshift generator, so that every run generates the same classes.
+    struct Random(u64);
+
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, bound: usize) -> usize {
+            (self.next() % bound as u64) as usize
+        }
+
+        fn pick<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+            &items[self.below(items.len())]
+        }
+    }
+
+    /// A constant of a generated class, with a class given by the value of its name.
+    enum GeneratedConstant {
+        Utf8(Vec<U1>),
+        Class(Vec<U1>),
+        Integer(U4),
+        Long(u64),
+    }
+
+    /// A class whose constants come in a random order, with duplicates that the members and
+    /// attributes are named by at random, and whose attributes have random bodies. Names hold
+    /// modified UTF-8 along with the forms only standard UTF-8 has, and unpaired surrogates.
+    fn generated_class(random: &mut Random) -> Vec<U1> {
+        let pieces: &[&[U1]] = &[b"a", b"Code", b"LineNumberTable", b"StackMapTable", b"/", b"(",
+                                 &[0xc0, 0x80], &[0x00], &[0xc3, 0xa9],
+                                 &[0xed, 0xa0, 0xbd, 0xed, 0xb8, 0x80], &[0xed, 0xa0, 0x80],
+                                 &[0xf0, 0x9f, 0x98, 0x80]];
+        let mut names: Vec<Vec<U1>> = vec![b"Generated".to_vec(), b"java/lang/Object".to_vec()];
+        for _ in 0..random.below(8) {
+            let mut name = vec![];
+            for _ in 0..random.below(4) {
+                name.extend_from_slice(*random.pick(pieces));
+            }
+            names.push(name);
+        }
+
+        let mut constants = vec![GeneratedConstant::Class(names[0].clone()),
+                                 GeneratedConstant::Class(names[1].clone())];
+        for name in &names {
+            for _ in 0..1 + random.below(3) {
+                constants.push(GeneratedConstant::Utf8(name.clone()));
+            }
+        }
+        for _ in 0..random.below(3) {
+            constants.push(GeneratedConstant::Integer(random.next() as U4));
+            constants.push(GeneratedConstant::Long(random.next()));
+        }
+        for i in (1..constants.len()).rev() {
+            constants.swap(i, random.below(i + 1));
+        }
+
+        // the indexes of the entries holding each name, which only the order of the pool fixes
+        let mut utf8_indexes = vec![];
+        let mut class_indexes = vec![];
+        let mut index = 1;
+        for constant in &constants {
+            match *constant {
+                GeneratedConstant::Utf8(ref name) => utf8_indexes.push((name.clone(), index)),
+                GeneratedConstant::Class(ref name) => class_indexes.push((name.clone(), index)),
+                _ => {}
+            }
+            index += match *constant {
+                GeneratedConstant::Long(..) => 2,
+                _ => 1,
+            };
+        }
+        let name_index = |random: &mut Random, name: &[U1]| {
+            let indexes = utf8_indexes.iter()
+                .filter(|entry| &entry.0[..] == name)
+                .map(|entry| entry.1)
+                .collect::<Vec<U2>>();
+            *random.pick(&indexes)
+        };
+        let class_index = |name: &[U1]| {
+            class_indexes.iter().find(|entry| &entry.0[..] == name).unwrap().1
+        };
+
+        let mut bytes = vec![];
+        bytes.write_u4(0xCAFEBABE);
+        bytes.write_u2(0);
+        bytes.write_u2(52);
+        bytes.write_u2(index);
+        for constant in &constants {
+            match *constant {
+                GeneratedConstant::Utf8(ref name) => {
+                    bytes.write_u1(1);
+                    bytes.write_u2(name.len() as U2);
+                    bytes.write_bytes(name);
+                }
+                GeneratedConstant::Class(ref name) => {
+                    bytes.write_u1(7);
+                    bytes.write_u2(name_index(random, name));
+                }
+                GeneratedConstant::Integer(value) => {
+                    bytes.write_u1(3);
+                    bytes.write_u4(value);
+                }
+                GeneratedConstant::Long(value) => {
+                    bytes.write_u1(5);
+                    bytes.write_u4((value >> 32) as U4);
+                    bytes.write_u4(value as U4);
+                }
+            }
+        }
+        bytes.write_u2((ClassAccess::PUBLIC | ClassAccess::SUPER).bits());
+        bytes.write_u2(class_index(&names[0]));
+        bytes.write_u2(class_index(&names[1]));
+        bytes.write_u2(0);
+
+        let write_attributes = |random: &mut Random, bytes: &mut Vec<U1>| {
+            let count = random.below(4);
+            bytes.write_u2(count as U2);
+            for _ in 0..count {
+                let name = random.pick(&names).clone();
+                bytes.write_u2(name_index(random, &name));
+                let length = random.below(16);
+                bytes.write_u4(length as U4);
+                for _ in 0..length {
+                    bytes.write_u1(random.next() as U1);
+                }
+            }
+        };
+        for _ in 0..2 {
+            let count = random.below(3);
+            bytes.write_u2(count as U2);
+            for _ in 0..count {
+                bytes.write_u2(random.next() as U2);
+                for _ in 0..2 {
+                    let name = random.pick(&names).clone();
+                    bytes.write_u2(name_index(random, &name));
+                }
+                write_attributes(random, &mut bytes);
+            }
+        }
+        write_attributes(random, &mut bytes);
+
+        bytes
+    }
+
+    #[test]
+    fn round_trips_generated_class_files_losslessly() {
+        let mut random = Random(0x2545F4914F6CDD1D);
+        for _ in 0..500 {
+            let bytes = generated_class(&mut random);
+            let classfile = ClassFile::parse_lossless(&bytes).unwrap();
+            assert_that(&classfile.to_bytes().unwrap()).is_equal_to(&bytes);
+        }
+    }
+
+    #[test]
+    fn normalizes_what_lossless_parsing_keeps() {
+        // the code is written under the first `Code` entry
+        let bytes = duplicate_code_name_fixture();
+        let written = ClassFile::parse(&bytes).unwrap().to_bytes().unwrap();
+        assert_that(&written).has_length(bytes.len());
+        assert_that(&(written == bytes)).is_equal_to(&false);
+
+        let mut bytes = vec![];
+        open_test_resource("classfile/HelloWorld.class").read_to_end(&mut bytes).unwrap();
+
+        // a line number table only belongs in code, so one on a method is kept as it is
+        let method_attribute = unknown("LineNumberTable", vec![0, 5]);
+        let with_table = with_attributes(&bytes, vec![method_attribute], vec![]);
+        let written = ClassFile::parse(&with_table).unwrap().to_bytes().unwrap();
+        assert_that(&written).is_equal_to(&with_table);
+
+        // code is read as code wherever it is, which fails on a body too short for it
+        let with_code = with_attributes(&bytes, vec![], vec![unknown("Code", vec![0xff])]);
+        let result = ClassFile::parse(&with_code);
+        assert_that(&result.is_err()).is_equal_to(&true);
+    }
+
+    #[test]
+    fn can_read_modified_utf8_constants() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ModifiedUtf8.class").read_to_end(&mut bytes).unwrap();
+        let classfile = ClassFile::parse(&bytes).unwrap();
+
+        let utf8_entries = classfile.constant_pool
+            .iter()
+            .filter_map(|item| match *item {
+                ConstantPoolItem::Utf8(ref info) => Some(info.clone()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let entry = |value: &str| utf8_entries.iter().find(|info| info.as_str() == value).cloned();
+
+        assert_that(&entry("a\0b").map(|info| info.length)).is_equal_to(&Some(4));
+        assert_that(&entry("\u{1f600}").map(|info| info.length)).is_equal_to(&Some(6));
+        assert_that(&entry("\u{e9}t\u{e9}").map(|info| info.bytes.clone()))
+            .is_equal_to(&Some(None));
+        assert_that(&entry("\u{fffd}").map(|info| info.bytes.clone()))
+            .is_equal_to(&Some(Some(vec![0xed, 0xa0, 0x80])));
+
+        assert_that(&classfile.to_bytes().unwrap()).is_equal_to(&bytes);
+    }
+
+    #[test]
+    fn can_view_class_files_without_copying() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+        let classfile = ClassFile::parse(&bytes).unwrap();
+        let view = ClassFileRef::parse(&bytes).unwrap();
+        let constant_pool = &view.constant_pool;
+
+        assert_that(&view.classname().unwrap().as_ref()).is_equal_to(&"ControlFlow");
+        assert_that(&view.super_class_name().unwrap())
+            .is_equal_to(&Some("java/lang/Object".into()));
+        assert_that(&constant_pool.len()).is_equal_to(&classfile.constant_pool.len());
+        assert_that(&view.fields).has_length(classfile.fields.len());
+
+        for (method, owned) in view.methods.iter().zip(&classfile.methods) {
+            assert_that(&method.name(constant_pool).unwrap())
+                .is_equal_to(&owned.name.as_str().into());
+            assert_that(&method.descriptor(constant_pool).unwrap())
+                .is_equal_to(&owned.descriptor.as_str().into());
+
+            let code = method.code(constant_pool).unwrap().unwrap();
+            let owned_code = owned.code().unwrap();
+            assert_that(&code.code).is_equal_to(&&owned_code.code[..]);
+            assert_that(&code.exception_table).is_equal_to(&owned_code.exception_table);
+            assert_that(&code.instructions().count())
+                .is_equal_to(&owned_code.instructions().count());
+
+            let start = bytes.as_ptr() as usize;
+            let code_start = code.code.as_ptr() as usize;
+            assert_that(&(code_start > start && code_start < start + bytes.len()))
+                .is_equal_to(&true);
+        }
+
+        let info = view.attribute("SourceFile").unwrap().unwrap().info;
+        let source_file = ((info[0] as U2) << 8) | info[1] as U2;
+        assert_that(&constant_pool.utf8(source_file).unwrap().as_ref())
+            .is_equal_to(&"ControlFlow.java");
+    }
+
+    #[test]
+    fn can_parse_method_bodies_lazily() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+        let classfile = ClassFile::parse(&bytes).unwrap();
+        let lazy = ClassFile::parse_lazy(&bytes).unwrap();
+
+        assert_that(&lazy.methods).has_length(classfile.methods.len());
+        for (method, eager) in lazy.methods.iter().zip(&classfile.methods) {
+            assert_that(&method.name).is_equal_to(&eager.name);
+            assert_that(&method.attributes).has_length(eager.attributes.len());
+
+            let attribute = match *method.attributes[0] {
+                Attribute::Lazy(ref attribute) => attribute.clone(),
+                ref attribute => panic!("expected a lazy attribute, got {:?}", attribute),
+            };
+            assert_that(&attribute.attribute_name.as_str()).is_equal_to(&"Code");
+            assert_that(&attribute.is_decoded()).is_equal_to(&false);
+
+            let code = method.code().unwrap();
+            let eager_code = eager.code().unwrap();
+            assert_that(&code.code).is_equal_to(&eager_code.code);
+            assert_that(&code.attributes).has_length(eager_code.attributes.len());
+            assert_that(&code.instructions().count())
+                .is_equal_to(&eager_code.instructions().count());
+            assert_that(&attribute.is_decoded()).is_equal_to(&true);
+        }
+
+        assert_that(&disassemble(&lazy).unwrap()).is_equal_to(&disassemble(&classfile).unwrap());
+        assert_that(&ClassFile::parse_lazy(&bytes[..bytes.len() - 1]).is_err())
+            .is_equal_to(&true);
+    }
+
+    #[test]
+    fn can_stream_class_files_through_a_visitor() {
+        #[derive(Default)]
+        struct Index {
+            methods: Vec<String>,
+            instructions: usize,
+            class_attributes: Vec<String>,
+        }
+
+        impl ClassFileVisitor for Index {
+            fn visit_method(&mut self,
+                            _access: MethodAccess,
+                            name: &Utf8Info,
+                            descriptor: &Utf8Info)
+                            -> ParserResult<()> {
+                self.methods.push(format!("{}{}", name.as_str(), descriptor.as_str()));
+                Ok(())
+            }
+
+            fn visit_instruction(&mut self, _pc: U2, _instruction: &Instruction)
+                                 -> ParserResult<()> {
+                self.instructions += 1;
+                Ok(())
+            }
+
+            fn visit_attribute(&mut self,
+                               owner: AttributeOwner,
+                               name: &Utf8Info,
+                               _info: &[U1])
+                               -> ParserResult<()> {
+                if owner == AttributeOwner::Class {
+                    self.class_attributes.push(name.to_string());
+                }
+                Ok(())
+            }
+        }
+
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+        let classfile = ClassFile::parse(&bytes).unwrap();
+        let mut index = Index::default();
+        stream::visit(&mut ByteSource(bytes.iter()), &mut index).unwrap();
+
+        let methods: Vec<String> = classfile.methods
+            .iter()
+            .map(|method| format!("{}{}", method.name.as_str(), method.descriptor.as_str()))
+            .collect();
+        assert_that(&index.methods).is_equal_to(&methods);
+        let instructions: usize = classfile.methods
+            .iter()
+            .map(|method| method.code().unwrap().instructions().count())
+            .sum();
+        assert_that(&index.instructions).is_equal_to(&instructions);
+        assert_that(&index.class_attributes).is_equal_to(&vec!["SourceFile".to_string(),
+                                                               "BootstrapMethods".to_string(),
+                                                               "InnerClasses".to_string()]);
+    }
+
+    #[test]
+    fn can_write_class_files_through_a_visitor_pipeline() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/ControlFlow.class").read_to_end(&mut bytes).unwrap();
+
+        for classfile in &[ClassFile::parse(&bytes).unwrap(),
+                           ClassFile::parse_lazy(&bytes).unwrap()] {
+            let mut writer = ClassWriter::new();
+            ClassReader::new(classfile).accept(&mut writer).unwrap();
+            assert_that(&writer.into_bytes()).is_equal_to(&Some(bytes.clone()));
+        }
+
+        struct Rename<V> {
+            next: V,
+        }
+
+        impl<V: ClassVisitor> ClassVisitor for Rename<V> {
+            fn next(&mut self) -> Option<&mut dyn ClassVisitor> {
+                Some(&mut self.next)
+            }
+
+            fn visit(&mut self, symbols: &mut SymbolTable, header: ClassHeader)
+                     -> ParserResult<()> {
+                let this_class = try!(symbols.constant_pool.class("Renamed"));
+                self.next.visit(symbols, ClassHeader { this_class: this_class, ..header })
+            }
+
+            fn visit_method<'a>(&'a mut self,
+                                symbols: &mut SymbolTable,
+                                method: MemberHeader)
+                                -> ParserResult<Option<Box<dyn MethodVisitor + 'a>>> {
+                match try!(symbols.constant_pool.utf8_value(method.name_index)) {
+                    "loop" => Ok(None),
+                    _ => self.next.visit_method(symbols, method),
+                }
+            }
+        }
+
+        let classfile = ClassFile::parse(&bytes).unwrap();
+        let mut rename = Rename { next: ClassWriter::new() };
+        ClassReader::new(&classfile).accept(&mut rename).unwrap();
+        let renamed = ClassFile::parse(&rename.next.into_bytes().unwrap()).unwrap();
+
+        let resolver = renamed.constant_pool_resolver();
+        assert_that(&resolver.resolve_class_name(renamed.this_class).unwrap().to_string())
+            .is_equal_to(&"Renamed".to_string());
+        assert_that(&renamed.methods).has_length(classfile.methods.len() - 1);
+        assert_that(&renamed.methods.iter().any(|method| &**method.name == "loop"))
+            .is_equal_to(&false);
+        assert_that(&renamed.constant_pool).has_length(classfile.constant_pool.len() + 2);
+    }
+
+    #[test]
+    fn can_instrument_methods_through_a_visitor_pipeline() {
+        struct Trace<V> {
+            next: V,
+        }
+
+        impl<V: ClassVisitor> ClassVisitor for Trace<V> {
+            fn next(&mut self) -> Option<&mut dyn ClassVisitor> {
+                Some(&mut self.next)
+            }
+
+            fn visit_method<'a>(&'a mut self,
+                                symbols: &mut SymbolTable,
+                                method: MemberHeader)
+                                -> ParserResult<Option<Box<dyn MethodVisitor + 'a>>> {
+                let name = try!(symbols.constant_pool.utf8_value(method.name_index)).to_string();
+                let next = try!(self.next.visit_method(symbols, method));
+
+                Ok(next.map(|next| {
+                    Box::new(TraceMethod {
+                        next: next,
+                        name: name,
+                    }) as Box<dyn MethodVisitor>
+                }))
+            }
+        }
+
+        // prints the name of the method on entry, leaving out the line numbers and the stack map
+        // table, which no longer match the code
+        struct TraceMethod<'a> {
+            next: Box<dyn MethodVisitor + 'a>,
+            name: String,
+        }
+
+        impl<'a> MethodVisitor for TraceMethod<'a> {
+            fn next(&mut self) -> Option<&mut dyn MethodVisitor> {
+                Some(&mut *self.next)
+            }
+
+            fn visit_code(&mut self, symbols: &mut SymbolTable, max_stack: U2, max_locals: U2)
+                          -> ParserResult<()> {
+                let out = try!(symbols.constant_pool
+                    .field("java/lang/System", "out", "Ljava/io/PrintStream;"));
+                let name = try!(symbols.constant_pool.string(&self.name));
+                let println = try!(symbols.constant_pool
+                    .method("java/io/PrintStream", "println", "(Ljava/lang/String;)V"));
+
+                try!(self.next.visit_code(symbols, cmp::max(max_stack, 2), max_locals));
+                try!(self.next.visit_instruction(symbols, Instruction::Getstatic(out)));
+                try!(self.next.visit_instruction(symbols, Instruction::LdcW(name)));
+                self.next.visit_instruction(symbols, Instruction::Invokevirtual(println))
+            }
+
+            fn visit_line_number(&mut self, _symbols: &mut SymbolTable, _: LabelledLineNumber)
+                                 -> ParserResult<()> {
+                Ok(())
+            }
+
+            fn visit_code_attribute(&mut self, symbols: &mut SymbolTable, attribute: Attribute)
+                                    -> ParserResult<()> {
+                match attribute {
+                    Attribute::StackMapTable(..) => Ok(()),
+                    _ => self.next.visit_code_attribute(symbols, attribute),
+                }
+            }
+        }
+
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+        let mut trace = Trace { next: ClassWriter::new() };
+        ClassReader::new(&classfile).accept(&mut trace).unwrap();
+        let traced = ClassFile::parse(&trace.next.into_bytes().unwrap()).unwrap();
+
+        for (method, original) in traced.methods.iter().zip(&classfile.methods) {
+            let code = method.code().unwrap();
+            let instructions: Vec<Instruction> =
+                code.instructions().map(|item| item.unwrap().1).collect();
+
+            assert_that(&instructions)
+                .has_length(original.code().unwrap().instructions().count() + 3);
+            match (&instructions[0], &instructions[1]) {
+                (&Instruction::Getstatic(_), &Instruction::LdcW(index)) => {
+                    let resolver = traced.constant_pool_resolver();
+                    assert_that(&resolver.resolve_string_constant(index).unwrap())
+                        .is_equal_to(&method.name.to_string());
+                }
+                _ => panic!("method does not start with a trace: {:?}", instructions),
+            }
+            assert_that(&code.attributes
+                    .iter()
+                    .any(|attribute| match *attribute {
+                        Attribute::LineNumberTable(..) |
+                        Attribute::StackMapTable(..) => true,
+                        _ => false,
+                    }))
+                .is_equal_to(&false);
+        }
+
+        let errors = verifier::infer_types(&traced, &exception_hierarchy()).unwrap();
+        assert_that(&errors).has_length(0);
+    }
+
+    #[test]
+    fn can_compute_limits_and_frames_of_changed_code() {
+        struct Prologue<V> {
+            next: V,
+        }
+
+        impl<V: ClassVisitor> ClassVisitor for Prologue<V> {
+            fn next(&mut self) -> Option<&mut dyn ClassVisitor> {
+                Some(&mut self.next)
+            }
+
+            fn visit_method<'a>(&'a mut self,
+                                symbols: &mut SymbolTable,
+                                method: MemberHeader)
+                                -> ParserResult<Option<Box<dyn MethodVisitor + 'a>>> {
+                let next = try!(self.next.visit_method(symbols, method));
+                Ok(next.map(|next| {
+                    Box::new(PrologueMethod { next: next }) as Box<dyn MethodVisitor>
+                }))
+            }
+        }
+
+        // pushes and pops two values on entry, which moves the code after it along and needs
+        // more of the stack than some methods declare
+        struct PrologueMethod<'a> {
+            next: Box<dyn MethodVisitor + 'a>,
+        }
+
+        impl<'a> MethodVisitor for PrologueMethod<'a> {
+            fn next(&mut self) -> Option<&mut dyn MethodVisitor> {
+                Some(&mut *self.next)
+            }
+
+            fn visit_code(&mut self, symbols: &mut SymbolTable, max_stack: U2, max_locals: U2)
+                          -> ParserResult<()> {
+                try!(self.next.visit_code(symbols, max_stack, max_locals));
+                try!(self.next.visit_instruction(symbols, Instruction::Iconst0));
+                try!(self.next.visit_instruction(symbols, Instruction::Iconst0));
+                self.next.visit_instruction(symbols, Instruction::Pop2)
+            }
+        }
+
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+        let hierarchy = exception_hierarchy();
+        let write = |options: WriterOptions| {
+            let writer = ClassWriter::with_options(options).hierarchy(&hierarchy);
+            let mut prologue = Prologue { next: writer };
+            ClassReader::new(&classfile).accept(&mut prologue).unwrap();
+            ClassFile::parse(&prologue.next.into_bytes().unwrap()).unwrap()
+        };
+        // the tables have no equality of their own, so they are compared as they are printed
+        let stack_map_tables = |classfile: &ClassFile| -> Vec<String> {
+            classfile.methods
+                .iter()
+                .flat_map(|method| method.code().unwrap().attributes.clone())
+                .filter_map(|attribute| match attribute {
+                    Attribute::StackMapTable(table) => Some(format!("{:?}", table)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        assert_that(&stack_map_tables(&classfile).is_empty()).is_equal_to(&false);
+
+        let unchanged = write(WriterOptions::default());
+        assert_that(&limits::check(&unchanged).unwrap().is_empty()).is_equal_to(&false);
+        assert_that(&verifier::check_types(&unchanged, &hierarchy).unwrap().is_empty())
+            .is_equal_to(&false);
+
+        let limited = write(WriterOptions { compute_limits: true, ..WriterOptions::default() });
+        assert_that(&limits::check(&limited).unwrap()).has_length(0);
+        assert_that(&stack_map_tables(&limited)).is_equal_to(&stack_map_tables(&classfile));
+
+        let framed = write(WriterOptions { compute_frames: true, ..WriterOptions::default() });
+        assert_that(&limits::check(&framed).unwrap()).has_length(0);
+        assert_that(&verifier::check_types(&framed, &hierarchy).unwrap()).has_length(0);
+        assert_that(&stack_map_tables(&framed)).has_length(stack_map_tables(&classfile).len());
+    }
+
     #[test]
     fn can_successfully_parse_class_attributes() {
         let test_file = open_test_resource("classfile/HelloWorld.class");
@@ -344,6 +1376,612 @@ mod tests {
             .contains(&"hello world".to_string());
     }
 
+    #[test]
+    fn can_decode_method_instructions() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let main_method = classfile.maybe_resolve_main_method().unwrap();
+        let code = main_method.code().unwrap();
+        let instructions: Vec<_> = code.instructions().map(|val| val.unwrap()).collect();
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::Ldc(2)),
+                                                      (2, Instruction::Invokestatic(3)),
+                                                      (5, Instruction::Return)]);
+    }
+
+    #[test]
+    fn can_resolve_instruction_operands_from_constant_pool() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let resolver = classfile.constant_pool_resolver();
+        let method = resolver.resolve_member_reference(3).unwrap();
+
+        assert_that(&method.class_name.to_string()).is_equal_to(&"HelloWorld".to_string());
+        assert_that(&method.name.to_string()).is_equal_to(&"println".to_string());
+        assert_that(&method.descriptor.to_string())
+            .is_equal_to(&"(Ljava/lang/String;)V".to_string());
+
+        asserting("ldc operand resolves to a string constant")
+            .that(&resolver.resolve_loadable_constant(2).unwrap())
+            .matches(|val| match *val {
+                LoadableConstant::String(ref value) => value.as_str() == "hello world",
+                _ => false,
+            });
+    }
+
+    #[test]
+    fn can_reassemble_method_bodies_unchanged() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        for method in &classfile.methods {
+            let code = method.code().unwrap();
+            let assembler = CodeAssembler::from_code(&code).unwrap();
+            let reassembled = assembler.assemble_into(&code).unwrap();
+
+            assert_that(&reassembled.code).is_equal_to(&code.code);
+            assert_that(&reassembled.exception_table).is_equal_to(&code.exception_table);
+        }
+    }
+
+    #[test]
+    fn can_build_control_flow_graph() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let method = classfile.methods.iter().find(|method| &**method.name == "tryCatch").unwrap();
+        let graph = ControlFlowGraph::from(&method.code().unwrap()).unwrap();
+
+        let starts: Vec<_> = graph.blocks.iter().map(|block| block.start_pc).collect();
+        assert_that(&starts).is_equal_to(&vec![0, 5, 17, 20, 32, 34, 47]);
+
+        let handlers: Vec<_> = graph.successors(0)
+            .iter()
+            .filter(|edge| edge.kind != EdgeKind::Fallthrough)
+            .map(|edge| graph.block(edge.target).start_pc)
+            .collect();
+        assert_that(&handlers).is_equal_to(&vec![17, 32]);
+        assert_that(&graph.predecessors(4)).has_length(3);
+    }
+
+    #[test]
+    fn can_find_nested_loops() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let method = classfile.methods.iter().find(|method| &**method.name == "nested").unwrap();
+        let graph = ControlFlowGraph::from(&method.code().unwrap()).unwrap();
+        let dominators = DominatorTree::dominators(&graph);
+        let forest = LoopForest::from(&graph, &dominators);
+
+        assert_that(&forest.loops).has_length(2);
+        assert_that(&forest.is_reducible()).is_equal_to(&true);
+
+        let inner = forest.loops.iter().find(|natural_loop| natural_loop.depth == 2).unwrap();
+        let outer = &forest.loops[inner.parent.unwrap()];
+        assert_that(&outer.depth).is_equal_to(&1);
+        asserting("outer loop contains the inner loop")
+            .that(&inner.blocks)
+            .matches(|blocks| blocks.iter().all(|id| outer.contains(*id)));
+        assert_that(&forest.loop_depth(inner.header)).is_equal_to(&2);
+    }
+
+    #[test]
+    fn can_solve_dataflow_across_exception_handlers() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let method = classfile.methods.iter().find(|method| &**method.name == "tryCatch").unwrap();
+        let graph = ControlFlowGraph::from(&method.code().unwrap()).unwrap();
+        let catch_block = graph.block_at(17).unwrap();
+        let finally_block = graph.block_at(32).unwrap();
+
+        let liveness = dataflow::solve(&Liveness, &graph);
+        assert_that(&liveness.entry[catch_block]).is_equal_to(&UnionSet(vec![0].into_iter().collect()));
+
+        let assignment = dataflow::solve(&DefiniteAssignment::new(2), &graph);
+        assert_that(&assignment.entry[finally_block])
+            .is_equal_to(&IntersectionSet::Elements(vec![0, 1].into_iter().collect()));
+    }
+
+    #[test]
+    fn can_simulate_stack_frames() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let constructor = classfile.methods.iter().find(|method| &**method.name == "<init>").unwrap();
+        let frames = Frames::from(&classfile, constructor).unwrap();
+        assert_that(&frames.at(0).unwrap().locals)
+            .is_equal_to(&vec![VerificationType::UninitializedThis, VerificationType::Integer]);
+        assert_that(&frames.at(4).unwrap().locals)
+            .is_equal_to(&vec![VerificationType::Object("ControlFlow".to_string()),
+                               VerificationType::Integer]);
+
+        let arrays = classfile.methods.iter().find(|method| &**method.name == "arrays").unwrap();
+        let frames = Frames::from(&classfile, arrays).unwrap();
+        assert_that(&frames.at(13).unwrap().locals)
+            .is_equal_to(&vec![VerificationType::Integer,
+                               VerificationType::Object("[[J".to_string()),
+                               VerificationType::Object("[I".to_string()),
+                               VerificationType::Integer]);
+        assert_that(&frames.at(29).unwrap().stack)
+            .is_equal_to(&vec![VerificationType::Object("[J".to_string()),
+                               VerificationType::Integer,
+                               VerificationType::Long]);
+        assert_that(&frames.at(29).unwrap().stack_height()).is_equal_to(&4);
+
+        let try_catch = classfile.methods.iter().find(|method| &**method.name == "tryCatch").unwrap();
+        let frames = Frames::from(&classfile, try_catch).unwrap();
+        assert_that(&frames.at(17).unwrap().stack)
+            .is_equal_to(&vec![VerificationType::Object("java/lang/NumberFormatException"
+                                   .to_string())]);
+    }
+
+    #[test]
+    fn can_compute_code_limits() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        for method in &classfile.methods {
+            let code = method.code().unwrap();
+            let limits = CodeLimits::compute(&classfile, method, &code).unwrap();
+
+            asserting(&format!("limits of {}", method.name.as_str()))
+                .that(&limits)
+                .is_equal_to(&CodeLimits::declared(&code));
+        }
+        assert_that(&limits::check(&classfile).unwrap()).has_length(0);
+    }
+
+    #[test]
+    fn reports_insufficient_code_limits() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let text = print(&classfile, &PrintOptions::default()).unwrap();
+        let text = text.replace(".code stack 6 locals 4", ".code stack 2 locals 1");
+        let bytes = assemble(&text).unwrap();
+
+        let reassembled = ClassFile::parse(&bytes).unwrap();
+
+        let insufficient = limits::check(&reassembled).unwrap();
+        assert_that(&insufficient).has_length(1);
+        assert_that(&insufficient[0].method_name).is_equal_to(&"arrays".to_string());
+        assert_that(&insufficient[0].declared).is_equal_to(&CodeLimits {
+            max_stack: 2,
+            max_locals: 1,
+        });
+        assert_that(&insufficient[0].required).is_equal_to(&CodeLimits {
+            max_stack: 6,
+            max_locals: 4,
+        });
+    }
+
+    #[test]
+    fn can_compute_stack_map_table() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let mut classfile = ClassFile::from(test_file).unwrap();
+        let constant_pool_count = classfile.constant_pool_count;
+
+        let method = classfile.methods.iter().find(|method| &**method.name == "loop").unwrap().clone();
+        let code = method.code().unwrap();
+        let table = stackmap::compute(&mut classfile, &method, &code, &ObjectHierarchy).unwrap();
+
+        assert_that(&table.entries).is_equal_to(&vec![StackMapFrame::Append {
+                                                          frame_type: 253,
+                                                          offset_delta: 4,
+                                                          locals: vec![VerificationTypeInfo::Integer,
+                                                                       VerificationTypeInfo::Integer],
+                                                      },
+                                                      StackMapFrame::Same { frame_type: 13 },
+                                                      StackMapFrame::Same { frame_type: 3 },
+                                                      StackMapFrame::Same { frame_type: 5 }]);
+        assert_that(&classfile.constant_pool_count).is_equal_to(&constant_pool_count);
+    }
+
+    fn exception_hierarchy() -> ClassFileHierarchy {
+        let mut hierarchy = ClassFileHierarchy::new();
+        hierarchy.add_class("java/lang/Throwable", Some("java/lang/Object"), false);
+        hierarchy.add_class("java/lang/Exception", Some("java/lang/Throwable"), false);
+        hierarchy.add_class("java/io/IOException", Some("java/lang/Exception"), false);
+        hierarchy.add_class("java/lang/RuntimeException", Some("java/lang/Exception"), false);
+        hierarchy.add_class("java/lang/IllegalArgumentException",
+                            Some("java/lang/RuntimeException"),
+                            false);
+        hierarchy.add_class("java/lang/NumberFormatException",
+                            Some("java/lang/IllegalArgumentException"),
+                            false);
+        hierarchy
+    }
+
+    #[test]
+    fn can_verify_class_file() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let errors = verifier::verify(&classfile, &exception_hierarchy()).unwrap();
+        assert_that(&errors).has_length(0);
+
+        // without the hierarchy, a caught exception is not known to be a Throwable
+        let errors = verifier::verify(&classfile, &ObjectHierarchy).unwrap();
+        assert_that(&errors).has_length(2);
+        assert_that(&errors[0].method_name).is_equal_to(&"tryCatch".to_string());
+        assert_that(&errors[0].kind).is_equal_to(&VerifyErrorKind::TypeMismatch {
+            expected: VerificationType::Object("java/lang/Throwable".to_string()),
+            actual: VerificationType::Object("java/lang/NumberFormatException".to_string()),
+        });
+    }
+
+    #[test]
+    fn reports_first_verify_error_of_method() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let text = print(&classfile, &PrintOptions::default()).unwrap();
+        let text = text.replace("    L28:\n        iload_2\n        ireturn",
+                                "    L28:\n        fload_2\n        ireturn");
+        let bytes = assemble(&text).unwrap();
+
+        let reassembled = ClassFile::parse(&bytes).unwrap();
+
+        let errors = verifier::verify(&reassembled, &exception_hierarchy()).unwrap();
+        assert_that(&errors).has_length(1);
+        assert_that(&errors[0].method_name).is_equal_to(&"loop".to_string());
+        assert_that(&errors[0].method_descriptor).is_equal_to(&"(I)I".to_string());
+        assert_that(&errors[0].pc).is_equal_to(&28);
+        assert_that(&errors[0].kind).is_equal_to(&VerifyErrorKind::TypeMismatch {
+            expected: VerificationType::Float,
+            actual: VerificationType::Integer,
+        });
+    }
+
+    #[test]
+    fn reports_constants_of_the_wrong_category() {
+        let text = [".version 52 0",
+                    ".class public super Constants",
+                    ".super java/lang/Object",
+                    ".method public static narrow ()J",
+                    "    .code stack 2 locals 0",
+                    "        ldc_w Long 1",
+                    "        lreturn",
+                    "    .end code",
+                    ".end method",
+                    ".method public static wide ()I",
+                    "    .code stack 2 locals 0",
+                    "        ldc2_w Integer 1",
+                    "        ireturn",
+                    "    .end code",
+                    ".end method",
+                    ""]
+            .join("\n");
+        let classfile = ClassFile::parse(&assemble(&text).unwrap()).unwrap();
+
+        let errors = verifier::verify(&classfile, &exception_hierarchy()).unwrap();
+        assert_that(&errors).has_length(2);
+        assert_that(&errors[0].method_name).is_equal_to(&"narrow".to_string());
+        assert_that(&errors[0].kind)
+            .is_equal_to(&VerifyErrorKind::WrongCategory(VerificationType::Long));
+        assert_that(&errors[1].method_name).is_equal_to(&"wide".to_string());
+        assert_that(&errors[1].kind)
+            .is_equal_to(&VerifyErrorKind::WrongCategory(VerificationType::Integer));
+    }
+
+    #[test]
+    fn rejects_verification_by_type_checking_before_version_50() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let mut classfile = ClassFile::from(test_file).unwrap();
+        classfile.major_version = 49;
+
+        match verifier::check_types(&classfile, &ObjectHierarchy) {
+            Err(ParserError::UnsupportedClassVersion(version)) => {
+                assert_that(&version).is_equal_to(&49)
+            }
+            result => panic!("expected an unsupported version, got {:?}", result),
+        }
+    }
+
+    const LEGACY_CLASS: &'static str = ".version 49 0
+.class public super Legacy
+.super java/lang/Object
+
+.method public static run (I)I
+    .code stack 1 locals 4
+    L0:
+        iload_0
+        istore_1
+    L2:
+        jsr L13
+        iload_1
+        ireturn
+    L7:
+        astore_2
+        jsr L13
+        aload_2
+        athrow
+    L13:
+        astore_3
+        iinc 0 1
+        ret 3
+        .catch any L0 L2 L7
+    .end code
+.end method
+";
+
+    fn assemble_legacy_class(text: &str) -> ClassFile {
+        ClassFile::parse(&assemble(text).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn can_verify_subroutines_by_type_inference() {
+        let classfile = assemble_legacy_class(LEGACY_CLASS);
+
+        let errors = verifier::infer_types(&classfile, &ObjectHierarchy).unwrap();
+        assert_that(&errors).has_length(0);
+
+        let errors = verifier::verify(&classfile, &ObjectHierarchy).unwrap();
+        assert_that(&errors).has_length(0);
+    }
+
+    #[test]
+    fn reports_locals_merged_across_subroutine_calls() {
+        // local 2 only holds the caught exception on one of the two paths into the subroutine
+        let text = LEGACY_CLASS.replace("iinc 0 1", "iinc 2 1");
+        let classfile = assemble_legacy_class(&text);
+
+        let errors = verifier::verify(&classfile, &ObjectHierarchy).unwrap();
+        assert_that(&errors).has_length(1);
+        assert_that(&errors[0].pc).is_equal_to(&14);
+        assert_that(&errors[0].kind).is_equal_to(&VerifyErrorKind::TypeMismatch {
+            expected: VerificationType::Integer,
+            actual: VerificationType::Top,
+        });
+    }
+
+    #[test]
+    fn can_compute_stack_map_table_of_inlined_subroutines() {
+        let mut classfile = assemble_legacy_class(LEGACY_CLASS);
+
+        let method = classfile.methods[0].clone();
+        let code = subroutines::inline(&method.code().unwrap()).unwrap();
+        assert_that(&code.instructions().any(|result| match result {
+                Ok((_, Instruction::Jsr(..))) | Ok((_, Instruction::Ret(..))) => true,
+                _ => false,
+            }))
+            .is_equal_to(&false);
+
+        let mut code = code;
+        let table = stackmap::compute(&mut classfile, &method, &code, &ObjectHierarchy).unwrap();
+        assert_that(&table.entries).has_length(5);
+        code.attributes.push(Attribute::StackMapTable(Rc::new(table)));
+        code.attributes_count += 1;
+
+        let attributes = vec![Rc::new(Attribute::Code(Rc::new(code)))];
+        classfile.methods = vec![Rc::new(Method {
+                                     access_flags: method.access_flags,
+                                     name_index: method.name_index,
+                                     name: method.name.clone(),
+                                     descriptor_index: method.descriptor_index,
+                                     descriptor: method.descriptor.clone(),
+                                     attributes_count: attributes.len() as U2,
+                                     attributes: attributes,
+                                 })];
+        classfile.major_version = 50;
+
+        let errors = verifier::check_types(&classfile, &ObjectHierarchy).unwrap();
+        assert_that(&errors).has_length(0);
+    }
+
+    #[test]
+    fn can_check_class_file_format() {
+        for resource in &["classfile/HelloWorld.class", "classfile/ControlFlow.class"] {
+            let classfile = ClassFile::from(open_test_resource(resource)).unwrap();
+
+            assert_that(&format::check(&classfile).unwrap()).has_length(0);
+        }
+    }
+
+    #[test]
+    fn reports_format_errors() {
+        let text = ".version 52 0
+.class public final abstract Broken
+.super java/lang/Object
+
+.field private public count I
+.end field
+
+.field private count I
+.end field
+
+.method public static <init> ()V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+
+.method public abstract run (V)V
+.end method
+
+.method static <clinit> (I)V
+    .code stack 0 locals 1
+        return
+    .end code
+.end method
+";
+        let classfile = ClassFile::parse(&assemble(text).unwrap()).unwrap();
+
+        let count = Location::Field {
+            name: "count".to_string(),
+            descriptor: "I".to_string(),
+        };
+        let method = |name: &str, descriptor: &str| {
+            Location::Method {
+                name: name.to_string(),
+                descriptor: descriptor.to_string(),
+            }
+        };
+        let errors = format::check(&classfile).unwrap();
+        assert_that(&errors).is_equal_to(&vec![FormatError {
+                                                  location: Location::Class,
+                                                  kind: FormatErrorKind::IllegalAccessFlags(0x0411),
+                                              },
+                                              FormatError {
+                                                  location: count.clone(),
+                                                  kind: FormatErrorKind::IllegalAccessFlags(0x0003),
+                                              },
+                                              FormatError {
+                                                  location: count,
+                                                  kind: FormatErrorKind::DuplicateMember,
+                                              },
+                                              FormatError {
+                                                  location: method("<init>", "()V"),
+                                                  kind: FormatErrorKind::IllegalAccessFlags(0x0009),
+                                              },
+                                              FormatError {
+                                                  location: method("run", "(V)V"),
+                                                  kind: FormatErrorKind::InvalidDescriptor("(V)V"
+                                                      .to_string()),
+                                              },
+                                              FormatError {
+                                                  location: method("<clinit>", "(I)V"),
+                                                  kind: FormatErrorKind::InvalidInitializer,
+                                              }]);
+    }
+
+    #[test]
+    fn can_disassemble_class_file() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let output = disassemble(&classfile).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_that(&lines[0]).is_equal_to(&"  Compiled from \"HelloWorld.java\"");
+        assert_that(&lines[1]).is_equal_to(&"public class HelloWorld");
+        assert_that(&lines).contains(&"   #3 = Methodref          #4.#23         // HelloWorld.println:(Ljava/lang/String;)V");
+        assert_that(&lines).contains(&"         2: invokestatic  #3                  // Method println:(Ljava/lang/String;)V");
+        assert_that(&lines).contains(&"  private static native void println(java.lang.String);");
+        assert_that(&lines.last()).is_equal_to(&Some(&"SourceFile: \"HelloWorld.java\""));
+    }
+
+    #[test]
+    fn can_disassemble_wide_instructions() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let mut classfile = ClassFile::from(test_file).unwrap();
+
+        let main_method = classfile.maybe_resolve_main_method().unwrap();
+        let mut assembler = CodeAssembler::new();
+        assembler.push(Instruction::Iinc(0, 1000));
+        assembler.push(Instruction::Iinc(0, 1));
+        assembler.push(Instruction::Return);
+        let code = assembler.assemble_into(&main_method.code().unwrap()).unwrap();
+
+        classfile.methods = vec![Rc::new(Method {
+                                     access_flags: main_method.access_flags,
+                                     name_index: main_method.name_index,
+                                     name: main_method.name.clone(),
+                                     descriptor_index: main_method.descriptor_index,
+                                     descriptor: main_method.descriptor.clone(),
+                                     attributes_count: 1,
+                                     attributes: vec![Rc::new(Attribute::Code(Rc::new(code)))],
+                                 })];
+        classfile.methods_count = 1;
+
+        let output = disassemble(&classfile).unwrap();
+        let lines = output.lines().collect::<Vec<_>>();
+
+        assert_that(&lines).contains(&"         0: iinc_w        0, 1000");
+        assert_that(&lines).contains(&"         6: iinc          0, 1");
+    }
+
+    #[test]
+    fn can_reassemble_printed_class_file_byte_for_byte() {
+        for resource in &["classfile/HelloWorld.class", "classfile/ControlFlow.class"] {
+            let mut bytes = vec![];
+            open_test_resource(resource).read_to_end(&mut bytes).unwrap();
+            let classfile = ClassFile::from(open_test_resource(resource)).unwrap();
+
+            let options = PrintOptions { preserve_constant_pool: true };
+            let text = print(&classfile, &options).unwrap();
+
+            assert_that(&assemble(&text).unwrap()).is_equal_to(&bytes);
+        }
+    }
+
+    #[test]
+    fn can_reassemble_printed_class_file_with_rebuilt_constant_pool() {
+        let test_file = open_test_resource("classfile/ControlFlow.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let text = print(&classfile, &PrintOptions::default()).unwrap();
+        let bytes = assemble(&text).unwrap();
+
+        let reassembled = ClassFile::parse(&bytes).unwrap();
+
+        assert_that(&print(&reassembled, &PrintOptions::default()).unwrap()).is_equal_to(&text);
+    }
+
+    #[test]
+    fn can_print_symbolic_assembly() {
+        let test_file = open_test_resource("classfile/HelloWorld.class");
+        let classfile = ClassFile::from(test_file).unwrap();
+
+        let text = print(&classfile, &PrintOptions::default()).unwrap();
+        let lines = text.lines().collect::<Vec<_>>();
+
+        assert_that(&lines[0]).is_equal_to(&".version 52 0");
+        assert_that(&lines).contains(&".class public super HelloWorld");
+        assert_that(&lines).contains(&"        invokestatic Method HelloWorld println (Ljava/lang/String;)V");
+        assert_that(&lines).contains(&".method private static native println (Ljava/lang/String;)V");
+        assert_that(&lines.last()).is_equal_to(&Some(&".sourcefile HelloWorld.java"));
+    }
+
+    #[test]
+    fn reports_line_of_invalid_assembly() {
+        let text = ".version 52 0\n.class public Broken\n.method public static run ()V\n    .code stack 1 locals 0\n        frobnicate\n    .end code\n.end method\n";
+
+        match assemble(text) {
+            Err(ParserError::InvalidAssembly(line, _)) => assert_that(&line).is_equal_to(&5),
+            result => panic!("expected an assembly error, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn fails_on_truncated_class_files() {
+        let mut bytes = vec![];
+        open_test_resource("classfile/HelloWorld.class").read_to_end(&mut bytes).unwrap();
+
+        for length in 0..bytes.len() {
+            assert_that(&ClassFile::parse(&bytes[..length]).is_err()).is_equal_to(&true);
+        }
+    }
+
+    #[test]
+    fn fails_on_empty_constant_pool_count_and_zero_index() {
+        let bytes = [0xca, 0xfe, 0xba, 0xbe, 0x00, 0x00, 0x00, 0x34, 0x00, 0x00];
+        assert_that(&ClassFile::parse(&bytes)).matches(|result| match *result {
+            Err(ParserError::InvalidConstantPoolCount(0)) => true,
+            _ => false,
+        });
+
+        let classfile = ClassFile::from(open_test_resource("classfile/HelloWorld.class")).unwrap();
+        let item = ConstantPoolItem::retrieve_utf8_info(0, &classfile.constant_pool);
+        assert_that(&item).matches(|result| match *result {
+            Err(ParserError::InvalidConstantPoolIndex(0)) => true,
+            _ => false,
+        });
+    }
+
+    /// A source of bytes other than a buffer, to show the parser can be driven by one.
+    struct ByteSource<'a>(::std::slice::Iter<'a, u8>);
+
+    impl<'a> PrimitiveIterator for ByteSource<'a> {
+        fn next_u1(&mut self) -> ::std::io::Result<u8> {
+            self.0.next().cloned().ok_or_else(|| ErrorKind::UnexpectedEof.into())
+        }
+    }
+
     fn open_test_resource(resource_path: &str) -> File {
         let mut file_path = PathBuf::from(MANIFEST_DIR);
         file_path.push("test-resources/");
@@ -352,4 +1990,70 @@ mod tests {
         File::open(file_path).unwrap()
     }
 
+    #[test]
+    fn keeps_debug_tables_outside_of_code_as_unknown_attributes() {
+        let constant_pool = vec![utf8_constant("LineNumberTable")];
+        let bytes: Vec<u8> = vec![0, 1, 0, 0, 0, 4, 0, 0, 0, 0];
+        let attribute = Attribute::from(&mut Cursor::new(bytes).bytes(), &constant_pool);
+
+        assert_that(&attribute).is_ok().matches(|val| match *val {
+            Attribute::Unknown { ref info, .. } => info == &vec![0, 0, 0, 0],
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn fails_on_attributes_not_matching_their_length() {
+        let constant_pool = vec![utf8_constant("Code"), utf8_constant("LineNumberTable")];
+
+        // the code is followed by a byte that belongs to none of its parts
+        let bytes: Vec<u8> = vec![0, 1, 0, 0, 0, 14, 0, 0, 0, 0, 0, 0, 0, 1, 0xb1, 0, 0, 0, 0, 0];
+        let attribute = Attribute::from(&mut Cursor::new(bytes).bytes(), &constant_pool);
+        assert_that(&attribute).matches(|val| match *val {
+            Err(ParserError::AttributeLengthMismatch(ref name)) => name == "Code",
+            _ => false,
+        });
+
+        // the nested line number table claims an entry its length leaves no room for
+        let bytes: Vec<u8> = vec![0, 1, 0, 0, 0, 21, 0, 0, 0, 0, 0, 0, 0, 1, 0xb1, 0, 0, 0, 1,
+                                  0, 2, 0, 0, 0, 2, 0, 1];
+        let attribute = Attribute::from(&mut Cursor::new(bytes).bytes(), &constant_pool);
+        assert_that(&attribute).matches(|val| match *val {
+            Err(ParserError::Io(..)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn decodes_lazy_attributes_like_member_attributes() {
+        let constant_pool = Rc::new(vec![utf8_constant("Code"), utf8_constant("LineNumberTable")]);
+        let decode = |bytes: Vec<u8>| {
+            let source = Rc::new(bytes);
+            let mut cursor = ByteCursor::new(&source);
+            LazyAttribute::from(&mut cursor, &source, &constant_pool).unwrap().decode()
+        };
+
+        let attribute = decode(vec![0, 2, 0, 0, 0, 4, 0, 0, 0, 0]);
+        assert_that(&attribute).is_ok().matches(|val| match **val {
+            Attribute::Unknown { ref info, .. } => info == &vec![0, 0, 0, 0],
+            _ => false,
+        });
+
+        let attribute = decode(vec![0, 1, 0, 0, 0, 14, 0, 0, 0, 0, 0, 0, 0, 1, 0xb1, 0, 0, 0, 0,
+                                    0]);
+        assert_that(&attribute).matches(|val| match *val {
+            Err(ParserError::AttributeLengthMismatch(ref name)) => name == "Code",
+            _ => false,
+        });
+    }
+
+    fn utf8_constant(value: &str) -> ConstantPoolItem {
+        ConstantPoolItem::Utf8(Rc::new(Utf8Info {
+            tag: 1,
+            length: value.len() as U2,
+            value: value.to_string(),
+            bytes: None,
+        }))
+    }
+
 }
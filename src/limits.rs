@@ -0,0 +1,95 @@
+use super::{ClassFile, ParserResult};
+use super::components::{CodeAttribute, Method};
+use super::frames::Frames;
+use super::hierarchy::ObjectHierarchy;
+use super::primitives::U2;
+
+use std::cmp;
+
+/// The operand stack and local variable sizes a method body declares or needs, in slots.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct CodeLimits {
+    pub max_stack: U2,
+    pub max_locals: U2,
+}
+
+impl CodeLimits {
+    /// The limits stored in `code`.
+    pub fn declared(code: &CodeAttribute) -> CodeLimits {
+        CodeLimits {
+            max_stack: code.max_stack,
+            max_locals: code.max_locals,
+        }
+    }
+
+    /// The smallest limits that `code` can run with as the body of `method`.
+    ///
+    /// The stack height comes from simulating the reachable instructions, so unreachable code
+    /// does not count towards `max_stack`. Every local variable instruction counts towards
+    /// `max_locals`, as do the parameters of the method.
+    pub fn compute(classfile: &ClassFile,
+                   method: &Method,
+                   code: &CodeAttribute)
+                   -> ParserResult<CodeLimits> {
+        let frames = try!(Frames::simulate(classfile, method, code, &ObjectHierarchy));
+
+        let mut max_stack = 0;
+        let mut max_locals = 0;
+        for (_, frame) in frames.iter() {
+            max_stack = cmp::max(max_stack, frame.stack_height());
+            max_locals = cmp::max(max_locals, frame.locals.len());
+        }
+
+        for result in code.instructions() {
+            let (_, instruction) = try!(result);
+            if let Some(access) = instruction.local_variable_access() {
+                max_locals = cmp::max(max_locals, access.index as usize + access.slots as usize);
+            }
+        }
+
+        Ok(CodeLimits {
+            max_stack: max_stack as U2,
+            max_locals: max_locals as U2,
+        })
+    }
+
+    /// Whether code that needs `required` can run within these limits.
+    pub fn covers(&self, required: &CodeLimits) -> bool {
+        self.max_stack >= required.max_stack && self.max_locals >= required.max_locals
+    }
+}
+
+/// A method whose stored limits are smaller than its code needs.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InsufficientLimits {
+    pub method_name: String,
+    pub method_descriptor: String,
+    pub declared: CodeLimits,
+    pub required: CodeLimits,
+}
+
+/// Finds the methods of `classfile` whose `max_stack` or `max_locals` is too small for their
+/// code. Limits larger than needed are allowed, as they are in the JVM.
+pub fn check(classfile: &ClassFile) -> ParserResult<Vec<InsufficientLimits>> {
+    let mut insufficient = vec![];
+
+    for method in &classfile.methods {
+        let code = match method.code() {
+            Some(code) => code,
+            None => continue,
+        };
+
+        let declared = CodeLimits::declared(&code);
+        let required = try!(CodeLimits::compute(classfile, method, &code));
+        if !declared.covers(&required) {
+            insufficient.push(InsufficientLimits {
+                method_name: method.name.to_string(),
+                method_descriptor: method.descriptor.to_string(),
+                declared: declared,
+                required: required,
+            });
+        }
+    }
+
+    Ok(insufficient)
+}
@@ -0,0 +1,219 @@
+use super::controlflow::{BlockId, ControlFlowGraph, Edge};
+use super::dominators::DominatorTree;
+
+use std::collections::BTreeSet;
+
+/// A natural loop, made of the blocks that can reach one of its back edges without going
+/// through its header.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Loop {
+    pub header: BlockId,
+    /// The blocks with a back edge to the header.
+    pub latches: Vec<BlockId>,
+    /// Every block of the loop in block order, including the header and nested loops.
+    pub blocks: Vec<BlockId>,
+    /// The index of the closest enclosing loop.
+    pub parent: Option<usize>,
+    /// The number of loops this loop is nested in, counting itself, so outermost loops are at
+    /// depth 1.
+    pub depth: usize,
+}
+
+impl Loop {
+    pub fn contains(&self, id: BlockId) -> bool {
+        self.blocks.binary_search(&id).is_ok()
+    }
+}
+
+/// The natural loops of a control-flow graph and how they nest.
+///
+/// A cycle that can be entered at more than one block has no header that dominates it, so it is
+/// not a natural loop. Such irreducible control flow is reported through the retreating edges
+/// that close those cycles instead.
+#[derive(Clone, Debug)]
+pub struct LoopForest {
+    /// Loops ordered by header.
+    pub loops: Vec<Loop>,
+    pub irreducible_edges: Vec<Edge>,
+    innermost_loops: Vec<Option<usize>>,
+}
+
+impl LoopForest {
+    pub fn from(graph: &ControlFlowGraph, dominators: &DominatorTree) -> LoopForest {
+        let mut latches: Vec<(BlockId, Vec<BlockId>)> = vec![];
+        for edge in &graph.edges {
+            if !dominators.dominates(edge.target, edge.source) {
+                continue;
+            }
+
+            match latches.iter_mut().find(|&&mut (header, _)| header == edge.target) {
+                Some(&mut (_, ref mut sources)) => sources.push(edge.source),
+                None => latches.push((edge.target, vec![edge.source])),
+            }
+        }
+        latches.sort_by_key(|&(header, _)| header);
+
+        let mut loops = vec![];
+        for (header, mut sources) in latches {
+            sources.sort();
+            sources.dedup();
+
+            loops.push(Loop {
+                header: header,
+                blocks: loop_blocks(graph, header, &sources),
+                latches: sources,
+                parent: None,
+                depth: 1,
+            });
+        }
+
+        for i in 0..loops.len() {
+            loops[i].parent = (0..loops.len())
+                .filter(|&candidate| {
+                    loops[candidate].header != loops[i].header &&
+                    loops[candidate].contains(loops[i].header)
+                })
+                .min_by_key(|&candidate| loops[candidate].blocks.len());
+        }
+        for i in 0..loops.len() {
+            let mut parent = loops[i].parent;
+            while let Some(index) = parent {
+                loops[i].depth += 1;
+                parent = loops[index].parent;
+            }
+        }
+
+        let mut innermost_loops: Vec<Option<usize>> = vec![None; graph.blocks.len()];
+        for (index, natural_loop) in loops.iter().enumerate() {
+            for id in &natural_loop.blocks {
+                let deeper = match innermost_loops[*id] {
+                    Some(current) => loops[current].depth < natural_loop.depth,
+                    None => true,
+                };
+                if deeper {
+                    innermost_loops[*id] = Some(index);
+                }
+            }
+        }
+
+        LoopForest {
+            loops: loops,
+            irreducible_edges: irreducible_edges(graph, dominators),
+            innermost_loops: innermost_loops,
+        }
+    }
+
+    pub fn is_reducible(&self) -> bool {
+        self.irreducible_edges.is_empty()
+    }
+
+    pub fn is_loop_header(&self, id: BlockId) -> bool {
+        self.loops.iter().any(|natural_loop| natural_loop.header == id)
+    }
+
+    /// The index of the deepest loop containing `id`.
+    pub fn innermost_loop(&self, id: BlockId) -> Option<usize> {
+        self.innermost_loops[id]
+    }
+
+    /// The number of loops containing `id`, or 0 if it is not in a loop.
+    pub fn loop_depth(&self, id: BlockId) -> usize {
+        self.innermost_loops[id].map(|index| self.loops[index].depth).unwrap_or(0)
+    }
+}
+
+fn loop_blocks(graph: &ControlFlowGraph, header: BlockId, latches: &[BlockId]) -> Vec<BlockId> {
+    let mut blocks = BTreeSet::new();
+    blocks.insert(header);
+
+    let mut worklist = latches.to_vec();
+    while let Some(id) = worklist.pop() {
+        if blocks.insert(id) {
+            worklist.extend(graph.predecessors(id).iter().map(|edge| edge.source));
+        }
+    }
+
+    blocks.into_iter().collect()
+}
+
+/// Finds the edges that go back to a block still being visited by a depth-first search, but
+/// whose target does not dominate their source.
+fn irreducible_edges(graph: &ControlFlowGraph, dominators: &DominatorTree) -> Vec<Edge> {
+    let mut irreducible_edges = vec![];
+    let mut visited = vec![false; graph.blocks.len()];
+    let mut on_stack = vec![false; graph.blocks.len()];
+
+    let entry = graph.entry();
+    let mut stack = vec![(entry, 0)];
+    visited[entry] = true;
+    on_stack[entry] = true;
+
+    while let Some((id, position)) = stack.pop() {
+        let edge = match graph.successors(id).get(position) {
+            Some(edge) => **edge,
+            None => {
+                on_stack[id] = false;
+                continue;
+            }
+        };
+        stack.push((id, position + 1));
+
+        if on_stack[edge.target] {
+            if !dominators.dominates(edge.target, edge.source) {
+                irreducible_edges.push(edge);
+            }
+        } else if !visited[edge.target] {
+            visited[edge.target] = true;
+            on_stack[edge.target] = true;
+            stack.push((edge.target, 0));
+        }
+    }
+
+    irreducible_edges
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::LoopForest;
+    use super::super::components::CodeAttribute;
+    use super::super::controlflow::ControlFlowGraph;
+    use super::super::dominators::DominatorTree;
+
+    fn loops(bytes: Vec<u8>) -> LoopForest {
+        let code = CodeAttribute::for_test(bytes, vec![]);
+        let graph = ControlFlowGraph::from(&code).unwrap();
+        let dominators = DominatorTree::dominators(&graph);
+
+        LoopForest::from(&graph, &dominators)
+    }
+
+    #[test]
+    fn can_find_natural_loop() {
+        // iinc 0 -1; iload_0; ifgt -4; return
+        let forest = loops(vec![0x84, 0x00, 0xff, 0x1a, 0x9d, 0xff, 0xfc, 0xb1]);
+
+        assert_that(&forest.loops).has_length(1);
+        assert_that(&forest.loops[0].header).is_equal_to(&0);
+        assert_that(&forest.loops[0].blocks).is_equal_to(&vec![0]);
+        assert_that(&forest.loop_depth(0)).is_equal_to(&1);
+        assert_that(&forest.loop_depth(1)).is_equal_to(&0);
+        assert_that(&forest.is_reducible()).is_equal_to(&true);
+    }
+
+    #[test]
+    fn can_detect_irreducible_loop() {
+        // iload_0; ifeq +7; iload_0; pop; nop; nop; iload_0; ifne -5; return
+        let forest = loops(vec![0x1a, 0x99, 0x00, 0x07, 0x1a, 0x57, 0x00, 0x00, 0x1a, 0x9a,
+                                0xff, 0xfb, 0xb1]);
+
+        assert_that(&forest.loops).has_length(0);
+        assert_that(&forest.irreducible_edges).has_length(1);
+        assert_that(&forest.is_reducible()).is_equal_to(&false);
+    }
+
+}
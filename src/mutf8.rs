@@ -0,0 +1,152 @@
+//! The modified UTF-8 that `Utf8` constants are stored in. It differs from standard UTF-8 in
+//! writing NUL as the two bytes `C0 80` and characters outside the Basic Multilingual Plane as
+//! the surrogate pair that encodes them in UTF-16, each surrogate taking three bytes.
+
+use super::{ParserError, ParserResult};
+use super::primitives::U1;
+
+use std::str;
+
+/// The bytes as a string slice, if they are standard UTF-8 that reads the same as modified
+/// UTF-8. Standard UTF-8 only differs where there are NULs and supplementary characters.
+pub fn as_str(bytes: &[U1]) -> Option<&str> {
+    if bytes.iter().any(|&byte| byte == 0 || byte >= 0xf0) {
+        return None;
+    }
+
+    str::from_utf8(bytes).ok()
+}
+
+/// Decodes `bytes`, along with whether `encode` gives them back for the decoded string. It
+/// doesn't for the standard encodings of NUL and supplementary characters, which are accepted
+/// as well, and for surrogates without their other half, which decode to U+FFFD.
+pub fn decode(bytes: &[U1]) -> ParserResult<(String, bool)> {
+    if let Some(value) = as_str(bytes) {
+        return Ok((value.to_owned(), true));
+    }
+
+    let mut units = Vec::with_capacity(bytes.len());
+    let mut exact = true;
+    let mut position = 0;
+    while position < bytes.len() {
+        let first = bytes[position] as u32;
+        let (length, minimum) = match first {
+            0x00..=0x7f => (1, 0),
+            0xc0..=0xdf => (2, 0x80),
+            0xe0..=0xef => (3, 0x800),
+            0xf0..=0xf4 => (4, 0x10000),
+            _ => return Err(invalid(bytes)),
+        };
+        if position + length > bytes.len() {
+            return Err(invalid(bytes));
+        }
+
+        let mut code_point = if length == 1 { first } else { first & (0x7f >> length) };
+        for &byte in &bytes[position + 1..position + length] {
+            if byte & 0xc0 != 0x80 {
+                return Err(invalid(bytes));
+            }
+            code_point = code_point << 6 | (byte & 0x3f) as u32;
+        }
+
+        if code_point == 0 && length <= 2 {
+            // NUL is written as `C0 80`, but a plain zero byte is read as well
+            exact = exact && length == 2;
+        } else if code_point < minimum || code_point > 0x10ffff {
+            return Err(invalid(bytes));
+        }
+
+        if code_point >= 0x10000 {
+            exact = false;
+            let offset = code_point - 0x10000;
+            units.push(0xd800 | (offset >> 10) as u16);
+            units.push(0xdc00 | (offset & 0x3ff) as u16);
+        } else {
+            units.push(code_point as u16);
+        }
+        position += length;
+    }
+
+    match String::from_utf16(&units) {
+        Ok(value) => Ok((value, exact)),
+        Err(_) => Ok((String::from_utf16_lossy(&units), false)),
+    }
+}
+
+/// Encodes `value` the way a class file stores it.
+pub fn encode(value: &str) -> Vec<U1> {
+    let mut bytes = Vec::with_capacity(encoded_len(value));
+    for unit in value.encode_utf16() {
+        if unit != 0 && unit < 0x80 {
+            bytes.push(unit as U1);
+        } else if unit < 0x800 {
+            bytes.push(0xc0 | (unit >> 6) as U1);
+            bytes.push(0x80 | (unit & 0x3f) as U1);
+        } else {
+            bytes.push(0xe0 | (unit >> 12) as U1);
+            bytes.push(0x80 | (unit >> 6 & 0x3f) as U1);
+            bytes.push(0x80 | (unit & 0x3f) as U1);
+        }
+    }
+
+    bytes
+}
+
+/// The number of bytes `encode` turns `value` into.
+pub fn encoded_len(value: &str) -> usize {
+    value.chars()
+        .map(|character| match character.len_utf8() {
+            1 if character == '\0' => 2,
+            4 => 6,
+            length => length,
+        })
+        .sum()
+}
+
+fn invalid(bytes: &[U1]) -> ParserError {
+    // whatever modified UTF-8 rejects, standard UTF-8 rejects too
+    ParserError::InvalidUtf8(String::from_utf8(bytes.to_vec()).unwrap_err())
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{as_str, decode, encode, encoded_len};
+    use super::super::ParserError;
+
+    #[test]
+    fn can_encode_nul_and_supplementary_characters() {
+        let value = "a\0\u{e9}\u{1f600}";
+        let bytes = encode(value);
+
+        assert_that(&bytes).is_equal_to(&vec![0x61, 0xc0, 0x80, 0xc3, 0xa9, 0xed, 0xa0, 0xbd, 0xed,
+                                              0xb8, 0x80]);
+        assert_that(&encoded_len(value)).is_equal_to(&bytes.len());
+        assert_that(&decode(&bytes).unwrap()).is_equal_to(&(value.to_string(), true));
+        assert_that(&as_str(&bytes)).is_none();
+        assert_that(&as_str(&[0x61, 0xc3, 0xa9])).is_equal_to(&Some("a\u{e9}"));
+    }
+
+    #[test]
+    fn reports_bytes_that_do_not_encode_back() {
+        assert_that(&decode(&[0x61, 0x00]).unwrap()).is_equal_to(&("a\0".to_string(), false));
+        assert_that(&decode(&[0xf0, 0x9f, 0x98, 0x80]).unwrap())
+            .is_equal_to(&("\u{1f600}".to_string(), false));
+        assert_that(&decode(&[0xed, 0xa0, 0x80]).unwrap())
+            .is_equal_to(&("\u{fffd}".to_string(), false));
+
+        assert_that(&decode(&[0xc1, 0x81])).matches(|result| match *result {
+            Err(ParserError::InvalidUtf8(..)) => true,
+            _ => false,
+        });
+        assert_that(&decode(&[0xe0, 0x80])).matches(|result| match *result {
+            Err(ParserError::InvalidUtf8(..)) => true,
+            _ => false,
+        });
+    }
+
+}
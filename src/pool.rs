@@ -0,0 +1,552 @@
+use super::{ParserError, ParserResult};
+use super::components::{ClassInfo, ConstantPoolItem, FieldOrMethodOrInterfaceMethodInfo,
+                        IntegerOrFloatInfo, LongOrDoubleInfo, NameAndTypeInfo, StringInfo,
+                        Utf8Info};
+use super::mutf8;
+use super::primitives::{PrimitiveWriter, U1, U2, U4};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// The value of a constant pool entry, with the entries it refers to given by their indexes.
+/// Entries with equal values can stand in for each other.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Constant {
+    Utf8(String),
+    Integer(U4),
+    Float(U4),
+    Long(u64),
+    Double(u64),
+    Class(U2),
+    String(U2),
+    Field(U2, U2),
+    Method(U2, U2),
+    InterfaceMethod(U2, U2),
+    NameAndType(U2, U2),
+    MethodHandle(U1, U2),
+    MethodType(U2),
+    InvokeDynamic(U2, U2),
+}
+
+impl Constant {
+    pub fn from_item(item: &ConstantPoolItem) -> Option<Constant> {
+        let constant = match *item {
+            ConstantPoolItem::Empty => return None,
+            ConstantPoolItem::Utf8(ref info) => Constant::Utf8(info.value.clone()),
+            ConstantPoolItem::Integer(ref info) => Constant::Integer(info.bytes),
+            ConstantPoolItem::Float(ref info) => Constant::Float(info.bytes),
+            ConstantPoolItem::Long(ref info) => {
+                Constant::Long((info.high_bytes as u64) << 32 | info.low_bytes as u64)
+            }
+            ConstantPoolItem::Double(ref info) => {
+                Constant::Double((info.high_bytes as u64) << 32 | info.low_bytes as u64)
+            }
+            ConstantPoolItem::Class(ref info) => Constant::Class(info.name_index),
+            ConstantPoolItem::String(ref info) => Constant::String(info.string_index),
+            ConstantPoolItem::Field(ref info) => {
+                Constant::Field(info.class_index, info.name_and_type_index)
+            }
+            ConstantPoolItem::Method(ref info) => {
+                Constant::Method(info.class_index, info.name_and_type_index)
+            }
+            ConstantPoolItem::InterfaceMethod(ref info) => {
+                Constant::InterfaceMethod(info.class_index, info.name_and_type_index)
+            }
+            ConstantPoolItem::NameAndType(ref info) => {
+                Constant::NameAndType(info.name_index, info.descriptor_index)
+            }
+            ConstantPoolItem::MethodHandle { reference_kind, reference_index, .. } => {
+                Constant::MethodHandle(reference_kind, reference_index)
+            }
+            ConstantPoolItem::MethodType { descriptor_index, .. } => {
+                Constant::MethodType(descriptor_index)
+            }
+            ConstantPoolItem::InvokeDynamic { bootstrap_method_attr_index,
+                                              name_and_type_index,
+                                              .. } => {
+                Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index)
+            }
+        };
+
+        Some(constant)
+    }
+
+    /// The entry the way a parsed constant pool holds it.
+    pub fn to_item(&self) -> ConstantPoolItem {
+        match *self {
+            Constant::Utf8(ref value) => {
+                ConstantPoolItem::Utf8(Rc::new(Utf8Info {
+                    tag: 1,
+                    length: mutf8::encoded_len(value) as U2,
+                    value: value.clone(),
+                    bytes: None,
+                }))
+            }
+            Constant::Integer(bytes) => {
+                ConstantPoolItem::Integer(Rc::new(IntegerOrFloatInfo {
+                    tag: 3,
+                    bytes: bytes,
+                }))
+            }
+            Constant::Float(bytes) => {
+                ConstantPoolItem::Float(Rc::new(IntegerOrFloatInfo {
+                    tag: 4,
+                    bytes: bytes,
+                }))
+            }
+            Constant::Long(bytes) => {
+                ConstantPoolItem::Long(Rc::new(LongOrDoubleInfo {
+                    tag: 5,
+                    high_bytes: (bytes >> 32) as U4,
+                    low_bytes: bytes as U4,
+                }))
+            }
+            Constant::Double(bytes) => {
+                ConstantPoolItem::Double(Rc::new(LongOrDoubleInfo {
+                    tag: 6,
+                    high_bytes: (bytes >> 32) as U4,
+                    low_bytes: bytes as U4,
+                }))
+            }
+            Constant::Class(name_index) => {
+                ConstantPoolItem::Class(Rc::new(ClassInfo {
+                    tag: 7,
+                    name_index: name_index,
+                }))
+            }
+            Constant::String(string_index) => {
+                ConstantPoolItem::String(Rc::new(StringInfo {
+                    tag: 8,
+                    string_index: string_index,
+                }))
+            }
+            Constant::Field(class_index, name_and_type_index) => {
+                ConstantPoolItem::Field(member_info(9, class_index, name_and_type_index))
+            }
+            Constant::Method(class_index, name_and_type_index) => {
+                ConstantPoolItem::Method(member_info(10, class_index, name_and_type_index))
+            }
+            Constant::InterfaceMethod(class_index, name_and_type_index) => {
+                ConstantPoolItem::InterfaceMethod(member_info(11, class_index, name_and_type_index))
+            }
+            Constant::NameAndType(name_index, descriptor_index) => {
+                ConstantPoolItem::NameAndType(Rc::new(NameAndTypeInfo {
+                    tag: 12,
+                    name_index: name_index,
+                    descriptor_index: descriptor_index,
+                }))
+            }
+            Constant::MethodHandle(reference_kind, reference_index) => {
+                ConstantPoolItem::MethodHandle {
+                    tag: 15,
+                    reference_kind: reference_kind,
+                    reference_index: reference_index,
+                }
+            }
+            Constant::MethodType(descriptor_index) => {
+                ConstantPoolItem::MethodType {
+                    tag: 16,
+                    descriptor_index: descriptor_index,
+                }
+            }
+            Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index) => {
+                ConstantPoolItem::InvokeDynamic {
+                    tag: 18,
+                    bootstrap_method_attr_index: bootstrap_method_attr_index,
+                    name_and_type_index: name_and_type_index,
+                }
+            }
+        }
+    }
+
+    pub fn to_friendly_name(&self) -> &'static str {
+        match *self {
+            Constant::Utf8(..) => "Utf8",
+            Constant::Integer(..) => "Integer",
+            Constant::Float(..) => "Float",
+            Constant::Long(..) => "Long",
+            Constant::Double(..) => "Double",
+            Constant::Class(..) => "Class",
+            Constant::String(..) => "String",
+            Constant::Field(..) => "Field",
+            Constant::Method(..) => "Method",
+            Constant::InterfaceMethod(..) => "InterfaceMethod",
+            Constant::NameAndType(..) => "NameAndType",
+            Constant::MethodHandle(..) => "MethodHandle",
+            Constant::MethodType(..) => "MethodType",
+            Constant::InvokeDynamic(..) => "InvokeDynamic",
+        }
+    }
+
+    /// The constant pool indexes this entry refers to.
+    pub fn references(&self) -> Vec<U2> {
+        match *self {
+            Constant::Class(index) |
+            Constant::String(index) |
+            Constant::MethodType(index) |
+            Constant::MethodHandle(_, index) |
+            Constant::InvokeDynamic(_, index) => vec![index],
+            Constant::Field(first, second) |
+            Constant::Method(first, second) |
+            Constant::InterfaceMethod(first, second) |
+            Constant::NameAndType(first, second) => vec![first, second],
+            _ => vec![],
+        }
+    }
+
+    /// Whether the entry takes up two slots, as a long or double does.
+    pub fn is_wide(&self) -> bool {
+        match *self {
+            Constant::Long(..) |
+            Constant::Double(..) => true,
+            _ => false,
+        }
+    }
+
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        match *self {
+            Constant::Utf8(ref value) => write_utf8(writer, &mutf8::encode(value)),
+            Constant::Integer(bytes) => {
+                writer.write_u1(3);
+                writer.write_u4(bytes);
+            }
+            Constant::Float(bytes) => {
+                writer.write_u1(4);
+                writer.write_u4(bytes);
+            }
+            Constant::Long(bytes) => {
+                writer.write_u1(5);
+                writer.write_u4((bytes >> 32) as U4);
+                writer.write_u4(bytes as U4);
+            }
+            Constant::Double(bytes) => {
+                writer.write_u1(6);
+                writer.write_u4((bytes >> 32) as U4);
+                writer.write_u4(bytes as U4);
+            }
+            Constant::Class(index) => {
+                writer.write_u1(7);
+                writer.write_u2(index);
+            }
+            Constant::String(index) => {
+                writer.write_u1(8);
+                writer.write_u2(index);
+            }
+            Constant::Field(class_index, name_and_type_index) => {
+                writer.write_u1(9);
+                writer.write_u2(class_index);
+                writer.write_u2(name_and_type_index);
+            }
+            Constant::Method(class_index, name_and_type_index) => {
+                writer.write_u1(10);
+                writer.write_u2(class_index);
+                writer.write_u2(name_and_type_index);
+            }
+            Constant::InterfaceMethod(class_index, name_and_type_index) => {
+                writer.write_u1(11);
+                writer.write_u2(class_index);
+                writer.write_u2(name_and_type_index);
+            }
+            Constant::NameAndType(name_index, descriptor_index) => {
+                writer.write_u1(12);
+                writer.write_u2(name_index);
+                writer.write_u2(descriptor_index);
+            }
+            Constant::MethodHandle(reference_kind, reference_index) => {
+                writer.write_u1(15);
+                writer.write_u1(reference_kind);
+                writer.write_u2(reference_index);
+            }
+            Constant::MethodType(descriptor_index) => {
+                writer.write_u1(16);
+                writer.write_u2(descriptor_index);
+            }
+            Constant::InvokeDynamic(bootstrap_method_attr_index, name_and_type_index) => {
+                writer.write_u1(18);
+                writer.write_u2(bootstrap_method_attr_index);
+                writer.write_u2(name_and_type_index);
+            }
+        }
+    }
+}
+
+fn write_utf8<W: PrimitiveWriter>(writer: &mut W, bytes: &[U1]) {
+    writer.write_u1(1);
+    writer.write_u2(bytes.len() as U2);
+    writer.write_bytes(bytes);
+}
+
+fn member_info(tag: U1,
+               class_index: U2,
+               name_and_type_index: U2)
+               -> Rc<FieldOrMethodOrInterfaceMethodInfo> {
+    Rc::new(FieldOrMethodOrInterfaceMethodInfo {
+        tag: tag,
+        class_index: class_index,
+        name_and_type_index: name_and_type_index,
+    })
+}
+
+/// A constant pool that entries are added to as they are needed, where adding a value the pool
+/// already has gives back the index of the first entry with it.
+#[derive(Clone, Debug, Default)]
+pub struct ConstantPool {
+    entries: Vec<Option<Constant>>,
+    indexes: HashMap<Constant, U2>,
+    /// The bytes of the `Utf8` entries whose value doesn't encode back to what was read.
+    utf8_bytes: HashMap<U2, Vec<U1>>,
+}
+
+impl ConstantPool {
+    pub fn new() -> ConstantPool {
+        ConstantPool::default()
+    }
+
+    /// A pool holding the entries of a parsed constant pool at their original indexes, which
+    /// new entries are added after.
+    pub fn from_items(items: &Vec<ConstantPoolItem>) -> ConstantPool {
+        let mut constant_pool = ConstantPool::new();
+        for item in items {
+            let index = constant_pool.next_index() as U2;
+            let constant = Constant::from_item(item);
+            if let ConstantPoolItem::Utf8(ref info) = *item {
+                if let Some(ref bytes) = info.bytes {
+                    constant_pool.utf8_bytes.insert(index, bytes.clone());
+                }
+            }
+            // an entry whose value only stands in for its bytes isn't handed out for the value
+            if let Some(ref constant) = constant {
+                if !constant_pool.utf8_bytes.contains_key(&index) {
+                    constant_pool.indexes.entry(constant.clone()).or_insert(index);
+                }
+            }
+            constant_pool.entries.push(constant);
+        }
+
+        constant_pool
+    }
+
+    /// The index the next entry is added at, which is also the `constant_pool_count` of a class
+    /// file holding this pool.
+    pub fn next_index(&self) -> usize {
+        self.entries.len() + 1
+    }
+
+    pub fn get(&self, index: U2) -> Option<&Constant> {
+        match index {
+            0 => None,
+            _ => self.entries.get(index as usize - 1).and_then(Option::as_ref),
+        }
+    }
+
+    /// Appends `constant`, even if an equal entry already exists.
+    pub fn push(&mut self, constant: Constant) -> ParserResult<U2> {
+        let index = self.next_index();
+        let slots = if constant.is_wide() { 2 } else { 1 };
+        if index + slots > U2::max_value() as usize {
+            return Err(ParserError::ConstantPoolTooLarge(index + slots));
+        }
+
+        self.indexes.entry(constant.clone()).or_insert(index as U2);
+        self.entries.push(Some(constant));
+        if slots == 2 {
+            self.entries.push(None);
+        }
+
+        Ok(index as U2)
+    }
+
+    /// Returns the index of the first entry equal to `constant`, adding it if there is none.
+    pub fn intern(&mut self, constant: Constant) -> ParserResult<U2> {
+        if let Some(index) = self.indexes.get(&constant) {
+            return Ok(*index);
+        }
+
+        self.push(constant)
+    }
+
+    pub fn utf8(&mut self, value: &str) -> ParserResult<U2> {
+        let length = mutf8::encoded_len(value);
+        if length > U2::max_value() as usize {
+            return Err(ParserError::StringTooLong(length));
+        }
+
+        self.intern(Constant::Utf8(value.to_string()))
+    }
+
+    /// The index of a `Utf8` entry holding `value`, which is `index` as long as the entry there
+    /// still holds it, so that a name keeps the entry it was read from.
+    pub fn utf8_at(&mut self, index: U2, value: &str) -> ParserResult<U2> {
+        match self.get(index) {
+            Some(&Constant::Utf8(ref existing)) if existing == value => return Ok(index),
+            _ => {}
+        }
+
+        self.utf8(value)
+    }
+
+    pub fn class(&mut self, name: &str) -> ParserResult<U2> {
+        let name_index = try!(self.utf8(name));
+        self.intern(Constant::Class(name_index))
+    }
+
+    pub fn string(&mut self, value: &str) -> ParserResult<U2> {
+        let string_index = try!(self.utf8(value));
+        self.intern(Constant::String(string_index))
+    }
+
+    pub fn name_and_type(&mut self, name: &str, descriptor: &str) -> ParserResult<U2> {
+        let name_index = try!(self.utf8(name));
+        let descriptor_index = try!(self.utf8(descriptor));
+        self.intern(Constant::NameAndType(name_index, descriptor_index))
+    }
+
+    pub fn field(&mut self, owner: &str, name: &str, descriptor: &str) -> ParserResult<U2> {
+        let class_index = try!(self.class(owner));
+        let name_and_type_index = try!(self.name_and_type(name, descriptor));
+        self.intern(Constant::Field(class_index, name_and_type_index))
+    }
+
+    pub fn method(&mut self, owner: &str, name: &str, descriptor: &str) -> ParserResult<U2> {
+        let class_index = try!(self.class(owner));
+        let name_and_type_index = try!(self.name_and_type(name, descriptor));
+        self.intern(Constant::Method(class_index, name_and_type_index))
+    }
+
+    pub fn interface_method(&mut self,
+                            owner: &str,
+                            name: &str,
+                            descriptor: &str)
+                            -> ParserResult<U2> {
+        let class_index = try!(self.class(owner));
+        let name_and_type_index = try!(self.name_and_type(name, descriptor));
+        self.intern(Constant::InterfaceMethod(class_index, name_and_type_index))
+    }
+
+    /// The value of the `Utf8` entry at `index`.
+    pub fn utf8_value(&self, index: U2) -> ParserResult<&str> {
+        match self.get(index) {
+            Some(&Constant::Utf8(ref value)) => Ok(value),
+            Some(constant) => {
+                Err(ParserError::UnexpectedConstantPoolItem(constant.to_friendly_name()))
+            }
+            None => Err(ParserError::ConstantPoolIndexOutOfBounds(index as usize)),
+        }
+    }
+
+    /// The name of the class the `Class` entry at `index` stands for.
+    pub fn class_name(&self, index: U2) -> ParserResult<&str> {
+        match self.get(index) {
+            Some(&Constant::Class(name_index)) => self.utf8_value(name_index),
+            Some(constant) => {
+                Err(ParserError::UnexpectedConstantPoolItem(constant.to_friendly_name()))
+            }
+            None => Err(ParserError::ConstantPoolIndexOutOfBounds(index as usize)),
+        }
+    }
+
+    /// The entries the way a parsed constant pool holds them, with an `Empty` item in the slot
+    /// after each `Long` or `Double`.
+    pub fn to_items(&self) -> Vec<ConstantPoolItem> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| match (entry.as_ref(), self.utf8_bytes.get(&(i as U2 + 1))) {
+                (Some(&Constant::Utf8(ref value)), Some(bytes)) => {
+                    ConstantPoolItem::Utf8(Rc::new(Utf8Info {
+                        tag: 1,
+                        length: bytes.len() as U2,
+                        value: value.clone(),
+                        bytes: Some(bytes.clone()),
+                    }))
+                }
+                (Some(constant), _) => constant.to_item(),
+                (None, _) => ConstantPoolItem::Empty,
+            })
+            .collect()
+    }
+
+    /// Writes the pool as a class file holds it, headed by its count. `Utf8` entries read from
+    /// bytes their value doesn't encode back to are written as those bytes.
+    pub fn write<W: PrimitiveWriter>(&self, writer: &mut W) {
+        writer.write_u2(self.next_index() as U2);
+        for (i, entry) in self.entries.iter().enumerate() {
+            match (entry.as_ref(), self.utf8_bytes.get(&(i as U2 + 1))) {
+                (Some(_), Some(bytes)) => write_utf8(writer, bytes),
+                (Some(constant), None) => constant.write(writer),
+                (None, _) => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{Constant, ConstantPool};
+    use super::super::ParserError;
+    use super::super::components::{ConstantPoolItem, Utf8Info};
+    use super::super::primitives::U2;
+
+    use std::rc::Rc;
+
+    #[test]
+    fn interning_reuses_equal_entries() {
+        let mut constant_pool = ConstantPool::new();
+        let method = constant_pool.method("java/io/PrintStream", "println", "(I)V").unwrap();
+        let out = "Ljava/io/PrintStream;";
+        let field = constant_pool.field("java/lang/System", "out", out).unwrap();
+
+        assert_that(&constant_pool.class("java/io/PrintStream").unwrap()).is_equal_to(&2);
+        assert_that(&constant_pool.method("java/io/PrintStream", "println", "(I)V").unwrap())
+            .is_equal_to(&method);
+        assert_that(&constant_pool.field("java/lang/System", "out", out).unwrap())
+            .is_equal_to(&field);
+        assert_that(&constant_pool.class_name(2).unwrap()).is_equal_to(&"java/io/PrintStream");
+        assert_that(&constant_pool.next_index()).is_equal_to(&13);
+    }
+
+    #[test]
+    fn wide_entries_take_two_indexes() {
+        let mut constant_pool = ConstantPool::new();
+
+        assert_that(&constant_pool.push(Constant::Long(1)).unwrap()).is_equal_to(&1);
+        assert_that(&constant_pool.utf8("after").unwrap()).is_equal_to(&3);
+        assert_that(&constant_pool.get(2)).is_none();
+        assert_that(&constant_pool.utf8_value(1)).matches(|result| match *result {
+            Err(ParserError::UnexpectedConstantPoolItem("Long")) => true,
+            _ => false,
+        });
+        assert_that(&constant_pool.utf8_value(4)).matches(|result| match *result {
+            Err(ParserError::ConstantPoolIndexOutOfBounds(4)) => true,
+            _ => false,
+        });
+    }
+
+    #[test]
+    fn parsed_entries_keep_their_indexes() {
+        let utf8 = |value: &str| {
+            ConstantPoolItem::Utf8(Rc::new(Utf8Info {
+                tag: 1,
+                length: value.len() as U2,
+                value: value.to_string(),
+                bytes: None,
+            }))
+        };
+        let items = vec![utf8("a"), utf8("b"), utf8("a")];
+        let mut constant_pool = ConstantPool::from_items(&items);
+
+        assert_that(&constant_pool.utf8_value(3).unwrap()).is_equal_to(&"a");
+        assert_that(&constant_pool.utf8("a").unwrap()).is_equal_to(&1);
+        assert_that(&constant_pool.utf8("c").unwrap()).is_equal_to(&4);
+
+        let mut bytes = vec![];
+        constant_pool.write(&mut bytes);
+        assert_that(&bytes).is_equal_to(&vec![0, 5, 1, 0, 1, 97, 1, 0, 1, 98, 1, 0, 1, 97, 1,
+                                              0, 1, 99]);
+    }
+}
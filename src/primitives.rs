@@ -1,14 +1,19 @@
 use std::io::{Bytes, Error as IoError, ErrorKind as IoErrorKind, Read, Result as IoResult};
+use std::cmp;
 use std::iter::Iterator;
 
 pub type U1 = u8;
 pub type U2 = u16;
 pub type U4 = u32;
 
-pub trait PrimitiveIterator: Iterator<Item = IoResult<u8>> {
-    fn next_u1(&mut self) -> IoResult<U1> {
-        self.next().as_result_or(new_eof_error())
-    }
+/// The most `next_bytes` reserves up front, since the length it is given comes from the input and
+/// may be far larger than what is left of it.
+const MAX_RESERVED_BYTES: usize = 64 * 1024;
+
+/// A source of the big-endian primitives a class file is made of. `ByteCursor` reads them
+/// straight out of a buffer, while any other source only has to supply single bytes.
+pub trait PrimitiveIterator {
+    fn next_u1(&mut self) -> IoResult<U1>;
 
     fn next_u2(&mut self) -> IoResult<U2> {
         let first = try!(self.next_u1()) as U2;
@@ -23,9 +28,112 @@ pub trait PrimitiveIterator: Iterator<Item = IoResult<u8>> {
 
         Ok((first << 16) + (second << 0))
     }
+
+    /// Reads the next `length` bytes, such as the body of an attribute.
+    fn next_bytes(&mut self, length: usize) -> IoResult<Vec<U1>> {
+        let mut bytes = Vec::with_capacity(cmp::min(length, MAX_RESERVED_BYTES));
+        for _ in 0..length {
+            bytes.push(try!(self.next_u1()));
+        }
+
+        Ok(bytes)
+    }
+}
+
+impl<R: Read> PrimitiveIterator for Bytes<R> {
+    fn next_u1(&mut self) -> IoResult<U1> {
+        self.next().as_result_or(new_eof_error())
+    }
+}
+
+/// Reads primitives out of a byte buffer, copying runs of bytes in one go.
+#[derive(Clone, Debug)]
+pub struct ByteCursor<'a> {
+    bytes: &'a [U1],
+    position: usize,
+}
+
+impl<'a> ByteCursor<'a> {
+    pub fn new(bytes: &'a [U1]) -> ByteCursor<'a> {
+        ByteCursor {
+            bytes: bytes,
+            position: 0,
+        }
+    }
+
+    /// The offset of the next byte to be read.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// The number of bytes left to read.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len() - self.position
+    }
+
+    /// Takes the next `length` bytes without copying them.
+    pub fn next_slice(&mut self, length: usize) -> IoResult<&'a [U1]> {
+        if length > self.remaining() {
+            return Err(new_eof_error());
+        }
+
+        let slice = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(slice)
+    }
 }
 
-impl<R: Read> PrimitiveIterator for Bytes<R> {}
+impl<'a> PrimitiveIterator for ByteCursor<'a> {
+    fn next_u1(&mut self) -> IoResult<U1> {
+        let bytes = try!(self.next_slice(1));
+        Ok(bytes[0])
+    }
+
+    fn next_u2(&mut self) -> IoResult<U2> {
+        let bytes = try!(self.next_slice(2));
+        Ok(((bytes[0] as U2) << 8) | bytes[1] as U2)
+    }
+
+    fn next_u4(&mut self) -> IoResult<U4> {
+        let bytes = try!(self.next_slice(4));
+        Ok(((bytes[0] as U4) << 24) | ((bytes[1] as U4) << 16) | ((bytes[2] as U4) << 8) |
+           bytes[3] as U4)
+    }
+
+    fn next_bytes(&mut self, length: usize) -> IoResult<Vec<U1>> {
+        self.next_slice(length).map(|bytes| bytes.to_vec())
+    }
+}
+
+pub trait PrimitiveWriter {
+    fn write_u1(&mut self, value: U1);
+
+    fn write_u2(&mut self, value: U2) {
+        self.write_u1((value >> 8) as U1);
+        self.write_u1((value >> 0) as U1);
+    }
+
+    fn write_u4(&mut self, value: U4) {
+        self.write_u2((value >> 16) as U2);
+        self.write_u2((value >> 0) as U2);
+    }
+
+    fn write_bytes(&mut self, bytes: &[U1]) {
+        for byte in bytes {
+            self.write_u1(*byte);
+        }
+    }
+}
+
+impl PrimitiveWriter for Vec<U1> {
+    fn write_u1(&mut self, value: U1) {
+        self.push(value);
+    }
+
+    fn write_bytes(&mut self, bytes: &[U1]) {
+        self.extend_from_slice(bytes);
+    }
+}
 
 fn new_eof_error() -> IoError {
     IoError::new(IoErrorKind::UnexpectedEof,
@@ -41,3 +149,42 @@ impl<T, E> FromOptionResult<T, E> for Option<Result<T, E>> {
         self.unwrap_or(Err(error))
     }
 }
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ByteCursor, PrimitiveIterator};
+
+    use std::io::{ErrorKind, Read};
+
+    #[test]
+    fn can_read_primitives_from_a_buffer() {
+        let bytes = [0xCA, 0xFE, 0xBA, 0xBE, 0x00, 0x34, 0x07, 0x01, 0x02, 0x03];
+        let mut cursor = ByteCursor::new(&bytes);
+
+        assert_that(&cursor.next_u4().unwrap()).is_equal_to(&0xCAFEBABE);
+        assert_that(&cursor.next_u2().unwrap()).is_equal_to(&0x0034);
+        assert_that(&cursor.next_u1().unwrap()).is_equal_to(&0x07);
+        assert_that(&cursor.position()).is_equal_to(&7);
+        assert_that(&cursor.next_bytes(2).unwrap()).is_equal_to(&vec![0x01, 0x02]);
+
+        assert_that(&cursor.next_u2().is_err()).is_equal_to(&true);
+        assert_that(&cursor.remaining()).is_equal_to(&1);
+        assert_that(&cursor.next_slice(1).unwrap()).is_equal_to(&&[0x03][..]);
+    }
+
+    #[test]
+    fn fails_on_lengths_past_the_end_of_a_stream() {
+        let bytes = [0x01, 0x02, 0x03];
+        let mut stream = (&bytes[..]).bytes();
+
+        let result = stream.next_bytes(u32::max_value() as usize);
+
+        assert_that(&result.unwrap_err().kind()).is_equal_to(&ErrorKind::UnexpectedEof);
+    }
+
+}
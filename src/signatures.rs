@@ -0,0 +1,620 @@
+use std::rc::Rc;
+
+use super::{ClassFile, ParserError, ParserResult};
+use super::components::{Attribute, ConstantPoolItem, Field, Method};
+use super::descriptors::{BaseType, MAX_DIMENSIONS};
+
+/// A type in a generic signature, which may refer to type variables and carry type arguments.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TypeSignature {
+    Base(BaseType),
+    Class(ClassTypeSignature),
+    /// A type variable, by its name, such as `T`.
+    TypeVariable(String),
+    /// An array type, by the type of its components.
+    Array(Box<TypeSignature>),
+}
+
+/// A class or interface type along with the type arguments of it and of the classes it is nested
+/// in, such as `java/util/Map<TK;TV;>` or `Outer<TT;>.Inner`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ClassTypeSignature {
+    /// The package in internal form, such as `java/util`, which is empty for the unnamed package.
+    pub package: String,
+    /// The outermost class that is given type arguments, followed by the classes nested in it.
+    pub classes: Vec<SimpleClassTypeSignature>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct SimpleClassTypeSignature {
+    pub name: String,
+    pub type_arguments: Vec<TypeArgument>,
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TypeArgument {
+    /// The unbounded wildcard `?`.
+    Any,
+    Exact(TypeSignature),
+    /// A wildcard with an upper bound, `? extends T`.
+    Extends(TypeSignature),
+    /// A wildcard with a lower bound, `? super T`.
+    Super(TypeSignature),
+}
+
+/// A type parameter of a generic class or method, such as `T extends Comparable<T>`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct TypeParameter {
+    pub name: String,
+    /// The bound that is a class or a type variable, which may be left out when there are
+    /// interface bounds.
+    pub class_bound: Option<TypeSignature>,
+    pub interface_bounds: Vec<TypeSignature>,
+}
+
+/// The generic signature of a class, given by its `Signature` attribute.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ClassSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub superclass: ClassTypeSignature,
+    pub interfaces: Vec<ClassTypeSignature>,
+}
+
+/// The generic signature of a method, given by its `Signature` attribute.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct MethodSignature {
+    pub type_parameters: Vec<TypeParameter>,
+    pub params: Vec<TypeSignature>,
+    /// The return type, which is `None` for `void`.
+    pub return_type: Option<TypeSignature>,
+    /// The exception types of the `throws` clause, which are class types or type variables.
+    pub throws: Vec<TypeSignature>,
+}
+
+impl TypeSignature {
+    /// Parses a type signature. The signature of a field is a type signature that is not a base
+    /// type.
+    pub fn from(signature: &str) -> ParserResult<TypeSignature> {
+        let mut parser = SignatureParser::new(signature);
+        let type_signature = try!(parser.type_signature());
+        try!(parser.finish());
+
+        Ok(type_signature)
+    }
+
+    /// The type as it is written in Java source, such as `java.util.List<? extends T>[]`.
+    pub fn java_name(&self) -> String {
+        match *self {
+            TypeSignature::Base(base_type) => base_type.java_name().to_string(),
+            TypeSignature::Class(ref class_type) => class_type.java_name(),
+            TypeSignature::TypeVariable(ref name) => name.clone(),
+            TypeSignature::Array(ref component) => format!("{}[]", component.java_name()),
+        }
+    }
+}
+
+impl ClassTypeSignature {
+    /// The name of the class in internal form, such as `java/util/Map$Entry`, which is what is
+    /// left of the type once its type arguments are erased.
+    pub fn class_name(&self) -> String {
+        let names: Vec<_> = self.classes.iter().map(|class| class.name.as_str()).collect();
+        let name = names.join("$");
+
+        if self.package.is_empty() {
+            name
+        } else {
+            format!("{}/{}", self.package, name)
+        }
+    }
+
+    pub fn java_name(&self) -> String {
+        let mut java_name = String::new();
+        if !self.package.is_empty() {
+            java_name.push_str(&self.package.replace('/', "."));
+            java_name.push('.');
+        }
+
+        for (i, class) in self.classes.iter().enumerate() {
+            if i > 0 {
+                java_name.push('.');
+            }
+            java_name.push_str(&class.name);
+            if !class.type_arguments.is_empty() {
+                let arguments: Vec<_> =
+                    class.type_arguments.iter().map(TypeArgument::java_name).collect();
+                java_name.push('<');
+                java_name.push_str(&arguments.join(", "));
+                java_name.push('>');
+            }
+        }
+
+        java_name
+    }
+}
+
+impl TypeArgument {
+    pub fn java_name(&self) -> String {
+        match *self {
+            TypeArgument::Any => "?".to_string(),
+            TypeArgument::Exact(ref bound) => bound.java_name(),
+            TypeArgument::Extends(ref bound) => format!("? extends {}", bound.java_name()),
+            TypeArgument::Super(ref bound) => format!("? super {}", bound.java_name()),
+        }
+    }
+}
+
+impl TypeParameter {
+    /// The type parameter as it is declared in Java source. Every bound is written out, so a
+    /// parameter bounded by `java/lang/Object` alone reads `T extends java.lang.Object`.
+    pub fn java_declaration(&self) -> String {
+        let bounds: Vec<_> = self.class_bound
+            .iter()
+            .chain(self.interface_bounds.iter())
+            .map(TypeSignature::java_name)
+            .collect();
+
+        if bounds.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{} extends {}", self.name, bounds.join(" & "))
+        }
+    }
+}
+
+impl ClassSignature {
+    pub fn from(signature: &str) -> ParserResult<ClassSignature> {
+        let mut parser = SignatureParser::new(signature);
+        let type_parameters = try!(parser.type_parameters());
+        let superclass = try!(parser.class_type_signature());
+        let mut interfaces = vec![];
+        while !parser.at_end() {
+            interfaces.push(try!(parser.class_type_signature()));
+        }
+
+        Ok(ClassSignature {
+            type_parameters: type_parameters,
+            superclass: superclass,
+            interfaces: interfaces,
+        })
+    }
+
+    /// The type parameters as they are declared in Java source, such as `<K, V>`, or nothing if
+    /// the class is not generic.
+    pub fn java_type_parameters(&self) -> String {
+        java_type_parameters(&self.type_parameters)
+    }
+}
+
+impl MethodSignature {
+    pub fn from(signature: &str) -> ParserResult<MethodSignature> {
+        let mut parser = SignatureParser::new(signature);
+        let type_parameters = try!(parser.type_parameters());
+
+        try!(parser.expect('('));
+        let mut params = vec![];
+        while !parser.next_is(')') {
+            params.push(try!(parser.type_signature()));
+        }
+        try!(parser.expect(')'));
+
+        let return_type = if parser.next_is('V') {
+            try!(parser.expect('V'));
+            None
+        } else {
+            Some(try!(parser.type_signature()))
+        };
+
+        let mut throws = vec![];
+        while !parser.at_end() {
+            try!(parser.expect('^'));
+            throws.push(try!(parser.reference_type_signature()));
+            match throws.last() {
+                Some(&TypeSignature::Array(..)) => return Err(parser.invalid()),
+                _ => {}
+            }
+        }
+
+        Ok(MethodSignature {
+            type_parameters: type_parameters,
+            params: params,
+            return_type: return_type,
+            throws: throws,
+        })
+    }
+
+    /// The type parameters as they are declared in Java source, such as `<T>`, or nothing if the
+    /// method is not generic.
+    pub fn java_type_parameters(&self) -> String {
+        java_type_parameters(&self.type_parameters)
+    }
+
+    /// The parameter types as they are written in Java source.
+    pub fn java_params(&self) -> Vec<String> {
+        self.params.iter().map(TypeSignature::java_name).collect()
+    }
+
+    /// The return type as it is written in Java source, which is `void` if there is none.
+    pub fn java_return_type(&self) -> String {
+        match self.return_type {
+            Some(ref return_type) => return_type.java_name(),
+            None => "void".to_string(),
+        }
+    }
+
+    pub fn java_throws(&self) -> Vec<String> {
+        self.throws.iter().map(TypeSignature::java_name).collect()
+    }
+}
+
+/// Parses the `Signature` attribute of `classfile`, if it has one.
+pub fn class_signature(classfile: &ClassFile) -> ParserResult<Option<ClassSignature>> {
+    match try!(signature(classfile, &classfile.attributes)) {
+        Some(signature) => Ok(Some(try!(ClassSignature::from(&signature)))),
+        None => Ok(None),
+    }
+}
+
+/// Parses the `Signature` attribute of `method`, if it has one.
+pub fn method_signature(classfile: &ClassFile,
+                        method: &Method)
+                        -> ParserResult<Option<MethodSignature>> {
+    match try!(signature(classfile, &method.attributes)) {
+        Some(signature) => Ok(Some(try!(MethodSignature::from(&signature)))),
+        None => Ok(None),
+    }
+}
+
+/// Parses the `Signature` attribute of `field`, if it has one.
+pub fn field_signature(classfile: &ClassFile,
+                       field: &Field)
+                       -> ParserResult<Option<TypeSignature>> {
+    match try!(signature(classfile, &field.attributes)) {
+        Some(signature) => Ok(Some(try!(TypeSignature::from(&signature)))),
+        None => Ok(None),
+    }
+}
+
+fn signature(classfile: &ClassFile,
+             attributes: &[Rc<Attribute>])
+             -> ParserResult<Option<String>> {
+    for attribute in attributes {
+        let (attribute_name, info) = match **attribute {
+            Attribute::Unknown { ref attribute_name, ref info } => (attribute_name, &info[..]),
+            Attribute::Lazy(ref lazy) => (&lazy.attribute_name, lazy.info()),
+            _ => continue,
+        };
+
+        if attribute_name.as_str() == "Signature" {
+            if info.len() < 2 {
+                return Err(ParserError::TruncatedAttribute(info.len()));
+            }
+
+            let index = (info[0] as u16) << 8 | info[1] as u16;
+            let signature = try!(ConstantPoolItem::retrieve_utf8_info(index,
+                                                                      &classfile.constant_pool));
+            return Ok(Some(signature.as_str().to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+fn java_type_parameters(type_parameters: &[TypeParameter]) -> String {
+    if type_parameters.is_empty() {
+        return String::new();
+    }
+
+    let declarations: Vec<_> =
+        type_parameters.iter().map(TypeParameter::java_declaration).collect();
+    format!("<{}>", declarations.join(", "))
+}
+
+struct SignatureParser<'s> {
+    signature: &'s str,
+    position: usize,
+}
+
+impl<'s> SignatureParser<'s> {
+    fn new(signature: &'s str) -> SignatureParser<'s> {
+        SignatureParser {
+            signature: signature,
+            position: 0,
+        }
+    }
+
+    fn invalid(&self) -> ParserError {
+        ParserError::InvalidSignature(self.signature.to_string())
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.signature[self.position..].chars().next()
+    }
+
+    fn next_is(&self, expected: char) -> bool {
+        self.peek() == Some(expected)
+    }
+
+    fn at_end(&self) -> bool {
+        self.position == self.signature.len()
+    }
+
+    fn finish(&self) -> ParserResult<()> {
+        if self.at_end() { Ok(()) } else { Err(self.invalid()) }
+    }
+
+    fn expect(&mut self, expected: char) -> ParserResult<()> {
+        if self.next_is(expected) {
+            self.position += expected.len_utf8();
+            Ok(())
+        } else {
+            Err(self.invalid())
+        }
+    }
+
+    /// Reads a name up to the next character that cannot be part of one.
+    fn identifier(&mut self) -> ParserResult<String> {
+        let rest = &self.signature[self.position..];
+        let length = rest.find(|c| ".;[/<>:".contains(c)).unwrap_or(rest.len());
+        if length == 0 {
+            return Err(self.invalid());
+        }
+
+        self.position += length;
+        Ok(rest[..length].to_string())
+    }
+
+    fn type_parameters(&mut self) -> ParserResult<Vec<TypeParameter>> {
+        let mut type_parameters = vec![];
+        if !self.next_is('<') {
+            return Ok(type_parameters);
+        }
+
+        try!(self.expect('<'));
+        loop {
+            let name = try!(self.identifier());
+            try!(self.expect(':'));
+            let class_bound = match self.peek() {
+                Some(':') | Some('>') => None,
+                _ => Some(try!(self.reference_type_signature())),
+            };
+            let mut interface_bounds = vec![];
+            while self.next_is(':') {
+                try!(self.expect(':'));
+                interface_bounds.push(try!(self.reference_type_signature()));
+            }
+
+            type_parameters.push(TypeParameter {
+                name: name,
+                class_bound: class_bound,
+                interface_bounds: interface_bounds,
+            });
+            if self.next_is('>') {
+                break;
+            }
+        }
+        try!(self.expect('>'));
+
+        Ok(type_parameters)
+    }
+
+    fn type_signature(&mut self) -> ParserResult<TypeSignature> {
+        let base_type = self.peek().and_then(BaseType::from);
+        match base_type {
+            Some(base_type) => {
+                self.position += 1;
+                Ok(TypeSignature::Base(base_type))
+            }
+            None => self.reference_type_signature(),
+        }
+    }
+
+    fn reference_type_signature(&mut self) -> ParserResult<TypeSignature> {
+        match self.peek() {
+            Some('L') => Ok(TypeSignature::Class(try!(self.class_type_signature()))),
+            Some('T') => {
+                try!(self.expect('T'));
+                let name = try!(self.identifier());
+                try!(self.expect(';'));
+                Ok(TypeSignature::TypeVariable(name))
+            }
+            Some('[') => {
+                let mut dimensions = 0;
+                while self.next_is('[') {
+                    try!(self.expect('['));
+                    dimensions += 1;
+                }
+                if dimensions > MAX_DIMENSIONS {
+                    return Err(self.invalid());
+                }
+
+                let mut signature = try!(self.type_signature());
+                for _ in 0..dimensions {
+                    signature = TypeSignature::Array(Box::new(signature));
+                }
+                Ok(signature)
+            }
+            _ => Err(self.invalid()),
+        }
+    }
+
+    fn class_type_signature(&mut self) -> ParserResult<ClassTypeSignature> {
+        try!(self.expect('L'));
+
+        let mut package = String::new();
+        let mut name = try!(self.identifier());
+        while self.next_is('/') {
+            try!(self.expect('/'));
+            if !package.is_empty() {
+                package.push('/');
+            }
+            package.push_str(&name);
+            name = try!(self.identifier());
+        }
+
+        let mut classes = vec![];
+        loop {
+            let type_arguments = try!(self.type_arguments());
+            classes.push(SimpleClassTypeSignature {
+                name: name,
+                type_arguments: type_arguments,
+            });
+
+            if !self.next_is('.') {
+                break;
+            }
+            try!(self.expect('.'));
+            name = try!(self.identifier());
+        }
+        try!(self.expect(';'));
+
+        Ok(ClassTypeSignature {
+            package: package,
+            classes: classes,
+        })
+    }
+
+    fn type_arguments(&mut self) -> ParserResult<Vec<TypeArgument>> {
+        let mut type_arguments = vec![];
+        if !self.next_is('<') {
+            return Ok(type_arguments);
+        }
+
+        try!(self.expect('<'));
+        loop {
+            let type_argument = match self.peek() {
+                Some('*') => {
+                    try!(self.expect('*'));
+                    TypeArgument::Any
+                }
+                Some('+') => {
+                    try!(self.expect('+'));
+                    TypeArgument::Extends(try!(self.reference_type_signature()))
+                }
+                Some('-') => {
+                    try!(self.expect('-'));
+                    TypeArgument::Super(try!(self.reference_type_signature()))
+                }
+                _ => TypeArgument::Exact(try!(self.reference_type_signature())),
+            };
+            type_arguments.push(type_argument);
+
+            if self.next_is('>') {
+                break;
+            }
+        }
+        try!(self.expect('>'));
+
+        Ok(type_arguments)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{ClassSignature, ClassTypeSignature, MethodSignature, SimpleClassTypeSignature,
+                TypeArgument, TypeParameter, TypeSignature};
+    use super::super::descriptors::BaseType;
+
+    fn class_type(package: &str, name: &str, type_arguments: Vec<TypeArgument>) -> TypeSignature {
+        TypeSignature::Class(ClassTypeSignature {
+            package: package.to_string(),
+            classes: vec![SimpleClassTypeSignature {
+                              name: name.to_string(),
+                              type_arguments: type_arguments,
+                          }],
+        })
+    }
+
+    fn type_variable(name: &str) -> TypeSignature {
+        TypeSignature::TypeVariable(name.to_string())
+    }
+
+    #[test]
+    fn can_parse_method_signatures() {
+        let signature = MethodSignature::from("<K::Ljava/lang/Comparable<TK;>;V:Ljava/lang/Object;>\
+                                               (Ljava/util/Map<+TK;Ljava/util/List<TV;>;>;TK;)I")
+            .unwrap();
+
+        assert_that(&signature.type_parameters).is_equal_to(&vec![
+            TypeParameter {
+                name: "K".to_string(),
+                class_bound: None,
+                interface_bounds: vec![class_type("java/lang",
+                                                  "Comparable",
+                                                  vec![TypeArgument::Exact(type_variable("K"))])],
+            },
+            TypeParameter {
+                name: "V".to_string(),
+                class_bound: Some(class_type("java/lang", "Object", vec![])),
+                interface_bounds: vec![],
+            },
+        ]);
+        let list = class_type("java/util", "List", vec![TypeArgument::Exact(type_variable("V"))]);
+        assert_that(&signature.params).is_equal_to(&vec![
+            class_type("java/util",
+                       "Map",
+                       vec![TypeArgument::Extends(type_variable("K")), TypeArgument::Exact(list)]),
+            type_variable("K"),
+        ]);
+        assert_that(&signature.return_type).is_equal_to(&Some(TypeSignature::Base(BaseType::Int)));
+        assert_that(&signature.throws).is_equal_to(&vec![]);
+    }
+
+    #[test]
+    fn can_print_java_source_form() {
+        let signature = MethodSignature::from("<T:Ljava/lang/Object;E:Ljava/lang/Exception;>\
+                                               ([TT;Ljava/util/List<*>;Ljava/util/Set<-TT;>;)V^TE;\
+                                               ^Ljava/io/IOException;")
+            .unwrap();
+
+        assert_that(&signature.java_type_parameters())
+            .is_equal_to(&"<T extends java.lang.Object, E extends java.lang.Exception>"
+                .to_string());
+        assert_that(&signature.java_params()).is_equal_to(&vec![
+            "T[]".to_string(),
+            "java.util.List<?>".to_string(),
+            "java.util.Set<? super T>".to_string(),
+        ]);
+        assert_that(&signature.java_return_type()).is_equal_to(&"void".to_string());
+        assert_that(&signature.java_throws())
+            .is_equal_to(&vec!["E".to_string(), "java.io.IOException".to_string()]);
+    }
+
+    #[test]
+    fn can_parse_class_signatures_with_inner_classes() {
+        let signature = ClassSignature::from("<T::Ljava/lang/Runnable;:Ljava/io/Closeable;>\
+                                              LOuter<TT;>.Inner<Ljava/lang/String;>;\
+                                              Ljava/lang/Iterable<TT;>;")
+            .unwrap();
+
+        assert_that(&signature.java_type_parameters())
+            .is_equal_to(&"<T extends java.lang.Runnable & java.io.Closeable>".to_string());
+        assert_that(&signature.superclass.java_name())
+            .is_equal_to(&"Outer<T>.Inner<java.lang.String>".to_string());
+        assert_that(&signature.superclass.class_name()).is_equal_to(&"Outer$Inner".to_string());
+        assert_that(&signature.interfaces).has_length(1);
+        assert_that(&signature.interfaces[0].java_name())
+            .is_equal_to(&"java.lang.Iterable<T>".to_string());
+    }
+
+    #[test]
+    fn rejects_malformed_signatures() {
+        for invalid in &["", "Ljava/util/List<>;", "Ljava/util/List", "TT", "Q", "I;"] {
+            assert_that(&TypeSignature::from(invalid).is_err()).is_equal_to(&true);
+        }
+        for invalid in &["()", "<>()V", "(I)V^[I", "(I)VV", "<T>()V"] {
+            assert_that(&MethodSignature::from(invalid).is_err()).is_equal_to(&true);
+        }
+        assert_that(&ClassSignature::from("<:TT;>Ljava/lang/Object;").is_err()).is_equal_to(&true);
+
+        let deepest = format!("{}TT;", "[".repeat(255));
+        assert_that(&TypeSignature::from(&deepest).is_ok()).is_equal_to(&true);
+        let too_deep = format!("{}TT;", "[".repeat(256));
+        assert_that(&TypeSignature::from(&too_deep).is_err()).is_equal_to(&true);
+    }
+
+}
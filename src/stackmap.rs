@@ -0,0 +1,286 @@
+use super::{ClassFile, ParserError, ParserResult};
+use super::components::{ClassInfo, CodeAttribute, ConstantPoolItem, Method, StackMapFrame,
+                        StackMapTableAttribute, Utf8Info, VerificationTypeInfo};
+use super::frames::{Frame, Frames, VerificationType};
+use super::hierarchy::ClassHierarchy;
+use super::instructions::Instruction;
+use super::mutf8;
+use super::primitives::U2;
+
+use std::collections::BTreeSet;
+use std::rc::Rc;
+
+/// Computes the `StackMapTable` of `code` as the body of `method`, so that code which has been
+/// rewritten passes the type-checking verifier of class files from version 50 onwards.
+///
+/// There is a frame at every branch target and exception handler, in which reference types
+/// meeting from different paths are merged with `hierarchy`. The `Class` entries the frames refer
+/// to are added to the constant pool of `classfile` when it does not have them yet, and so is the
+/// name of the attribute.
+///
+/// Unreachable code cannot be given a frame that it is known to satisfy, and subroutines cannot
+/// be described by a stack map at all, so both have to be removed from `code` beforehand, the
+/// latter with `subroutines::inline`.
+pub fn compute<H: ClassHierarchy>(classfile: &mut ClassFile,
+                                  method: &Method,
+                                  code: &CodeAttribute,
+                                  hierarchy: &H)
+                                  -> ParserResult<StackMapTableAttribute> {
+    let frames = try!(Frames::simulate(classfile, method, code, hierarchy));
+
+    let mut targets = BTreeSet::new();
+    for result in code.instructions() {
+        let (pc, instruction) = try!(result);
+        match instruction {
+            Instruction::Jsr(..) |
+            Instruction::JsrW(..) |
+            Instruction::Ret(..) => return Err(ParserError::UnsupportedSubroutine(pc as usize)),
+            _ => {}
+        }
+        if frames.at(pc).is_none() {
+            return Err(ParserError::UnreachableCode(pc as usize));
+        }
+
+        for offset in instruction.branch_targets() {
+            targets.insert((pc as i64 + *offset as i64) as U2);
+        }
+    }
+    for handler in &code.exception_table {
+        targets.insert(handler.handler_pc);
+    }
+
+    let mut previous_locals = try!(locals(classfile, frames.entry()));
+    let mut previous_pc = None;
+    let mut entries = vec![];
+    for pc in targets {
+        let frame = try!(frames.at(pc).ok_or(ParserError::InvalidCodeOffset(pc as usize)));
+        let locals = try!(locals(classfile, frame));
+        let mut stack = vec![];
+        for value in &frame.stack {
+            stack.push(try!(verification_type_info(classfile, value)));
+        }
+
+        let offset_delta = match previous_pc {
+            Some(previous_pc) => pc - previous_pc - 1,
+            None => pc,
+        };
+        entries.push(encode(offset_delta, &previous_locals, &locals, stack));
+
+        previous_locals = locals;
+        previous_pc = Some(pc);
+    }
+
+    try!(utf8_index(classfile, "StackMapTable"));
+
+    Ok(StackMapTableAttribute {
+        number_of_entries: entries.len() as U2,
+        entries: entries,
+    })
+}
+
+/// Whether `code` has a branch target or an exception handler, which are the places that need a
+/// frame. Code without any can do without a `StackMapTable`.
+pub fn is_required(code: &CodeAttribute) -> ParserResult<bool> {
+    if !code.exception_table.is_empty() {
+        return Ok(true);
+    }
+    for result in code.instructions() {
+        let (_, instruction) = try!(result);
+        if !instruction.branch_targets().is_empty() {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Picks the most compact frame type that describes `locals` and `stack` relative to the locals
+/// of the previous frame.
+fn encode(offset_delta: U2,
+          previous_locals: &[VerificationTypeInfo],
+          locals: &[VerificationTypeInfo],
+          mut stack: Vec<VerificationTypeInfo>)
+          -> StackMapFrame {
+    let same_locals = locals == previous_locals;
+
+    if same_locals && stack.is_empty() {
+        if offset_delta < 64 {
+            StackMapFrame::Same { frame_type: offset_delta as u8 }
+        } else {
+            StackMapFrame::SameExtended { offset_delta: offset_delta }
+        }
+    } else if same_locals && stack.len() == 1 {
+        if offset_delta < 64 {
+            StackMapFrame::SameLocals1StackItem {
+                frame_type: 64 + offset_delta as u8,
+                stack: stack.remove(0),
+            }
+        } else {
+            StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: offset_delta,
+                stack: stack.remove(0),
+            }
+        }
+    } else if stack.is_empty() && locals.len() < previous_locals.len() &&
+              previous_locals.len() - locals.len() <= 3 &&
+              previous_locals.starts_with(locals) {
+        StackMapFrame::Chop {
+            frame_type: (251 - (previous_locals.len() - locals.len())) as u8,
+            offset_delta: offset_delta,
+        }
+    } else if stack.is_empty() && locals.len() > previous_locals.len() &&
+              locals.len() - previous_locals.len() <= 3 &&
+              locals.starts_with(previous_locals) {
+        StackMapFrame::Append {
+            frame_type: (251 + (locals.len() - previous_locals.len())) as u8,
+            offset_delta: offset_delta,
+            locals: locals[previous_locals.len()..].to_vec(),
+        }
+    } else {
+        StackMapFrame::Full {
+            offset_delta: offset_delta,
+            locals: locals.to_vec(),
+            stack: stack,
+        }
+    }
+}
+
+/// The local variables of `frame` as a stack map lists them, where a long or double takes up a
+/// single entry and trailing unusable locals are left out.
+fn locals(classfile: &mut ClassFile, frame: &Frame) -> ParserResult<Vec<VerificationTypeInfo>> {
+    let mut locals = vec![];
+    let mut index = 0;
+    while index < frame.locals.len() {
+        let value = &frame.locals[index];
+        locals.push(try!(verification_type_info(classfile, value)));
+        index += value.size();
+    }
+
+    while locals.last() == Some(&VerificationTypeInfo::Top) {
+        locals.pop();
+    }
+
+    Ok(locals)
+}
+
+fn verification_type_info(classfile: &mut ClassFile,
+                          value: &VerificationType)
+                          -> ParserResult<VerificationTypeInfo> {
+    let info = match *value {
+        VerificationType::Top |
+        VerificationType::ReturnAddress => VerificationTypeInfo::Top,
+        VerificationType::Integer => VerificationTypeInfo::Integer,
+        VerificationType::Float => VerificationTypeInfo::Float,
+        VerificationType::Long => VerificationTypeInfo::Long,
+        VerificationType::Double => VerificationTypeInfo::Double,
+        VerificationType::Null => VerificationTypeInfo::Null,
+        VerificationType::UninitializedThis => VerificationTypeInfo::UninitializedThis,
+        VerificationType::Object(ref name) => {
+            VerificationTypeInfo::Object(try!(class_index(classfile, name)))
+        }
+        VerificationType::Uninitialized(pc) => VerificationTypeInfo::Uninitialized(pc),
+    };
+
+    Ok(info)
+}
+
+fn class_index(classfile: &mut ClassFile, name: &str) -> ParserResult<U2> {
+    for (position, item) in classfile.constant_pool.iter().enumerate() {
+        if let ConstantPoolItem::Class(ref info) = *item {
+            match ConstantPoolItem::retrieve_utf8_info(info.name_index, &classfile.constant_pool) {
+                Ok(ref utf8) if utf8.as_str() == name => return Ok((position + 1) as U2),
+                _ => {}
+            }
+        }
+    }
+
+    let name_index = try!(utf8_index(classfile, name));
+    push_constant(classfile,
+                  ConstantPoolItem::Class(Rc::new(ClassInfo {
+                      tag: 7,
+                      name_index: name_index,
+                  })))
+}
+
+fn utf8_index(classfile: &mut ClassFile, value: &str) -> ParserResult<U2> {
+    for (position, item) in classfile.constant_pool.iter().enumerate() {
+        if let ConstantPoolItem::Utf8(ref utf8) = *item {
+            if utf8.as_str() == value && utf8.bytes.is_none() {
+                return Ok((position + 1) as U2);
+            }
+        }
+    }
+
+    push_constant(classfile,
+                  ConstantPoolItem::Utf8(Rc::new(Utf8Info {
+                      tag: 1,
+                      length: mutf8::encoded_len(value) as U2,
+                      value: value.to_string(),
+                      bytes: None,
+                  })))
+}
+
+fn push_constant(classfile: &mut ClassFile, item: ConstantPoolItem) -> ParserResult<U2> {
+    let index = classfile.constant_pool.len() + 1;
+    if index > 0xfffe {
+        return Err(ParserError::ConstantPoolTooLarge(index + 1));
+    }
+
+    classfile.constant_pool.push(item);
+    classfile.constant_pool_count = (index + 1) as U2;
+
+    Ok(index as U2)
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::encode;
+    use super::super::components::{StackMapFrame, VerificationTypeInfo};
+
+    #[test]
+    fn can_encode_compact_frames() {
+        let previous = vec![VerificationTypeInfo::Object(2), VerificationTypeInfo::Integer];
+
+        assert_that(&encode(5, &previous, &previous, vec![]))
+            .is_equal_to(&StackMapFrame::Same { frame_type: 5 });
+        assert_that(&encode(70, &previous, &previous, vec![VerificationTypeInfo::Null]))
+            .is_equal_to(&StackMapFrame::SameLocals1StackItemExtended {
+                offset_delta: 70,
+                stack: VerificationTypeInfo::Null,
+            });
+        assert_that(&encode(3, &previous, &previous[..1], vec![]))
+            .is_equal_to(&StackMapFrame::Chop {
+                frame_type: 250,
+                offset_delta: 3,
+            });
+
+        let appended = vec![VerificationTypeInfo::Object(2),
+                            VerificationTypeInfo::Integer,
+                            VerificationTypeInfo::Long];
+        assert_that(&encode(0, &previous, &appended, vec![]))
+            .is_equal_to(&StackMapFrame::Append {
+                frame_type: 252,
+                offset_delta: 0,
+                locals: vec![VerificationTypeInfo::Long],
+            });
+    }
+
+    #[test]
+    fn falls_back_to_full_frames() {
+        let previous = vec![VerificationTypeInfo::Integer];
+        let locals = vec![VerificationTypeInfo::Float];
+
+        assert_that(&encode(1, &previous, &locals, vec![]))
+            .is_equal_to(&StackMapFrame::Full {
+                offset_delta: 1,
+                locals: locals.clone(),
+                stack: vec![],
+            });
+    }
+
+}
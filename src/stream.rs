@@ -0,0 +1,397 @@
+use super::{ParserError, ParserResult};
+use super::access::{ClassAccess, FieldAccess, MethodAccess};
+use super::components::{ConstantPoolItem, ExceptionHandler, Utf8Info};
+use super::instructions::{Instruction, InstructionIterator};
+use super::primitives::{ByteCursor, PrimitiveIterator, U1, U2, U4};
+
+use std::rc::Rc;
+
+/// What an attribute passed to `ClassFileVisitor::visit_attribute` belongs to. A field, method
+/// or code attribute is always the last one that was visited.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AttributeOwner {
+    Class,
+    Field,
+    Method,
+    Code,
+}
+
+/// Receives the parts of a class file from `visit` in the order they are read. Every method does
+/// nothing by default, so a visitor only implements the ones it is interested in, and an error
+/// returned from any of them stops the visit.
+pub trait ClassFileVisitor {
+    fn visit_header(&mut self, _magic: U4, _minor_version: U2, _major_version: U2)
+                    -> ParserResult<()> {
+        Ok(())
+    }
+
+    /// Called for each constant by its index, which skips the unusable slot after a long or
+    /// double.
+    fn visit_constant(&mut self, _index: U2, _constant: &ConstantPoolItem) -> ParserResult<()> {
+        Ok(())
+    }
+
+    /// Called once the constant pool has been read, with the names of the class, its superclass
+    /// and its interfaces.
+    fn visit_class(&mut self,
+                   _access: ClassAccess,
+                   _name: &Utf8Info,
+                   _super_name: Option<&Utf8Info>,
+                   _interfaces: &[Rc<Utf8Info>])
+                   -> ParserResult<()> {
+        Ok(())
+    }
+
+    fn visit_field(&mut self,
+                   _access: FieldAccess,
+                   _name: &Utf8Info,
+                   _descriptor: &Utf8Info)
+                   -> ParserResult<()> {
+        Ok(())
+    }
+
+    fn visit_method(&mut self,
+                    _access: MethodAccess,
+                    _name: &Utf8Info,
+                    _descriptor: &Utf8Info)
+                    -> ParserResult<()> {
+        Ok(())
+    }
+
+    /// Called for the `Code` attribute of a method in place of `visit_attribute`. Its
+    /// instructions, exception handlers and attributes follow.
+    fn visit_code(&mut self, _max_stack: U2, _max_locals: U2) -> ParserResult<()> {
+        Ok(())
+    }
+
+    fn visit_instruction(&mut self, _pc: U2, _instruction: &Instruction) -> ParserResult<()> {
+        Ok(())
+    }
+
+    fn visit_exception_handler(&mut self, _handler: &ExceptionHandler) -> ParserResult<()> {
+        Ok(())
+    }
+
+    /// Called for every attribute other than the `Code` of a method, with its undecoded body.
+    fn visit_attribute(&mut self,
+                       _owner: AttributeOwner,
+                       _name: &Utf8Info,
+                       _info: &[U1])
+                       -> ParserResult<()> {
+        Ok(())
+    }
+
+    fn visit_end(&mut self) -> ParserResult<()> {
+        Ok(())
+    }
+}
+
+/// Reads a class file from `bytes`, handing each part of it to `visitor` as soon as it has been
+/// read. Only the constant pool is kept until the end, along with the body of the attribute being
+/// read, so the memory a visit takes does not grow with the number of members or instructions.
+///
+/// Unlike parsing a `ClassFile`, this decodes the instructions of each method, and so fails on
+/// code that cannot be decoded.
+pub fn visit<T, V>(bytes: &mut T, visitor: &mut V) -> ParserResult<()>
+    where T: PrimitiveIterator,
+          V: ClassFileVisitor
+{
+    let magic = try!(bytes.next_u4());
+    let minor_version = try!(bytes.next_u2());
+    let major_version = try!(bytes.next_u2());
+    try!(visitor.visit_header(magic, minor_version, major_version));
+
+    let constant_pool_count = try!(bytes.next_u2());
+    let mut constant_pool = vec![];
+    while constant_pool.len() + 1 < constant_pool_count as usize {
+        let constant = try!(ConstantPoolItem::from(bytes));
+        try!(visitor.visit_constant(constant_pool.len() as U2 + 1, &constant));
+
+        let is_wide = match constant {
+            ConstantPoolItem::Long(..) |
+            ConstantPoolItem::Double(..) => true,
+            _ => false,
+        };
+        constant_pool.push(constant);
+        if is_wide {
+            constant_pool.push(ConstantPoolItem::Empty);
+        }
+    }
+
+    let access_flags = try!(bytes.next_u2());
+    let name = try!(class_name(try!(bytes.next_u2()), &constant_pool));
+    let super_name = match try!(bytes.next_u2()) {
+        0 => None,
+        super_class => Some(try!(class_name(super_class, &constant_pool))),
+    };
+
+    let interfaces_count = try!(bytes.next_u2());
+    let mut interfaces = vec![];
+    for _ in 0..interfaces_count {
+        interfaces.push(try!(class_name(try!(bytes.next_u2()), &constant_pool)));
+    }
+
+    try!(visitor.visit_class(ClassAccess::from(access_flags),
+                             &name,
+                             super_name.as_deref(),
+                             &interfaces));
+
+    let fields_count = try!(bytes.next_u2());
+    for _ in 0..fields_count {
+        let (access_flags, name, descriptor) = try!(read_member(bytes, &constant_pool));
+        try!(visitor.visit_field(FieldAccess::from(access_flags), &name, &descriptor));
+        try!(read_attributes(bytes, AttributeOwner::Field, &constant_pool, visitor));
+    }
+
+    let methods_count = try!(bytes.next_u2());
+    for _ in 0..methods_count {
+        let (access_flags, name, descriptor) = try!(read_member(bytes, &constant_pool));
+        try!(visitor.visit_method(MethodAccess::from(access_flags), &name, &descriptor));
+        try!(read_attributes(bytes, AttributeOwner::Method, &constant_pool, visitor));
+    }
+
+    try!(read_attributes(bytes, AttributeOwner::Class, &constant_pool, visitor));
+
+    visitor.visit_end()
+}
+
+fn class_name(index: U2, constant_pool: &Vec<ConstantPoolItem>) -> ParserResult<Rc<Utf8Info>> {
+    let class_info = try!(ConstantPoolItem::retrieve_class_info(index, constant_pool));
+    ConstantPoolItem::retrieve_utf8_info(class_info.name_index, constant_pool)
+}
+
+fn read_member<T: PrimitiveIterator>(bytes: &mut T,
+                                     constant_pool: &Vec<ConstantPoolItem>)
+                                     -> ParserResult<(U2, Rc<Utf8Info>, Rc<Utf8Info>)> {
+    let access_flags = try!(bytes.next_u2());
+    let name = try!(ConstantPoolItem::retrieve_utf8_info(try!(bytes.next_u2()), constant_pool));
+    let descriptor = try!(ConstantPoolItem::retrieve_utf8_info(try!(bytes.next_u2()),
+                                                               constant_pool));
+
+    Ok((access_flags, name, descriptor))
+}
+
+fn read_attributes<T, V>(bytes: &mut T,
+                         owner: AttributeOwner,
+                         constant_pool: &Vec<ConstantPoolItem>,
+                         visitor: &mut V)
+                         -> ParserResult<()>
+    where T: PrimitiveIterator,
+          V: ClassFileVisitor
+{
+    let attributes_count = try!(bytes.next_u2());
+    for _ in 0..attributes_count {
+        let name = try!(ConstantPoolItem::retrieve_utf8_info(try!(bytes.next_u2()),
+                                                             constant_pool));
+        let length = try!(bytes.next_u4());
+        let info = try!(bytes.next_bytes(length as usize));
+
+        if owner == AttributeOwner::Method && name.as_str() == "Code" {
+            // the code is read from its own body only, which it has to use up
+            let mut body = ByteCursor::new(&info);
+            try!(read_code(&mut body, constant_pool, visitor));
+            if body.remaining() != 0 {
+                return Err(ParserError::AttributeLengthMismatch(name.to_string()));
+            }
+        } else {
+            try!(visitor.visit_attribute(owner, &name, &info));
+        }
+    }
+
+    Ok(())
+}
+
+fn read_code<T, V>(bytes: &mut T,
+                   constant_pool: &Vec<ConstantPoolItem>,
+                   visitor: &mut V)
+                   -> ParserResult<()>
+    where T: PrimitiveIterator,
+          V: ClassFileVisitor
+{
+    let max_stack = try!(bytes.next_u2());
+    let max_locals = try!(bytes.next_u2());
+    try!(visitor.visit_code(max_stack, max_locals));
+
+    let code_length = try!(bytes.next_u4());
+    let code = try!(bytes.next_bytes(code_length as usize));
+    for instruction in InstructionIterator::new(&code) {
+        let (pc, instruction) = try!(instruction);
+        try!(visitor.visit_instruction(pc, &instruction));
+    }
+
+    let exception_table_length = try!(bytes.next_u2());
+    for _ in 0..exception_table_length {
+        try!(visitor.visit_exception_handler(&try!(ExceptionHandler::from(bytes))));
+    }
+
+    read_attributes(bytes, AttributeOwner::Code, constant_pool, visitor)
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{visit, AttributeOwner, ClassFileVisitor};
+    use super::super::{ParserError, ParserResult};
+    use super::super::access::ClassAccess;
+    use super::super::components::{ConstantPoolItem, Utf8Info};
+    use super::super::primitives::{ByteCursor, U1, U2, U4};
+
+    use std::rc::Rc;
+
+    /// A class `A` extending `java/lang/Object` with no members and a `Deprecated` attribute,
+    /// for version 52.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    const DEPRECATED_CLASS: &'static [u8] = &[
+        0xCA, 0xFE, 0xBA, 0xBE, 0x00, 0x00, 0x00, 0x34,
+        0x00, 0x08,
+        0x01, 0x00, 0x01, b'A',
+        0x07, 0x00, 0x01,
+        0x01, 0x00, 0x10, b'j', b'a', b'v', b'a', b'/', b'l', b'a', b'n', b'g', b'/',
+        b'O', b'b', b'j', b'e', b'c', b't',
+        0x07, 0x00, 0x03,
+        0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A,
+        0x01, 0x00, 0x0A, b'D', b'e', b'p', b'r', b'e', b'c', b'a', b't', b'e', b'd',
+        0x00, 0x21, 0x00, 0x02, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x01, 0x00, 0x07, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    /// Writes down every part of a class it is shown.
+    #[derive(Default)]
+    struct Recorder {
+        events: Vec<String>,
+    }
+
+    impl ClassFileVisitor for Recorder {
+        fn visit_header(&mut self, _magic: U4, _minor_version: U2, major_version: U2)
+                        -> ParserResult<()> {
+            self.events.push(format!("version {}", major_version));
+            Ok(())
+        }
+
+        fn visit_constant(&mut self, index: U2, _constant: &ConstantPoolItem) -> ParserResult<()> {
+            self.events.push(format!("constant {}", index));
+            Ok(())
+        }
+
+        fn visit_class(&mut self,
+                       access: ClassAccess,
+                       name: &Utf8Info,
+                       super_name: Option<&Utf8Info>,
+                       _interfaces: &[Rc<Utf8Info>])
+                       -> ParserResult<()> {
+            self.events.push(format!("{} class {} extends {}",
+                                     access,
+                                     name.as_str(),
+                                     super_name.unwrap().as_str()));
+            Ok(())
+        }
+
+        fn visit_attribute(&mut self,
+                           owner: AttributeOwner,
+                           name: &Utf8Info,
+                           info: &[U1])
+                           -> ParserResult<()> {
+            self.events.push(format!("{:?} attribute {} of {} bytes",
+                                     owner,
+                                     name.as_str(),
+                                     info.len()));
+            Ok(())
+        }
+
+        fn visit_end(&mut self) -> ParserResult<()> {
+            self.events.push("end".to_string());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn can_visit_the_parts_of_a_class_in_order() {
+        let mut recorder = Recorder::default();
+        visit(&mut ByteCursor::new(DEPRECATED_CLASS), &mut recorder).unwrap();
+
+        assert_that(&recorder.events).is_equal_to(&vec![
+            "version 52".to_string(),
+            "constant 1".to_string(),
+            "constant 2".to_string(),
+            "constant 3".to_string(),
+            "constant 4".to_string(),
+            "constant 5".to_string(),
+            "constant 7".to_string(),
+            "public class A extends java/lang/Object".to_string(),
+            "Class attribute Deprecated of 0 bytes".to_string(),
+            "end".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn stops_at_the_first_error() {
+        struct Refuser;
+
+        impl ClassFileVisitor for Refuser {
+            fn visit_constant(&mut self, index: U2, _constant: &ConstantPoolItem)
+                              -> ParserResult<()> {
+                Err(ParserError::ConstantPoolIndexOutOfBounds(index as usize))
+            }
+        }
+
+        match visit(&mut ByteCursor::new(DEPRECATED_CLASS), &mut Refuser) {
+            Err(ParserError::ConstantPoolIndexOutOfBounds(index)) => {
+                assert_that(&index).is_equal_to(&1)
+            }
+            result => panic!("expected the visitor's error, got {:?}", result),
+        }
+
+        for length in 0..DEPRECATED_CLASS.len() {
+            let result = visit(&mut ByteCursor::new(&DEPRECATED_CLASS[..length]),
+                               &mut Recorder::default());
+            assert_that(&result.is_err()).is_equal_to(&true);
+        }
+    }
+
+    /// A class `A` with a method `A` whose `Code` attribute is `length` bytes long.
+    fn class_with_code(length: U4, code: &[U1]) -> Vec<U1> {
+        let mut bytes = DEPRECATED_CLASS[..8].to_vec();
+        bytes.extend_from_slice(&[0x00, 0x09]);
+        bytes.extend_from_slice(&DEPRECATED_CLASS[10..61]);
+        bytes.extend_from_slice(&[0x01, 0x00, 0x04, b'C', b'o', b'd', b'e']);
+        bytes.extend_from_slice(&[0x00, 0x21, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00]);
+        bytes.extend_from_slice(&[0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x01]);
+        bytes.extend_from_slice(&[0x00, 0x08, (length >> 24) as U1, (length >> 16) as U1,
+                                  (length >> 8) as U1, length as U1]);
+        bytes.extend_from_slice(code);
+        bytes.extend_from_slice(&[0x00, 0x00]);
+        bytes
+    }
+
+    #[test]
+    fn reads_code_from_its_own_body_only() {
+        let code = [0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0xB1, 0x00, 0x00, 0x00, 0x00];
+        let result = visit(&mut ByteCursor::new(&class_with_code(13, &code)),
+                           &mut Recorder::default());
+        assert_that(&result.is_ok()).is_equal_to(&true);
+
+        // a byte after the nested attributes that belongs to none of the parts of the code
+        let mut padded = code.to_vec();
+        padded.push(0x00);
+        let result = visit(&mut ByteCursor::new(&class_with_code(14, &padded)),
+                           &mut Recorder::default());
+        assert_that(&result).matches(|result| match *result {
+            Err(ParserError::AttributeLengthMismatch(ref name)) => name == "Code",
+            _ => false,
+        });
+
+        // a length that leaves out the count of nested attributes, which follows it anyway
+        let result = visit(&mut ByteCursor::new(&class_with_code(11, &code)),
+                           &mut Recorder::default());
+        assert_that(&result).matches(|result| match *result {
+            Err(ParserError::Io(..)) => true,
+            _ => false,
+        });
+    }
+
+}
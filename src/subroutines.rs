@@ -0,0 +1,626 @@
+use super::{ParserError, ParserResult};
+use super::assembler::{CodeAssembler, Label, LabelledLocalVariable};
+use super::components::{Attribute, CodeAttribute};
+use super::instructions::{Instruction, LocalAccess};
+use super::primitives::U2;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+/// A subroutine of a method body, entered by `jsr` and left by `ret`, as emitted by compilers for
+/// `finally` blocks before class file version 50.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Subroutine {
+    /// The pc of the first instruction, which every `jsr` calling the subroutine targets.
+    pub entry: U2,
+    /// The pcs of the `jsr` instructions that call the subroutine.
+    pub callers: Vec<U2>,
+    /// The pcs of the instructions reachable from the entry without returning, along with the
+    /// exception handlers covering any of them, leaving out those the main code or a subroutine
+    /// found earlier reaches as well.
+    pub instructions: BTreeSet<U2>,
+    /// The local variables written by the subroutine or by the subroutines it calls in turn.
+    pub locals: BTreeSet<U2>,
+}
+
+/// Finds the subroutines of `code`, ordered by entry.
+pub fn find(code: &CodeAttribute) -> ParserResult<Vec<Subroutine>> {
+    let body = try!(Body::decode(code));
+
+    let mut entries = BTreeMap::new();
+    for &(pc, ref instruction) in &body.instructions {
+        if let Some(target) = try!(body.subroutine_target(pc, instruction)) {
+            entries.entry(target).or_insert(vec![]).push(pc);
+        }
+    }
+
+    let (_, mut members) = try!(body.partition());
+    let mut subroutines = vec![];
+    for (entry, callers) in entries {
+        let members = members.remove(&entry).unwrap_or_default();
+
+        let mut locals = BTreeSet::new();
+        for &index in &members {
+            let access = body.instructions[index].1.local_variable_access();
+            if let Some(access) = access {
+                if access.access != LocalAccess::Load {
+                    locals.extend(access.slot_indexes());
+                }
+            }
+        }
+
+        subroutines.push(Subroutine {
+            entry: body.instructions[entry].0,
+            callers: callers,
+            instructions: members.iter().map(|&index| body.instructions[index].0).collect(),
+            locals: locals,
+        });
+    }
+
+    // a subroutine also writes whatever the subroutines it calls write
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for i in 0..subroutines.len() {
+            for j in 0..subroutines.len() {
+                let calls = subroutines[j]
+                    .callers
+                    .iter()
+                    .any(|caller| subroutines[i].instructions.contains(caller));
+                if i != j && calls && !subroutines[j].locals.is_subset(&subroutines[i].locals) {
+                    let called = subroutines[j].locals.clone();
+                    subroutines[i].locals.extend(called);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    Ok(subroutines)
+}
+
+/// Rewrites `code` so that it no longer uses subroutines, by giving every `jsr` its own copy of
+/// the subroutine it calls.
+///
+/// A `jsr` becomes an `aconst_null` standing in for the return address, followed by a `goto` to
+/// the copy, and a `ret` becomes a `goto` back to the instruction following the `jsr`. Exception
+/// handlers and debug tables are copied along with the code they cover. Code that cannot be
+/// reached is left out, and code without subroutines is returned as is.
+///
+/// Every copy adds to the length of the code, so deeply nested subroutines may no longer fit.
+/// Recursive subroutines cannot be inlined at all.
+pub fn inline(code: &CodeAttribute) -> ParserResult<CodeAttribute> {
+    let body = try!(Body::decode(code));
+    let has_subroutines = body.instructions
+        .iter()
+        .any(|&(_, ref instruction)| is_subroutine_call(instruction));
+    if !has_subroutines {
+        return Ok(code.clone());
+    }
+
+    let (main, subroutines) = try!(body.partition());
+    let mut inliner = Inliner {
+        body: &body,
+        subroutines: subroutines,
+        assembler: CodeAssembler::new(),
+        instances: vec![],
+    };
+    inliner.add_instance(None, main, None, None);
+
+    // instances are added as the jsr instructions calling them are emitted
+    let mut instance = 0;
+    while instance < inliner.instances.len() {
+        try!(inliner.emit(instance));
+        instance += 1;
+    }
+
+    inliner.assembler.assemble_into(code)
+}
+
+/// The instructions of a method body, indexed in pc order.
+struct Body<'c> {
+    code: &'c CodeAttribute,
+    instructions: Vec<(U2, Instruction)>,
+    indices: HashMap<usize, usize>,
+}
+
+impl<'c> Body<'c> {
+    fn decode(code: &'c CodeAttribute) -> ParserResult<Body<'c>> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+        let indices = instructions.iter()
+            .enumerate()
+            .map(|(index, &(pc, _))| (pc as usize, index))
+            .collect();
+
+        Ok(Body {
+            code: code,
+            instructions: instructions,
+            indices: indices,
+        })
+    }
+
+    fn index_of(&self, pc: usize) -> ParserResult<usize> {
+        self.indices.get(&pc).cloned().ok_or(ParserError::InvalidCodeOffset(pc))
+    }
+
+    /// The index of an instruction boundary, where the end of the code counts as one past the
+    /// last instruction.
+    fn boundary_of(&self, pc: usize) -> ParserResult<usize> {
+        if pc == self.code.code.len() {
+            Ok(self.instructions.len())
+        } else {
+            self.index_of(pc)
+        }
+    }
+
+    fn target_of(&self, pc: U2, offset: i32) -> ParserResult<usize> {
+        self.index_of((pc as i64 + offset as i64) as usize)
+    }
+
+    fn subroutine_target(&self, pc: U2, instruction: &Instruction) -> ParserResult<Option<usize>> {
+        match *instruction {
+            Instruction::Jsr(offset) |
+            Instruction::JsrW(offset) => Ok(Some(try!(self.target_of(pc, offset)))),
+            _ => Ok(None),
+        }
+    }
+
+    /// The indices of the instructions of the main code, and those of each subroutine by the
+    /// index of its entry. An instruction belongs to the first of them to reach it, starting with
+    /// the main code, so an exception handler covering both a subroutine and its caller stays
+    /// with the caller.
+    fn partition(&self) -> ParserResult<(BTreeSet<usize>, BTreeMap<usize, BTreeSet<usize>>)> {
+        let mut owned = BTreeSet::new();
+        let main = try!(self.walk(0, &owned));
+        owned.extend(main.iter().cloned());
+
+        let mut subroutines = BTreeMap::new();
+        let mut pending = VecDeque::new();
+        try!(self.push_subroutine_targets(&main, &mut pending));
+        while let Some(entry) = pending.pop_front() {
+            if subroutines.contains_key(&entry) {
+                continue;
+            }
+
+            let members = try!(self.walk(entry, &owned));
+            owned.extend(members.iter().cloned());
+            try!(self.push_subroutine_targets(&members, &mut pending));
+            subroutines.insert(entry, members);
+        }
+
+        Ok((main, subroutines))
+    }
+
+    fn push_subroutine_targets(&self,
+                               members: &BTreeSet<usize>,
+                               pending: &mut VecDeque<usize>)
+                               -> ParserResult<()> {
+        for &index in members {
+            let (pc, ref instruction) = self.instructions[index];
+            if let Some(target) = try!(self.subroutine_target(pc, instruction)) {
+                pending.push_back(target);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The indices of the instructions reachable from the one at `start` that are not `owned`
+    /// already, where a `jsr` is taken to return to the instruction following it, along with any
+    /// exception handler covering them.
+    fn walk(&self, start: usize, owned: &BTreeSet<usize>) -> ParserResult<BTreeSet<usize>> {
+        let mut members = BTreeSet::new();
+        let mut pending = vec![start];
+
+        while !pending.is_empty() {
+            while let Some(index) = pending.pop() {
+                if owned.contains(&index) || !members.insert(index) {
+                    continue;
+                }
+
+                let (pc, ref instruction) = self.instructions[index];
+                if !is_subroutine_call(instruction) {
+                    for offset in instruction.branch_targets() {
+                        pending.push(try!(self.target_of(pc, *offset)));
+                    }
+                }
+                if falls_through(instruction) && index + 1 < self.instructions.len() {
+                    pending.push(index + 1);
+                }
+            }
+
+            for handler in &self.code.exception_table {
+                let covered = members.iter().any(|&index| {
+                    let pc = self.instructions[index].0;
+                    pc >= handler.start_pc && pc < handler.end_pc
+                });
+                let handler_index = try!(self.index_of(handler.handler_pc as usize));
+                let visited = members.contains(&handler_index) || owned.contains(&handler_index);
+                if covered && !visited {
+                    pending.push(handler_index);
+                }
+            }
+        }
+
+        Ok(members)
+    }
+}
+
+/// A copy of the main code or of a subroutine, with a label at every instruction boundary of the
+/// original code.
+struct Instance {
+    entry: Option<usize>,
+    members: BTreeSet<usize>,
+    parent: Option<usize>,
+    labels: Vec<Label>,
+    return_label: Option<Label>,
+}
+
+struct Inliner<'b, 'c: 'b> {
+    body: &'b Body<'c>,
+    subroutines: BTreeMap<usize, BTreeSet<usize>>,
+    assembler: CodeAssembler,
+    instances: Vec<Instance>,
+}
+
+impl<'b, 'c> Inliner<'b, 'c> {
+    fn add_instance(&mut self,
+                    entry: Option<usize>,
+                    members: BTreeSet<usize>,
+                    parent: Option<usize>,
+                    return_label: Option<Label>)
+                    -> usize {
+        let mut labels = vec![];
+        for _ in 0..self.body.instructions.len() + 1 {
+            labels.push(self.assembler.new_label());
+        }
+
+        self.instances.push(Instance {
+            entry: entry,
+            members: members,
+            parent: parent,
+            labels: labels,
+            return_label: return_label,
+        });
+        self.instances.len() - 1
+    }
+
+    /// The label to jump to for the instruction at `index` from within `instance`, which is the
+    /// copy belonging to the innermost instance that contains it.
+    fn jump_label(&self, instance: usize, index: usize) -> ParserResult<Label> {
+        let mut current = Some(instance);
+        while let Some(instance) = current {
+            if self.instances[instance].members.contains(&index) {
+                return Ok(self.instances[instance].labels[index]);
+            }
+            current = self.instances[instance].parent;
+        }
+
+        let pc = match self.body.instructions.get(index) {
+            Some(&(pc, _)) => pc as usize,
+            None => self.body.code.code.len(),
+        };
+        Err(ParserError::InvalidCodeOffset(pc))
+    }
+
+    fn emit(&mut self, instance: usize) -> ParserResult<()> {
+        let body = self.body;
+
+        for (index, &(pc, ref instruction)) in body.instructions.iter().enumerate() {
+            let label = self.instances[instance].labels[index];
+            self.assembler.place_label(label);
+            if !self.instances[instance].members.contains(&index) {
+                continue;
+            }
+
+            match *instruction {
+                Instruction::Jsr(..) |
+                Instruction::JsrW(..) => {
+                    let target = try!(body.subroutine_target(pc, instruction)).unwrap();
+                    let entry = try!(self.call(instance, index, target));
+                    self.assembler.push(Instruction::AconstNull);
+                    self.assembler.push(Instruction::Goto(entry));
+                    continue;
+                }
+                Instruction::Ret(..) => {
+                    match self.instances[instance].return_label {
+                        Some(return_label) => self.assembler.push(Instruction::Goto(return_label)),
+                        None => return Err(ParserError::UnsupportedSubroutine(pc as usize)),
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+
+            let mut targets = vec![];
+            for offset in instruction.branch_targets() {
+                let target = try!(body.target_of(pc, *offset));
+                targets.push(try!(self.jump_label(instance, target)));
+            }
+            let mut targets = targets.into_iter();
+            self.assembler.push(instruction.clone().map_targets(|_| targets.next().unwrap()));
+
+            // code falling through into an instruction that was not copied has to jump to it
+            let copied_next = self.instances[instance].members.contains(&(index + 1));
+            if falls_through(instruction) && !copied_next {
+                let next = try!(self.jump_label(instance, index + 1));
+                self.assembler.push(Instruction::Goto(next));
+            }
+        }
+        let end = self.instances[instance].labels[body.instructions.len()];
+        self.assembler.place_label(end);
+
+        self.copy_tables(instance)
+    }
+
+    /// Adds a copy of the subroutine starting at `target` for the `jsr` at `index`, returning the
+    /// label of its entry.
+    fn call(&mut self, instance: usize, index: usize, target: usize) -> ParserResult<Label> {
+        let pc = self.body.instructions[index].0 as usize;
+
+        let mut current = Some(instance);
+        while let Some(ancestor) = current {
+            if self.instances[ancestor].entry == Some(target) {
+                return Err(ParserError::UnsupportedSubroutine(pc));
+            }
+            current = self.instances[ancestor].parent;
+        }
+
+        if index + 1 == self.body.instructions.len() {
+            return Err(ParserError::InvalidCodeOffset(self.body.code.code.len()));
+        }
+        let return_label = try!(self.jump_label(instance, index + 1));
+        let members = self.subroutines.get(&target).cloned().unwrap_or_default();
+        let called = self.add_instance(Some(target), members, Some(instance), Some(return_label));
+
+        self.jump_label(called, target)
+    }
+
+    /// Copies the exception handlers, line numbers and local variables covering the code of
+    /// `instance`.
+    fn copy_tables(&mut self, instance: usize) -> ParserResult<()> {
+        let body = self.body;
+
+        for handler in &body.code.exception_table {
+            let start = try!(body.boundary_of(handler.start_pc as usize));
+            let end = try!(body.boundary_of(handler.end_pc as usize));
+            if !self.covers(instance, start, end) {
+                continue;
+            }
+
+            let handler_index = try!(body.index_of(handler.handler_pc as usize));
+            let handler_label = try!(self.jump_label(instance, handler_index));
+            let labels = &self.instances[instance].labels;
+            self.assembler.exception_handler(labels[start],
+                                             labels[end],
+                                             handler_label,
+                                             handler.catch_type);
+        }
+
+        for attribute in &body.code.attributes {
+            match *attribute {
+                Attribute::LineNumberTable(ref table) => {
+                    for line_number in &table.line_number_table {
+                        let start = try!(body.boundary_of(line_number.start_pc as usize));
+                        if self.instances[instance].members.contains(&start) {
+                            let label = self.instances[instance].labels[start];
+                            self.assembler.line_number(label, line_number.line_number);
+                        }
+                    }
+                }
+                Attribute::LocalVariableTable(ref table) |
+                Attribute::LocalVariableTypeTable(ref table) => {
+                    for local_variable in &table.local_variable_table {
+                        let start_pc = local_variable.start_pc as usize;
+                        let start = try!(body.boundary_of(start_pc));
+                        let end = try!(body.boundary_of(start_pc + local_variable.length as usize));
+                        if !self.covers(instance, start, end) {
+                            continue;
+                        }
+
+                        let labels = &self.instances[instance].labels;
+                        let local_variable = LabelledLocalVariable {
+                            start: labels[start],
+                            end: labels[end],
+                            name_index: local_variable.name_index,
+                            descriptor_index: local_variable.descriptor_index,
+                            index: local_variable.index,
+                        };
+                        if let Attribute::LocalVariableTable(..) = *attribute {
+                            self.assembler.local_variables.push(local_variable);
+                        } else {
+                            self.assembler.local_variable_types.push(local_variable);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether any instruction between the boundaries `start` and `end` was copied to `instance`.
+    fn covers(&self, instance: usize, start: usize, end: usize) -> bool {
+        self.instances[instance].members.range(start..end).next().is_some()
+    }
+}
+
+fn is_subroutine_call<B>(instruction: &Instruction<B>) -> bool {
+    match *instruction {
+        Instruction::Jsr(..) |
+        Instruction::JsrW(..) => true,
+        _ => false,
+    }
+}
+
+/// Whether execution can continue with the next instruction, counting the return from a `jsr`.
+fn falls_through<B>(instruction: &Instruction<B>) -> bool {
+    match *instruction {
+        Instruction::Goto(..) |
+        Instruction::GotoW(..) |
+        Instruction::Ret(..) |
+        Instruction::Tableswitch { .. } |
+        Instruction::Lookupswitch { .. } |
+        Instruction::Ireturn |
+        Instruction::Lreturn |
+        Instruction::Freturn |
+        Instruction::Dreturn |
+        Instruction::Areturn |
+        Instruction::Return |
+        Instruction::Athrow => false,
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{find, inline, Subroutine};
+    use super::super::assembler::CodeAssembler;
+    use super::super::components::{CodeAttribute, ExceptionHandler};
+    use super::super::instructions::Instruction;
+
+    /// A method in the style of javac before version 50, whose `finally` block is a subroutine
+    /// called from the end of the `try` block and from the handler for any exception.
+    fn try_finally() -> CodeAttribute {
+        let mut assembler = CodeAssembler::new();
+        let try_start = assembler.new_label();
+        let try_end = assembler.new_label();
+        let handler = assembler.new_label();
+        let finally = assembler.new_label();
+
+        assembler.place_label(try_start);
+        assembler.push(Instruction::Iload0);
+        assembler.push(Instruction::Istore1);
+        assembler.place_label(try_end);
+        assembler.push(Instruction::Jsr(finally));
+        assembler.push(Instruction::Iload1);
+        assembler.push(Instruction::Ireturn);
+        assembler.place_label(handler);
+        assembler.push(Instruction::Astore2);
+        assembler.push(Instruction::Jsr(finally));
+        assembler.push(Instruction::Aload2);
+        assembler.push(Instruction::Athrow);
+        assembler.place_label(finally);
+        assembler.push(Instruction::Astore3);
+        assembler.push(Instruction::Iinc(0, 1));
+        assembler.push(Instruction::Ret(3));
+        assembler.exception_handler(try_start, try_end, handler, 0);
+
+        assembler.assemble_into(&CodeAttribute {
+                max_stack: 1,
+                max_locals: 4,
+                ..CodeAttribute::default()
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn can_find_subroutines() {
+        let subroutines = find(&try_finally()).unwrap();
+
+        assert_that(&subroutines).is_equal_to(&vec![Subroutine {
+                                                        entry: 13,
+                                                        callers: vec![2, 8],
+                                                        instructions: vec![13, 14, 17]
+                                                            .into_iter()
+                                                            .collect(),
+                                                        locals: vec![0, 3].into_iter().collect(),
+                                                    }]);
+    }
+
+    #[test]
+    fn can_inline_subroutines() {
+        let inlined = inline(&try_finally()).unwrap();
+        let instructions: Vec<_> = inlined.instructions().map(|val| val.unwrap()).collect();
+
+        assert_that(&instructions).is_equal_to(&vec![(0, Instruction::Iload0),
+                                                      (1, Instruction::Istore1),
+                                                      (2, Instruction::AconstNull),
+                                                      (3, Instruction::Goto(12)),
+                                                      (6, Instruction::Iload1),
+                                                      (7, Instruction::Ireturn),
+                                                      (8, Instruction::Astore2),
+                                                      (9, Instruction::AconstNull),
+                                                      (10, Instruction::Goto(12)),
+                                                      (13, Instruction::Aload2),
+                                                      (14, Instruction::Athrow),
+                                                      (15, Instruction::Astore3),
+                                                      (16, Instruction::Iinc(0, 1)),
+                                                      (19, Instruction::Goto(-13)),
+                                                      (22, Instruction::Astore3),
+                                                      (23, Instruction::Iinc(0, 1)),
+                                                      (26, Instruction::Goto(-13))]);
+        assert_that(&inlined.exception_table).is_equal_to(&vec![ExceptionHandler {
+                                                                     start_pc: 0,
+                                                                     end_pc: 2,
+                                                                     handler_pc: 8,
+                                                                     catch_type: 0,
+                                                                 }]);
+    }
+
+    #[test]
+    fn leaves_handlers_shared_with_callers_to_main_code() {
+        let mut assembler = CodeAssembler::new();
+        let start = assembler.new_label();
+        let end = assembler.new_label();
+        let finally = assembler.new_label();
+
+        assembler.place_label(start);
+        assembler.push(Instruction::Iload0);
+        assembler.push(Instruction::Istore1);
+        assembler.push(Instruction::Jsr(finally));
+        assembler.push(Instruction::Iload1);
+        assembler.push(Instruction::Ireturn);
+        assembler.place_label(finally);
+        assembler.push(Instruction::Astore3);
+        assembler.push(Instruction::Iinc(0, 1));
+        assembler.push(Instruction::Ret(3));
+        assembler.place_label(end);
+        assembler.push(Instruction::Astore2);
+        assembler.push(Instruction::Aload2);
+        assembler.push(Instruction::Athrow);
+        assembler.exception_handler(start, end, end, 0);
+        let code = assembler.assemble_into(&CodeAttribute::default()).unwrap();
+
+        let subroutines = find(&code).unwrap();
+        assert_that(&subroutines).has_length(1);
+        assert_that(&subroutines[0].instructions).is_equal_to(&vec![7, 8, 11].into_iter().collect());
+
+        let inlined = inline(&code).unwrap();
+        assert_that(&inlined.exception_table).is_equal_to(&vec![ExceptionHandler {
+                                                                     start_pc: 0,
+                                                                     end_pc: 8,
+                                                                     handler_pc: 8,
+                                                                     catch_type: 0,
+                                                                 },
+                                                                 ExceptionHandler {
+                                                                     start_pc: 11,
+                                                                     end_pc: 18,
+                                                                     handler_pc: 8,
+                                                                     catch_type: 0,
+                                                                 }]);
+    }
+
+    #[test]
+    fn rejects_recursive_subroutines() {
+        let mut assembler = CodeAssembler::new();
+        let subroutine = assembler.new_label();
+
+        assembler.place_label(subroutine);
+        assembler.push(Instruction::Astore0);
+        assembler.push(Instruction::Jsr(subroutine));
+        assembler.push(Instruction::Ret(0));
+        let code = assembler.assemble_into(&CodeAttribute::default()).unwrap();
+
+        assert_that(&inline(&code).is_err()).is_equal_to(&true);
+    }
+
+}
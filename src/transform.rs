@@ -0,0 +1,914 @@
+//! A pipeline of visitors for transforming class files.
+//!
+//! A `ClassReader` walks a parsed class and reports each part of it to a `ClassVisitor`, which
+//! hands out a `FieldVisitor` or `MethodVisitor` for every member it wants to see the inside of.
+//! Visitors are chained: an adapter passes each event on to the visitor after it, changing it,
+//! dropping it, or adding events of its own along the way, and a `ClassWriter` at the end of the
+//! chain turns the events it receives back into class file bytes.
+//!
+//! Every event comes with the `SymbolTable` of the pipeline. Its constant pool starts out as the
+//! pool of the class being read and is what the writer emits, so the constant pool indexes of the
+//! class stay valid, and a visitor that needs a new constant interns it there.
+//!
+//! Method bodies are given as instructions whose branch targets are labels, and the writer
+//! assembles them again, so instructions can be added or removed freely. The sizes given to
+//! `visit_code` and any `StackMapTable` are passed on as they are, though, and no longer fit code
+//! that has been changed. A writer created with `WriterOptions` that ask for it computes both
+//! for every method body it writes, the way `CodeLimits::compute` and `stackmap::compute` do.
+
+use super::{ClassFile, ParserError, ParserResult};
+use super::assembler::{CodeAssembler, CodeItem, Label, LabelledExceptionHandler,
+                       LabelledLineNumber, LabelledLocalVariable};
+use super::components::{Attribute, CodeAttribute, ConstantPoolItem, LineNumberTableAttribute,
+                        LocalVariableTableAttribute, Method};
+use super::hierarchy::{ClassHierarchy, ObjectHierarchy};
+use super::instructions::Instruction;
+use super::limits::CodeLimits;
+use super::pool::{Constant, ConstantPool};
+use super::primitives::{PrimitiveWriter, U1, U2, U4};
+use super::stackmap;
+
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+/// What the visitors of a pipeline share.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    /// The constant pool of the class being written.
+    pub constant_pool: ConstantPool,
+    next_label: usize,
+}
+
+impl SymbolTable {
+    pub fn new(constant_pool: ConstantPool) -> SymbolTable {
+        SymbolTable {
+            constant_pool: constant_pool,
+            next_label: 0,
+        }
+    }
+
+    /// A label that is distinct from every other label of the pipeline.
+    pub fn new_label(&mut self) -> Label {
+        self.next_label += 1;
+        Label::new(self.next_label - 1)
+    }
+}
+
+/// The declaration of a class, with names given by constant pool index.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClassHeader {
+    pub minor_version: U2,
+    pub major_version: U2,
+    pub access_flags: U2,
+    pub this_class: U2,
+    pub super_class: U2,
+    pub interfaces: Vec<U2>,
+}
+
+/// The declaration of a field or method, with its name and descriptor given by the constant pool
+/// indexes of their `Utf8` entries.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemberHeader {
+    pub access_flags: U2,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+}
+
+/// Receives the parts of a class in the order a class file lists them: the header, the fields,
+/// the methods and the attributes of the class, then the end.
+///
+/// Every method passes its event on to `next` unless it is overridden, so an adapter only
+/// implements the events it changes. A visitor without a `next` drops whatever it does not
+/// handle itself.
+pub trait ClassVisitor {
+    fn next(&mut self) -> Option<&mut dyn ClassVisitor> {
+        None
+    }
+
+    fn visit(&mut self, symbols: &mut SymbolTable, header: ClassHeader) -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit(symbols, header),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the visitor for the attributes of the field, or `None` to drop the field.
+    fn visit_field<'a>(&'a mut self,
+                       symbols: &mut SymbolTable,
+                       field: MemberHeader)
+                       -> ParserResult<Option<Box<dyn FieldVisitor + 'a>>> {
+        match self.next() {
+            Some(next) => next.visit_field(symbols, field),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the visitor for the body of the method, or `None` to drop the method.
+    fn visit_method<'a>(&'a mut self,
+                        symbols: &mut SymbolTable,
+                        method: MemberHeader)
+                        -> ParserResult<Option<Box<dyn MethodVisitor + 'a>>> {
+        match self.next() {
+            Some(next) => next.visit_method(symbols, method),
+            None => Ok(None),
+        }
+    }
+
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_attribute(symbols, attribute),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_end(&mut self, symbols: &mut SymbolTable) -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_end(symbols),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Receives the attributes of a field, followed by the end of the field.
+pub trait FieldVisitor {
+    fn next(&mut self) -> Option<&mut dyn FieldVisitor> {
+        None
+    }
+
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_attribute(symbols, attribute),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_end(&mut self, symbols: &mut SymbolTable) -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_end(symbols),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Receives the attributes of a method, followed by the end of the method. The `Code` attribute
+/// is taken apart: `visit_code` is followed by the labels and instructions of the code, then by
+/// its exception handlers, and then by its nested attributes, where the line number and local
+/// variable tables come entry by entry.
+pub trait MethodVisitor {
+    fn next(&mut self) -> Option<&mut dyn MethodVisitor> {
+        None
+    }
+
+    /// Called for every attribute of the method other than `Code`.
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_attribute(symbols, attribute),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_code(&mut self,
+                  symbols: &mut SymbolTable,
+                  max_stack: U2,
+                  max_locals: U2)
+                  -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_code(symbols, max_stack, max_locals),
+            None => Ok(()),
+        }
+    }
+
+    /// Places `label` before the next instruction.
+    fn visit_label(&mut self, symbols: &mut SymbolTable, label: Label) -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_label(symbols, label),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_instruction(&mut self,
+                         symbols: &mut SymbolTable,
+                         instruction: Instruction<Label>)
+                         -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_instruction(symbols, instruction),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_exception_handler(&mut self,
+                               symbols: &mut SymbolTable,
+                               handler: LabelledExceptionHandler)
+                               -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_exception_handler(symbols, handler),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_line_number(&mut self,
+                         symbols: &mut SymbolTable,
+                         line_number: LabelledLineNumber)
+                         -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_line_number(symbols, line_number),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_local_variable(&mut self,
+                            symbols: &mut SymbolTable,
+                            local_variable: LabelledLocalVariable)
+                            -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_local_variable(symbols, local_variable),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_local_variable_type(&mut self,
+                                 symbols: &mut SymbolTable,
+                                 local_variable: LabelledLocalVariable)
+                                 -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_local_variable_type(symbols, local_variable),
+            None => Ok(()),
+        }
+    }
+
+    /// Called for every attribute of the code other than its line number and local variable
+    /// tables, which are only passed here when they have no entries.
+    fn visit_code_attribute(&mut self,
+                            symbols: &mut SymbolTable,
+                            attribute: Attribute)
+                            -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_code_attribute(symbols, attribute),
+            None => Ok(()),
+        }
+    }
+
+    fn visit_end(&mut self, symbols: &mut SymbolTable) -> ParserResult<()> {
+        match self.next() {
+            Some(next) => next.visit_end(symbols),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Drives a pipeline with the parts of a parsed class.
+pub struct ClassReader<'c> {
+    classfile: &'c ClassFile,
+}
+
+impl<'c> ClassReader<'c> {
+    pub fn new(classfile: &'c ClassFile) -> ClassReader<'c> {
+        ClassReader { classfile: classfile }
+    }
+
+    /// Passes the class to `visitor`, with a symbol table holding the constant pool of the class.
+    pub fn accept<V: ClassVisitor + ?Sized>(&self, visitor: &mut V) -> ParserResult<()> {
+        let classfile = self.classfile;
+        let mut symbols = SymbolTable::new(ConstantPool::from_items(&classfile.constant_pool));
+
+        try!(visitor.visit(&mut symbols,
+                           ClassHeader {
+                               minor_version: classfile.minor_version,
+                               major_version: classfile.major_version,
+                               access_flags: classfile.access_flags,
+                               this_class: classfile.this_class,
+                               super_class: classfile.super_class,
+                               interfaces: classfile.interfaces.clone(),
+                           }));
+
+        for field in &classfile.fields {
+            let header = MemberHeader {
+                access_flags: field.access_flags,
+                name_index: try!(symbols.constant_pool.utf8_at(field.name_index, &field.name)),
+                descriptor_index: try!(symbols.constant_pool
+                    .utf8_at(field.descriptor_index, &field.descriptor)),
+            };
+
+            if let Some(mut field_visitor) = try!(visitor.visit_field(&mut symbols, header)) {
+                for attribute in &field.attributes {
+                    try!(field_visitor.visit_attribute(&mut symbols, attribute.clone()));
+                }
+                try!(field_visitor.visit_end(&mut symbols));
+            }
+        }
+
+        for method in &classfile.methods {
+            let header = MemberHeader {
+                access_flags: method.access_flags,
+                name_index: try!(symbols.constant_pool.utf8_at(method.name_index, &method.name)),
+                descriptor_index: try!(symbols.constant_pool
+                    .utf8_at(method.descriptor_index, &method.descriptor)),
+            };
+
+            if let Some(mut method_visitor) = try!(visitor.visit_method(&mut symbols, header)) {
+                for attribute in &method.attributes {
+                    let attribute = match **attribute {
+                        Attribute::Lazy(ref lazy) if lazy.attribute_name.as_str() == "Code" => {
+                            try!(lazy.decode())
+                        }
+                        _ => attribute.clone(),
+                    };
+
+                    match *attribute {
+                        Attribute::Code(ref code) => {
+                            try!(read_code(code, &mut *method_visitor, &mut symbols))
+                        }
+                        _ => try!(method_visitor.visit_attribute(&mut symbols, attribute.clone())),
+                    }
+                }
+                try!(method_visitor.visit_end(&mut symbols));
+            }
+        }
+
+        for attribute in &classfile.attributes {
+            try!(visitor.visit_attribute(&mut symbols, attribute.clone()));
+        }
+
+        visitor.visit_end(&mut symbols)
+    }
+}
+
+fn read_code(code: &CodeAttribute,
+             visitor: &mut dyn MethodVisitor,
+             symbols: &mut SymbolTable)
+             -> ParserResult<()> {
+    try!(visitor.visit_code(symbols, code.max_stack, code.max_locals));
+
+    let assembler = try!(CodeAssembler::from_code(code));
+
+    // every label of the assembler is placed, so it can be swapped for one of the pipeline first
+    let mut labels = HashMap::new();
+    for item in &assembler.items {
+        if let CodeItem::Label(label) = *item {
+            labels.insert(label, symbols.new_label());
+        }
+    }
+    let relabel = |label: Label| labels[&label];
+
+    for item in assembler.items {
+        match item {
+            CodeItem::Label(label) => try!(visitor.visit_label(symbols, relabel(label))),
+            CodeItem::Instruction(instruction) => {
+                try!(visitor.visit_instruction(symbols, instruction.map_targets(&relabel)))
+            }
+        }
+    }
+
+    for handler in assembler.exception_handlers {
+        let handler = LabelledExceptionHandler {
+            start: relabel(handler.start),
+            end: relabel(handler.end),
+            handler: relabel(handler.handler),
+            catch_type: handler.catch_type,
+        };
+        try!(visitor.visit_exception_handler(symbols, handler));
+    }
+
+    let relabel_local_variable = |local_variable: LabelledLocalVariable| {
+        LabelledLocalVariable {
+            start: relabel(local_variable.start),
+            end: relabel(local_variable.end),
+            ..local_variable
+        }
+    };
+
+    // the assembler gathers each kind of table into one, which takes the place of the first, and
+    // a kind with no entries at all is passed on as it is so that its table is not lost
+    let mut line_numbers = Some(assembler.line_numbers);
+    let mut local_variables = Some(assembler.local_variables);
+    let mut local_variable_types = Some(assembler.local_variable_types);
+    for attribute in &code.attributes {
+        match *attribute {
+            Attribute::LineNumberTable(..) => {
+                match line_numbers.take() {
+                    Some(ref table) if table.is_empty() => {
+                        try!(visitor.visit_code_attribute(symbols, attribute.clone()))
+                    }
+                    table => {
+                        for line_number in table.unwrap_or_default() {
+                            let line_number = LabelledLineNumber {
+                                start: relabel(line_number.start),
+                                line_number: line_number.line_number,
+                            };
+                            try!(visitor.visit_line_number(symbols, line_number));
+                        }
+                    }
+                }
+            }
+            Attribute::LocalVariableTable(..) => {
+                match local_variables.take() {
+                    Some(ref table) if table.is_empty() => {
+                        try!(visitor.visit_code_attribute(symbols, attribute.clone()))
+                    }
+                    table => {
+                        for local_variable in table.unwrap_or_default() {
+                            let local_variable = relabel_local_variable(local_variable);
+                            try!(visitor.visit_local_variable(symbols, local_variable));
+                        }
+                    }
+                }
+            }
+            Attribute::LocalVariableTypeTable(..) => {
+                match local_variable_types.take() {
+                    Some(ref table) if table.is_empty() => {
+                        try!(visitor.visit_code_attribute(symbols, attribute.clone()))
+                    }
+                    table => {
+                        for local_variable in table.unwrap_or_default() {
+                            let local_variable = relabel_local_variable(local_variable);
+                            try!(visitor.visit_local_variable_type(symbols, local_variable));
+                        }
+                    }
+                }
+            }
+            _ => try!(visitor.visit_code_attribute(symbols, attribute.clone())),
+        }
+    }
+
+    Ok(())
+}
+
+/// What a `ClassWriter` computes for the method bodies it writes instead of taking it from the
+/// events it receives.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WriterOptions {
+    /// Computes the operand stack and local variable sizes of each body with
+    /// `CodeLimits::compute`, in place of the ones given to `visit_code`.
+    pub compute_limits: bool,
+    /// Drops any `StackMapTable` of each body and, from version 50 on, computes a new one with
+    /// `stackmap::compute` for bodies that need one. This computes the sizes of each body as
+    /// well, and fails on bodies with unreachable code or subroutines.
+    pub compute_frames: bool,
+}
+
+/// Ends a pipeline by writing the class it is shown as class file bytes. The constant pool
+/// written is the one of the symbol table.
+pub struct ClassWriter<'h> {
+    options: WriterOptions,
+    hierarchy: &'h dyn ClassHierarchy,
+    header: Option<ClassHeader>,
+    fields: Vec<Vec<U1>>,
+    methods: Vec<Vec<U1>>,
+    attributes: Vec<Vec<U1>>,
+    bytes: Option<Vec<U1>>,
+}
+
+impl<'h> ClassWriter<'h> {
+    /// A writer that writes method bodies as it is given them.
+    pub fn new() -> ClassWriter<'h> {
+        ClassWriter::with_options(WriterOptions::default())
+    }
+
+    pub fn with_options(options: WriterOptions) -> ClassWriter<'h> {
+        ClassWriter {
+            options: options,
+            hierarchy: &ObjectHierarchy,
+            header: None,
+            fields: vec![],
+            methods: vec![],
+            attributes: vec![],
+            bytes: None,
+        }
+    }
+
+    /// Merges reference types with `hierarchy` when computing frames rather than with
+    /// `ObjectHierarchy`, which merges different classes to `java/lang/Object`.
+    pub fn hierarchy<H: ClassHierarchy>(mut self, hierarchy: &'h H) -> ClassWriter<'h> {
+        self.hierarchy = hierarchy;
+        self
+    }
+
+    /// The class file, once the end of the class has been visited.
+    pub fn into_bytes(self) -> Option<Vec<U1>> {
+        self.bytes
+    }
+}
+
+impl<'h> Default for ClassWriter<'h> {
+    fn default() -> ClassWriter<'h> {
+        ClassWriter::new()
+    }
+}
+
+impl<'h> ClassVisitor for ClassWriter<'h> {
+    fn visit(&mut self, _symbols: &mut SymbolTable, header: ClassHeader) -> ParserResult<()> {
+        self.header = Some(header);
+        Ok(())
+    }
+
+    fn visit_field<'a>(&'a mut self,
+                       _symbols: &mut SymbolTable,
+                       field: MemberHeader)
+                       -> ParserResult<Option<Box<dyn FieldVisitor + 'a>>> {
+        Ok(Some(Box::new(MemberWriter {
+            members: &mut self.fields,
+            header: field,
+            attributes: vec![],
+        })))
+    }
+
+    fn visit_method<'a>(&'a mut self,
+                        _symbols: &mut SymbolTable,
+                        method: MemberHeader)
+                        -> ParserResult<Option<Box<dyn MethodVisitor + 'a>>> {
+        Ok(Some(Box::new(MethodWriter {
+            member: MemberWriter {
+                members: &mut self.methods,
+                header: method,
+                attributes: vec![],
+            },
+            code: None,
+            class: self.header.as_ref(),
+            options: self.options,
+            hierarchy: self.hierarchy,
+        })))
+    }
+
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        let mut bytes = vec![];
+        try!(write_attribute(&attribute, &mut bytes, symbols));
+        self.attributes.push(bytes);
+
+        Ok(())
+    }
+
+    fn visit_end(&mut self, symbols: &mut SymbolTable) -> ParserResult<()> {
+        let header = try!(self.header.take().ok_or(ParserError::MissingClassHeader));
+
+        let mut bytes = vec![];
+        bytes.write_u4(0xCAFEBABE);
+        bytes.write_u2(header.minor_version);
+        bytes.write_u2(header.major_version);
+        symbols.constant_pool.write(&mut bytes);
+        bytes.write_u2(header.access_flags);
+        bytes.write_u2(header.this_class);
+        bytes.write_u2(header.super_class);
+
+        bytes.write_u2(header.interfaces.len() as U2);
+        for interface in header.interfaces {
+            bytes.write_u2(interface);
+        }
+        for members in &[&self.fields, &self.methods, &self.attributes] {
+            bytes.write_u2(members.len() as U2);
+            for member in members.iter() {
+                bytes.write_bytes(member);
+            }
+        }
+
+        self.bytes = Some(bytes);
+        Ok(())
+    }
+}
+
+fn write_attribute(attribute: &Attribute,
+                   bytes: &mut Vec<U1>,
+                   symbols: &mut SymbolTable)
+                   -> ParserResult<()> {
+    attribute.write(bytes, &mut |name| symbols.constant_pool.utf8(name))
+}
+
+/// Writes a field or method to the members of a `ClassWriter` once its end is visited.
+struct MemberWriter<'w> {
+    members: &'w mut Vec<Vec<U1>>,
+    header: MemberHeader,
+    attributes: Vec<Vec<U1>>,
+}
+
+impl<'w> MemberWriter<'w> {
+    fn write(&mut self) {
+        let mut bytes = vec![];
+        bytes.write_u2(self.header.access_flags);
+        bytes.write_u2(self.header.name_index);
+        bytes.write_u2(self.header.descriptor_index);
+        bytes.write_u2(self.attributes.len() as U2);
+        for attribute in &self.attributes {
+            bytes.write_bytes(attribute);
+        }
+
+        self.members.push(bytes);
+    }
+}
+
+impl<'w> FieldVisitor for MemberWriter<'w> {
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        let mut bytes = vec![];
+        try!(write_attribute(&attribute, &mut bytes, symbols));
+        self.attributes.push(bytes);
+
+        Ok(())
+    }
+
+    fn visit_end(&mut self, _symbols: &mut SymbolTable) -> ParserResult<()> {
+        self.write();
+        Ok(())
+    }
+}
+
+/// A nested attribute of code being written, in the order the first of its kind was visited.
+enum CodeTable {
+    LineNumbers,
+    LocalVariables,
+    LocalVariableTypes,
+    Other(Attribute),
+}
+
+struct CodeWriter {
+    max_stack: U2,
+    max_locals: U2,
+    assembler: CodeAssembler,
+    tables: Vec<CodeTable>,
+    /// Where the code goes among the attributes of the method.
+    position: usize,
+}
+
+impl CodeWriter {
+    fn add_table(&mut self, table: CodeTable) {
+        let is_new = match table {
+            CodeTable::Other(..) => true,
+            _ => {
+                !self.tables
+                    .iter()
+                    .any(|existing| mem::discriminant(existing) == mem::discriminant(&table))
+            }
+        };
+
+        if is_new {
+            self.tables.push(table);
+        }
+    }
+
+    fn assemble(self) -> ParserResult<CodeAttribute> {
+        let assembled = try!(self.assembler.assemble());
+
+        let mut line_numbers = Some(assembled.line_numbers);
+        let mut local_variables = Some(assembled.local_variables);
+        let mut local_variable_types = Some(assembled.local_variable_types);
+        let mut attributes = vec![];
+        for table in self.tables {
+            let attribute = match table {
+                CodeTable::LineNumbers => {
+                    let table = line_numbers.take().unwrap_or_default();
+                    Attribute::LineNumberTable(Rc::new(LineNumberTableAttribute {
+                        line_number_table_length: table.len() as U2,
+                        line_number_table: table,
+                    }))
+                }
+                CodeTable::LocalVariables => {
+                    let table = local_variables.take().unwrap_or_default();
+                    Attribute::LocalVariableTable(Rc::new(LocalVariableTableAttribute {
+                        local_variable_table_length: table.len() as U2,
+                        local_variable_table: table,
+                    }))
+                }
+                CodeTable::LocalVariableTypes => {
+                    let table = local_variable_types.take().unwrap_or_default();
+                    Attribute::LocalVariableTypeTable(Rc::new(LocalVariableTableAttribute {
+                        local_variable_table_length: table.len() as U2,
+                        local_variable_table: table,
+                    }))
+                }
+                CodeTable::Other(attribute) => attribute,
+            };
+            attributes.push(attribute);
+        }
+
+        Ok(CodeAttribute {
+            max_stack: self.max_stack,
+            max_locals: self.max_locals,
+            code_length: assembled.code.len() as U4,
+            code: assembled.code,
+            exception_table_length: assembled.exception_table.len() as U2,
+            exception_table: assembled.exception_table,
+            attributes_count: attributes.len() as U2,
+            attributes: attributes,
+        })
+    }
+}
+
+struct MethodWriter<'w> {
+    member: MemberWriter<'w>,
+    code: Option<CodeWriter>,
+    class: Option<&'w ClassHeader>,
+    options: WriterOptions,
+    hierarchy: &'w dyn ClassHierarchy,
+}
+
+impl<'w> MethodWriter<'w> {
+    /// The code being written, which starts out with no room for locals or the stack when code
+    /// arrives without `visit_code`.
+    fn code(&mut self) -> &mut CodeWriter {
+        let position = self.member.attributes.len();
+        self.code.get_or_insert_with(|| {
+            CodeWriter {
+                max_stack: 0,
+                max_locals: 0,
+                assembler: CodeAssembler::new(),
+                tables: vec![],
+                position: position,
+            }
+        })
+    }
+
+    /// Computes what the options ask for of `code`, as the body of this method in the class
+    /// written so far.
+    fn compute(&self, code: &mut CodeAttribute, symbols: &mut SymbolTable) -> ParserResult<()> {
+        let header = try!(self.class.ok_or(ParserError::MissingClassHeader));
+        let mut classfile = class_file(header, &symbols.constant_pool);
+        let method = try!(method(&self.member.header, &classfile.constant_pool));
+
+        let limits = try!(CodeLimits::compute(&classfile, &method, code));
+        code.max_stack = limits.max_stack;
+        code.max_locals = limits.max_locals;
+
+        if !self.options.compute_frames {
+            return Ok(());
+        }
+
+        // the new table takes the place of the old one, if there was one
+        let position = code.attributes
+            .iter()
+            .position(is_stack_map_table)
+            .unwrap_or(code.attributes.len());
+        code.attributes.retain(|attribute| !is_stack_map_table(attribute));
+
+        if classfile.major_version >= 50 && try!(stackmap::is_required(code)) {
+            let count = classfile.constant_pool.len();
+            let table = try!(stackmap::compute(&mut classfile, &method, code, &self.hierarchy));
+
+            // computing the table appends the entries it is missing to the pool of the class
+            for item in &classfile.constant_pool[count..] {
+                if let Some(constant) = Constant::from_item(item) {
+                    try!(symbols.constant_pool.push(constant));
+                }
+            }
+            code.attributes.insert(position, Attribute::StackMapTable(Rc::new(table)));
+        }
+        code.attributes_count = code.attributes.len() as U2;
+
+        Ok(())
+    }
+}
+
+fn is_stack_map_table(attribute: &Attribute) -> bool {
+    match *attribute {
+        Attribute::StackMapTable(..) => true,
+        _ => false,
+    }
+}
+
+/// A class with the header and constant pool of the class being written, and no members.
+fn class_file(header: &ClassHeader, constant_pool: &ConstantPool) -> ClassFile {
+    ClassFile {
+        magic: 0xCAFEBABE,
+        minor_version: header.minor_version,
+        major_version: header.major_version,
+        constant_pool_count: constant_pool.next_index() as U2,
+        constant_pool: constant_pool.to_items(),
+        access_flags: header.access_flags,
+        this_class: header.this_class,
+        super_class: header.super_class,
+        interfaces_count: header.interfaces.len() as U2,
+        interfaces: header.interfaces.clone(),
+        fields_count: 0,
+        fields: vec![],
+        methods_count: 0,
+        methods: vec![],
+        attributes_count: 0,
+        attributes: vec![],
+    }
+}
+
+fn method(header: &MemberHeader, constant_pool: &Vec<ConstantPoolItem>) -> ParserResult<Method> {
+    Ok(Method {
+        access_flags: header.access_flags,
+        name_index: header.name_index,
+        name: try!(ConstantPoolItem::retrieve_utf8_info(header.name_index, constant_pool)),
+        descriptor_index: header.descriptor_index,
+        descriptor: try!(ConstantPoolItem::retrieve_utf8_info(header.descriptor_index,
+                                                              constant_pool)),
+        attributes_count: 0,
+        attributes: vec![],
+    })
+}
+
+impl<'w> MethodVisitor for MethodWriter<'w> {
+    fn visit_attribute(&mut self,
+                       symbols: &mut SymbolTable,
+                       attribute: Rc<Attribute>)
+                       -> ParserResult<()> {
+        FieldVisitor::visit_attribute(&mut self.member, symbols, attribute)
+    }
+
+    fn visit_code(&mut self,
+                  _symbols: &mut SymbolTable,
+                  max_stack: U2,
+                  max_locals: U2)
+                  -> ParserResult<()> {
+        let code = self.code();
+        code.max_stack = max_stack;
+        code.max_locals = max_locals;
+
+        Ok(())
+    }
+
+    fn visit_label(&mut self, _symbols: &mut SymbolTable, label: Label) -> ParserResult<()> {
+        self.code().assembler.place_label(label);
+        Ok(())
+    }
+
+    fn visit_instruction(&mut self,
+                         _symbols: &mut SymbolTable,
+                         instruction: Instruction<Label>)
+                         -> ParserResult<()> {
+        self.code().assembler.push(instruction);
+        Ok(())
+    }
+
+    fn visit_exception_handler(&mut self,
+                               _symbols: &mut SymbolTable,
+                               handler: LabelledExceptionHandler)
+                               -> ParserResult<()> {
+        self.code().assembler.exception_handlers.push(handler);
+        Ok(())
+    }
+
+    fn visit_line_number(&mut self,
+                         _symbols: &mut SymbolTable,
+                         line_number: LabelledLineNumber)
+                         -> ParserResult<()> {
+        let code = self.code();
+        code.add_table(CodeTable::LineNumbers);
+        code.assembler.line_numbers.push(line_number);
+
+        Ok(())
+    }
+
+    fn visit_local_variable(&mut self,
+                            _symbols: &mut SymbolTable,
+                            local_variable: LabelledLocalVariable)
+                            -> ParserResult<()> {
+        let code = self.code();
+        code.add_table(CodeTable::LocalVariables);
+        code.assembler.local_variables.push(local_variable);
+
+        Ok(())
+    }
+
+    fn visit_local_variable_type(&mut self,
+                                 _symbols: &mut SymbolTable,
+                                 local_variable: LabelledLocalVariable)
+                                 -> ParserResult<()> {
+        let code = self.code();
+        code.add_table(CodeTable::LocalVariableTypes);
+        code.assembler.local_variable_types.push(local_variable);
+
+        Ok(())
+    }
+
+    fn visit_code_attribute(&mut self,
+                            _symbols: &mut SymbolTable,
+                            attribute: Attribute)
+                            -> ParserResult<()> {
+        self.code().add_table(CodeTable::Other(attribute));
+        Ok(())
+    }
+
+    fn visit_end(&mut self, symbols: &mut SymbolTable) -> ParserResult<()> {
+        if let Some(code) = self.code.take() {
+            let position = code.position;
+            let mut code = try!(code.assemble());
+            if self.options.compute_limits || self.options.compute_frames {
+                try!(self.compute(&mut code, symbols));
+            }
+            let code = Attribute::Code(Rc::new(code));
+
+            let mut bytes = vec![];
+            try!(write_attribute(&code, &mut bytes, symbols));
+            self.member.attributes.insert(position, bytes);
+        }
+
+        self.member.write();
+        Ok(())
+    }
+}
@@ -0,0 +1,1520 @@
+use super::{ClassFile, ParserError, ParserResult};
+use super::access::MethodAccess;
+use super::components::{Attribute, CodeAttribute, ConstantPoolResolver, LoadableConstant,
+                        Method, StackMapFrame, VerificationTypeInfo};
+use super::frames::{self, VerificationType};
+use super::hierarchy::ClassHierarchy;
+use super::instructions::{ArrayType, Instruction};
+use super::primitives::U2;
+use super::subroutines::{self, Subroutine};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum VerifyErrorKind {
+    /// A value does not have the type an instruction or a stack map frame requires. Where any
+    /// reference will do, `java/lang/Object` is expected.
+    TypeMismatch {
+        expected: VerificationType,
+        actual: VerificationType,
+    },
+    /// The operand stack does not have the height a stack map frame requires, in slots.
+    StackHeightMismatch { expected: usize, actual: usize },
+    /// A long or double used where a single slot value is needed, or split by an untyped stack
+    /// instruction. Also a constant loaded by `ldc2_w` that is not a long or double.
+    WrongCategory(VerificationType),
+    StackUnderflow,
+    /// The operand stack grows beyond `max_stack`.
+    StackOverflow,
+    /// A local variable at or beyond `max_locals`.
+    InvalidLocal(U2),
+    /// A branch target, exception handler or instruction following an unconditional branch that
+    /// has no stack map frame, given by its pc.
+    MissingFrame(U2),
+    /// A stack map frame that is not at the start of an instruction or cannot be decoded.
+    MalformedFrame,
+    /// A return instruction that does not match the return type of the method.
+    InvalidReturn,
+    /// A `jsr` or `ret` in code verified by type checking, which does not allow them, or a `ret`
+    /// that is not part of any subroutine.
+    Subroutine,
+    /// Execution can continue past the last instruction.
+    FallsOffEnd,
+}
+
+/// The first instruction of a method found not to be type safe.
+#[derive(Clone, Debug, PartialEq)]
+pub struct VerifyError {
+    pub method_name: String,
+    pub method_descriptor: String,
+    pub pc: U2,
+    pub kind: VerifyErrorKind,
+}
+
+/// Verifies every method of `classfile` the way the JVM does for its version, and returns the
+/// first error found in each method.
+///
+/// Class files from version 50 onwards are verified by type checking against their
+/// `StackMapTable` attributes, older ones by type inference. A method of a version 50 class file
+/// that fails type checking is verified again by type inference, as the JVM falls back to it for
+/// that version.
+///
+/// Questions about classes other than the one being verified are answered by `hierarchy`, so it
+/// has to know about every class the code refers to for the result to be exact. Access to
+/// protected members is not checked.
+pub fn verify<H: ClassHierarchy>(classfile: &ClassFile,
+                                 hierarchy: &H)
+                                 -> ParserResult<Vec<VerifyError>> {
+    verify_methods(classfile,
+                   |method| verify_method(classfile, method, hierarchy))
+}
+
+/// Verifies a single method of `classfile` the way the JVM does for its version. Methods without
+/// code are always type safe.
+pub fn verify_method<H: ClassHierarchy>(classfile: &ClassFile,
+                                        method: &Method,
+                                        hierarchy: &H)
+                                        -> ParserResult<Option<VerifyError>> {
+    if classfile.major_version < 50 {
+        return verify_method_by(classfile, method, hierarchy, Strategy::TypeInference);
+    }
+
+    let error = try!(verify_method_by(classfile, method, hierarchy, Strategy::TypeChecking));
+    if error.is_some() && classfile.major_version == 50 {
+        verify_method_by(classfile, method, hierarchy, Strategy::TypeInference)
+    } else {
+        Ok(error)
+    }
+}
+
+/// Verifies every method of `classfile` by type checking, as described in section 4.10.1 of the
+/// JVM specification.
+///
+/// Type checking requires the `StackMapTable` attributes introduced in version 50, so older class
+/// files are rejected.
+pub fn check_types<H: ClassHierarchy>(classfile: &ClassFile,
+                                      hierarchy: &H)
+                                      -> ParserResult<Vec<VerifyError>> {
+    if classfile.major_version < 50 {
+        return Err(ParserError::UnsupportedClassVersion(classfile.major_version));
+    }
+
+    verify_methods(classfile, |method| {
+        verify_method_by(classfile, method, hierarchy, Strategy::TypeChecking)
+    })
+}
+
+/// Verifies every method of `classfile` by type inference, as described in section 4.10.2 of
+/// the JVM specification, ignoring any `StackMapTable` attribute.
+///
+/// Subroutines are allowed. The local variables a subroutine does not write keep the types they
+/// had at the `jsr` when it returns, so each caller can use its own.
+pub fn infer_types<H: ClassHierarchy>(classfile: &ClassFile,
+                                      hierarchy: &H)
+                                      -> ParserResult<Vec<VerifyError>> {
+    verify_methods(classfile, |method| {
+        verify_method_by(classfile, method, hierarchy, Strategy::TypeInference)
+    })
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Strategy {
+    TypeChecking,
+    TypeInference,
+}
+
+fn verify_methods<F>(classfile: &ClassFile, mut verify_method: F) -> ParserResult<Vec<VerifyError>>
+    where F: FnMut(&Method) -> ParserResult<Option<VerifyError>>
+{
+    let mut errors = vec![];
+    for method in &classfile.methods {
+        if let Some(error) = try!(verify_method(method)) {
+            errors.push(error);
+        }
+    }
+
+    Ok(errors)
+}
+
+fn verify_method_by<H: ClassHierarchy>(classfile: &ClassFile,
+                                       method: &Method,
+                                       hierarchy: &H,
+                                       strategy: Strategy)
+                                       -> ParserResult<Option<VerifyError>> {
+    let code = match method.code() {
+        Some(code) => code,
+        None => return Ok(None),
+    };
+
+    let resolver = ConstantPoolResolver { constant_pool: &classfile.constant_pool };
+    let this_class = try!(resolver.resolve_class_name(classfile.this_class)).to_string();
+    let super_class = if classfile.super_class == 0 {
+        None
+    } else {
+        Some(try!(resolver.resolve_class_name(classfile.super_class)).to_string())
+    };
+    let (parameters, return_type) = try!(frames::method_types(&method.descriptor));
+
+    let checker = TypeChecker {
+        resolver: resolver,
+        this_class: this_class,
+        super_class: super_class,
+        method: method,
+        parameters: parameters,
+        return_type: return_type,
+        hierarchy: hierarchy,
+    };
+
+    let result = match strategy {
+        Strategy::TypeChecking => checker.check(&code),
+        Strategy::TypeInference => checker.infer(&code),
+    };
+    match result {
+        Ok(()) => Ok(None),
+        Err(Failure::Unsafe(pc, kind)) => {
+            Ok(Some(VerifyError {
+                method_name: method.name.to_string(),
+                method_descriptor: method.descriptor.to_string(),
+                pc: pc,
+                kind: kind,
+            }))
+        }
+        Err(Failure::Parser(error)) => Err(error),
+    }
+}
+
+/// Why checking stopped: either the code is not type safe, or the class file is too malformed to
+/// check it at all.
+enum Failure {
+    Unsafe(U2, VerifyErrorKind),
+    Parser(ParserError),
+}
+
+impl From<ParserError> for Failure {
+    fn from(error: ParserError) -> Failure {
+        Failure::Parser(error)
+    }
+}
+
+type CheckResult<T> = Result<T, Failure>;
+
+/// The types of the local variables and operand stack while checking an instruction, along with
+/// whether `this` has yet to be initialized in a constructor.
+#[derive(Clone, Debug, PartialEq)]
+struct State {
+    locals: Vec<VerificationType>,
+    stack: Vec<VerificationType>,
+    this_uninitialized: bool,
+}
+
+impl State {
+    fn stack_height(&self) -> usize {
+        self.stack.iter().map(|value| value.size()).sum()
+    }
+
+    fn push(&mut self, value: VerificationType) {
+        self.stack.push(value);
+    }
+
+    fn pop(&mut self, pc: U2) -> CheckResult<VerificationType> {
+        self.stack.pop().ok_or(Failure::Unsafe(pc, VerifyErrorKind::StackUnderflow))
+    }
+
+    /// Pops entries taking up exactly `slots` slots, as the untyped stack instructions do, and
+    /// returns them from bottom to top.
+    fn pop_slots(&mut self, slots: usize, pc: U2) -> CheckResult<Vec<VerificationType>> {
+        let mut values = vec![];
+        let mut popped = 0;
+        while popped < slots {
+            let value = try!(self.pop(pc));
+            popped += value.size();
+            if popped > slots {
+                return Err(Failure::Unsafe(pc, VerifyErrorKind::WrongCategory(value)));
+            }
+            values.push(value);
+        }
+
+        values.reverse();
+        Ok(values)
+    }
+
+    fn local(&self, index: U2, size: usize, pc: U2) -> CheckResult<VerificationType> {
+        if index as usize + size > self.locals.len() {
+            return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidLocal(index)));
+        }
+
+        Ok(self.locals[index as usize].clone())
+    }
+
+    fn store(&mut self, index: U2, value: VerificationType, pc: U2) -> CheckResult<()> {
+        let index = index as usize;
+        let size = value.size();
+        if index + size > self.locals.len() {
+            return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidLocal(index as U2)));
+        }
+
+        // overwriting the second slot of a long or double destroys it
+        if index > 0 && self.locals[index - 1].size() == 2 {
+            self.locals[index - 1] = VerificationType::Top;
+        }
+
+        self.locals[index] = value;
+        if size == 2 {
+            self.locals[index + 1] = VerificationType::Top;
+        }
+
+        Ok(())
+    }
+
+    fn replace(&mut self, old: &VerificationType, new: &VerificationType) {
+        for value in self.locals.iter_mut().chain(self.stack.iter_mut()) {
+            if value == old {
+                *value = new.clone();
+            }
+        }
+    }
+}
+
+struct TypeChecker<'c, 'h, H: ClassHierarchy + 'h> {
+    resolver: ConstantPoolResolver<'c>,
+    this_class: String,
+    super_class: Option<String>,
+    method: &'c Method,
+    parameters: Vec<VerificationType>,
+    return_type: Option<VerificationType>,
+    hierarchy: &'h H,
+}
+
+impl<'c, 'h, H: ClassHierarchy> TypeChecker<'c, 'h, H> {
+    fn check(&self, code: &CodeAttribute) -> CheckResult<()> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+        let indices = instructions.iter()
+            .enumerate()
+            .map(|(index, &(pc, _))| (pc, index))
+            .collect::<HashMap<_, _>>();
+
+        let initial = try!(self.initial_state(code.max_locals as usize));
+        let stack_map = try!(self.stack_map(code, &initial));
+        for (pc, frame) in &stack_map {
+            if !indices.contains_key(pc) {
+                return Err(Failure::Unsafe(*pc, VerifyErrorKind::MalformedFrame));
+            }
+            if frame.stack_height() > code.max_stack as usize {
+                return Err(Failure::Unsafe(*pc, VerifyErrorKind::StackOverflow));
+            }
+        }
+
+        let mut current = Some(initial);
+        for &(pc, ref instruction) in &instructions {
+            let state = match (stack_map.get(&pc), current.take()) {
+                (Some(frame), Some(incoming)) => {
+                    try!(self.check_assignable(pc, &incoming, frame));
+                    frame.clone()
+                }
+                (Some(frame), None) => frame.clone(),
+                (None, Some(incoming)) => incoming,
+                (None, None) => return Err(Failure::Unsafe(pc, VerifyErrorKind::MissingFrame(pc))),
+            };
+
+            for handler in &code.exception_table {
+                if pc < handler.start_pc || pc >= handler.end_pc {
+                    continue;
+                }
+
+                let catch_type = if handler.catch_type == 0 {
+                    "java/lang/Throwable".to_string()
+                } else {
+                    try!(self.resolver.resolve_class_name(handler.catch_type)).to_string()
+                };
+                let exception = VerificationType::Object(catch_type);
+                let throwable = VerificationType::Object("java/lang/Throwable".to_string());
+                try!(self.check_type(pc, &exception, &throwable));
+
+                let target = try!(self.frame_at(&stack_map, handler.handler_pc, pc));
+                let thrown = State {
+                    locals: state.locals.clone(),
+                    stack: vec![exception],
+                    this_uninitialized: state.this_uninitialized,
+                };
+                try!(self.check_assignable(pc, &thrown, target));
+            }
+
+            let mut after = state;
+            try!(self.execute(pc, instruction, &mut after, &instructions, &indices));
+            if after.stack_height() > code.max_stack as usize {
+                return Err(Failure::Unsafe(pc, VerifyErrorKind::StackOverflow));
+            }
+
+            for offset in instruction.branch_targets() {
+                let target_pc = (pc as i64 + *offset as i64) as U2;
+                let target = try!(self.frame_at(&stack_map, target_pc, pc));
+                try!(self.check_assignable(pc, &after, target));
+            }
+
+            current = match *instruction {
+                Instruction::Goto(..) |
+                Instruction::GotoW(..) |
+                Instruction::Tableswitch { .. } |
+                Instruction::Lookupswitch { .. } |
+                Instruction::Ireturn |
+                Instruction::Lreturn |
+                Instruction::Freturn |
+                Instruction::Dreturn |
+                Instruction::Areturn |
+                Instruction::Return |
+                Instruction::Athrow => None,
+                _ => Some(after),
+            };
+        }
+
+        match (current, instructions.last()) {
+            (Some(_), Some(&(pc, _))) => Err(Failure::Unsafe(pc, VerifyErrorKind::FallsOffEnd)),
+            (Some(_), None) => Err(Failure::Unsafe(0, VerifyErrorKind::FallsOffEnd)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Infers the state before every reachable instruction of `code` by simulating it until
+    /// nothing changes, checking each instruction along the way.
+    fn infer(&self, code: &CodeAttribute) -> CheckResult<()> {
+        let mut instructions = vec![];
+        for result in code.instructions() {
+            instructions.push(try!(result));
+        }
+        if instructions.is_empty() {
+            return Err(Failure::Unsafe(0, VerifyErrorKind::FallsOffEnd));
+        }
+        let indices = instructions.iter()
+            .enumerate()
+            .map(|(index, &(pc, _))| (pc, index))
+            .collect::<HashMap<_, _>>();
+        let index_of = |pc: usize| {
+            indices.get(&(pc as U2))
+                .cloned()
+                .ok_or(Failure::Parser(ParserError::InvalidCodeOffset(pc)))
+        };
+        let subroutines = try!(subroutines::find(code));
+
+        let mut states = vec![None; instructions.len()];
+        states[0] = Some(try!(self.initial_state(code.max_locals as usize)));
+
+        let mut worklist = BTreeSet::new();
+        worklist.insert(0);
+        while let Some(index) = worklist.iter().next().cloned() {
+            worklist.remove(&index);
+            let (pc, ref instruction) = instructions[index];
+            let state = states[index].clone().unwrap();
+
+            let mut successors = vec![];
+            for handler in &code.exception_table {
+                if pc < handler.start_pc || pc >= handler.end_pc {
+                    continue;
+                }
+
+                let catch_type = if handler.catch_type == 0 {
+                    "java/lang/Throwable".to_string()
+                } else {
+                    try!(self.resolver.resolve_class_name(handler.catch_type)).to_string()
+                };
+                let exception = VerificationType::Object(catch_type);
+                let throwable = VerificationType::Object("java/lang/Throwable".to_string());
+                try!(self.check_type(pc, &exception, &throwable));
+
+                let thrown = State {
+                    locals: state.locals.clone(),
+                    stack: vec![exception],
+                    this_uninitialized: state.this_uninitialized,
+                };
+                successors.push((try!(index_of(handler.handler_pc as usize)), thrown));
+            }
+
+            let mut after = state.clone();
+            match *instruction {
+                Instruction::Jsr(..) |
+                Instruction::JsrW(..) => after.push(VerificationType::ReturnAddress),
+                Instruction::Ret(index) => {
+                    let value = try!(after.local(index, 1, pc));
+                    try!(self.check_type(pc, &value, &VerificationType::ReturnAddress));
+                }
+                _ => try!(self.execute(pc, instruction, &mut after, &instructions, &indices)),
+            }
+            if after.stack_height() > code.max_stack as usize {
+                return Err(Failure::Unsafe(pc, VerifyErrorKind::StackOverflow));
+            }
+
+            for offset in instruction.branch_targets() {
+                let target = try!(index_of((pc as i64 + *offset as i64) as usize));
+                successors.push((target, after.clone()));
+            }
+
+            match *instruction {
+                Instruction::Goto(..) |
+                Instruction::GotoW(..) |
+                Instruction::Tableswitch { .. } |
+                Instruction::Lookupswitch { .. } |
+                Instruction::Ireturn |
+                Instruction::Lreturn |
+                Instruction::Freturn |
+                Instruction::Dreturn |
+                Instruction::Areturn |
+                Instruction::Return |
+                Instruction::Athrow => {}
+                Instruction::Jsr(offset) |
+                Instruction::JsrW(offset) => {
+                    if index + 1 == instructions.len() {
+                        return Err(Failure::Unsafe(pc, VerifyErrorKind::FallsOffEnd));
+                    }
+
+                    // the subroutine may already have returned to other callers
+                    let entry = (pc as i64 + offset as i64) as U2;
+                    for subroutine in subroutines.iter().filter(|called| called.entry == entry) {
+                        for ret_pc in &subroutine.instructions {
+                            let ret_index = indices[ret_pc];
+                            if let (&Instruction::Ret(..), &Some(ref returning)) =
+                                   (&instructions[ret_index].1, &states[ret_index]) {
+                                let returned = returned(returning, &state, subroutine);
+                                successors.push((index + 1, returned));
+                            }
+                        }
+                    }
+                }
+                Instruction::Ret(..) => {
+                    let returning = subroutines.iter()
+                        .filter(|subroutine| subroutine.instructions.contains(&pc))
+                        .collect::<Vec<_>>();
+                    if returning.is_empty() {
+                        return Err(Failure::Unsafe(pc, VerifyErrorKind::Subroutine));
+                    }
+
+                    for subroutine in returning {
+                        for caller in &subroutine.callers {
+                            let caller_index = indices[caller];
+                            if let Some(ref calling) = states[caller_index] {
+                                // a jsr at the end of the code reports falling off it itself
+                                if caller_index + 1 < instructions.len() {
+                                    let returned = returned(&after, calling, subroutine);
+                                    successors.push((caller_index + 1, returned));
+                                }
+                            }
+                        }
+                    }
+                }
+                _ => {
+                    if index + 1 == instructions.len() {
+                        return Err(Failure::Unsafe(pc, VerifyErrorKind::FallsOffEnd));
+                    }
+                    successors.push((index + 1, after.clone()));
+                }
+            }
+
+            for (target, incoming) in successors {
+                if try!(self.merge(&mut states[target], incoming, pc)) {
+                    worklist.insert(target);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Merges a state flowing from `pc` into the state inferred so far for its successor,
+    /// returning whether that changed it.
+    fn merge(&self, existing: &mut Option<State>, incoming: State, pc: U2) -> CheckResult<bool> {
+        let state = match *existing {
+            Some(ref mut state) => state,
+            None => {
+                *existing = Some(incoming);
+                return Ok(true);
+            }
+        };
+
+        if state.stack.len() != incoming.stack.len() ||
+           state.stack_height() != incoming.stack_height() {
+            return Err(Failure::Unsafe(pc,
+                                       VerifyErrorKind::StackHeightMismatch {
+                                           expected: state.stack_height(),
+                                           actual: incoming.stack_height(),
+                                       }));
+        }
+
+        let mut changed = false;
+        for (value, other) in state.stack.iter_mut().zip(incoming.stack) {
+            let merged = match frames::merge_types(value, &other, self.hierarchy) {
+                Some(ref merged) if merged.size() == value.size() => merged.clone(),
+                _ => return Err(mismatch(pc, value.clone(), other)),
+            };
+            if *value != merged {
+                *value = merged;
+                changed = true;
+            }
+        }
+
+        for (value, other) in state.locals.iter_mut().zip(incoming.locals.iter()) {
+            let merged = frames::merge_types(value, other, self.hierarchy)
+                .unwrap_or(VerificationType::Top);
+            if *value != merged {
+                *value = merged;
+                changed = true;
+            }
+        }
+
+        if incoming.this_uninitialized && !state.this_uninitialized {
+            state.this_uninitialized = true;
+            changed = true;
+        }
+
+        Ok(changed)
+    }
+
+    fn initial_state(&self, max_locals: usize) -> CheckResult<State> {
+        let mut locals = vec![];
+        let mut this_uninitialized = false;
+
+        if !self.method.access().contains(MethodAccess::STATIC) {
+            if self.method.name.as_str() == "<init>" && self.this_class != "java/lang/Object" {
+                locals.push(VerificationType::UninitializedThis);
+                this_uninitialized = true;
+            } else {
+                locals.push(VerificationType::Object(self.this_class.clone()));
+            }
+        }
+        locals.extend(self.parameters.iter().cloned());
+
+        Ok(State {
+            locals: try!(expand_locals(&locals, max_locals, 0)),
+            stack: vec![],
+            this_uninitialized: this_uninitialized,
+        })
+    }
+
+    /// Decodes the stack map frames of `code`, keyed by pc, with their locals expanded to one
+    /// entry per slot.
+    fn stack_map(&self, code: &CodeAttribute, initial: &State) -> CheckResult<BTreeMap<U2, State>> {
+        let mut stack_map = BTreeMap::new();
+        let table = code.attributes
+            .iter()
+            .filter_map(|attribute| match *attribute {
+                Attribute::StackMapTable(ref table) => Some(table.clone()),
+                _ => None,
+            })
+            .next();
+        let table = match table {
+            Some(table) => table,
+            None => return Ok(stack_map),
+        };
+
+        // frames describe locals with a single entry for a long or double
+        let mut locals = vec![];
+        let mut index = 0;
+        while index < initial.locals.len() {
+            locals.push(initial.locals[index].clone());
+            index += initial.locals[index].size();
+        }
+        while locals.last() == Some(&VerificationType::Top) {
+            locals.pop();
+        }
+
+        let mut previous_pc: Option<usize> = None;
+        for entry in &table.entries {
+            let pc = match previous_pc {
+                Some(previous_pc) => previous_pc + entry.offset_delta() as usize + 1,
+                None => entry.offset_delta() as usize,
+            };
+            if pc > 0xffff {
+                return Err(Failure::Unsafe(0xffff, VerifyErrorKind::MalformedFrame));
+            }
+            let pc_u2 = pc as U2;
+
+            let mut stack = vec![];
+            match *entry {
+                StackMapFrame::Same { .. } |
+                StackMapFrame::SameExtended { .. } => {}
+                StackMapFrame::SameLocals1StackItem { stack: ref value, .. } |
+                StackMapFrame::SameLocals1StackItemExtended { stack: ref value, .. } => {
+                    stack.push(try!(self.verification_type(value)));
+                }
+                StackMapFrame::Chop { frame_type, .. } => {
+                    let chopped = 251 - frame_type as usize;
+                    if chopped > locals.len() {
+                        return Err(Failure::Unsafe(pc_u2, VerifyErrorKind::MalformedFrame));
+                    }
+                    let remaining = locals.len() - chopped;
+                    locals.truncate(remaining);
+                }
+                StackMapFrame::Append { locals: ref appended, .. } => {
+                    for value in appended {
+                        locals.push(try!(self.verification_type(value)));
+                    }
+                }
+                StackMapFrame::Full { locals: ref full_locals, stack: ref full_stack, .. } => {
+                    locals.clear();
+                    for value in full_locals {
+                        locals.push(try!(self.verification_type(value)));
+                    }
+                    for value in full_stack {
+                        stack.push(try!(self.verification_type(value)));
+                    }
+                }
+            }
+
+            let this_uninitialized = locals.contains(&VerificationType::UninitializedThis);
+            stack_map.insert(pc_u2,
+                             State {
+                                 locals: try!(expand_locals(&locals,
+                                                            code.max_locals as usize,
+                                                            pc_u2)),
+                                 stack: stack,
+                                 this_uninitialized: this_uninitialized,
+                             });
+            previous_pc = Some(pc);
+        }
+
+        Ok(stack_map)
+    }
+
+    fn verification_type(&self, info: &VerificationTypeInfo) -> CheckResult<VerificationType> {
+        let value = match *info {
+            VerificationTypeInfo::Top => VerificationType::Top,
+            VerificationTypeInfo::Integer => VerificationType::Integer,
+            VerificationTypeInfo::Float => VerificationType::Float,
+            VerificationTypeInfo::Long => VerificationType::Long,
+            VerificationTypeInfo::Double => VerificationType::Double,
+            VerificationTypeInfo::Null => VerificationType::Null,
+            VerificationTypeInfo::UninitializedThis => VerificationType::UninitializedThis,
+            VerificationTypeInfo::Object(index) => {
+                VerificationType::Object(try!(self.resolver.resolve_class_name(index)).to_string())
+            }
+            VerificationTypeInfo::Uninitialized(offset) => VerificationType::Uninitialized(offset),
+        };
+
+        Ok(value)
+    }
+
+    fn frame_at<'m>(&self,
+                    stack_map: &'m BTreeMap<U2, State>,
+                    target: U2,
+                    pc: U2)
+                    -> CheckResult<&'m State> {
+        stack_map.get(&target).ok_or(Failure::Unsafe(pc, VerifyErrorKind::MissingFrame(target)))
+    }
+
+    /// Checks that a state flowing from `pc` can be used where the stack map frame `frame` is
+    /// expected.
+    fn check_assignable(&self, pc: U2, state: &State, frame: &State) -> CheckResult<()> {
+        if state.stack.len() != frame.stack.len() ||
+           state.stack_height() != frame.stack_height() {
+            return Err(Failure::Unsafe(pc,
+                                       VerifyErrorKind::StackHeightMismatch {
+                                           expected: frame.stack_height(),
+                                           actual: state.stack_height(),
+                                       }));
+        }
+
+        if state.this_uninitialized && !frame.this_uninitialized {
+            let this_type = VerificationType::Object(self.this_class.clone());
+            return Err(mismatch(pc, this_type, VerificationType::UninitializedThis));
+        }
+
+        for (value, expected) in state.locals.iter().zip(frame.locals.iter()) {
+            try!(self.check_type(pc, value, expected));
+        }
+        for (value, expected) in state.stack.iter().zip(frame.stack.iter()) {
+            try!(self.check_type(pc, value, expected));
+        }
+
+        Ok(())
+    }
+
+    fn check_type(&self,
+                  pc: U2,
+                  value: &VerificationType,
+                  expected: &VerificationType)
+                  -> CheckResult<()> {
+        if self.is_assignable(value, expected) {
+            Ok(())
+        } else {
+            Err(mismatch(pc, expected.clone(), value.clone()))
+        }
+    }
+
+    fn is_assignable(&self, value: &VerificationType, expected: &VerificationType) -> bool {
+        match (value, expected) {
+            _ if value == expected => true,
+            (_, &VerificationType::Top) => true,
+            (&VerificationType::Null, &VerificationType::Object(_)) => true,
+            (&VerificationType::Object(ref value), &VerificationType::Object(ref expected)) => {
+                self.is_class_assignable(value, expected)
+            }
+            _ => false,
+        }
+    }
+
+    fn is_class_assignable(&self, value: &str, expected: &str) -> bool {
+        if value == expected || expected == "java/lang/Object" {
+            return true;
+        }
+
+        match (value.starts_with('['), expected.starts_with('[')) {
+            (true, true) => {
+                match (frames::component_name(&value[1..]),
+                       frames::component_name(&expected[1..])) {
+                    (Some(value), Some(expected)) => self.is_class_assignable(value, expected),
+                    _ => false,
+                }
+            }
+            (true, false) => {
+                expected == "java/lang/Cloneable" || expected == "java/io/Serializable"
+            }
+            (false, true) => false,
+            (false, false) => self.hierarchy.is_assignable(value, expected),
+        }
+    }
+
+    fn pop_type(&self,
+                state: &mut State,
+                expected: VerificationType,
+                pc: U2)
+                -> CheckResult<VerificationType> {
+        let value = try!(state.pop(pc));
+        try!(self.check_type(pc, &value, &expected));
+
+        Ok(value)
+    }
+
+    /// Pops a reference that may not have been initialized yet.
+    fn pop_reference(&self, state: &mut State, pc: U2) -> CheckResult<VerificationType> {
+        let value = try!(state.pop(pc));
+        if value.is_reference() {
+            Ok(value)
+        } else {
+            Err(mismatch(pc, object(), value))
+        }
+    }
+
+    /// Pops an array whose type is one of `descriptors`, or `null`.
+    fn pop_array(&self, state: &mut State, descriptors: &[&str], pc: U2) -> CheckResult<()> {
+        let value = try!(state.pop(pc));
+        match value {
+            VerificationType::Null => Ok(()),
+            VerificationType::Object(ref name) if descriptors.contains(&name.as_str()) => Ok(()),
+            _ => {
+                let expected = VerificationType::Object(descriptors[0].to_string());
+                Err(mismatch(pc, expected, value.clone()))
+            }
+        }
+    }
+
+    /// Pops an array of references, or `null`, and returns its component type.
+    fn pop_reference_array(&self, state: &mut State, pc: U2) -> CheckResult<VerificationType> {
+        let value = try!(state.pop(pc));
+        let component = match value {
+            VerificationType::Null => Some(VerificationType::Null),
+            VerificationType::Object(ref name) if name.starts_with('[') => {
+                frames::component_name(&name[1..])
+                    .map(|component| VerificationType::Object(component.to_string()))
+            }
+            _ => None,
+        };
+
+        component.ok_or(mismatch(pc, object_array(), value.clone()))
+    }
+
+    fn load(&self,
+            state: &mut State,
+            index: U2,
+            expected: VerificationType,
+            pc: U2)
+            -> CheckResult<()> {
+        let value = try!(state.local(index, expected.size(), pc));
+        try!(self.check_type(pc, &value, &expected));
+        state.push(expected);
+
+        Ok(())
+    }
+
+    fn aload(&self, state: &mut State, index: U2, pc: U2) -> CheckResult<()> {
+        let value = try!(state.local(index, 1, pc));
+        if !value.is_reference() {
+            return Err(mismatch(pc, object(), value));
+        }
+        state.push(value);
+
+        Ok(())
+    }
+
+    fn store(&self,
+             state: &mut State,
+             index: U2,
+             expected: VerificationType,
+             pc: U2)
+             -> CheckResult<()> {
+        let value = try!(self.pop_type(state, expected, pc));
+        state.store(index, value, pc)
+    }
+
+    /// Stores a reference, or the return address a subroutine starts with.
+    fn astore(&self, state: &mut State, index: U2, pc: U2) -> CheckResult<()> {
+        let value = try!(state.pop(pc));
+        if !value.is_reference() && value != VerificationType::ReturnAddress {
+            return Err(mismatch(pc, object(), value));
+        }
+
+        state.store(index, value, pc)
+    }
+
+    /// Pops operands of type `operand` and pushes the result.
+    fn operation(&self,
+                 state: &mut State,
+                 operands: &[VerificationType],
+                 result: VerificationType,
+                 pc: U2)
+                 -> CheckResult<()> {
+        for operand in operands.iter().rev() {
+            try!(self.pop_type(state, operand.clone(), pc));
+        }
+        state.push(result);
+
+        Ok(())
+    }
+
+    /// Pops two operands of type `operand` and pushes a result of the same type.
+    fn binary(&self, state: &mut State, operand: VerificationType, pc: U2) -> CheckResult<()> {
+        self.operation(state, &[operand.clone(), operand.clone()], operand, pc)
+    }
+
+    /// Pops two operands of type `operand` and pushes the `int` result of comparing them.
+    fn compare(&self, state: &mut State, operand: VerificationType, pc: U2) -> CheckResult<()> {
+        self.operation(state, &[operand.clone(), operand], VerificationType::Integer, pc)
+    }
+
+    fn execute(&self,
+               pc: U2,
+               instruction: &Instruction,
+               state: &mut State,
+               instructions: &Vec<(U2, Instruction)>,
+               indices: &HashMap<U2, usize>)
+               -> CheckResult<()> {
+        let integer = VerificationType::Integer;
+        let float = VerificationType::Float;
+        let long = VerificationType::Long;
+        let double = VerificationType::Double;
+
+        match *instruction {
+            Instruction::Nop |
+            Instruction::Goto(..) |
+            Instruction::GotoW(..) |
+            Instruction::Breakpoint |
+            Instruction::Impdep1 |
+            Instruction::Impdep2 => {}
+            Instruction::AconstNull => state.push(VerificationType::Null),
+            Instruction::IconstM1 |
+            Instruction::Iconst0 |
+            Instruction::Iconst1 |
+            Instruction::Iconst2 |
+            Instruction::Iconst3 |
+            Instruction::Iconst4 |
+            Instruction::Iconst5 |
+            Instruction::Bipush(..) |
+            Instruction::Sipush(..) => state.push(integer),
+            Instruction::Lconst0 |
+            Instruction::Lconst1 => state.push(long),
+            Instruction::Fconst0 |
+            Instruction::Fconst1 |
+            Instruction::Fconst2 => state.push(float),
+            Instruction::Dconst0 |
+            Instruction::Dconst1 => state.push(double),
+            Instruction::Ldc(index) => state.push(try!(self.loaded_constant(index as U2, 1, pc))),
+            Instruction::LdcW(index) => state.push(try!(self.loaded_constant(index, 1, pc))),
+            Instruction::Ldc2W(index) => state.push(try!(self.loaded_constant(index, 2, pc))),
+            Instruction::Iload(index) => try!(self.load(state, index, integer, pc)),
+            Instruction::Iload0 => try!(self.load(state, 0, integer, pc)),
+            Instruction::Iload1 => try!(self.load(state, 1, integer, pc)),
+            Instruction::Iload2 => try!(self.load(state, 2, integer, pc)),
+            Instruction::Iload3 => try!(self.load(state, 3, integer, pc)),
+            Instruction::Lload(index) => try!(self.load(state, index, long, pc)),
+            Instruction::Lload0 => try!(self.load(state, 0, long, pc)),
+            Instruction::Lload1 => try!(self.load(state, 1, long, pc)),
+            Instruction::Lload2 => try!(self.load(state, 2, long, pc)),
+            Instruction::Lload3 => try!(self.load(state, 3, long, pc)),
+            Instruction::Fload(index) => try!(self.load(state, index, float, pc)),
+            Instruction::Fload0 => try!(self.load(state, 0, float, pc)),
+            Instruction::Fload1 => try!(self.load(state, 1, float, pc)),
+            Instruction::Fload2 => try!(self.load(state, 2, float, pc)),
+            Instruction::Fload3 => try!(self.load(state, 3, float, pc)),
+            Instruction::Dload(index) => try!(self.load(state, index, double, pc)),
+            Instruction::Dload0 => try!(self.load(state, 0, double, pc)),
+            Instruction::Dload1 => try!(self.load(state, 1, double, pc)),
+            Instruction::Dload2 => try!(self.load(state, 2, double, pc)),
+            Instruction::Dload3 => try!(self.load(state, 3, double, pc)),
+            Instruction::Aload(index) => try!(self.aload(state, index, pc)),
+            Instruction::Aload0 => try!(self.aload(state, 0, pc)),
+            Instruction::Aload1 => try!(self.aload(state, 1, pc)),
+            Instruction::Aload2 => try!(self.aload(state, 2, pc)),
+            Instruction::Aload3 => try!(self.aload(state, 3, pc)),
+            Instruction::Iaload |
+            Instruction::Baload |
+            Instruction::Caload |
+            Instruction::Saload |
+            Instruction::Laload |
+            Instruction::Faload |
+            Instruction::Daload => {
+                let (descriptors, result) = match *instruction {
+                    Instruction::Iaload => (&["[I"][..], integer),
+                    Instruction::Baload => (&["[B", "[Z"][..], integer),
+                    Instruction::Caload => (&["[C"][..], integer),
+                    Instruction::Saload => (&["[S"][..], integer),
+                    Instruction::Laload => (&["[J"][..], long),
+                    Instruction::Faload => (&["[F"][..], float),
+                    _ => (&["[D"][..], double),
+                };
+                try!(self.pop_type(state, VerificationType::Integer, pc));
+                try!(self.pop_array(state, descriptors, pc));
+                state.push(result);
+            }
+            Instruction::Aaload => {
+                try!(self.pop_type(state, integer, pc));
+                let component = try!(self.pop_reference_array(state, pc));
+                state.push(component);
+            }
+            Instruction::Istore(index) => try!(self.store(state, index, integer, pc)),
+            Instruction::Istore0 => try!(self.store(state, 0, integer, pc)),
+            Instruction::Istore1 => try!(self.store(state, 1, integer, pc)),
+            Instruction::Istore2 => try!(self.store(state, 2, integer, pc)),
+            Instruction::Istore3 => try!(self.store(state, 3, integer, pc)),
+            Instruction::Lstore(index) => try!(self.store(state, index, long, pc)),
+            Instruction::Lstore0 => try!(self.store(state, 0, long, pc)),
+            Instruction::Lstore1 => try!(self.store(state, 1, long, pc)),
+            Instruction::Lstore2 => try!(self.store(state, 2, long, pc)),
+            Instruction::Lstore3 => try!(self.store(state, 3, long, pc)),
+            Instruction::Fstore(index) => try!(self.store(state, index, float, pc)),
+            Instruction::Fstore0 => try!(self.store(state, 0, float, pc)),
+            Instruction::Fstore1 => try!(self.store(state, 1, float, pc)),
+            Instruction::Fstore2 => try!(self.store(state, 2, float, pc)),
+            Instruction::Fstore3 => try!(self.store(state, 3, float, pc)),
+            Instruction::Dstore(index) => try!(self.store(state, index, double, pc)),
+            Instruction::Dstore0 => try!(self.store(state, 0, double, pc)),
+            Instruction::Dstore1 => try!(self.store(state, 1, double, pc)),
+            Instruction::Dstore2 => try!(self.store(state, 2, double, pc)),
+            Instruction::Dstore3 => try!(self.store(state, 3, double, pc)),
+            Instruction::Astore(index) => try!(self.astore(state, index, pc)),
+            Instruction::Astore0 => try!(self.astore(state, 0, pc)),
+            Instruction::Astore1 => try!(self.astore(state, 1, pc)),
+            Instruction::Astore2 => try!(self.astore(state, 2, pc)),
+            Instruction::Astore3 => try!(self.astore(state, 3, pc)),
+            Instruction::Iastore |
+            Instruction::Bastore |
+            Instruction::Castore |
+            Instruction::Sastore |
+            Instruction::Lastore |
+            Instruction::Fastore |
+            Instruction::Dastore => {
+                let (descriptors, value) = match *instruction {
+                    Instruction::Iastore => (&["[I"][..], integer),
+                    Instruction::Bastore => (&["[B", "[Z"][..], integer),
+                    Instruction::Castore => (&["[C"][..], integer),
+                    Instruction::Sastore => (&["[S"][..], integer),
+                    Instruction::Lastore => (&["[J"][..], long),
+                    Instruction::Fastore => (&["[F"][..], float),
+                    _ => (&["[D"][..], double),
+                };
+                try!(self.pop_type(state, value, pc));
+                try!(self.pop_type(state, VerificationType::Integer, pc));
+                try!(self.pop_array(state, descriptors, pc));
+            }
+            Instruction::Aastore => {
+                try!(self.pop_type(state, object(), pc));
+                try!(self.pop_type(state, integer, pc));
+                try!(self.pop_reference_array(state, pc));
+            }
+            Instruction::Pop => {
+                try!(state.pop_slots(1, pc));
+            }
+            Instruction::Pop2 => {
+                try!(state.pop_slots(2, pc));
+            }
+            Instruction::Dup => try!(duplicate(state, 1, 0, pc)),
+            Instruction::DupX1 => try!(duplicate(state, 1, 1, pc)),
+            Instruction::DupX2 => try!(duplicate(state, 1, 2, pc)),
+            Instruction::Dup2 => try!(duplicate(state, 2, 0, pc)),
+            Instruction::Dup2X1 => try!(duplicate(state, 2, 1, pc)),
+            Instruction::Dup2X2 => try!(duplicate(state, 2, 2, pc)),
+            Instruction::Swap => {
+                let top = try!(state.pop_slots(1, pc));
+                let below = try!(state.pop_slots(1, pc));
+                state.stack.extend(top);
+                state.stack.extend(below);
+            }
+            Instruction::Iadd |
+            Instruction::Isub |
+            Instruction::Imul |
+            Instruction::Idiv |
+            Instruction::Irem |
+            Instruction::Ishl |
+            Instruction::Ishr |
+            Instruction::Iushr |
+            Instruction::Iand |
+            Instruction::Ior |
+            Instruction::Ixor => try!(self.binary(state, integer, pc)),
+            Instruction::Ladd |
+            Instruction::Lsub |
+            Instruction::Lmul |
+            Instruction::Ldiv |
+            Instruction::Lrem |
+            Instruction::Land |
+            Instruction::Lor |
+            Instruction::Lxor => try!(self.binary(state, long, pc)),
+            Instruction::Lshl |
+            Instruction::Lshr |
+            Instruction::Lushr => try!(self.operation(state, &[long.clone(), integer], long, pc)),
+            Instruction::Fadd |
+            Instruction::Fsub |
+            Instruction::Fmul |
+            Instruction::Fdiv |
+            Instruction::Frem => try!(self.binary(state, float, pc)),
+            Instruction::Dadd |
+            Instruction::Dsub |
+            Instruction::Dmul |
+            Instruction::Ddiv |
+            Instruction::Drem => try!(self.binary(state, double, pc)),
+            Instruction::Ineg |
+            Instruction::I2b |
+            Instruction::I2c |
+            Instruction::I2s => try!(self.operation(state, &[integer.clone()], integer, pc)),
+            Instruction::Lneg => try!(self.operation(state, &[long.clone()], long, pc)),
+            Instruction::Fneg => try!(self.operation(state, &[float.clone()], float, pc)),
+            Instruction::Dneg => try!(self.operation(state, &[double.clone()], double, pc)),
+            Instruction::Iinc(index, _) => {
+                let value = try!(state.local(index, 1, pc));
+                try!(self.check_type(pc, &value, &integer));
+            }
+            Instruction::I2l => try!(self.operation(state, &[integer], long, pc)),
+            Instruction::I2f => try!(self.operation(state, &[integer], float, pc)),
+            Instruction::I2d => try!(self.operation(state, &[integer], double, pc)),
+            Instruction::L2i => try!(self.operation(state, &[long], integer, pc)),
+            Instruction::L2f => try!(self.operation(state, &[long], float, pc)),
+            Instruction::L2d => try!(self.operation(state, &[long], double, pc)),
+            Instruction::F2i => try!(self.operation(state, &[float], integer, pc)),
+            Instruction::F2l => try!(self.operation(state, &[float], long, pc)),
+            Instruction::F2d => try!(self.operation(state, &[float], double, pc)),
+            Instruction::D2i => try!(self.operation(state, &[double], integer, pc)),
+            Instruction::D2l => try!(self.operation(state, &[double], long, pc)),
+            Instruction::D2f => try!(self.operation(state, &[double], float, pc)),
+            Instruction::Lcmp => try!(self.compare(state, long, pc)),
+            Instruction::Fcmpl |
+            Instruction::Fcmpg => try!(self.compare(state, float, pc)),
+            Instruction::Dcmpl |
+            Instruction::Dcmpg => try!(self.compare(state, double, pc)),
+            Instruction::Ifeq(..) |
+            Instruction::Ifne(..) |
+            Instruction::Iflt(..) |
+            Instruction::Ifge(..) |
+            Instruction::Ifgt(..) |
+            Instruction::Ifle(..) |
+            Instruction::Tableswitch { .. } |
+            Instruction::Lookupswitch { .. } => {
+                try!(self.pop_type(state, integer, pc));
+            }
+            Instruction::IfIcmpeq(..) |
+            Instruction::IfIcmpne(..) |
+            Instruction::IfIcmplt(..) |
+            Instruction::IfIcmpge(..) |
+            Instruction::IfIcmpgt(..) |
+            Instruction::IfIcmple(..) => {
+                try!(self.pop_type(state, integer.clone(), pc));
+                try!(self.pop_type(state, integer, pc));
+            }
+            Instruction::IfAcmpeq(..) |
+            Instruction::IfAcmpne(..) => {
+                try!(self.pop_reference(state, pc));
+                try!(self.pop_reference(state, pc));
+            }
+            Instruction::Ifnull(..) |
+            Instruction::Ifnonnull(..) |
+            Instruction::Monitorenter |
+            Instruction::Monitorexit => {
+                try!(self.pop_reference(state, pc));
+            }
+            Instruction::Jsr(..) |
+            Instruction::JsrW(..) |
+            Instruction::Ret(..) => return Err(Failure::Unsafe(pc, VerifyErrorKind::Subroutine)),
+            Instruction::Ireturn |
+            Instruction::Lreturn |
+            Instruction::Freturn |
+            Instruction::Dreturn => {
+                let expected = match *instruction {
+                    Instruction::Ireturn => integer,
+                    Instruction::Lreturn => long,
+                    Instruction::Freturn => float,
+                    _ => double,
+                };
+                if self.return_type.as_ref() != Some(&expected) {
+                    return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidReturn));
+                }
+                try!(self.pop_type(state, expected, pc));
+            }
+            Instruction::Areturn => {
+                let expected = match self.return_type {
+                    Some(ref expected @ VerificationType::Object(_)) => expected.clone(),
+                    _ => return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidReturn)),
+                };
+                try!(self.pop_type(state, expected, pc));
+            }
+            Instruction::Return => {
+                if self.return_type.is_some() {
+                    return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidReturn));
+                }
+                if state.this_uninitialized {
+                    let this_type = VerificationType::Object(self.this_class.clone());
+                    return Err(mismatch(pc, this_type, VerificationType::UninitializedThis));
+                }
+            }
+            Instruction::Getstatic(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                state.push(try!(frames::field_type(&field.descriptor)));
+            }
+            Instruction::Putstatic(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                try!(self.pop_type(state, try!(frames::field_type(&field.descriptor)), pc));
+            }
+            Instruction::Getfield(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                let owner = VerificationType::Object(field.class_name.to_string());
+                try!(self.pop_type(state, owner, pc));
+                state.push(try!(frames::field_type(&field.descriptor)));
+            }
+            Instruction::Putfield(index) => {
+                let field = try!(self.resolver.resolve_member_reference(index));
+                try!(self.pop_type(state, try!(frames::field_type(&field.descriptor)), pc));
+
+                // a constructor may assign the fields of its own class before calling super
+                let receiver = try!(state.pop(pc));
+                if receiver != VerificationType::UninitializedThis ||
+                   field.class_name.as_str() != self.this_class {
+                    let owner = VerificationType::Object(field.class_name.to_string());
+                    try!(self.check_type(pc, &receiver, &owner));
+                }
+            }
+            Instruction::Invokevirtual(index) |
+            Instruction::Invokespecial(index) |
+            Instruction::Invokestatic(index) |
+            Instruction::Invokeinterface(index, _) => {
+                let method = try!(self.resolver.resolve_member_reference(index));
+                let (parameters, return_type) = try!(frames::method_types(&method.descriptor));
+                for parameter in parameters.into_iter().rev() {
+                    try!(self.pop_type(state, parameter, pc));
+                }
+
+                let owner = VerificationType::Object(method.class_name.to_string());
+                match *instruction {
+                    Instruction::Invokestatic(..) => {}
+                    Instruction::Invokeinterface(..) => {
+                        try!(self.pop_type(state, object(), pc));
+                    }
+                    Instruction::Invokespecial(..) if method.name.as_str() == "<init>" => {
+                        let receiver = try!(state.pop(pc));
+                        try!(self.initialize(state,
+                                             receiver,
+                                             &method.class_name,
+                                             pc,
+                                             instructions,
+                                             indices));
+                    }
+                    Instruction::Invokespecial(..) => {
+                        let current = VerificationType::Object(self.this_class.clone());
+                        try!(self.pop_type(state, current, pc));
+                    }
+                    _ => {
+                        try!(self.pop_type(state, owner, pc));
+                    }
+                }
+
+                if let Some(return_type) = return_type {
+                    state.push(return_type);
+                }
+            }
+            Instruction::Invokedynamic(index) => {
+                let call_site = try!(self.resolver.resolve_invoke_dynamic(index));
+                let (parameters, return_type) = try!(frames::method_types(&call_site.descriptor));
+                for parameter in parameters.into_iter().rev() {
+                    try!(self.pop_type(state, parameter, pc));
+                }
+
+                if let Some(return_type) = return_type {
+                    state.push(return_type);
+                }
+            }
+            Instruction::New(..) => {
+                // an earlier object from the same instruction can no longer be told apart
+                let uninitialized = VerificationType::Uninitialized(pc);
+                for value in state.locals.iter_mut() {
+                    if *value == uninitialized {
+                        *value = VerificationType::Top;
+                    }
+                }
+                state.push(uninitialized);
+            }
+            Instruction::Newarray(ref array_type) => {
+                let descriptor = match *array_type {
+                    ArrayType::Boolean => "[Z",
+                    ArrayType::Char => "[C",
+                    ArrayType::Float => "[F",
+                    ArrayType::Double => "[D",
+                    ArrayType::Byte => "[B",
+                    ArrayType::Short => "[S",
+                    ArrayType::Int => "[I",
+                    ArrayType::Long => "[J",
+                };
+                try!(self.operation(state,
+                                    &[integer],
+                                    VerificationType::Object(descriptor.to_string()),
+                                    pc));
+            }
+            Instruction::Anewarray(index) => {
+                let component = try!(self.resolver.resolve_class_name(index));
+                try!(self.operation(state,
+                                    &[integer],
+                                    VerificationType::Object(frames::array_of(&component)),
+                                    pc));
+            }
+            Instruction::Arraylength => {
+                let value = try!(state.pop(pc));
+                match value {
+                    VerificationType::Null => {}
+                    VerificationType::Object(ref name) if name.starts_with('[') => {}
+                    _ => {
+                        return Err(mismatch(pc, object_array(), value.clone()))
+                    }
+                }
+                state.push(integer);
+            }
+            Instruction::Athrow => {
+                let throwable = VerificationType::Object("java/lang/Throwable".to_string());
+                try!(self.pop_type(state, throwable, pc));
+            }
+            Instruction::Checkcast(index) => {
+                let class_name = try!(self.resolver.resolve_class_name(index));
+                try!(self.operation(state,
+                                    &[object()],
+                                    VerificationType::Object(class_name.to_string()),
+                                    pc));
+            }
+            Instruction::Instanceof(..) => try!(self.operation(state, &[object()], integer, pc)),
+            Instruction::Multianewarray(index, dimensions) => {
+                let class_name = try!(self.resolver.resolve_class_name(index));
+                let operands = vec![integer; dimensions as usize];
+                try!(self.operation(state,
+                                    &operands,
+                                    VerificationType::Object(class_name.to_string()),
+                                    pc));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks that a constructor of `class_name` is called on an object it can initialize, and
+    /// marks every copy of that object as initialized.
+    fn initialize(&self,
+                  state: &mut State,
+                  receiver: VerificationType,
+                  class_name: &str,
+                  pc: U2,
+                  instructions: &Vec<(U2, Instruction)>,
+                  indices: &HashMap<U2, usize>)
+                  -> CheckResult<()> {
+        let expected = match receiver {
+            VerificationType::UninitializedThis => {
+                // a constructor delegates to another constructor of its class or its superclass
+                let is_superclass = match self.super_class {
+                    Some(ref super_class) => super_class == class_name,
+                    None => false,
+                };
+                if class_name != self.this_class && !is_superclass {
+                    VerificationType::Object(class_name.to_string())
+                } else {
+                    state.replace(&receiver, &VerificationType::Object(self.this_class.clone()));
+                    state.this_uninitialized = false;
+                    return Ok(());
+                }
+            }
+            VerificationType::Uninitialized(new_pc) => {
+                let created = match indices.get(&new_pc).map(|index| &instructions[*index].1) {
+                    Some(&Instruction::New(class_index)) => {
+                        Some(try!(self.resolver.resolve_class_name(class_index)).to_string())
+                    }
+                    _ => None,
+                };
+
+                match created {
+                    Some(ref created) if created == class_name => {
+                        state.replace(&receiver, &VerificationType::Object(class_name.to_string()));
+                        return Ok(());
+                    }
+                    _ => VerificationType::Object(class_name.to_string()),
+                }
+            }
+            _ => VerificationType::UninitializedThis,
+        };
+
+        Err(mismatch(pc, expected, receiver))
+    }
+
+    /// The type of the constant loaded by an `ldc` instruction at `pc`, which has to take up as
+    /// many slots as the instruction is for.
+    fn loaded_constant(&self, index: U2, size: usize, pc: U2) -> CheckResult<VerificationType> {
+        let constant_type = try!(self.constant_type(index));
+        if constant_type.size() != size {
+            return Err(Failure::Unsafe(pc, VerifyErrorKind::WrongCategory(constant_type)));
+        }
+
+        Ok(constant_type)
+    }
+
+    fn constant_type(&self, index: U2) -> CheckResult<VerificationType> {
+        let constant_type = match try!(self.resolver.resolve_loadable_constant(index)) {
+            LoadableConstant::Integer(..) => VerificationType::Integer,
+            LoadableConstant::Float(..) => VerificationType::Float,
+            LoadableConstant::Long(..) => VerificationType::Long,
+            LoadableConstant::Double(..) => VerificationType::Double,
+            LoadableConstant::String(..) => {
+                VerificationType::Object("java/lang/String".to_string())
+            }
+            LoadableConstant::Class(..) => VerificationType::Object("java/lang/Class".to_string()),
+            LoadableConstant::MethodType(..) => {
+                VerificationType::Object("java/lang/invoke/MethodType".to_string())
+            }
+            LoadableConstant::MethodHandle { .. } => {
+                VerificationType::Object("java/lang/invoke/MethodHandle".to_string())
+            }
+        };
+
+        Ok(constant_type)
+    }
+}
+
+fn object() -> VerificationType {
+    VerificationType::Object("java/lang/Object".to_string())
+}
+
+fn object_array() -> VerificationType {
+    VerificationType::Object("[Ljava/lang/Object;".to_string())
+}
+
+fn mismatch(pc: U2, expected: VerificationType, actual: VerificationType) -> Failure {
+    Failure::Unsafe(pc,
+                    VerifyErrorKind::TypeMismatch {
+                        expected: expected,
+                        actual: actual,
+                    })
+}
+
+/// The state after returning from `subroutine` to the instruction following a `jsr`, where the
+/// local variables the subroutine does not write keep their types from the `jsr`.
+fn returned(returning: &State, calling: &State, subroutine: &Subroutine) -> State {
+    let mut locals = calling.locals.clone();
+    for &index in &subroutine.locals {
+        if let Some(value) = returning.locals.get(index as usize) {
+            locals[index as usize] = value.clone();
+        }
+    }
+
+    State {
+        locals: locals,
+        stack: returning.stack.clone(),
+        this_uninitialized: returning.this_uninitialized,
+    }
+}
+
+/// Gives a long or double its second slot, and pads the locals with `Top` up to `max_locals`.
+fn expand_locals(locals: &[VerificationType],
+                 max_locals: usize,
+                 pc: U2)
+                 -> CheckResult<Vec<VerificationType>> {
+    let mut expanded = vec![];
+    for value in locals {
+        expanded.push(value.clone());
+        if value.size() == 2 {
+            expanded.push(VerificationType::Top);
+        }
+    }
+
+    if expanded.len() > max_locals {
+        return Err(Failure::Unsafe(pc, VerifyErrorKind::InvalidLocal(max_locals as U2)));
+    }
+    expanded.resize(max_locals, VerificationType::Top);
+
+    Ok(expanded)
+}
+
+/// Duplicates the top `slots` slots of the stack, inserting the copy below the `skipped` slots
+/// under them, as the `dup` family does.
+fn duplicate(state: &mut State, slots: usize, skipped: usize, pc: U2) -> CheckResult<()> {
+    let top = try!(state.pop_slots(slots, pc));
+    let below = try!(state.pop_slots(skipped, pc));
+
+    state.stack.extend(top.iter().cloned());
+    state.stack.extend(below);
+    state.stack.extend(top);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::{duplicate, expand_locals, Failure, State, VerifyErrorKind};
+    use super::super::frames::VerificationType;
+
+    fn state(stack: Vec<VerificationType>) -> State {
+        State {
+            locals: vec![],
+            stack: stack,
+            this_uninitialized: false,
+        }
+    }
+
+    #[test]
+    fn can_duplicate_by_slots() {
+        let mut state = state(vec![VerificationType::Long,
+                                   VerificationType::Integer,
+                                   VerificationType::Float]);
+        duplicate(&mut state, 2, 2, 0).ok().unwrap();
+
+        assert_that(&state.stack).is_equal_to(&vec![VerificationType::Integer,
+                                                     VerificationType::Float,
+                                                     VerificationType::Long,
+                                                     VerificationType::Integer,
+                                                     VerificationType::Float]);
+    }
+
+    #[test]
+    fn rejects_splitting_long_values() {
+        let mut state = state(vec![VerificationType::Integer, VerificationType::Double]);
+
+        match duplicate(&mut state, 1, 0, 4) {
+            Err(Failure::Unsafe(4, VerifyErrorKind::WrongCategory(VerificationType::Double))) => {}
+            _ => panic!("expected a category error"),
+        }
+    }
+
+    #[test]
+    fn expands_locals_to_slots() {
+        let locals = vec![VerificationType::Long, VerificationType::Integer];
+
+        assert_that(&expand_locals(&locals, 5, 0).ok().unwrap())
+            .is_equal_to(&vec![VerificationType::Long,
+                               VerificationType::Top,
+                               VerificationType::Integer,
+                               VerificationType::Top,
+                               VerificationType::Top]);
+        assert_that(&expand_locals(&locals, 2, 0).is_err()).is_equal_to(&true);
+    }
+
+}
@@ -0,0 +1,442 @@
+use super::{ParserError, ParserResult};
+use super::mutf8;
+use super::access::{ClassAccess, FieldAccess, MethodAccess};
+use super::components::ExceptionHandler;
+use super::instructions::InstructionIterator;
+use super::primitives::{ByteCursor, PrimitiveIterator, U1, U2, U4};
+
+use std::borrow::Cow;
+
+/// A class file that borrows from the bytes it was parsed from. Only the layout of the class is
+/// read up front: constants are decoded when they are asked for, and attributes are kept as
+/// slices of the input, which makes it cheap to scan many classes for their names and flags.
+#[derive(Clone, Debug)]
+pub struct ClassFileRef<'a> {
+    pub magic: U4,
+    pub minor_version: U2,
+    pub major_version: U2,
+    pub constant_pool: ConstantPoolRef<'a>,
+    pub access_flags: U2,
+    pub this_class: U2,
+    pub super_class: U2,
+    pub interfaces: Vec<U2>,
+    pub fields: Vec<FieldRef<'a>>,
+    pub methods: Vec<MethodRef<'a>>,
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+/// The constant pool of a `ClassFileRef`, indexed from one like the constant pool of a class file.
+#[derive(Clone, Debug)]
+pub struct ConstantPoolRef<'a> {
+    /// The tag and body of each entry, with `None` for the slot after a long or double.
+    entries: Vec<Option<(U1, &'a [U1])>>,
+}
+
+/// An attribute by the index of its name and its undecoded body.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AttributeRef<'a> {
+    pub name_index: U2,
+    pub info: &'a [U1],
+}
+
+#[derive(Clone, Debug)]
+pub struct FieldRef<'a> {
+    pub access_flags: U2,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+#[derive(Clone, Debug)]
+pub struct MethodRef<'a> {
+    pub access_flags: U2,
+    pub name_index: U2,
+    pub descriptor_index: U2,
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+/// The `Code` attribute of a method, whose bytecode and nested attributes stay in the input.
+#[derive(Clone, Debug)]
+pub struct CodeRef<'a> {
+    pub max_stack: U2,
+    pub max_locals: U2,
+    pub code: &'a [U1],
+    pub exception_table: Vec<ExceptionHandler>,
+    pub attributes: Vec<AttributeRef<'a>>,
+}
+
+impl<'a> ClassFileRef<'a> {
+    pub fn parse(bytes: &'a [U1]) -> ParserResult<ClassFileRef<'a>> {
+        let mut cursor = ByteCursor::new(bytes);
+
+        let magic = try!(cursor.next_u4());
+        let minor_version = try!(cursor.next_u2());
+        let major_version = try!(cursor.next_u2());
+        let constant_pool = try!(ConstantPoolRef::from(&mut cursor));
+
+        let access_flags = try!(cursor.next_u2());
+        let this_class = try!(cursor.next_u2());
+        let super_class = try!(cursor.next_u2());
+
+        let interfaces_count = try!(cursor.next_u2());
+        let mut interfaces = vec![];
+        for _ in 0..interfaces_count {
+            interfaces.push(try!(cursor.next_u2()));
+        }
+
+        let fields_count = try!(cursor.next_u2());
+        let mut fields = vec![];
+        for _ in 0..fields_count {
+            fields.push(try!(FieldRef::from(&mut cursor)));
+        }
+
+        let methods_count = try!(cursor.next_u2());
+        let mut methods = vec![];
+        for _ in 0..methods_count {
+            methods.push(try!(MethodRef::from(&mut cursor)));
+        }
+
+        let attributes = try!(AttributeRef::from_all(&mut cursor));
+
+        Ok(ClassFileRef {
+            magic: magic,
+            minor_version: minor_version,
+            major_version: major_version,
+            constant_pool: constant_pool,
+            access_flags: access_flags,
+            this_class: this_class,
+            super_class: super_class,
+            interfaces: interfaces,
+            fields: fields,
+            methods: methods,
+            attributes: attributes,
+        })
+    }
+
+    pub fn access(&self) -> ClassAccess {
+        ClassAccess::from(self.access_flags)
+    }
+
+    pub fn classname(&self) -> ParserResult<Cow<'a, str>> {
+        self.constant_pool.class_name(self.this_class)
+    }
+
+    /// The name of the superclass, which only `java/lang/Object` and modules lack.
+    pub fn super_class_name(&self) -> ParserResult<Option<Cow<'a, str>>> {
+        if self.super_class == 0 {
+            return Ok(None);
+        }
+
+        self.constant_pool.class_name(self.super_class).map(Some)
+    }
+
+    pub fn interface_names(&self) -> ParserResult<Vec<Cow<'a, str>>> {
+        self.interfaces.iter().map(|interface| self.constant_pool.class_name(*interface)).collect()
+    }
+
+    /// The first attribute of the class called `name`.
+    pub fn attribute(&self, name: &str) -> ParserResult<Option<AttributeRef<'a>>> {
+        find_attribute(&self.attributes, name, &self.constant_pool)
+    }
+}
+
+impl<'a> ConstantPoolRef<'a> {
+    fn from(cursor: &mut ByteCursor<'a>) -> ParserResult<ConstantPoolRef<'a>> {
+        let constant_pool_count = try!(cursor.next_u2());
+
+        let mut entries = vec![];
+        while entries.len() + 1 < constant_pool_count as usize {
+            let tag = try!(cursor.next_u1());
+            let length = match tag {
+                1 => try!(cursor.next_u2()) as usize,
+                7 | 8 | 16 => 2,
+                15 => 3,
+                3 | 4 | 9 | 10 | 11 | 12 | 18 => 4,
+                5 | 6 => 8,
+                _ => return Err(ParserError::UnknownConstantPoolTag(tag)),
+            };
+
+            entries.push(Some((tag, try!(cursor.next_slice(length)))));
+            if tag == 5 || tag == 6 {
+                entries.push(None);
+            }
+        }
+
+        Ok(ConstantPoolRef { entries: entries })
+    }
+
+    /// The number of slots in the constant pool, which is one less than its count in the class
+    /// file.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The tag and undecoded body of the entry at `index`. The body of a `Utf8` entry leaves out
+    /// its length.
+    pub fn entry(&self, index: U2) -> ParserResult<(U1, &'a [U1])> {
+        let entry = if index == 0 { None } else { self.entries.get(index as usize - 1) };
+        match entry {
+            Some(&Some(entry)) => Ok(entry),
+            Some(&None) => Err(ParserError::UnexpectedConstantPoolItem("Empty")),
+            None => Err(ParserError::ConstantPoolIndexOutOfBounds(index as usize)),
+        }
+    }
+
+    /// Decodes the `Utf8` entry at `index`. It is only copied when its modified UTF-8 differs
+    /// from standard UTF-8, that is when it holds NULs or supplementary characters.
+    pub fn utf8(&self, index: U2) -> ParserResult<Cow<'a, str>> {
+        let bytes = try!(self.expect(index, 1));
+        match mutf8::as_str(bytes) {
+            Some(value) => Ok(Cow::Borrowed(value)),
+            None => mutf8::decode(bytes).map(|(value, _)| Cow::Owned(value)),
+        }
+    }
+
+    /// The name of the `Class` entry at `index`, in internal form.
+    pub fn class_name(&self, index: U2) -> ParserResult<Cow<'a, str>> {
+        let body = try!(self.expect(index, 7));
+        self.utf8(read_u2(body, 0))
+    }
+
+    /// The name and descriptor of the `NameAndType` entry at `index`.
+    pub fn name_and_type(&self, index: U2) -> ParserResult<(Cow<'a, str>, Cow<'a, str>)> {
+        let body = try!(self.expect(index, 12));
+        Ok((try!(self.utf8(read_u2(body, 0))), try!(self.utf8(read_u2(body, 2)))))
+    }
+
+    fn expect(&self, index: U2, expected_tag: U1) -> ParserResult<&'a [U1]> {
+        let (tag, body) = try!(self.entry(index));
+        if tag != expected_tag {
+            return Err(ParserError::UnexpectedConstantPoolItem(tag_name(tag)));
+        }
+
+        Ok(body)
+    }
+}
+
+impl<'a> AttributeRef<'a> {
+    fn from_all(cursor: &mut ByteCursor<'a>) -> ParserResult<Vec<AttributeRef<'a>>> {
+        let attributes_count = try!(cursor.next_u2());
+        let mut attributes = vec![];
+        for _ in 0..attributes_count {
+            let name_index = try!(cursor.next_u2());
+            let length = try!(cursor.next_u4());
+            attributes.push(AttributeRef {
+                name_index: name_index,
+                info: try!(cursor.next_slice(length as usize)),
+            });
+        }
+
+        Ok(attributes)
+    }
+
+    pub fn name(&self, constant_pool: &ConstantPoolRef<'a>) -> ParserResult<Cow<'a, str>> {
+        constant_pool.utf8(self.name_index)
+    }
+}
+
+macro_rules! generate_member_ref_impl {
+    ($impl_name:ident, $access:ident) => {
+        impl<'a> $impl_name<'a> {
+            fn from(cursor: &mut ByteCursor<'a>) -> ParserResult<$impl_name<'a>> {
+                let access_flags = try!(cursor.next_u2());
+                let name_index = try!(cursor.next_u2());
+                let descriptor_index = try!(cursor.next_u2());
+                let attributes = try!(AttributeRef::from_all(cursor));
+
+                Ok($impl_name {
+                    access_flags: access_flags,
+                    name_index: name_index,
+                    descriptor_index: descriptor_index,
+                    attributes: attributes,
+                })
+            }
+
+            pub fn access(&self) -> $access {
+                $access::from(self.access_flags)
+            }
+
+            pub fn name(&self, constant_pool: &ConstantPoolRef<'a>) -> ParserResult<Cow<'a, str>> {
+                constant_pool.utf8(self.name_index)
+            }
+
+            pub fn descriptor(&self, constant_pool: &ConstantPoolRef<'a>)
+                -> ParserResult<Cow<'a, str>> {
+                constant_pool.utf8(self.descriptor_index)
+            }
+
+            /// The first attribute called `name`.
+            pub fn attribute(&self,
+                             name: &str,
+                             constant_pool: &ConstantPoolRef<'a>)
+                             -> ParserResult<Option<AttributeRef<'a>>> {
+                find_attribute(&self.attributes, name, constant_pool)
+            }
+        }
+    }
+}
+
+generate_member_ref_impl!(FieldRef, FieldAccess);
+generate_member_ref_impl!(MethodRef, MethodAccess);
+
+impl<'a> MethodRef<'a> {
+    /// Reads the `Code` attribute of the method, if it has one.
+    pub fn code(&self, constant_pool: &ConstantPoolRef<'a>) -> ParserResult<Option<CodeRef<'a>>> {
+        match try!(self.attribute("Code", constant_pool)) {
+            Some(attribute) => CodeRef::from(attribute.info).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+impl<'a> CodeRef<'a> {
+    pub fn from(info: &'a [U1]) -> ParserResult<CodeRef<'a>> {
+        let mut cursor = ByteCursor::new(info);
+
+        let max_stack = try!(cursor.next_u2());
+        let max_locals = try!(cursor.next_u2());
+        let code_length = try!(cursor.next_u4());
+        let code = try!(cursor.next_slice(code_length as usize));
+
+        let exception_table_length = try!(cursor.next_u2());
+        let mut exception_table = vec![];
+        for _ in 0..exception_table_length {
+            exception_table.push(try!(ExceptionHandler::from(&mut cursor)));
+        }
+
+        let attributes = try!(AttributeRef::from_all(&mut cursor));
+        if cursor.remaining() != 0 {
+            return Err(ParserError::AttributeLengthMismatch("Code".to_owned()));
+        }
+
+        Ok(CodeRef {
+            max_stack: max_stack,
+            max_locals: max_locals,
+            code: code,
+            exception_table: exception_table,
+            attributes: attributes,
+        })
+    }
+
+    pub fn instructions(&self) -> InstructionIterator<'a> {
+        InstructionIterator::new(self.code)
+    }
+}
+
+fn find_attribute<'a>(attributes: &[AttributeRef<'a>],
+                      name: &str,
+                      constant_pool: &ConstantPoolRef<'a>)
+                      -> ParserResult<Option<AttributeRef<'a>>> {
+    for attribute in attributes {
+        if try!(attribute.name(constant_pool)) == name {
+            return Ok(Some(*attribute));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Reads a `u2` out of a constant pool entry, whose length was checked when it was parsed.
+fn read_u2(body: &[U1], position: usize) -> U2 {
+    ((body[position] as U2) << 8) | body[position + 1] as U2
+}
+
+#[cfg_attr(rustfmt, rustfmt_skip)]
+fn tag_name(tag: U1) -> &'static str {
+    match tag {
+        1 => "Utf8",
+        3 => "Integer",
+        4 => "Float",
+        5 => "Long",
+        6 => "Double",
+        7 => "Class",
+        8 => "String",
+        9 => "Field",
+        10 => "Method",
+        11 => "InterfaceMethod",
+        12 => "NameAndType",
+        15 => "MethodHandle",
+        16 => "MethodType",
+        _ => "InvokeDynamic",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    extern crate spectral;
+
+    use self::spectral::prelude::*;
+
+    use super::ClassFileRef;
+    use super::super::{ClassFile, ParserError};
+
+    use std::borrow::Cow;
+
+    /// A class `A` extending `java/lang/Object` with no members, for version 52.
+    #[cfg_attr(rustfmt, rustfmt_skip)]
+    const EMPTY_CLASS: &'static [u8] = &[
+        0xCA, 0xFE, 0xBA, 0xBE, 0x00, 0x00, 0x00, 0x34,
+        0x00, 0x07,
+        0x01, 0x00, 0x01, b'A',
+        0x07, 0x00, 0x01,
+        0x01, 0x00, 0x10, b'j', b'a', b'v', b'a', b'/', b'l', b'a', b'n', b'g', b'/',
+        b'O', b'b', b'j', b'e', b'c', b't',
+        0x07, 0x00, 0x03,
+        0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x2A,
+        0x00, 0x21, 0x00, 0x02, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn can_read_constants_lazily() {
+        let classfile = ClassFileRef::parse(EMPTY_CLASS).unwrap();
+
+        assert_that(&classfile.constant_pool.len()).is_equal_to(&6);
+        assert_that(&classfile.classname().unwrap().as_ref()).is_equal_to(&"A");
+        assert_that(&classfile.super_class_name().unwrap())
+            .is_equal_to(&Some("java/lang/Object".into()));
+        assert_that(&classfile.interface_names().unwrap()).has_length(0);
+
+        match classfile.constant_pool.class_name(1) {
+            Err(ParserError::UnexpectedConstantPoolItem(name)) => {
+                assert_that(&name).is_equal_to(&"Utf8")
+            }
+            result => panic!("expected a Utf8 entry, got {:?}", result),
+        }
+        assert_that(&classfile.constant_pool.utf8(6).is_err()).is_equal_to(&true);
+        assert_that(&classfile.constant_pool.utf8(0).is_err()).is_equal_to(&true);
+    }
+
+    #[test]
+    fn rejects_truncated_class_files() {
+        for length in 0..EMPTY_CLASS.len() {
+            let result = ClassFileRef::parse(&EMPTY_CLASS[..length]);
+            assert_that(&result.is_err()).is_equal_to(&true);
+        }
+    }
+
+    #[test]
+    fn decodes_modified_utf8_like_the_parser() {
+        // renames the class to "a\0", with the NUL in its two byte form
+        let mut bytes = EMPTY_CLASS[..10].to_vec();
+        bytes.extend_from_slice(&[0x01, 0x00, 0x03, 0x61, 0xC0, 0x80]);
+        bytes.extend_from_slice(&EMPTY_CLASS[14..]);
+
+        let view = ClassFileRef::parse(&bytes).unwrap();
+        let classfile = ClassFile::parse(&bytes).unwrap();
+
+        assert_that(&view.classname().unwrap().as_ref()).is_equal_to(&"a\0");
+        assert_that(&view.classname().unwrap().as_ref())
+            .is_equal_to(&classfile.classname().unwrap().as_str());
+        match view.super_class_name().unwrap() {
+            Some(Cow::Borrowed(name)) => assert_that(&name).is_equal_to(&"java/lang/Object"),
+            name => panic!("expected a borrowed name, got {:?}", name),
+        }
+    }
+
+}